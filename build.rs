@@ -0,0 +1,21 @@
+//! Embeds information about the build (like the git sha it was built from)
+//! so it can be shown by `dev-loop version`.
+
+use std::process::Command;
+
+fn main() {
+	println!("cargo:rerun-if-changed=.git/HEAD");
+	println!("cargo:rerun-if-changed=.git/refs");
+
+	// Building from a source tarball (without git) is fine, we just won't have
+	// a sha to show.
+	if let Ok(output) = Command::new("git")
+		.args(["rev-parse", "--short", "HEAD"])
+		.output()
+	{
+		if output.status.success() {
+			let sha = String::from_utf8_lossy(&output.stdout);
+			println!("cargo:rustc-env=DL_GIT_SHA={}", sha.trim());
+		}
+	}
+}
@@ -3,24 +3,72 @@
 //! circumstances where we like powered off while running. However, it should
 //! always be safe to run.
 
-use crate::executors::{docker, host};
+use crate::{
+	executors::{docker, host},
+	time_helper::parse_human_duration,
+};
 
-use color_eyre::Result;
+use color_eyre::{eyre::eyre, Result, Section};
+use std::time::Duration;
 use tracing::info;
 
+/// Parse the arguments to clean.
+///
+/// `args`: the arguments passed to the clean command.
+///
+/// Returns the `--since` duration if it was specified, and if
+/// `--labeled-only` was passed.
+///
+/// # Errors
+///
+/// - When `--since` is passed without a value, or with an invalid duration.
+/// - When an argument we don't know about is passed.
+fn parse_clean_args(args: &[String]) -> Result<(Option<Duration>, bool)> {
+	let mut since = None;
+	let mut labeled_only = false;
+	let mut arg_iter = args.iter();
+
+	while let Some(arg) = arg_iter.next() {
+		if let Some(value) = arg.strip_prefix("--since=") {
+			since = Some(parse_human_duration(value)?);
+		} else if arg == "--since" {
+			let value = arg_iter.next().ok_or_else(|| {
+				eyre!("The `--since` flag for clean was specified without a duration.")
+			})?;
+			since = Some(parse_human_duration(value)?);
+		} else if arg == "--labeled-only" {
+			labeled_only = true;
+		} else {
+			return Err(eyre!("Unknown argument to clean: [{}]", arg)).suggestion(
+				"Clean only accepts: `--since <duration>`, and `--labeled-only`, for example: `clean --since 2h`.",
+			);
+		}
+	}
+
+	Ok((since, labeled_only))
+}
+
 /// Execute the clean command.
 ///
+/// `args`: the arguments for this clean command.
+///
 /// # Errors
 ///
+/// - When the arguments to clean are invalid.
 /// - When one of the underlying executor cleanups fail.
-pub async fn handle_clean_command() -> Result<()> {
+pub async fn handle_clean_command(args: &[String]) -> Result<()> {
 	let span = tracing::info_span!("clean");
 	let _guard = span.enter();
 
-	info!("Cleaning resources ...");
+	let (older_than, labeled_only) = parse_clean_args(args)?;
+	if let Some(age) = older_than {
+		info!("Cleaning resources older than: [{}s] ...", age.as_secs());
+	} else {
+		info!("Cleaning resources ...");
+	}
 
-	host::Executor::clean().await;
-	docker::Executor::clean().await?;
+	host::Executor::clean(older_than).await;
+	docker::Executor::clean(older_than, labeled_only).await?;
 
 	info!("Cleaned.");
 	Ok(())
@@ -0,0 +1,151 @@
+//! Implements the `doctor` command, or the command that checks the
+//! environment dev-loop is running in, and tells the user how to fix
+//! anything that looks wrong. This is mostly useful for new users who are
+//! still getting everything setup.
+
+use crate::{
+	config,
+	dirs::get_tmp_dir,
+	executors::{docker, host, CompatibilityStatus},
+	terminal::TERM,
+};
+
+use color_eyre::{eyre::eyre, Result, Section};
+use colored::Colorize;
+use std::path::Path;
+
+/// The result of a single check that `doctor` performs.
+struct DoctorCheck {
+	/// The name of the thing being checked, e.g. `bash`.
+	name: &'static str,
+	/// If this check failing means dev-loop can't run at all.
+	critical: bool,
+	/// `None` when the check passed, otherwise how to fix it.
+	failure: Option<String>,
+}
+
+impl DoctorCheck {
+	/// Turn the status of an executor's compatibility check into a doctor
+	/// check, reusing the help the executor gives.
+	fn from_compatibility(name: &'static str, critical: bool, status: CompatibilityStatus) -> Self {
+		let failure = match status {
+			CompatibilityStatus::Compatible => None,
+			CompatibilityStatus::CouldBeCompatible(how_to_fix) => {
+				Some(format!("not available, to fix: {}", how_to_fix))
+			}
+			CompatibilityStatus::CannotBeCompatible(help) => {
+				Some(help.unwrap_or_else(|| "can not be used on this system".to_owned()))
+			}
+		};
+
+		Self {
+			name,
+			critical,
+			failure,
+		}
+	}
+
+	/// Render the description for this check in the checklist.
+	fn render_description(&self) -> String {
+		let (status, message) = match (&self.failure, self.critical) {
+			(None, _) => ("PASS", "ok".to_owned()),
+			(Some(failure), true) => ("FAIL", failure.to_owned()),
+			(Some(failure), false) => ("WARN", format!("{} (optional)", failure)),
+		};
+
+		if !TERM.should_color_stdout() {
+			return format!("[{}] {}", status, message);
+		}
+		let coloured_status = match status {
+			"PASS" => status.green(),
+			"WARN" => status.yellow(),
+			_ => status.red(),
+		};
+		format!("[{}] {}", coloured_status, message)
+	}
+}
+
+/// Check that the project configuration can be found, and parsed.
+///
+/// `config_override`: an explicitly provided configuration to check.
+fn check_config(config_override: Option<&Path>) -> DoctorCheck {
+	let failure = match config::get_top_level(config_override) {
+		Ok(Some(_)) => None,
+		Ok(None) => Some(
+			"could not find [.dl/config.yml] in the current directory, or any parent directory, to fix: create one, or run dev-loop from inside your project.".to_owned(),
+		),
+		Err(config_err) => Some(format!(
+			"[.dl/config.yml] is not valid, to fix: {}",
+			config_err
+		)),
+	};
+
+	DoctorCheck {
+		name: "config",
+		critical: true,
+		failure,
+	}
+}
+
+/// Check that the temporary directory dev-loop writes scripts to can
+/// actually be written to.
+fn check_tmp_dir() -> DoctorCheck {
+	let tmp_dir = get_tmp_dir();
+	let probe_path = tmp_dir.join(format!("dl-doctor-{}", std::process::id()));
+	let failure = match std::fs::write(&probe_path, b"dev-loop") {
+		Ok(_) => {
+			let _ = std::fs::remove_file(&probe_path);
+			None
+		}
+		Err(io_err) => Some(format!(
+			"can not write to: [{}] ({}), to fix: make it writeable, or point `TMPDIR` at a directory that is.",
+			tmp_dir.to_string_lossy(),
+			io_err,
+		)),
+	};
+
+	DoctorCheck {
+		name: "tmp dir",
+		critical: true,
+		failure,
+	}
+}
+
+/// Handle the `doctor` command, this never needs any configuration (since
+/// checking the configuration is one of the things it does).
+///
+/// `config_override`: an explicitly provided configuration (through
+///                    `--config`, or `DL_CONFIG`) to check.
+///
+/// # Errors
+///
+/// - When any critical check fails.
+pub async fn handle_doctor_command(config_override: Option<&Path>) -> Result<()> {
+	let checks = [
+		DoctorCheck::from_compatibility("bash", true, host::Executor::is_compatible()),
+		DoctorCheck::from_compatibility("docker", false, docker::Executor::is_compatible().await),
+		check_config(config_override),
+		check_tmp_dir(),
+	];
+
+	let items = checks
+		.iter()
+		.map(|check| (check.name.to_owned(), check.render_description()))
+		.collect::<Vec<(String, String)>>();
+	println!("{}", TERM.render_list_section("Doctor", &items));
+
+	let failed_checks = checks
+		.iter()
+		.filter(|check| check.critical && check.failure.is_some())
+		.map(|check| check.name)
+		.collect::<Vec<&str>>();
+	if failed_checks.is_empty() {
+		Ok(())
+	} else {
+		Err(eyre!(
+			"Critical checks failed: [{}]",
+			failed_checks.join(", ")
+		))
+		.note("dev-loop will not be able to run until these checks pass.")
+	}
+}
@@ -3,15 +3,17 @@
 //! most times, perhaps the only thing that comes close is list.
 
 use crate::{
+	commands::{apply_cwd_flag, handle_execution_result, parse_execution_flags},
 	config::types::{TaskConf, TaskType, TopLevelConf},
 	executors::ExecutorRepository,
 	fetch::FetcherRepository,
-	strsim::add_did_you_mean_text,
+	strsim::add_adaptive_did_you_mean_text,
 	tasks::{
 		execution::{
 			execute_tasks_in_parallel,
 			preparation::{
-				build_ordered_execution_list, fetch_helpers, new_pipeline_id, WorkQueue,
+				build_ordered_execution_list, describe_execution_plan, fetch_helpers,
+				new_pipeline_id, WorkQueue,
 			},
 		},
 		fs::ensure_dirs,
@@ -19,9 +21,38 @@ use crate::{
 	},
 };
 
-use color_eyre::{eyre::eyre, Report, Result, Section};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Report, Result, Section,
+};
 use crossbeam_deque::Worker;
 use std::{collections::HashMap, path::PathBuf};
+use tracing::warn;
+
+/// Get the names of every task that can be run with `exec` (e.g. that isn't
+/// internal), sorted by name.
+///
+/// This is best-effort, if the tasks can't be loaded no names are returned.
+///
+/// `config`: the top level configuration.
+/// `fetcher`: used for fetching the task files.
+pub async fn get_runnable_task_names(
+	config: &TopLevelConf,
+	fetcher: &FetcherRepository,
+) -> Vec<String> {
+	let tasks = match TaskGraph::new(config, fetcher).await {
+		Ok(graph) => graph.consume_and_get_tasks(),
+		Err(_) => return Vec::new(),
+	};
+
+	let mut names = tasks
+		.into_iter()
+		.filter(|(_, task_conf)| !task_conf.is_internal())
+		.map(|(name, _)| name)
+		.collect::<Vec<String>>();
+	names.sort();
+	names
+}
 
 /// Attempt to find simple replacement for an internal task.
 fn report_potential_internal_task_names<T>(
@@ -100,7 +131,7 @@ fn report_potential_internal_task_names<T>(
 ///
 /// # Errors
 ///
-/// - Can Error when no argument was provided.
+/// - Can Error when no argument was provided, or an unknown flag was provided.
 /// - Error constructing the `TaskGraph`.
 /// - Error finding the task the user wants to run/running an internal task.
 /// - Error creating directories that need to be ensured.
@@ -116,12 +147,15 @@ pub async fn handle_exec_command(
 	let span = tracing::info_span!("exec");
 	let _guard = span.enter();
 
+	let (flags, args) = parse_execution_flags(args)?;
+	let fetcher = &apply_cwd_flag(fetcher, &flags, root_dir)?;
+
 	// The order of exec:
 	//
 	//  1. Validate we have a task to run:
 	//     * Task name should be args[0]
 	//     * The task must exist.
-	//     * Task should not be marked internal.
+	//     * Task should not be marked internal (unless `--allow-internal`).
 	//  2. Create all the directories we need for execution.
 	//  3. Fetch all the executors definitions.
 	//  4. Build the list of tasks to run, and in what order.
@@ -141,22 +175,28 @@ pub async fn handle_exec_command(
 	// Now let's make sure we can actually run the task we need to.
 	let user_specified_task = &args[0];
 	if !tasks.contains_key(user_specified_task) {
-		return add_did_you_mean_text(
+		return add_adaptive_did_you_mean_text(
 			Err(eyre!("There is no task named: [{}]", user_specified_task,)),
 			user_specified_task,
 			&tasks.keys().map(String::as_str).collect::<Vec<&str>>(),
-			3,
 			Some("You can use the list subcommand to get a list of tasks you can execute"),
 		);
 	}
 	let selected_task = &tasks[user_specified_task];
 	if selected_task.is_internal() {
-		return report_potential_internal_task_names(
-			Err(eyre!(
-				"Internal Task: [{}] cannot be run directly.",
+		if !flags.allow_internal {
+			return report_potential_internal_task_names(
+				Err(eyre!(
+					"Internal Task: [{}] cannot be run directly.",
+					user_specified_task,
+				)),
+				&tasks,
 				user_specified_task,
-			)),
-			&tasks,
+			)
+			.note("If you're debugging this task you can pass `--allow-internal` to `exec` (before the task name) to run it directly.");
+		}
+		warn!(
+			"Running Internal Task: [{}] directly, since `--allow-internal` was passed.",
 			user_specified_task,
 		);
 	}
@@ -166,7 +206,9 @@ pub async fn handle_exec_command(
 	ensure_dirs(config, root_dir)?;
 
 	// Let's fetch all the executors so we know how to assign them to tasks.
-	let mut erepo = ExecutorRepository::new(config, fetcher, root_dir).await?;
+	let mut erepo = ExecutorRepository::new(config, fetcher, root_dir)
+		.await?
+		.with_executor_override(flags.executor.clone());
 
 	// Generate the task execution order.
 	let pid = new_pipeline_id();
@@ -187,31 +229,35 @@ pub async fn handle_exec_command(
 		.await?;
 	}
 
+	// Describe the plan rather than running it, this is built exactly the same
+	// way so it always matches what would be run.
+	if flags.plan_json {
+		let plan = describe_execution_plan(&worker, &erepo);
+		println!(
+			"{}",
+			serde_json::to_string(&plan).wrap_err("Failed to serialize the execution plan")?
+		);
+		return Ok(());
+	}
+
 	// Finally fetch all the helpers...
 	let helpers = fetch_helpers(config, fetcher).await?;
 
-	let mut parallelism = num_cpus::get_physical();
-	if let Ok(env_var) = std::env::var("DL_WORKER_COUNT") {
-		if let Ok(worker_count) = env_var.parse::<usize>() {
-			parallelism = worker_count;
-		}
-	}
-
-	let res = execute_tasks_in_parallel(helpers, worker, task_size, parallelism).await;
-	// Don't clean if we encouter an error, aid in debugging.
-	match res {
-		Ok(exit_code) => {
-			if exit_code == 0 {
-				// Don't cause an error for cleaning if the task succeeded, the user can always clean manually.
-				let _ = crate::executors::docker::Executor::clean().await;
-				Ok(())
-			} else {
-				Err(eyre!(
-					"One of the tasks being run failed. You can use the logs above from your tasks to debug.",
-				))
-				.note(format!("Failing exit code: {}", exit_code))
-			}
-		}
-		Err(err_code) => Err(err_code),
-	}
+	let res = execute_tasks_in_parallel(
+		helpers,
+		config.get_variables(),
+		&flags.env,
+		worker,
+		task_size,
+		flags.get_parallelism(),
+		flags.get_execution_settings(),
+	)
+	.await;
+	handle_execution_result(
+		res,
+		&erepo,
+		&flags,
+		"One of the tasks being run failed. You can use the logs above from your tasks to debug.",
+	)
+	.await
 }
@@ -0,0 +1,266 @@
+//! Represents the "explain" command, which describes how a task would run:
+//! what it expands to, where each task (and script) comes from, which
+//! executor was chosen, and why. Nothing is actually executed.
+
+use crate::{
+	commands::{apply_cwd_flag, parse_execution_flags},
+	config::types::{TaskConf, TopLevelConf},
+	executors::ExecutorRepository,
+	fetch::FetcherRepository,
+	strsim::add_adaptive_did_you_mean_text,
+	tasks::{
+		execution::preparation::{
+			build_ordered_execution_list, new_pipeline_id, ExecutableTask, WorkQueue, WorkUnit,
+		},
+		TaskGraph,
+	},
+	terminal::TERM,
+};
+
+use color_eyre::{eyre::eyre, Result, Section};
+use crossbeam_deque::Worker;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Describe why an executor would be chosen for a task, following the same
+/// precedence as `ExecutorRepository::select_executor`.
+///
+/// `task`: the configuration of the task.
+/// `executor_override`: the executor passed with `--executor`, if any.
+fn describe_executor_choice(task: &TaskConf, executor_override: Option<&str>) -> String {
+	if executor_override.is_some() {
+		return "`--executor` was passed".to_owned();
+	}
+
+	if let Some(custom_executor) = task.get_custom_executor() {
+		return if let Some(label) = custom_executor.get_label() {
+			format!("the task has a `custom_executor` labeled: [{}]", label)
+		} else {
+			"the task has a `custom_executor`".to_owned()
+		};
+	}
+
+	if let Some(needs) = task.get_execution_needs() {
+		let described_needs = needs
+			.iter()
+			.map(|need| {
+				if let Some(matcher) = need.get_version_matcher() {
+					format!("{} {}", need.get_name(), matcher)
+				} else {
+					need.get_name().to_owned()
+				}
+			})
+			.collect::<Vec<_>>()
+			.join(", ");
+		return format!(
+			"it meets the `execution_needs`: [{}] (active executors are preferred, then the one declared first)",
+			described_needs,
+		);
+	}
+
+	"it's the default executor, the task has no `custom_executor`, or `execution_needs`".to_owned()
+}
+
+/// Describe a single task that would be executed, as a list of
+/// `(field, description)` ready to be rendered.
+///
+/// `executable`: the task as it was prepared for execution.
+/// `tasks`: every task, to find the configuration of the task.
+/// `executors`: the repository executors were selected from.
+/// `executor_override`: the executor passed with `--executor`, if any.
+fn describe_executable_task(
+	executable: &ExecutableTask,
+	tasks: &HashMap<String, TaskConf>,
+	executors: &ExecutorRepository,
+	executor_override: Option<&str>,
+) -> Vec<(String, String)> {
+	let mut described = Vec::new();
+	let conf = tasks.get(executable.get_task_name());
+
+	if let Some(conf) = conf {
+		described.push(("defined in".to_owned(), conf.get_source_path().to_owned()));
+	}
+	described.push((
+		"script".to_owned(),
+		executable.get_contents().get_source().to_owned(),
+	));
+	described.push((
+		"args".to_owned(),
+		if executable.get_args().is_empty() {
+			"none".to_owned()
+		} else {
+			executable.get_arg_string()
+		},
+	));
+
+	let executor_id = executors
+		.get_executor_id(executable.get_executor())
+		.unwrap_or_else(|| "unknown".to_owned());
+	described.push((
+		"executor".to_owned(),
+		format!(
+			"{} ({})",
+			executor_id,
+			executable.get_executor().get_type_name()
+		),
+	));
+	if let Some(conf) = conf {
+		described.push((
+			"chosen because".to_owned(),
+			describe_executor_choice(conf, executor_override),
+		));
+	}
+
+	described.push((
+		"pipeline id".to_owned(),
+		executable.get_pipeline_id().to_owned(),
+	));
+	if let Some(only_if) = executable.get_only_if() {
+		described.push((
+			"only if".to_owned(),
+			format!("the task: [{}] succeeds", only_if.get_task_name()),
+		));
+	}
+	if executable.allows_failure() {
+		described.push((
+			"allow failure".to_owned(),
+			"a failing exit code won't fail the run".to_owned(),
+		));
+	}
+
+	described
+}
+
+/// Handle the "explain" command provided by dev loop.
+///
+/// This builds the list of tasks to run exactly like `exec` does, and then
+/// describes it rather than running it.
+///
+/// # Errors
+///
+/// - When no task name was provided, or an unknown flag was provided.
+/// - Error constructing the `TaskGraph`.
+/// - When the task doesn't exist.
+/// - Error creating an executor/choosing an executor for tasks.
+pub async fn handle_explain_command(
+	config: &TopLevelConf,
+	fetcher: &FetcherRepository,
+	args: &[String],
+	root_dir: &PathBuf,
+) -> Result<()> {
+	let span = tracing::info_span!("explain");
+	let _guard = span.enter();
+
+	let (flags, args) = parse_execution_flags(args)?;
+	let fetcher = &apply_cwd_flag(fetcher, &flags, root_dir)?;
+	if args.is_empty() {
+		return Err(eyre!("Please specify a task name to explain!"))
+			.suggestion("You can use the list subcommand to get a list of tasks you can explain.");
+	}
+
+	let tasks = TaskGraph::new(config, fetcher)
+		.await?
+		.consume_and_get_tasks();
+	let user_specified_task = &args[0];
+	let selected_task = if let Some(task) = tasks.get(user_specified_task) {
+		task
+	} else {
+		return add_adaptive_did_you_mean_text(
+			Err(eyre!("There is no task named: [{}]", user_specified_task)),
+			user_specified_task,
+			&tasks.keys().map(String::as_str).collect::<Vec<&str>>(),
+			Some("You can use the list subcommand to get a list of tasks you can explain"),
+		);
+	};
+
+	let mut erepo = ExecutorRepository::new(config, fetcher, root_dir)
+		.await?
+		.with_executor_override(flags.executor.clone());
+	let mut worker = Worker::new_fifo();
+	{
+		let mut worker_as_queue = WorkQueue::Queue(&mut worker);
+		build_ordered_execution_list(
+			&tasks,
+			selected_task,
+			fetcher,
+			&mut erepo,
+			root_dir.clone(),
+			&args[1..],
+			new_pipeline_id(),
+			&mut worker_as_queue,
+		)
+		.await?;
+	}
+
+	let mut overview = vec![
+		("type".to_owned(), selected_task.get_type().to_string()),
+		(
+			"defined in".to_owned(),
+			selected_task.get_source_path().to_owned(),
+		),
+		(
+			"description".to_owned(),
+			selected_task
+				.get_description()
+				.unwrap_or("no description provided")
+				.to_owned(),
+		),
+	];
+	if selected_task.is_internal() {
+		overview.push((
+			"internal".to_owned(),
+			"can only be run directly with `--allow-internal`".to_owned(),
+		));
+	}
+
+	let mut rendered =
+		TERM.render_list_section(&format!("TASK {}", user_specified_task), &overview);
+	let mut unit_idx = 0;
+	while let Some(unit) = worker.pop() {
+		unit_idx += 1;
+		match unit {
+			WorkUnit::SingleTask(task) => {
+				rendered += &format!(
+					"\n{}",
+					TERM.render_list_section(
+						&format!("RUNS {}: {}", unit_idx, task.get_task_name()),
+						&describe_executable_task(&task, &tasks, &erepo, flags.executor.as_deref()),
+					)
+				);
+			}
+			WorkUnit::Pipeline(pipeline) => {
+				for (step_idx, task) in pipeline.iter().enumerate() {
+					rendered += &format!(
+						"\n{}",
+						TERM.render_list_section(
+							&format!(
+								"RUNS {}.{} (pipeline step {} of {}): {}",
+								unit_idx,
+								step_idx + 1,
+								step_idx + 1,
+								pipeline.len(),
+								task.get_task_name()
+							),
+							&describe_executable_task(
+								task,
+								&tasks,
+								&erepo,
+								flags.executor.as_deref()
+							),
+						)
+					);
+				}
+			}
+		}
+	}
+	if unit_idx == 0 {
+		rendered += "\nNothing would be run, see any warnings above.\n";
+	}
+
+	println!(
+		"{}\n\n{}",
+		TERM.render_title_bar("Dev-Loop", "[explain]"),
+		rendered,
+	);
+
+	Ok(())
+}
@@ -8,16 +8,20 @@
 
 use crate::{
 	config::types::{OneofOption, TaskConf, TaskType, TopLevelConf},
+	executors::{docker, ExecutorRepository},
 	fetch::FetcherRepository,
-	strsim::calculate_did_you_mean_possibilities,
+	strsim::calculate_adaptive_did_you_mean_possibilities,
 	tasks::TaskGraph,
 	terminal::TERM,
 };
 use color_eyre::Result;
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 use tracing::error;
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+/// How long we're willing to wait on docker when checking for leftover
+/// resources, so a slow docker daemon never holds up the list output.
+const LEFTOVER_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Fetch a series of preset lists so they can be be rendered with
 /// `render_list_section`.
@@ -133,6 +137,131 @@ fn is_selectable_top_level_arg<'a, 'b>(
 	Some(selected_task)
 }
 
+/// Add the nodes for everything a task runs (the steps of a pipeline, or the
+/// options of a oneof) to a tree, recursing into each of them.
+///
+/// `task`: the task to add the children of.
+/// `tasks`: every task, internal tasks included.
+/// `prefix`: the tree prefix for the children of this task.
+/// `ancestors`: the tasks above this one, so a cycle is never followed.
+/// `nodes`: the tree being built.
+fn add_task_tree_children<'a>(
+	task: &'a TaskConf,
+	tasks: &'a HashMap<String, TaskConf>,
+	prefix: &str,
+	ancestors: &mut Vec<&'a str>,
+	nodes: &mut Vec<(String, String, String)>,
+) {
+	let children = match task.get_type() {
+		TaskType::Command => Vec::new(),
+		TaskType::Oneof => task
+			.get_options()
+			.map(|options| {
+				options
+					.iter()
+					.map(|option| {
+						(
+							option.get_name(),
+							option.get_task_name(),
+							option.get_description(),
+						)
+					})
+					.collect()
+			})
+			.unwrap_or_default(),
+		TaskType::Pipeline | TaskType::ParallelPipeline => task
+			.get_steps()
+			.map(|steps| {
+				steps
+					.iter()
+					.map(|step| {
+						(
+							step.get_name(),
+							step.get_task_name(),
+							step.get_description(),
+						)
+					})
+					.collect()
+			})
+			.unwrap_or_default(),
+	};
+
+	ancestors.push(task.get_name());
+	for (idx, (child_name, child_task_name, child_description)) in children.iter().enumerate() {
+		let is_last = idx + 1 == children.len();
+		let item = if child_name == child_task_name {
+			(*child_name).to_owned()
+		} else {
+			format!("{} -> {}", child_name, child_task_name)
+		};
+		let child_task = tasks.get(*child_task_name);
+		let mut description = child_description
+			.or_else(|| child_task.and_then(TaskConf::get_description))
+			.unwrap_or("no description provided")
+			.to_owned();
+		if matches!(child_task, Some(child) if child.is_internal()) {
+			description += " (internal)";
+		}
+		let is_cycle = ancestors.contains(child_task_name);
+		if is_cycle {
+			description += " (cycle, not expanded)";
+		}
+
+		nodes.push((
+			format!("{}{}", prefix, if is_last { "└─ " } else { "├─ " }),
+			item,
+			description,
+		));
+		if let (Some(child), false) = (child_task, is_cycle) {
+			add_task_tree_children(
+				child,
+				tasks,
+				&format!("{}{}", prefix, if is_last { "   " } else { "│  " }),
+				ancestors,
+				nodes,
+			);
+		}
+	}
+	ancestors.pop();
+}
+
+/// Render tasks as a tree, with the steps of pipelines, and the options of
+/// oneofs nested under them. Internal tasks are only shown under a task that
+/// runs them.
+///
+/// `selected_task`: only render the tree for this task, rather than every
+///                  task that isn't internal.
+/// `tasks`: every task, internal tasks included.
+fn handle_tree_list(selected_task: Option<&TaskConf>, tasks: &HashMap<String, TaskConf>) {
+	let mut roots = if let Some(task) = selected_task {
+		vec![task]
+	} else {
+		tasks
+			.values()
+			.filter(|task| !task.is_internal())
+			.collect::<Vec<&TaskConf>>()
+	};
+	roots.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+
+	let mut nodes = Vec::new();
+	for root in roots {
+		nodes.push((
+			String::new(),
+			root.get_name().to_owned(),
+			root.get_description()
+				.unwrap_or("no description provided")
+				.to_owned(),
+		));
+		add_task_tree_children(root, tasks, "", &mut Vec::new(), &mut nodes);
+	}
+
+	println!(
+		"{}\n\n{}",
+		TERM.render_title_bar("Dev-Loop", &format!("[{}]", VERSION.unwrap_or("unknown"))),
+		TERM.render_tree_section("Task Tree", &nodes),
+	);
+}
+
 /// Check if a task has options that can be selected.
 fn task_has_options(task: &TaskConf) -> bool {
 	if let Some(options) = task.get_options() {
@@ -160,9 +289,33 @@ fn handle_raw_list(config: &TopLevelConf, tasks: &HashMap<String, TaskConf>) {
 		"clean".to_owned(),
 		"to cleanup all dev-loop managed resources".to_owned(),
 	));
+	items.push((
+		"doctor".to_owned(),
+		"to check your environment is setup to run dev-loop".to_owned(),
+	));
+	items.push((
+		"explain".to_owned(),
+		"to describe how a task would run, and why, without running it".to_owned(),
+	));
+	items.push((
+		"schema".to_owned(),
+		"to print the JSON Schema for a config file (config, task, or executor)".to_owned(),
+	));
+	items.push((
+		"version".to_owned(),
+		"to show the version of dev-loop".to_owned(),
+	));
+	items.push((
+		"--list-executors".to_owned(),
+		"to show every executor, and the tools they provide".to_owned(),
+	));
 
-	let presets = get_presets_display(config);
-	let tasks = get_tasks_listable(tasks);
+	// Tasks come out of a map, so sort them (and presets) to keep the output
+	// stable between runs.
+	let mut presets = get_presets_display(config);
+	presets.sort_by(|a, b| a.0.cmp(&b.0));
+	let mut tasks = get_tasks_listable(tasks);
+	tasks.sort_by(|a, b| a.0.cmp(&b.0));
 
 	println!(
 		"{}\n\n{}{}{}",
@@ -207,13 +360,12 @@ fn handle_listing_arg<'a, 'b>(
 			// Don't check for internal task here since a oneof could be built off
 			// of internal options, and we want those to be selectable.
 			if potential_current_option.is_none() {
-				let did_you_mean_options = calculate_did_you_mean_possibilities(
+				let did_you_mean_options = calculate_adaptive_did_you_mean_possibilities(
 					arg,
 					&options
 						.iter()
 						.map(OneofOption::get_name)
 						.collect::<Vec<&str>>(),
-					3,
 				);
 
 				let error_str = if did_you_mean_options.is_empty() {
@@ -272,7 +424,8 @@ fn handle_listing_arg<'a, 'b>(
 ///
 /// `config` - the top level configuration object.
 /// `fetcher` - the thing that goes and fetches for us.
-/// `args` - the arguments for this list command.
+/// `args` - the arguments for this list command. A leading `--tree` renders
+///          tasks as a tree of the tasks they run.
 ///
 /// # Errors
 ///
@@ -292,10 +445,30 @@ pub async fn handle_list_command(
 	let tasks = TaskGraph::new(config, fetcher)
 		.await?
 		.consume_and_get_tasks();
+
+	if args.first().map(String::as_str) == Some("--tree") {
+		let selected_task = match args.get(1) {
+			Some(task_name) => {
+				let selected_task = tasks.get(task_name);
+				if selected_task.is_none() {
+					error!(
+						"Argument #2 ({}) is not a task that exists. Showing the tree for all tasks.",
+						task_name,
+					);
+				}
+				selected_task
+			}
+			None => None,
+		};
+		handle_tree_list(selected_task, &tasks);
+		return Ok(());
+	}
+
 	let last_selected_task = handle_listing_arg(&tasks, args);
 
 	if last_selected_task.is_none() {
 		handle_raw_list(config, &tasks);
+		report_leftover_docker_resources().await;
 		return Ok(());
 	}
 
@@ -314,3 +487,99 @@ pub async fn handle_list_command(
 
 	Ok(())
 }
+
+/// Let the user know about any dev-loop docker containers/networks that
+/// exist, since they're normally left behind by a run that crashed.
+///
+/// This happens after the list has been printed, and is best effort: when
+/// docker isn't available (or takes too long to answer) nothing is printed.
+async fn report_leftover_docker_resources() {
+	let leftovers = async_std::future::timeout(
+		LEFTOVER_CHECK_TIMEOUT,
+		docker::Executor::list_leftover_resources(),
+	)
+	.await;
+	let (containers, networks) = if let Ok(Some(resources)) = leftovers {
+		resources
+	} else {
+		return;
+	};
+	if containers.is_empty() && networks.is_empty() {
+		return;
+	}
+
+	let mut items = containers
+		.into_iter()
+		.map(|container| (container, "container".to_owned()))
+		.collect::<Vec<(String, String)>>();
+	items.extend(
+		networks
+			.into_iter()
+			.map(|network| (network, "network".to_owned())),
+	);
+	eprintln!(
+		"{}\nThese may have been left behind by a dev-loop run that crashed, if no dev-loop is running you can remove them with: `dev-loop clean`.\n",
+		TERM.render_list_section("Leftover Docker Resources", &items),
+	);
+}
+
+/// Handle listing all the executors that could be selected, for debugging
+/// why a task can't find an executor.
+///
+/// `config` - the top level configuration object.
+/// `fetcher` - the thing that goes and fetches for us.
+/// `root_dir` - the root directory of the project.
+///
+/// # Errors
+///
+/// - When fetching, or parsing the executor configuration files.
+pub async fn handle_list_executors_command(
+	config: &TopLevelConf,
+	fetcher: &FetcherRepository,
+	root_dir: &PathBuf,
+) -> Result<()> {
+	let span = tracing::info_span!("list_executors");
+	let _guard = span.enter();
+
+	let erepo = ExecutorRepository::new(config, fetcher, root_dir).await?;
+	let executors = erepo.describe_executors();
+
+	let mut rendered = String::new();
+	for (id, label, type_name, provided_tools) in &executors {
+		let tools = provided_tools
+			.iter()
+			.map(|(name, version)| {
+				(
+					name.clone(),
+					version.as_ref().map_or_else(
+						|| "any version".to_owned(),
+						std::string::ToString::to_string,
+					),
+				)
+			})
+			.collect::<Vec<(String, String)>>();
+
+		rendered += &format!(
+			"\n{}",
+			TERM.render_list_section(
+				&if let Some(label) = label {
+					format!("{} [{}] ({})", label, id, type_name)
+				} else {
+					format!("{} ({})", id, type_name)
+				},
+				&tools
+			)
+		);
+	}
+	if executors.is_empty() {
+		rendered += "\nNo executors could be created, see any warnings above.\n";
+	}
+
+	println!(
+		"{}\n{}",
+		TERM.render_title_bar("Dev-Loop", &format!("[{}]", VERSION.unwrap_or("unknown"))),
+		rendered,
+	);
+
+	Ok(())
+}
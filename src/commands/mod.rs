@@ -1,6 +1,576 @@
 //! The overarching module for all particular commands implemented by Dev-Loop.
 
+use crate::{
+	executors::ExecutorRepository,
+	fetch::FetcherRepository,
+	tasks::execution::{
+		is_valid_variable_name, preparation::TagMatchMode, ExecutionResult, ExecutionSettings,
+	},
+	terminal::ColourMode,
+	time_helper::parse_human_duration,
+};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
+use std::{
+	fs::canonicalize,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicI32, Ordering},
+	time::Duration,
+};
+
 pub(crate) mod clean;
+pub(crate) mod doctor;
 pub(crate) mod exec;
+pub(crate) mod explain;
 pub(crate) mod list;
 pub(crate) mod run;
+pub(crate) mod schema;
+pub(crate) mod version;
+
+/// The exit code dev-loop should exit with when a command fails, if it isn't
+/// the default of `1`.
+static FAILING_EXIT_CODE: AtomicI32 = AtomicI32::new(1);
+
+/// Set the exit code dev-loop exits with when the command returns an error,
+/// so scripts can tell some failures (like being interrupted) apart.
+pub fn set_failing_exit_code(exit_code: i32) {
+	FAILING_EXIT_CODE.store(exit_code, Ordering::Release);
+}
+
+/// Get the exit code dev-loop should exit with when the command returns an
+/// error.
+#[must_use]
+pub fn get_failing_exit_code() -> i32 {
+	FAILING_EXIT_CODE.load(Ordering::Acquire)
+}
+
+/// The flags dev-loop understands for every command, these must come before
+/// the name of the command.
+#[derive(Debug, Default)]
+pub struct GlobalFlags {
+	/// An explicit project configuration to use, passed with `--config`.
+	pub config: Option<String>,
+	/// An explicit project root to use, passed with `--project-root`.
+	pub project_root: Option<String>,
+	/// When to use colour, passed with `--color`.
+	pub colour: ColourMode,
+}
+
+/// Parse the value of a `--color` flag: `auto`, `always`, or `never`.
+///
+/// # Errors
+///
+/// - When the value is not one of the known modes.
+fn parse_colour_flag(value: &str) -> Result<ColourMode> {
+	ColourMode::parse(value)
+		.ok_or_else(|| eyre!("The `--color` flag: [{}] is invalid.", value))
+		.suggestion("Pass one of: `--color auto`, `--color always`, or `--color never`.")
+}
+
+/// Find the `--color` passed in the leading global flags, without erroring.
+///
+/// Colour needs to be decided before error reporting is setup, so this can't
+/// create any errors itself. An invalid `--color` is treated as `auto` here,
+/// and reported later by `parse_global_flags`.
+#[must_use]
+pub fn peek_colour_mode(args: &[String]) -> ColourMode {
+	let mut mode = ColourMode::Auto;
+	let mut idx = 0;
+
+	while idx < args.len() {
+		match args[idx].as_str() {
+			"--config" | "--project-root" => idx += 1,
+			"--color" => {
+				idx += 1;
+				if let Some(parsed) = args.get(idx).and_then(|value| ColourMode::parse(value)) {
+					mode = parsed;
+				}
+			}
+			flag if flag.starts_with("--color=") => {
+				if let Some(parsed) = ColourMode::parse(flag.trim_start_matches("--color=")) {
+					mode = parsed;
+				}
+			}
+			flag if flag.starts_with("--config=") || flag.starts_with("--project-root=") => {}
+			_ => break,
+		}
+		idx += 1;
+	}
+
+	mode
+}
+
+/// Parse the leading global flags out of the arguments.
+///
+/// Returns the parsed flags, and the remaining arguments starting at the
+/// command name.
+///
+/// # Errors
+///
+/// - When a flag is missing it's value.
+pub fn parse_global_flags(args: &[String]) -> Result<(GlobalFlags, &[String])> {
+	let mut flags = GlobalFlags::default();
+	let mut idx = 0;
+
+	while idx < args.len() {
+		match args[idx].as_str() {
+			"--config" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--config` flag requires a path."))
+						.suggestion("Pass a configuration like: `--config ./.dl/config.yml`.");
+				}
+				flags.config = Some(args[idx].clone());
+			}
+			flag if flag.starts_with("--config=") => {
+				flags.config = Some(flag.trim_start_matches("--config=").to_owned());
+			}
+			"--project-root" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--project-root` flag requires a directory."))
+						.suggestion("Pass a project root like: `--project-root ./my-project`.");
+				}
+				flags.project_root = Some(args[idx].clone());
+			}
+			flag if flag.starts_with("--project-root=") => {
+				flags.project_root = Some(flag.trim_start_matches("--project-root=").to_owned());
+			}
+			"--color" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--color` flag requires a mode.")).suggestion(
+						"Pass one of: `--color auto`, `--color always`, or `--color never`.",
+					);
+				}
+				flags.colour = parse_colour_flag(&args[idx])?;
+			}
+			flag if flag.starts_with("--color=") => {
+				flags.colour = parse_colour_flag(flag.trim_start_matches("--color="))?;
+			}
+			_ => break,
+		}
+		idx += 1;
+	}
+
+	Ok((flags, &args[idx..]))
+}
+
+/// The flags dev-loop itself understands for commands that execute tasks
+/// (`exec`, and `run`).
+///
+/// These must come before the task/preset name, since everything after the
+/// name is passed along to the task.
+#[derive(Debug, Default)]
+pub struct ExecutionFlags {
+	/// Print a summary of every task that ran, once they have all finished.
+	pub summary: bool,
+	/// The ID of an executor to run every task on, ignoring the normal
+	/// executor selection.
+	pub executor: Option<String>,
+	/// Treat a `run` that matches no tasks as an error, rather than a warning.
+	pub fail_on_empty: bool,
+	/// Treat a `run` where any requested tag matched no tasks as an error.
+	pub require_all_tags_used: bool,
+	/// If a task needs any, or all of a preset's tags to be run by `run`.
+	pub tag_match_mode: TagMatchMode,
+	/// Environment variables passed with `--env`, these take precedence over
+	/// any environment variables from configuration.
+	pub env: Vec<(String, String)>,
+	/// Run incremental tasks even if none of their `inputs` have changed.
+	pub force: bool,
+	/// The amount of tasks to run in parallel, passed with `--parallel`.
+	pub parallel: Option<usize>,
+	/// Never render the list of running tasks, only stream their output.
+	pub no_indicator: bool,
+	/// Allow `exec` to run an internal task directly, for debugging.
+	pub allow_internal: bool,
+	/// Where to collect the `artifacts` of tasks into, passed with `--output-dir`.
+	pub output_dir: Option<PathBuf>,
+	/// How long the whole run may take, passed with `--max-runtime`.
+	pub max_runtime: Option<Duration>,
+	/// Print the plan of what would be run as JSON, rather than running it.
+	pub plan_json: bool,
+	/// The directory task scripts are fetched relative to, and run in, passed
+	/// with `--cwd`.
+	pub cwd: Option<PathBuf>,
+}
+
+impl ExecutionFlags {
+	/// Get the settings to run tasks with, based on these flags.
+	#[must_use]
+	pub fn get_execution_settings(&self) -> ExecutionSettings {
+		ExecutionSettings {
+			print_summary: self.summary,
+			force: self.force,
+			output_dir: self.output_dir.clone(),
+			no_indicator: self.no_indicator,
+			max_runtime: self.max_runtime,
+			event_sink: None,
+			sink_only: false,
+		}
+	}
+
+	/// Get the amount of tasks to run in parallel.
+	///
+	/// `--parallel` takes precedence over the `DL_WORKER_COUNT` environment
+	/// variable, which takes precedence over the count of physical CPUs.
+	#[must_use]
+	pub fn get_parallelism(&self) -> usize {
+		if let Some(parallel) = self.parallel {
+			return parallel;
+		}
+
+		let mut parallelism = num_cpus::get_physical();
+		if let Ok(env_var) = std::env::var("DL_WORKER_COUNT") {
+			if let Ok(worker_count) = env_var.parse::<usize>() {
+				parallelism = worker_count;
+			}
+		}
+		parallelism
+	}
+}
+
+/// Parse the value of a `--parallel` flag, which should be a positive number.
+///
+/// # Errors
+///
+/// - When the value is not a number, or is zero.
+fn parse_parallel_flag(value: &str) -> Result<usize> {
+	match value.parse::<usize>() {
+		Ok(parallel) if parallel > 0 => Ok(parallel),
+		_ => Err(eyre!("The `--parallel` flag: [{}] is invalid.", value))
+			.suggestion("Pass a positive number of tasks to run at once like: `--parallel 4`."),
+	}
+}
+
+/// Parse the value of an `--env` flag, which should look like: `KEY=VALUE`.
+///
+/// # Errors
+///
+/// - When there is no `=`, or the key is not a valid variable name.
+fn parse_env_flag(value: &str) -> Result<(String, String)> {
+	match value.split_once('=') {
+		Some((name, env_value)) if is_valid_variable_name(name) => {
+			Ok((name.to_owned(), env_value.to_owned()))
+		}
+		_ => Err(eyre!("The `--env` flag: [{}] is invalid.", value))
+			.suggestion("Pass an environment variable like: `--env KEY=VALUE`, where the key only contains letters, numbers, and underscores, and does not start with a number."),
+	}
+}
+
+/// Resolve the directory passed with `--cwd` (relative to the directory
+/// dev-loop was run from), making sure it's inside of the project.
+///
+/// Returns the directory relative to the project root.
+///
+/// `cwd`: the directory passed with `--cwd`.
+/// `project_root`: the root of the project.
+///
+/// # Errors
+///
+/// - When the directory doesn't exist, or is outside of the project root.
+fn resolve_cwd_flag(cwd: &Path, project_root: &Path) -> Result<PathBuf> {
+	let resolved = canonicalize(cwd)
+		.wrap_err(format!(
+			"The `--cwd` directory: [{:?}] could not be found.",
+			cwd
+		))
+		.suggestion("`--cwd` is relative to the directory you're running dev-loop from.")?;
+	let canonical_root = canonicalize(project_root).unwrap_or_else(|_| project_root.to_path_buf());
+
+	if !resolved.is_dir() || !resolved.starts_with(&canonical_root) {
+		return Err(eyre!(
+			"The `--cwd` directory: [{:?}] is not a directory inside of the project root: [{:?}].",
+			resolved,
+			canonical_root,
+		))
+		.suggestion("To run a different project entirely use `--project-root` instead.");
+	}
+
+	Ok(resolved
+		.strip_prefix(&canonical_root)
+		.map(Path::to_path_buf)
+		.unwrap_or_default())
+}
+
+/// Get the fetcher `exec`/`explain` should use, which fetches task scripts
+/// from (and runs tasks in) the directory passed with `--cwd`, if any.
+///
+/// `fetcher`: the fetcher for the project.
+/// `flags`: the flags that were passed.
+/// `project_root`: the root of the project.
+///
+/// # Errors
+///
+/// - When the `--cwd` directory doesn't exist, or is outside of the project root.
+pub fn apply_cwd_flag(
+	fetcher: &FetcherRepository,
+	flags: &ExecutionFlags,
+	project_root: &Path,
+) -> Result<FetcherRepository> {
+	let cwd = if let Some(cwd) = &flags.cwd {
+		Some(resolve_cwd_flag(cwd, project_root)?)
+	} else {
+		None
+	};
+
+	Ok(fetcher.clone().with_cwd(cwd))
+}
+
+/// Parse the leading dev-loop flags out of the arguments for `exec`/`run`.
+///
+/// Returns the parsed flags, and the remaining arguments starting at the
+/// task/preset name.
+///
+/// # Errors
+///
+/// - When an unknown flag is passed before the task/preset name.
+pub fn parse_execution_flags(args: &[String]) -> Result<(ExecutionFlags, &[String])> {
+	let mut flags = ExecutionFlags::default();
+	let mut idx = 0;
+
+	while idx < args.len() && args[idx].starts_with("--") {
+		match args[idx].as_str() {
+			"--summary" => flags.summary = true,
+			"--fail-on-empty" => flags.fail_on_empty = true,
+			"--require-all-tags-used" => flags.require_all_tags_used = true,
+			"--force" => flags.force = true,
+			"--no-indicator" => flags.no_indicator = true,
+			"--allow-internal" => flags.allow_internal = true,
+			"--plan-json" => flags.plan_json = true,
+			"--tags-any" => flags.tag_match_mode = TagMatchMode::Any,
+			"--tags-all" => flags.tag_match_mode = TagMatchMode::All,
+			"--executor" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--executor` flag requires an executor ID."))
+						.suggestion("Pass an executor ID like: `--executor host`.");
+				}
+				flags.executor = Some(args[idx].clone());
+			}
+			flag if flag.starts_with("--executor=") => {
+				flags.executor = Some(flag.trim_start_matches("--executor=").to_owned());
+			}
+			"--parallel" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--parallel` flag requires a number of tasks."))
+						.suggestion(
+							"Pass a positive number of tasks to run at once like: `--parallel 4`.",
+						);
+				}
+				flags.parallel = Some(parse_parallel_flag(&args[idx])?);
+			}
+			flag if flag.starts_with("--parallel=") => {
+				flags.parallel = Some(parse_parallel_flag(flag.trim_start_matches("--parallel="))?);
+			}
+			"--output-dir" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--output-dir` flag requires a directory."))
+						.suggestion("Pass a directory to collect artifacts into like: `--output-dir ./artifacts`.");
+				}
+				flags.output_dir = Some(PathBuf::from(&args[idx]));
+			}
+			flag if flag.starts_with("--output-dir=") => {
+				flags.output_dir = Some(PathBuf::from(flag.trim_start_matches("--output-dir=")));
+			}
+			"--cwd" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--cwd` flag requires a directory.")).suggestion(
+						"Pass a directory inside of the project like: `--cwd ./services/api`.",
+					);
+				}
+				flags.cwd = Some(PathBuf::from(&args[idx]));
+			}
+			flag if flag.starts_with("--cwd=") => {
+				flags.cwd = Some(PathBuf::from(flag.trim_start_matches("--cwd=")));
+			}
+			"--max-runtime" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--max-runtime` flag requires a duration."))
+						.suggestion("Pass how long the run may take like: `--max-runtime 30m`.");
+				}
+				flags.max_runtime = Some(parse_human_duration(&args[idx])?);
+			}
+			flag if flag.starts_with("--max-runtime=") => {
+				flags.max_runtime = Some(parse_human_duration(
+					flag.trim_start_matches("--max-runtime="),
+				)?);
+			}
+			"--env" => {
+				idx += 1;
+				if idx >= args.len() {
+					return Err(eyre!("The `--env` flag requires a `KEY=VALUE` pair."))
+						.suggestion("Pass an environment variable like: `--env KEY=VALUE`.");
+				}
+				flags.env.push(parse_env_flag(&args[idx])?);
+			}
+			flag if flag.starts_with("--env=") => {
+				flags
+					.env
+					.push(parse_env_flag(flag.trim_start_matches("--env="))?);
+			}
+			unknown => {
+				return Err(eyre!("Unknown flag: [{}]", unknown))
+					.note("The flags dev-loop supports are: `--summary`, `--executor <id>`, `--env <KEY=VALUE>`, `--parallel <N>`, `--output-dir <dir>`, `--cwd <dir>`, `--max-runtime <duration>`, `--fail-on-empty`, `--require-all-tags-used`, `--force`, `--no-indicator`, `--allow-internal`, `--plan-json`, `--tags-any`, `--tags-all`")
+					.suggestion(
+						"Flags for dev-loop must come before the task name, anything after the task name is passed to the task.",
+					);
+			}
+		}
+		idx += 1;
+	}
+
+	Ok((flags, &args[idx..]))
+}
+
+/// Attach a note to a failed run for every container that has been kept
+/// around (through the `keep_on_failure` executor param) so it can be
+/// debugged.
+pub fn add_kept_container_notes<T>(result: Result<T>, executors: &ExecutorRepository) -> Result<T> {
+	executors
+		.get_containers_kept_on_failure()
+		.into_iter()
+		.fold(result, |result, (container_name, shell_path)| {
+			result.note(format!(
+				"Container: [{}] has been kept for debugging, you can enter it with: `docker exec -it {} {}`, and remove it with: `dev-loop clean`.",
+				container_name, container_name, shell_path,
+			))
+		})
+}
+
+/// Turn the outcome of running tasks for `exec`/`run` into the result of the
+/// command.
+///
+/// A run that succeeded, went over `--max-runtime`, or was interrupted is
+/// cleaned up. Any other failure is left around to aid in debugging.
+///
+/// `res`: the result of executing the tasks.
+/// `executors`: the executors the tasks were run with.
+/// `flags`: the flags that were passed.
+/// `failure_msg`: the error to report when one of the tasks failed.
+///
+/// # Errors
+///
+/// - When executing the tasks errored, or any task failed.
+pub async fn handle_execution_result(
+	res: Result<ExecutionResult>,
+	executors: &ExecutorRepository,
+	flags: &ExecutionFlags,
+	failure_msg: &str,
+) -> Result<()> {
+	let result = res?;
+	// A run that timed out may have been hung, and one that was interrupted
+	// was stopped on purpose, so neither is worth keeping anything around to
+	// debug. Don't cause an error for cleaning, the user can always clean
+	// manually.
+	if result.is_success() || result.timed_out || result.interrupted {
+		executors.teardown().await;
+		let _ = crate::executors::docker::Executor::clean(None, false).await;
+	}
+
+	if result.is_success() {
+		return Ok(());
+	}
+	set_failing_exit_code(result.exit_code);
+	if result.timed_out {
+		Err(eyre!(
+			"The run went over the `--max-runtime` of: [{}s], and was stopped.",
+			flags
+				.max_runtime
+				.map_or(0, |max_runtime| max_runtime.as_secs()),
+		))
+		.note(format!("Failing exit code: {}", result.exit_code))
+		.suggestion("If the run just needs more time, raise the `--max-runtime`.")
+	} else if result.interrupted {
+		Err(eyre!("The run was interrupted by Ctrl-C, and stopped."))
+			.note(format!("Failing exit code: {}", result.exit_code))
+	} else {
+		add_kept_container_notes(
+			Err(eyre!("{}", failure_msg))
+				.note(format!("Failing exit code: {}", result.exit_code))
+				.note(format!(
+					"Tasks that failed: [{}]",
+					result.failed_tasks().join(", ")
+				)),
+			executors,
+		)
+	}
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn parses_global_flags() {
+		let args = [
+			"--config",
+			"a.yml",
+			"--project-root=dir",
+			"--config=b.yml",
+			"--version",
+			"--project-root",
+		]
+		.iter()
+		.map(|arg| (*arg).to_owned())
+		.collect::<Vec<String>>();
+		let (flags, remaining) = parse_global_flags(&args).unwrap();
+		assert_eq!(flags.config.as_deref(), Some("b.yml"));
+		assert_eq!(flags.project_root.as_deref(), Some("dir"));
+		assert_eq!(remaining, &args[4..]);
+
+		assert!(parse_global_flags(&args[5..]).is_err());
+
+		let args = ["--color", "never", "exec"]
+			.iter()
+			.map(|arg| (*arg).to_owned())
+			.collect::<Vec<String>>();
+		let (flags, remaining) = parse_global_flags(&args).unwrap();
+		assert_eq!(flags.colour, ColourMode::Never);
+		assert_eq!(peek_colour_mode(&args), ColourMode::Never);
+		assert_eq!(remaining, &args[2..]);
+		assert!(parse_global_flags(&["--color=sometimes".to_owned()]).is_err());
+	}
+
+	#[test]
+	fn parses_execution_flags() {
+		let args = ["--parallel", "2", "--env=A=b=c", "build", "--parallel", "0"]
+			.iter()
+			.map(|arg| (*arg).to_owned())
+			.collect::<Vec<String>>();
+		let (flags, remaining) = parse_execution_flags(&args).unwrap();
+		assert_eq!(flags.get_parallelism(), 2);
+		assert_eq!(flags.env, vec![("A".to_owned(), "b=c".to_owned())]);
+		assert_eq!(remaining, &args[3..]);
+
+		for invalid in &[
+			"--parallel=0",
+			"--parallel=many",
+			"--parallel",
+			"--env=1A=b",
+			"--cwd",
+		] {
+			assert!(parse_execution_flags(&[(*invalid).to_owned()]).is_err());
+		}
+	}
+
+	#[test]
+	fn resolves_cwd_flag() {
+		let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+		assert_eq!(
+			resolve_cwd_flag(&root.join("src/../src/commands"), root).unwrap(),
+			Path::new("src/commands")
+		);
+		assert_eq!(resolve_cwd_flag(root, root).unwrap(), Path::new(""));
+		assert!(resolve_cwd_flag(&root.join("Cargo.toml"), root).is_err());
+		assert!(resolve_cwd_flag(&root.join("does-not-exist"), root).is_err());
+		assert!(resolve_cwd_flag(&root.join("src"), &root.join("docs")).is_err());
+	}
+}
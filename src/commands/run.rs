@@ -3,27 +3,116 @@
 //! of tasks at once.
 
 use crate::{
-	config::types::TopLevelConf,
+	commands::{handle_execution_result, parse_execution_flags},
+	config::types::{PresetConf, TopLevelConf},
 	executors::ExecutorRepository,
 	fetch::FetcherRepository,
 	tasks::{
 		execution::{
 			execute_tasks_in_parallel,
-			preparation::{build_concurrent_execution_list, fetch_helpers},
+			preparation::{
+				build_concurrent_execution_list, describe_execution_plan, fetch_helpers,
+			},
 		},
 		fs::ensure_dirs,
 		TaskGraph,
 	},
 };
-use color_eyre::{eyre::eyre, Result, Section};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
 use crossbeam_deque::Worker;
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
+use tracing::warn;
+
+/// Resolve the full list of tags for a preset, including the tags of every
+/// preset it (transitively) includes. Duplicate tags are removed.
+///
+/// `presets`: every preset that has been configured.
+/// `preset_name`: the name of the preset to resolve.
+///
+/// # Errors
+///
+/// - If a preset includes a preset that doesn't exist.
+/// - If presets include each other in a cycle.
+fn resolve_preset_tags(presets: &[PresetConf], preset_name: &str) -> Result<Vec<String>> {
+	let mut tags = Vec::new();
+	let mut resolved = HashSet::new();
+	let mut include_chain = Vec::new();
+	resolve_preset_tags_into(
+		presets,
+		preset_name,
+		&mut include_chain,
+		&mut resolved,
+		&mut tags,
+	)?;
+	Ok(tags)
+}
+
+/// Recursively collect the tags of a preset, and the presets it includes.
+///
+/// `include_chain`: the presets currently being resolved, used to find cycles.
+/// `resolved`: the presets that have already been fully resolved.
+/// `tags`: the deduplicated tags collected so far.
+fn resolve_preset_tags_into(
+	presets: &[PresetConf],
+	preset_name: &str,
+	include_chain: &mut Vec<String>,
+	resolved: &mut HashSet<String>,
+	tags: &mut Vec<String>,
+) -> Result<()> {
+	if include_chain.iter().any(|name| name == preset_name) {
+		return Err(eyre!(
+			"The preset: [{}] includes itself through: [{} -> {}]",
+			preset_name,
+			include_chain.join(" -> "),
+			preset_name,
+		))
+		.suggestion(
+			"Remove one of the `includes` so presets don't include each other in a cycle.",
+		);
+	}
+	if resolved.contains(preset_name) {
+		return Ok(());
+	}
+
+	let preset = if let Some(preset) = presets
+		.iter()
+		.find(|preset| preset.get_name() == preset_name)
+	{
+		preset
+	} else {
+		return Err(eyre!(
+			"The preset: [{}] includes the preset: [{}], which does not exist.",
+			include_chain.last().map_or("", String::as_str),
+			preset_name,
+		))
+		.note("You can use the `list` command in order to see all the presets that exist.");
+	};
+
+	for tag in preset.get_tags() {
+		if !tags.contains(tag) {
+			tags.push(tag.clone());
+		}
+	}
+
+	include_chain.push(preset_name.to_owned());
+	for included_name in preset.get_includes() {
+		resolve_preset_tags_into(presets, included_name, include_chain, resolved, tags)?;
+	}
+	include_chain.pop();
+	resolved.insert(preset_name.to_owned());
+
+	Ok(())
+}
 
 /// Handle the "run" command provided by dev loop.
 ///
 /// # Errors
 ///
-/// - Can Error when no argument was provided.
+/// - Can Error when no argument was provided, or an unknown flag (or `--cwd`)
+///   was provided.
 /// - Error constructing the `TaskGraph`.
 /// - Error finding the task the user wants to run/running an internal task.
 /// - Error creating directories that need to be ensured.
@@ -39,6 +128,14 @@ pub async fn handle_run_command(
 	let span = tracing::info_span!("run");
 	let _guard = span.enter();
 
+	let (flags, args) = parse_execution_flags(args)?;
+	if flags.cwd.is_some() {
+		return Err(eyre!(
+			"The `--cwd` flag can only be passed to `exec`, and `explain`."
+		))
+		.suggestion("To run a preset from a different project use `--project-root` instead.");
+	}
+
 	// The order of run:
 	//
 	// 1. Validate we have a series of tasks to run.
@@ -63,12 +160,12 @@ pub async fn handle_run_command(
 		)).note("You can define presets in `.dl/config.yml`, the format is specified here: https://dev-loop.kungfury.dev/docs/schemas/preset-conf");
 	}
 	let presets = presets_opt.unwrap();
-	let mut tags = Vec::new();
-	for preset in presets {
-		if preset.get_name() == args[0] {
-			tags = Vec::from(preset.get_tags());
-		}
-	}
+	let found_preset = presets.iter().any(|preset| preset.get_name() == args[0]);
+	let tags = if found_preset {
+		resolve_preset_tags(presets, &args[0])?
+	} else {
+		Vec::new()
+	};
 
 	// Now we also need a valid TaskGraph...
 	let tasks = TaskGraph::new(config, fetcher)
@@ -80,46 +177,129 @@ pub async fn handle_run_command(
 	ensure_dirs(config, root_dir)?;
 
 	// Let's fetch all the executors so we know how to assign them to tasks.
-	let mut erepo = ExecutorRepository::new(config, fetcher, root_dir).await?;
+	let mut erepo = ExecutorRepository::new(config, fetcher, root_dir)
+		.await?
+		.with_executor_override(flags.executor.clone());
 
 	// Let's build a list of tasks to execute.
 	let mut worker = Worker::new_fifo();
+	let mut used_tags = HashSet::new();
 	let task_size = build_concurrent_execution_list(
 		&tasks,
 		&tags,
+		flags.tag_match_mode,
 		fetcher,
 		&mut erepo,
 		root_dir.clone(),
 		&mut worker,
+		&mut used_tags,
 	)
 	.await?;
 
+	// In strict mode every tag needs to have selected something, otherwise a
+	// typo'd tag could silently skip part of a run.
+	if flags.require_all_tags_used {
+		let mut unused_tags = tags
+			.iter()
+			.filter(|tag| !used_tags.contains(*tag))
+			.map(String::as_str)
+			.collect::<Vec<&str>>();
+		unused_tags.sort_unstable();
+		unused_tags.dedup();
+		if !unused_tags.is_empty() {
+			return Err(eyre!(
+				"The tags: [{}] requested by the preset: [{}] did not select any task to run.",
+				unused_tags.join(", "),
+				args[0],
+			))
+			.note("`--require-all-tags-used` was passed, so every tag a preset requests must select at least one task.")
+			.suggestion("You can use the `list` command to see all the presets that can be run, and check the tags of your tasks.");
+		}
+	}
+
+	// A preset that matches nothing is almost always a typo, so make sure it
+	// doesn't look like a passing run.
+	if task_size == 0 {
+		let reason = if found_preset {
+			format!(
+				"The preset: [{}] has the tags: [{}], which no task has.",
+				args[0],
+				tags.join(", ")
+			)
+		} else {
+			format!("There is no preset named: [{}].", args[0])
+		};
+
+		if flags.fail_on_empty {
+			return Err(eyre!("The preset: [{}] matched no tasks to run.", args[0]))
+				.note(reason)
+				.suggestion("You can use the `list` command to see all the presets that can be run, and check the tags of your tasks.");
+		}
+		warn!(
+			"The preset: [{}] matched no tasks to run, so nothing was run. {} Pass `--fail-on-empty` to make this an error.",
+			args[0], reason,
+		);
+		return Ok(());
+	}
+
+	// Describe the plan rather than running it, this is built exactly the same
+	// way so it always matches what would be run.
+	if flags.plan_json {
+		let plan = describe_execution_plan(&worker, &erepo);
+		println!(
+			"{}",
+			serde_json::to_string(&plan).wrap_err("Failed to serialize the execution plan")?
+		);
+		return Ok(());
+	}
+
 	// Let's fetch all the helpers.
 	let helpers = fetch_helpers(config, fetcher).await?;
 
-	let mut parallelism = num_cpus::get_physical();
-	if let Ok(env_var) = std::env::var("DL_WORKER_COUNT") {
-		if let Ok(worker_count) = env_var.parse::<usize>() {
-			parallelism = worker_count;
-		}
+	let res = execute_tasks_in_parallel(
+		helpers,
+		config.get_variables(),
+		&flags.env,
+		worker,
+		task_size,
+		flags.get_parallelism(),
+		flags.get_execution_settings(),
+	)
+	.await;
+
+	handle_execution_result(res, &erepo, &flags, "One of the inner tasks returned a non-zero exit code, please use the logs to debug what went wrong.").await
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	fn preset(name: &str, tags: &[&str], includes: &[&str]) -> PresetConf {
+		serde_yaml::from_str(&format!(
+			"name: {}\ntags: [{}]\nincludes: [{}]",
+			name,
+			tags.join(", "),
+			includes.join(", "),
+		))
+		.unwrap()
 	}
 
-	let res = execute_tasks_in_parallel(helpers, worker, task_size, parallelism).await;
-
-	// Don't clean if we encouter an error, aid in debugging.
-	match res {
-		Ok(exit_code) => {
-			if exit_code == 0 {
-				// Don't cause an error for cleaning if the task succeeded, the user can always clean manually.
-				let _ = crate::executors::docker::Executor::clean().await;
-				Ok(())
-			} else {
-				Err(eyre!(
-					"One of the inner tasks returned a non-zero exit code: [{}], please use the logs to debug what went wrong.",
-					exit_code,
-				))
-			}
-		}
-		Err(err_code) => Err(err_code),
+	#[test]
+	fn resolves_included_presets() {
+		let presets = vec![
+			preset("ci", &["ci"], &["lint", "test"]),
+			preset("lint", &["lint", "shared"], &[]),
+			preset("test", &["test", "shared"], &["lint"]),
+		];
+		assert_eq!(
+			resolve_preset_tags(&presets, "ci").unwrap(),
+			vec!["ci", "lint", "shared", "test"],
+		);
+
+		let cyclic = vec![preset("a", &["a"], &["b"]), preset("b", &["b"], &["a"])];
+		assert!(resolve_preset_tags(&cyclic, "a").is_err());
+
+		let missing = vec![preset("a", &["a"], &["nope"])];
+		assert!(resolve_preset_tags(&missing, "a").is_err());
 	}
 }
@@ -0,0 +1,86 @@
+//! Implements the `schema` command, or the command that prints the JSON
+//! Schema for one of dev-loop's configuration files. Editors with YAML schema
+//! support can use these to provide autocompletion, and validation.
+
+use crate::{
+	config::types::{ExecutorConfFile, TaskConfFile, TopLevelConf},
+	strsim::add_did_you_mean_text,
+};
+
+use color_eyre::{eyre::eyre, Result, Section};
+use schemars::{schema::RootSchema, schema_for};
+
+/// The configuration files a schema can be printed for.
+const SCHEMA_KINDS: &[&str] = &["config", "task", "executor"];
+
+/// Get the JSON Schema for a particular kind of configuration file.
+///
+/// Returns `None` if the kind of configuration file is not known.
+#[must_use]
+pub fn get_schema(kind: &str) -> Option<RootSchema> {
+	match kind {
+		"config" => Some(schema_for!(TopLevelConf)),
+		"task" => Some(schema_for!(TaskConfFile)),
+		"executor" => Some(schema_for!(ExecutorConfFile)),
+		_ => None,
+	}
+}
+
+/// Handle the `schema` command, this never needs any configuration so it can
+/// be used even when `.dl/config.yml` is invalid.
+///
+/// # Errors
+///
+/// - When the kind of configuration file is missing, or not known.
+pub fn handle_schema_command(args: &[String]) -> Result<()> {
+	let kind = if let Some(kind) = args.first() {
+		kind
+	} else {
+		return Err(eyre!(
+			"The `schema` command needs to know which configuration file to print the schema for."
+		))
+		.note(format!(
+			"Schemas can be printed for: [{}]",
+			SCHEMA_KINDS.join(", ")
+		))
+		.suggestion("Run a command like: `dev-loop schema config`.");
+	};
+
+	if let Some(schema) = get_schema(kind) {
+		println!(
+			"{}",
+			serde_json::to_string_pretty(&schema).expect("A JSON Schema is always valid JSON")
+		);
+		Ok(())
+	} else {
+		add_did_you_mean_text(
+			Err(eyre!("Unknown configuration file: [{}]", kind)).note(format!(
+				"Schemas can be printed for: [{}]",
+				SCHEMA_KINDS.join(", ")
+			)),
+			kind,
+			SCHEMA_KINDS,
+			2,
+			None,
+		)
+	}
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn generates_schemas() {
+		for kind in SCHEMA_KINDS {
+			let schema = serde_json::to_value(get_schema(kind).unwrap()).unwrap();
+			assert!(schema["properties"].is_object());
+		}
+		assert!(get_schema("unknown").is_none());
+
+		let task_schema = serde_json::to_value(get_schema("task").unwrap()).unwrap();
+		assert!(task_schema["definitions"]["TaskConf"]["properties"]
+			.get("completely_useless")
+			.is_none());
+	}
+}
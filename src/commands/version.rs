@@ -0,0 +1,23 @@
+//! Implements the `version` command, or the command that prints which
+//! version of dev-loop is running.
+
+const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+const GIT_SHA: Option<&'static str> = option_env!("DL_GIT_SHA");
+
+/// Render the version of dev-loop, including the git sha it was built from
+/// if it's known.
+#[must_use]
+pub fn render_version() -> String {
+	let version = VERSION.unwrap_or("unknown");
+	if let Some(sha) = GIT_SHA {
+		format!("dev-loop {} ({})", version, sha)
+	} else {
+		format!("dev-loop {}", version)
+	}
+}
+
+/// Handle the `version` command, this never needs any configuration so it
+/// can be used even when `.dl/config.yml` is invalid.
+pub fn handle_version_command() {
+	println!("{}", render_version());
+}
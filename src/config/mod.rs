@@ -4,23 +4,140 @@
 //!
 //! Those validations happen at different stages within the program.
 
-use crate::yaml_err::contextualize;
-use color_eyre::{eyre::WrapErr, Result, Section};
+use crate::{
+	config::types::LocationConf, fetch::FetcherRepository,
+	strsim::calculate_did_you_mean_possibilities_str, yaml_err::contextualize,
+};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
+use schemars::{schema_for, JsonSchema};
+use serde_yaml::Value as YamlValue;
 use std::{
 	fs::{canonicalize, File},
 	io::{Error as IoError, Read},
-	path::PathBuf,
+	path::{Path, PathBuf},
 };
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 pub(crate) mod types;
 
+/// Resolve the path to an explicitly provided project configuration.
+///
+/// `flag`: the value of the `--config` flag if it was passed, this takes
+///         precedence over the `DL_CONFIG` environment variable.
+/// `project_root_override`: the project root passed with `--project-root`,
+///                          when no configuration was explicitly provided
+///                          `.dl/config.yml` inside of it is used.
+///
+/// Returns `None` when no configuration was explicitly provided (nor a
+/// project root), and the configuration should be discovered automatically.
+///
+/// # Errors
+///
+/// - When the provided configuration does not exist, or is not a file.
+/// - When a project root was provided without a configuration, and there is
+///   no `.dl/config.yml` inside of it.
+pub fn resolve_config_override(
+	flag: Option<&str>,
+	project_root_override: Option<&Path>,
+) -> Result<Option<PathBuf>> {
+	let provided = if let Some(path) = flag {
+		path.to_owned()
+	} else if let Some(path) = std::env::var("DL_CONFIG")
+		.ok()
+		.filter(|path| !path.is_empty())
+	{
+		path
+	} else if let Some(project_root) = project_root_override {
+		let config_path = project_root.join(".dl/config.yml");
+		if !config_path.is_file() {
+			return Err(eyre!(
+				"The project root: [{}] has no configuration at: [{}].",
+				project_root.to_string_lossy(),
+				config_path.to_string_lossy(),
+			))
+			.suggestion(
+				"Create a `.dl/config.yml` in the project root, or pass the configuration to use with `--config`.",
+			);
+		}
+		return Ok(Some(config_path));
+	} else {
+		return Ok(None);
+	};
+
+	let config_path = canonicalize(&provided)
+		.wrap_err(format!("Failed to find the configuration: [{}]", provided))
+		.suggestion("Make sure the path passed with `--config`, or `DL_CONFIG` exists.")?;
+	if !config_path.is_file() {
+		return Err(eyre!(
+			"The configuration: [{}] is not a file.",
+			config_path.to_string_lossy()
+		))
+		.suggestion(
+			"Point `--config`, or `DL_CONFIG` at a configuration file like: `.dl/config.yml`.",
+		);
+	}
+
+	Ok(Some(config_path))
+}
+
+/// Resolve an explicitly provided project root, passed with `--project-root`.
+///
+/// Returns `None` when no project root was provided, and it should be
+/// discovered automatically.
+///
+/// # Errors
+///
+/// - When the provided project root does not exist, or is not a directory.
+pub fn resolve_project_root_override(flag: Option<&str>) -> Result<Option<PathBuf>> {
+	let provided = if let Some(path) = flag {
+		path
+	} else {
+		return Ok(None);
+	};
+
+	let project_root = canonicalize(provided)
+		.wrap_err(format!("Failed to find the project root: [{}]", provided))
+		.suggestion("Make sure the path passed with `--project-root` exists.")?;
+	if !project_root.is_dir() {
+		return Err(eyre!(
+			"The project root: [{}] is not a directory.",
+			project_root.to_string_lossy()
+		))
+		.suggestion("Point `--project-root` at the root directory of your project.");
+	}
+
+	Ok(Some(project_root))
+}
+
+/// Get the project root for an explicitly provided configuration file.
+///
+/// This is the directory the configuration is in, unless it's in a `.dl`
+/// directory in which case it's the directory that contains `.dl`.
+fn get_project_root_for_config(config_path: &Path) -> PathBuf {
+	let mut project_root = config_path.to_path_buf();
+	project_root.pop();
+	if project_root.ends_with(".dl") {
+		project_root.pop();
+	}
+	project_root
+}
+
 /// Get the root of the project repository.
 ///
+/// `config_override`: an explicitly provided configuration, when present the
+///                    project root is derived from it rather than searched for.
+///
 /// This discovers the project directory automatically by looking at
 /// `std::env::current_dir()`, and walking the path up.
 #[must_use]
-pub fn get_project_root() -> Option<PathBuf> {
+pub fn get_project_root(config_override: Option<&Path>) -> Option<PathBuf> {
+	if let Some(config_path) = config_override {
+		return Some(get_project_root_for_config(config_path));
+	}
+
 	// Get the current directory (this is where we start looking...)
 	//
 	// We need the full "canonicalized" directory to ensure we can "pop"
@@ -57,8 +174,17 @@ pub fn get_project_root() -> Option<PathBuf> {
 }
 
 /// Find and open a file handle the the project level configuration.
-fn find_and_open_project_config() -> Option<(File, PathBuf)> {
-	get_project_root().and_then(|mut project_root| {
+///
+/// `config_override`: an explicitly provided configuration to open instead.
+fn find_and_open_project_config(config_override: Option<&Path>) -> Option<(File, PathBuf)> {
+	if let Some(config_path) = config_override {
+		trace!("Opening Config Path: [{:?}]", config_path);
+		return File::open(config_path)
+			.ok()
+			.map(|fh| (fh, config_path.to_path_buf()));
+	}
+
+	get_project_root(None).and_then(|mut project_root| {
 		project_root.push(".dl/config.yml");
 		trace!("Opening Config Path: [{:?}]", project_root);
 
@@ -71,13 +197,286 @@ fn find_and_open_project_config() -> Option<(File, PathBuf)> {
 	})
 }
 
+/// Find the fields of a YAML mapping that dev-loop doesn't know about.
+/// Fields starting with `x-` (like a block of YAML anchors) are never
+/// included.
+///
+/// `value`: the YAML mapping to check, anything else has no unknown fields.
+/// `known_fields`: every field dev-loop knows about.
+fn find_unknown_fields(value: &YamlValue, known_fields: &[String]) -> Vec<String> {
+	let mapping = if let YamlValue::Mapping(mapping) = value {
+		mapping
+	} else {
+		return Vec::new();
+	};
+
+	mapping
+		.iter()
+		.filter_map(|(key, _)| key.as_str())
+		.filter(|key| !key.starts_with("x-") && !known_fields.iter().any(|known| known == key))
+		.map(ToOwned::to_owned)
+		.collect()
+}
+
+/// Get every field a type of configuration knows about, from it's schema.
+fn get_known_fields<T: JsonSchema>() -> Vec<String> {
+	schema_for!(T)
+		.schema
+		.object
+		.map(|object| object.properties.keys().cloned().collect::<Vec<_>>())
+		.unwrap_or_default()
+}
+
+/// Warn about every field of a YAML mapping dev-loop doesn't know about,
+/// with a suggestion for what might have been meant.
+///
+/// `value`: the YAML mapping to check.
+/// `known_fields`: every field dev-loop knows about.
+/// `described_as`: where the mapping is, e.g. `[.dl/config.yml]`.
+fn warn_about_unknown_fields(value: &YamlValue, known_fields: &[String], described_as: &str) {
+	for unknown_field in find_unknown_fields(value, known_fields) {
+		let mut report = eyre!(
+			"The field: `{}` in: {} is not known, and will be ignored.",
+			unknown_field,
+			described_as,
+		);
+		for did_you_mean_text in
+			calculate_did_you_mean_possibilities_str(&unknown_field, known_fields, 3)
+		{
+			report = report.suggestion(did_you_mean_text);
+		}
+		warn!(
+			"{:?}",
+			report.note(format!(
+				"The valid fields are: {}",
+				known_fields
+					.iter()
+					.map(|field| format!("`{}`", field))
+					.collect::<Vec<String>>()
+					.join(", "),
+			))
+		);
+	}
+}
+
+/// Warn about fields dev-loop doesn't know about in a file that holds a list
+/// of items (like tasks, or executors), both at the top level of the file,
+/// and in every item of the list.
+///
+/// Unknown fields are ignored rather than failing, so a file that uses a
+/// field from a newer version of dev-loop still works, but a typo shouldn't
+/// go unnoticed.
+///
+/// `contents`: the contents of the file.
+/// `src_filepath`: the path of the file, for warnings.
+/// `list_key`: the key in the file that holds the list of items.
+/// `item_kind`: what the items are called, e.g. `Task`.
+pub fn warn_about_unknown_list_fields<File: JsonSchema, Item: JsonSchema>(
+	contents: &[u8],
+	src_filepath: &str,
+	list_key: &str,
+	item_kind: &str,
+) {
+	let document = match serde_yaml::from_slice::<YamlValue>(contents) {
+		Ok(document) => document,
+		Err(_) => return,
+	};
+	warn_about_unknown_fields(
+		&document,
+		&get_known_fields::<File>(),
+		&format!("[{}]", src_filepath),
+	);
+
+	let items = match document.get(list_key) {
+		Some(YamlValue::Sequence(items)) => items,
+		_ => return,
+	};
+	let known_item_fields = get_known_fields::<Item>();
+	for (idx, item) in items.iter().enumerate() {
+		let item_name = item
+			.get("name")
+			.and_then(YamlValue::as_str)
+			.map_or_else(String::new, |name| format!(" (`{}`)", name));
+		warn_about_unknown_fields(
+			item,
+			&known_item_fields,
+			&format!(
+				"{} #{}{} of: [{}]",
+				item_kind,
+				idx + 1,
+				item_name,
+				src_filepath
+			),
+		);
+	}
+}
+
+/// Parse a top level configuration file.
+///
+/// Fields dev-loop doesn't know about (including in tasks defined directly
+/// in the configuration) are warned about rather than failing, so a
+/// configuration that uses a field from a newer version of dev-loop still
+/// works.
+///
+/// `contents`: the contents of the configuration file.
+/// `src_filepath`: the path of the configuration file, for errors.
+///
+/// # Errors
+///
+/// - When the configuration is not valid.
+fn parse_top_level(contents: &str, src_filepath: &str) -> Result<types::TopLevelConf> {
+	let config = contextualize(
+		serde_yaml::from_str::<types::TopLevelConf>(contents),
+		src_filepath,
+		contents,
+	)?;
+	warn_about_unknown_list_fields::<types::TopLevelConf, types::TaskConf>(
+		contents.as_bytes(),
+		src_filepath,
+		"tasks",
+		"Task",
+	);
+
+	Ok(config)
+}
+
+/// Apply the local configuration overlay: `.dl/config.local.yml` if it exists
+/// next to the project configuration.
+///
+/// `config`: the already parsed project configuration.
+/// `config_path`: the path to `.dl/config.yml`.
+fn apply_local_overlay(
+	config: types::TopLevelConf,
+	config_path: &Path,
+) -> Result<types::TopLevelConf> {
+	let local_path = config_path.with_file_name("config.local.yml");
+	if !local_path.is_file() {
+		return Ok(config);
+	}
+	trace!("Applying Local Config Overlay: [{:?}]", local_path);
+
+	let contents = std::fs::read_to_string(&local_path)
+		.wrap_err("Failed to read local configuration overlay")
+		.note(format!(
+			"Full path to local configuration is: {}",
+			local_path.to_string_lossy()
+		))?;
+	let mut local_config = parse_top_level(&contents, ".dl/config.local.yml").note(format!(
+		"Full path to local configuration is: {}",
+		local_path.to_string_lossy()
+	))?;
+	local_config.set_task_location(".dl/config.local.yml");
+
+	Ok(config.overlay(local_config))
+}
+
+/// Fetch, and parse the base configuration a project `extends`.
+///
+/// `location`: where the base configuration is.
+/// `project_root`: the root of the project, `path` locations are relative to it.
+///
+/// # Errors
+///
+/// - When the base configuration could not be fetched, or nothing was fetched.
+/// - When the base configuration is not a single valid configuration file.
+fn fetch_base_config(location: &LocationConf, project_root: &Path) -> Result<types::TopLevelConf> {
+	let fetcher = FetcherRepository::new(project_root.to_path_buf())?;
+	let fetched =
+		async_std::task::block_on(fetcher.fetch_filter(location, None)).wrap_err(format!(
+			"Failed to fetch the base configuration: [{}]",
+			location.get_at(),
+		))?;
+	if fetched.is_empty() {
+		return Err(eyre!(
+			"Nothing was fetched for the base configuration: [{}].",
+			location.get_at(),
+		))
+		.suggestion("Make sure `extends` points at a configuration file that exists.");
+	}
+	if fetched.len() != 1 {
+		return Err(eyre!(
+			"The base configuration: [{}] is not a single file.",
+			location.get_at(),
+		))
+		.suggestion("`extends` should point at a single configuration file, like another projects `.dl/config.yml`.");
+	}
+
+	let contents = String::from_utf8(fetched[0].get_contents().to_vec())
+		.wrap_err("The base configuration is not valid UTF-8")?;
+	let mut base = parse_top_level(&contents, location.get_at())?;
+	base.set_task_location(location.get_at());
+	if base.get_extends().is_some() {
+		warn!(
+			"The base configuration: [{}] extends another configuration, only one level of `extends` is supported so it will be ignored.",
+			location.get_at(),
+		);
+	}
+
+	Ok(base)
+}
+
+/// Apply the base configuration a project `extends` (if any) underneath it,
+/// the project's own fields override the base field by field.
+///
+/// When the base configuration can't be loaded, but the project configuration
+/// has `task_locations` of it's own this only warns, so dev-loop can still be
+/// used (e.g. when offline).
+///
+/// `config`: the project configuration, with any local overlay applied.
+/// `project_root`: the root of the project.
+///
+/// # Errors
+///
+/// - When the base configuration couldn't be loaded, and the project
+///   configuration can't be used without it.
+fn apply_base_config(
+	config: types::TopLevelConf,
+	project_root: &Path,
+) -> Result<types::TopLevelConf> {
+	let location = if let Some(location) = config.get_extends() {
+		location
+	} else {
+		return Ok(config);
+	};
+	trace!("Applying Base Config: [{}]", location.get_at());
+
+	match fetch_base_config(location, project_root) {
+		Ok(base) => Ok(base.overlay(config)),
+		Err(base_err) => {
+			let base_err = base_err.wrap_err(format!(
+				"Failed to load the base configuration: [{}] from `extends`",
+				location.get_at(),
+			));
+			if config.get_task_locations().is_none() && config.get_tasks().is_none() {
+				return Err(base_err)
+					.note("The project configuration has no `task_locations`, or `tasks` of it's own, so it can't be used without it's base configuration.");
+			}
+
+			warn!(
+				"{:?}\nContinuing with only the project configuration.",
+				base_err
+			);
+			Ok(config)
+		}
+	}
+}
+
 /// Attempt to fetch the top level project configuration for this project.
 ///
+/// If a `.dl/config.local.yml` exists (which should be ignored by version
+/// control) it is overlayed field by field on top of `.dl/config.yml`. If the
+/// configuration `extends` a base configuration, both are overlayed on top
+/// of the base.
+///
+/// `config_override`: an explicitly provided configuration to use instead of
+///                    searching for `.dl/config.yml`.
+///
 /// # Errors
 ///
 /// - When there is error doing a file read on a found configuration file.
-pub fn get_top_level() -> Result<Option<types::TopLevelConf>> {
-	let config_fh_opt = find_and_open_project_config();
+/// - When either configuration file is not valid.
+pub fn get_top_level(config_override: Option<&Path>) -> Result<Option<types::TopLevelConf>> {
+	let config_fh_opt = find_and_open_project_config(config_override);
 	if config_fh_opt.is_none() {
 		error!("Could not find project configuration [.dl/config.yml] looking in current directory, and parent directories.");
 		return Ok(None);
@@ -88,15 +487,141 @@ pub fn get_top_level() -> Result<Option<types::TopLevelConf>> {
 	let mut contents = String::new();
 	config_fh.read_to_string(&mut contents)?;
 
-	Ok(Some(
-		contextualize(
-			serde_yaml::from_str::<types::TopLevelConf>(&contents),
-			".dl/config.yml",
-			&contents,
+	let config_source = if config_override.is_some() {
+		config_path_as_str
+	} else {
+		".dl/config.yml"
+	};
+	let mut config = parse_top_level(&contents, config_source).note(format!(
+		"Full path to project configuration is: {}",
+		config_path_as_str
+	))?;
+	config.set_task_location(config_source);
+
+	let config = apply_local_overlay(config, &config_path)?;
+	Ok(Some(apply_base_config(
+		config,
+		&get_project_root_for_config(&config_path),
+	)?))
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn ignores_unknown_fields() {
+		let contents =
+			"task_location: []\nx-common: &common\n  type: path\nfrom_a_newer_version: true\n";
+		assert!(parse_top_level(contents, ".dl/config.yml").is_ok());
+		assert_eq!(
+			find_unknown_fields(
+				&serde_yaml::from_str(contents).unwrap(),
+				&["task_locations".to_owned()]
+			),
+			vec!["task_location", "from_a_newer_version"],
+		);
+		assert_eq!(
+			find_unknown_fields(
+				&serde_yaml::from_str(
+					"name: build
+execution_need: []
+"
+				)
+				.unwrap(),
+				&get_known_fields::<types::TaskConf>()
+			),
+			vec!["execution_need"],
+		);
+		assert!(get_known_fields::<types::ExecutorConf>().contains(&"type".to_owned()));
+		// Known fields with an invalid value are still an error.
+		assert!(parse_top_level("task_locations: true\n", ".dl/config.yml").is_err());
+	}
+
+	#[test]
+	fn overlays_local_config() {
+		let base = serde_yaml::from_str::<types::TopLevelConf>(
+			"ensure_directories: [\"base\"]\ndefault_executor:\n  type: host\n",
 		)
-		.note(format!(
-			"Full path to project configuration is: {}",
-			config_path_as_str
-		))?,
-	))
+		.unwrap();
+		let local =
+			serde_yaml::from_str::<types::TopLevelConf>("ensure_directories: [\"local\"]\n")
+				.unwrap();
+
+		let merged = base.overlay(local);
+		assert_eq!(
+			merged.get_dirs_to_ensure(),
+			Some(&vec![types::EnsureDirectory::Path("local".to_owned())])
+		);
+		assert!(merged.get_default_executor().is_some());
+
+		let unchanged =
+			serde_yaml::from_str::<types::TopLevelConf>("ensure_directories: [\"base\"]\n")
+				.unwrap()
+				.overlay(types::TopLevelConf::create_empty_config());
+		assert_eq!(
+			unchanged.get_dirs_to_ensure(),
+			Some(&vec![types::EnsureDirectory::Path("base".to_owned())])
+		);
+	}
+
+	#[test]
+	fn applies_base_config() {
+		let test_dir = crate::dirs::TestDir::new("base-config");
+		let project_root = test_dir.get_path();
+		std::fs::write(
+			project_root.join("base.yml"),
+			"default_shell: zsh\nensure_directories: [\"base\"]\n",
+		)
+		.unwrap();
+
+		let config = serde_yaml::from_str::<types::TopLevelConf>(
+			"extends:\n  type: path\n  at: base.yml\nensure_directories: [\"local\"]\n",
+		)
+		.unwrap();
+		let merged = apply_base_config(config, project_root).unwrap();
+		assert_eq!(merged.get_default_shell(), Some("zsh"));
+		assert_eq!(
+			merged.get_dirs_to_ensure(),
+			Some(&vec![types::EnsureDirectory::Path("local".to_owned())])
+		);
+
+		// A missing base is only fatal when the project can't stand on it's own.
+		let missing = "extends:\n  type: path\n  at: missing.yml\n";
+		assert!(apply_base_config(
+			serde_yaml::from_str::<types::TopLevelConf>(missing).unwrap(),
+			project_root
+		)
+		.is_err());
+		let self_sufficient = format!(
+			"{}task_locations:\n  - type: path\n    at: tasks\n",
+			missing
+		);
+		assert!(apply_base_config(
+			serde_yaml::from_str::<types::TopLevelConf>(&self_sufficient).unwrap(),
+			project_root
+		)
+		.is_ok());
+
+		// Fetching nothing is reported as such, not as too many files.
+		std::fs::create_dir_all(project_root.join("empty")).unwrap();
+		let empty = serde_yaml::from_str::<LocationConf>("type: path\nat: empty\n").unwrap();
+		let empty_err = fetch_base_config(&empty, project_root).unwrap_err();
+		assert!(format!("{:?}", empty_err).contains("Nothing was fetched"));
+	}
+
+	#[test]
+	fn requires_a_config_in_the_project_root() {
+		let test_dir = crate::dirs::TestDir::new("project-root-config");
+		let project_root = test_dir.get_path();
+		// Never fall back to searching from the current directory.
+		assert!(resolve_config_override(None, Some(project_root)).is_err());
+
+		std::fs::create_dir_all(project_root.join(".dl")).unwrap();
+		std::fs::write(project_root.join(".dl/config.yml"), "").unwrap();
+		assert_eq!(
+			resolve_config_override(None, Some(project_root)).unwrap(),
+			Some(project_root.join(".dl/config.yml")),
+		);
+	}
 }
@@ -3,17 +3,21 @@
 //! These are essentially just the actual config objects in a typed structure
 //! so they can be deserialized with Serde.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Describes the configuration for a specific provided version
 /// of a tool.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct ProvideConf {
 	/// The name of the item provided.
 	name: String,
 	/// The version of the tool this provides.
 	version: Option<String>,
+	/// A command to run inside the executor to find the version of the tool,
+	/// e.g. `python --version`.
+	version_command: Option<String>,
 }
 
 impl ProvideConf {
@@ -21,7 +25,11 @@ impl ProvideConf {
 	#[cfg(test)]
 	#[must_use]
 	pub fn new(name: String, version: Option<String>) -> Self {
-		Self { name, version }
+		Self {
+			name,
+			version,
+			version_command: None,
+		}
 	}
 
 	/// Get the name of the thing provided.
@@ -39,10 +47,16 @@ impl ProvideConf {
 			self.version.as_ref().unwrap()
 		}
 	}
+
+	/// Get the command to run to determine the version of the thing provided.
+	#[must_use]
+	pub fn get_version_command(&self) -> Option<&String> {
+		self.version_command.as_ref()
+	}
 }
 
 /// All of the possible types of executors that dev-loop supports executing.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub enum ExecutorType {
 	/// Represents an executor type that utilizes docker containers.
 	#[serde(rename = "docker")]
@@ -55,17 +69,24 @@ pub enum ExecutorType {
 /// Describes the configuration for an executor.
 ///
 /// This may not be valid executor, this is just the configuration for it.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct ExecutorConf {
 	/// The type this executor is.
 	///
 	/// For example "docker", or "host".
 	#[serde(rename = "type")]
 	typ: ExecutorType,
+	/// A unique human readable name for this executor, kept alongside the
+	/// generated ID.
+	label: Option<String>,
 	/// The parameters to this particular executor.
 	params: Option<HashMap<String, String>>,
 	/// The list of provided installed utilities.
 	provides: Option<Vec<ProvideConf>>,
+	/// A command to run once, the first time this executor runs a task.
+	setup: Option<String>,
+	/// A command to run when the executor is cleaned up, if it was setup.
+	teardown: Option<String>,
 }
 
 impl ExecutorConf {
@@ -75,6 +96,12 @@ impl ExecutorConf {
 		&self.typ
 	}
 
+	/// Get the human readable label for this executor, if one was given.
+	#[must_use]
+	pub fn get_label(&self) -> Option<&str> {
+		self.label.as_deref()
+	}
+
 	/// Get all the parameters.
 	#[must_use]
 	pub fn get_parameters(&self) -> HashMap<String, String> {
@@ -88,10 +115,22 @@ impl ExecutorConf {
 	pub fn get_provided(&self) -> Vec<ProvideConf> {
 		self.provides.as_ref().cloned().unwrap_or_else(Vec::new)
 	}
+
+	/// Get the command to run the first time this executor runs a task.
+	#[must_use]
+	pub fn get_setup(&self) -> Option<&String> {
+		self.setup.as_ref()
+	}
+
+	/// Get the command to run when this executor is cleaned up.
+	#[must_use]
+	pub fn get_teardown(&self) -> Option<&String> {
+		self.teardown.as_ref()
+	}
 }
 
 /// All of the possible types of locations that dev-loop supports fetching from.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Eq, Hash, Serialize)]
 pub enum LocationType {
 	/// Fetch from a path on the filesystem.
 	#[serde(rename = "path")]
@@ -115,7 +154,7 @@ impl std::fmt::Display for LocationType {
 ///
 /// This may not be a valid location (and location type), but is just the
 /// configuration.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct LocationConf {
 	/// The type of this location.
 	///
@@ -132,6 +171,11 @@ pub struct LocationConf {
 	///
 	/// Only valid for `path` currently, ignored otherwise.
 	recurse: Option<bool>,
+	/// Whether or not this location may point outside of the project root.
+	///
+	/// Only valid for `path`, ignored otherwise. This is off by default since
+	/// paths outside the project may not exist on someone elses machine.
+	allow_outside_root: Option<bool>,
 }
 
 impl LocationConf {
@@ -154,17 +198,26 @@ impl LocationConf {
 	pub fn get_recurse(&self) -> bool {
 		self.recurse.unwrap_or(false)
 	}
+
+	/// Return if this location is allowed to point outside of the project root.
+	#[must_use]
+	pub fn allows_outside_root(&self) -> bool {
+		self.allow_outside_root.unwrap_or(false)
+	}
 }
 
 /// Describes a preset, or a predefined "tag group" to run.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct PresetConf {
 	/// The name of this particular preset.
 	name: String,
 	/// The description of this particular preset.
 	description: Option<String>,
 	/// The list of tags that are included in this preset.
+	#[serde(default)]
 	tags: Vec<String>,
+	/// The names of other presets whose tags are included in this preset.
+	includes: Option<Vec<String>>,
 }
 
 impl PresetConf {
@@ -190,17 +243,29 @@ impl PresetConf {
 	pub fn get_tags(&self) -> &[String] {
 		&self.tags
 	}
+
+	/// Get the names of the other presets this preset includes.
+	#[must_use]
+	pub fn get_includes(&self) -> &[String] {
+		self.includes.as_deref().unwrap_or_default()
+	}
 }
 
 /// The `TopLevelConf` for dev-loop, also known as what's in
 /// `.dl/config.yml`.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct TopLevelConf {
 	/// The default executor to use if no other executor has been specified,
 	/// or if no requirements have been specified.
 	default_executor: Option<ExecutorConf>,
+	/// The shell to run tasks with on the host executor, unless the host
+	/// executor sets its own `shell` param.
+	default_shell: Option<String>,
 	/// The list of directories to ensure exist before running a task.
-	ensure_directories: Option<Vec<String>>,
+	ensure_directories: Option<Vec<EnsureDirectory>>,
+	/// A base configuration (e.g. one shared by an organization) this
+	/// configuration is overlayed on top of.
+	extends: Option<LocationConf>,
 	/// Defines a place for executors.
 	executor_locations: Option<Vec<LocationConf>>,
 	/// The list of locations to fetch helpers from.
@@ -209,6 +274,11 @@ pub struct TopLevelConf {
 	presets: Option<Vec<PresetConf>>,
 	/// The list of locations for task files to be found.
 	task_locations: Option<Vec<LocationConf>>,
+	/// Tasks defined directly in this configuration, rather than in a
+	/// separate task file.
+	tasks: Option<Vec<TaskConf>>,
+	/// Variables to export into the environment of every task.
+	variables: Option<HashMap<String, String>>,
 }
 
 impl TopLevelConf {
@@ -217,20 +287,66 @@ impl TopLevelConf {
 	pub fn create_empty_config() -> Self {
 		Self {
 			default_executor: None,
+			default_shell: None,
 			ensure_directories: None,
+			extends: None,
 			executor_locations: None,
 			helper_locations: None,
 			presets: None,
 			task_locations: None,
+			tasks: None,
+			variables: None,
 		}
 	}
 
+	/// Overlay another configuration on top of this one, field by field.
+	///
+	/// Any field set in `local` replaces the field in this configuration
+	/// entirely, fields that aren't set in `local` are kept as is.
+	///
+	/// `local`: the configuration to overlay, e.g. `.dl/config.local.yml`.
+	#[must_use]
+	pub fn overlay(self, local: Self) -> Self {
+		Self {
+			default_executor: local.default_executor.or(self.default_executor),
+			default_shell: local.default_shell.or(self.default_shell),
+			ensure_directories: local.ensure_directories.or(self.ensure_directories),
+			extends: local.extends.or(self.extends),
+			executor_locations: local.executor_locations.or(self.executor_locations),
+			helper_locations: local.helper_locations.or(self.helper_locations),
+			presets: local.presets.or(self.presets),
+			task_locations: local.task_locations.or(self.task_locations),
+			tasks: local.tasks.or(self.tasks),
+			variables: local.variables.or(self.variables),
+		}
+	}
+
+	/// Get the location of the base configuration this configuration
+	/// extends, if any.
+	#[must_use]
+	pub fn get_extends(&self) -> Option<&LocationConf> {
+		self.extends.as_ref()
+	}
+
 	/// Get the default executor if one has been defined.
 	#[must_use]
 	pub fn get_default_executor(&self) -> Option<&ExecutorConf> {
 		self.default_executor.as_ref()
 	}
 
+	/// Get the shell to run tasks with on the host executor, if one has
+	/// been defined.
+	#[must_use]
+	pub fn get_default_shell(&self) -> Option<&str> {
+		self.default_shell.as_deref()
+	}
+
+	/// Get the variables to export into every task's environment.
+	#[must_use]
+	pub fn get_variables(&self) -> Option<&HashMap<String, String>> {
+		self.variables.as_ref()
+	}
+
 	/// Get the list of locations where helpers are located.
 	#[must_use]
 	pub fn get_helper_locations(&self) -> Option<&Vec<LocationConf>> {
@@ -249,9 +365,24 @@ impl TopLevelConf {
 		self.task_locations.as_ref()
 	}
 
+	/// Get the tasks defined directly in this configuration.
+	#[must_use]
+	pub fn get_tasks(&self) -> Option<&Vec<TaskConf>> {
+		self.tasks.as_ref()
+	}
+
+	/// Set the configuration file the tasks defined directly in this
+	/// configuration come from, this should be called before overlaying so
+	/// every task keeps the file it was actually defined in.
+	pub fn set_task_location(&mut self, loc: &str) {
+		for task in self.tasks.iter_mut().flatten() {
+			task.set_inline_path(loc.to_owned());
+		}
+	}
+
 	/// Get the list of directories to ensure exist.
 	#[must_use]
-	pub fn get_dirs_to_ensure(&self) -> Option<&Vec<String>> {
+	pub fn get_dirs_to_ensure(&self) -> Option<&Vec<EnsureDirectory>> {
 		self.ensure_directories.as_ref()
 	}
 
@@ -262,8 +393,48 @@ impl TopLevelConf {
 	}
 }
 
+/// The options for a directory to ensure exists.
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct EnsureDirectoryOptions {
+	/// The path of the directory, relative to the project root unless it's
+	/// absolute, or starts with `~/`.
+	path: String,
+	/// The permissions to set on the directory as an octal string, e.g. `"0755"`.
+	mode: Option<String>,
+}
+
+/// Represents a directory to ensure exists before running a task.
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum EnsureDirectory {
+	/// Just the path of the directory.
+	Path(String),
+	/// The path of the directory, along with other options for it.
+	WithOptions(EnsureDirectoryOptions),
+}
+
+impl EnsureDirectory {
+	/// Get the path of the directory to ensure exists, as it was configured.
+	#[must_use]
+	pub fn get_path(&self) -> &str {
+		match self {
+			Self::Path(path) => path,
+			Self::WithOptions(options) => &options.path,
+		}
+	}
+
+	/// Get the permissions to set on the directory, as it was configured.
+	#[must_use]
+	pub fn get_mode(&self) -> Option<&str> {
+		match self {
+			Self::Path(_) => None,
+			Self::WithOptions(options) => options.mode.as_deref(),
+		}
+	}
+}
+
 /// Describes a requirement that's needed for a particular task.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct NeedsRequirement {
 	/// The name of this requirement.
 	name: String,
@@ -307,17 +478,13 @@ impl NeedsRequirement {
 ///
 /// These only ever get used for a task type of pipeline, but if it makes
 /// you feel better you can put them in any task to be fair.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct PipelineStep {
 	/// The name of this pipeline step.
 	///
 	/// Not globally unique, but should be unique within the pipeline.
 	name: String,
-	/// The description of this pipeline step.
-	///
-	/// NOTE: we won't ever display this right now because frankly we haven't
-	/// seen a nice way of displaying it yet. However, we want to allow people
-	/// to configure it now, so it's not breaking whenever we do add it in.
+	/// The description of this pipeline step, shown by: `list --tree`.
 	description: Option<String>,
 	/// The name of the actual task to run.
 	task: String,
@@ -334,7 +501,6 @@ impl PipelineStep {
 	}
 
 	/// Get the description of this `PipelineStep`
-	#[allow(unused)]
 	#[must_use]
 	pub fn get_description(&self) -> Option<&str> {
 		if let Some(desc) = &self.description {
@@ -358,7 +524,7 @@ impl PipelineStep {
 }
 
 /// Describe a particular option inside a oneof task.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct OneofOption {
 	/// The name of this option. Will be used to match with the argument.
 	///
@@ -373,6 +539,9 @@ pub struct OneofOption {
 	task: String,
 	/// The list of tags that apply to this particular option.
 	tags: Option<Vec<String>>,
+	/// If the arguments after the option name should be passed along to the
+	/// task, after `args`.
+	forward_args: Option<bool>,
 }
 
 impl OneofOption {
@@ -409,10 +578,16 @@ impl OneofOption {
 	pub fn get_tags(&self) -> Option<&Vec<String>> {
 		self.tags.as_ref()
 	}
+
+	/// If the arguments after the option name should be passed along to the task.
+	#[must_use]
+	pub fn forwards_args(&self) -> bool {
+		self.forward_args.unwrap_or(false)
+	}
 }
 
 /// All of the possible types of tasks that dev-loop supports executing.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub enum TaskType {
 	/// Represents a "command", or a task that actually executes a script.
 	#[serde(rename = "command")]
@@ -439,8 +614,61 @@ impl std::fmt::Display for TaskType {
 	}
 }
 
+/// Represents what should be passed into the standard input of a task.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum TaskStdin {
+	/// Read standard input from a file, relative to the task file.
+	File {
+		/// The path of the file to read from.
+		file: String,
+	},
+	/// Pass a string directly as standard input.
+	Contents(String),
+}
+
+/// An artifact of a task, along with other options for it.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct TaskArtifactOptions {
+	/// The path (or glob) of the artifact, relative to the project root.
+	path: String,
+	/// If the task should fail when nothing matches the path.
+	required: Option<bool>,
+}
+
+/// Represents a file, or directory a task produces that should be copied
+/// into the `--output-dir` once the task finishes.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum TaskArtifact {
+	/// Just the path (or glob) of the artifact.
+	Path(String),
+	/// The path (or glob) of the artifact, along with other options for it.
+	WithOptions(TaskArtifactOptions),
+}
+
+impl TaskArtifact {
+	/// Get the path (or glob) of the artifact, as it was configured.
+	#[must_use]
+	pub fn get_path(&self) -> &str {
+		match self {
+			Self::Path(path) => path,
+			Self::WithOptions(options) => &options.path,
+		}
+	}
+
+	/// If the task should fail when nothing matches the path.
+	#[must_use]
+	pub fn is_required(&self) -> bool {
+		match self {
+			Self::Path(_) => false,
+			Self::WithOptions(options) => options.required.unwrap_or(false),
+		}
+	}
+}
+
 /// Represents the configuration for a singular task.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct TaskConf {
 	/// The name of this task, this should be globally unique.
 	name: String,
@@ -469,11 +697,48 @@ pub struct TaskConf {
 	/// If this task should keep running until a user hits CtrlC. E.g.
 	/// Ctrl-C should not be marked as a failure.
 	ctrlc_is_failure: Option<bool>,
+	/// What to pass into the standard input of this task, only used on a
+	/// command type of task.
+	stdin: Option<TaskStdin>,
+	/// Arguments that are always passed to this task, before any arguments
+	/// that were provided when invoking it. Only used on a command type of task.
+	default_args: Option<Vec<String>>,
+	/// If a non-zero exit code from this task should only be warned about,
+	/// rather than failing the run. Only used on a command type of task.
+	allow_failure: Option<bool>,
+	/// Files, or directories (relative to the project root) this task reads
+	/// from. When set the task is skipped if none of them have changed since
+	/// it last succeeded. Only used on a command type of task.
+	inputs: Option<Vec<String>>,
+	/// Files, or directories (relative to the project root) this task
+	/// produces. A task is never skipped if any of these are missing.
+	outputs: Option<Vec<String>>,
+	/// The interpreter to run the script with (e.g. `python3`), when unset the
+	/// script is run as a bash script. Only used on a command type of task.
+	interpreter: Option<String>,
+	/// A shell snippet that is run (on the same executor) before the task, if
+	/// it exits non-zero the task is skipped rather than run. Only used on a
+	/// command type of task.
+	only_if: Option<String>,
+	/// Files, or directories (relative to the project root, and optionally
+	/// globs) to copy into the `--output-dir` after the task runs. Only used
+	/// on a command type of task.
+	artifacts: Option<Vec<TaskArtifact>>,
+	/// The most steps of a parallel-pipeline that may run at once, when unset
+	/// every step runs at once. Only used on a parallel-pipeline type of task.
+	max_concurrency: Option<usize>,
 	/// Represents the source path this task configuration file is at.
 	/// This is always overriden by dev-loop itself, and will never
 	/// use a user provided value.
 	#[serde(rename = "completely_useless")]
+	#[schemars(skip)]
 	source_path: Option<String>,
+	/// If this task was defined directly in a project configuration, rather
+	/// than a task file. Like `source_path` this is always overriden by
+	/// dev-loop itself.
+	#[serde(default, rename = "completely_useless_inline")]
+	#[schemars(skip)]
+	defined_inline: bool,
 }
 
 impl TaskConf {
@@ -486,6 +751,14 @@ impl TaskConf {
 	/// Set the source path for this `TaskConfiguration`.
 	pub fn set_path(&mut self, source_path: String) {
 		self.source_path = Some(source_path);
+		self.defined_inline = false;
+	}
+
+	/// Set the source path for this `TaskConfiguration`, when it was defined
+	/// directly in a project configuration.
+	pub fn set_inline_path(&mut self, source_path: String) {
+		self.source_path = Some(source_path);
+		self.defined_inline = true;
 	}
 
 	/// Get the type of this particular task.
@@ -557,6 +830,60 @@ impl TaskConf {
 		self.ctrlc_is_failure.unwrap_or(true)
 	}
 
+	/// Get what should be passed into the standard input of this task.
+	#[must_use]
+	pub fn get_stdin(&self) -> Option<&TaskStdin> {
+		self.stdin.as_ref()
+	}
+
+	/// Get the arguments that are always passed to this task.
+	#[must_use]
+	pub fn get_default_args(&self) -> Option<&Vec<String>> {
+		self.default_args.as_ref()
+	}
+
+	/// Determine if this task is allowed to fail without failing the run.
+	#[must_use]
+	pub fn allows_failure(&self) -> bool {
+		self.allow_failure.unwrap_or(false)
+	}
+
+	/// Get the files this task reads from, for incremental runs.
+	#[must_use]
+	pub fn get_inputs(&self) -> Option<&Vec<String>> {
+		self.inputs.as_ref()
+	}
+
+	/// Get the files this task produces, for incremental runs.
+	#[must_use]
+	pub fn get_outputs(&self) -> Option<&Vec<String>> {
+		self.outputs.as_ref()
+	}
+
+	/// Get the interpreter to run this task's script with, if it isn't bash.
+	#[must_use]
+	pub fn get_interpreter(&self) -> Option<&str> {
+		self.interpreter.as_deref()
+	}
+
+	/// Get the condition that needs to succeed for this task to run, if any.
+	#[must_use]
+	pub fn get_only_if(&self) -> Option<&str> {
+		self.only_if.as_deref()
+	}
+
+	/// Get the artifacts to collect after this task runs.
+	#[must_use]
+	pub fn get_artifacts(&self) -> Option<&Vec<TaskArtifact>> {
+		self.artifacts.as_ref()
+	}
+
+	/// Get the most steps of a parallel-pipeline that may run at once.
+	#[must_use]
+	pub fn get_max_concurrency(&self) -> Option<usize> {
+		self.max_concurrency
+	}
+
 	/// Get the original path of this particular task.
 	#[must_use]
 	pub fn get_source_path(&self) -> &str {
@@ -566,10 +893,17 @@ impl TaskConf {
 			""
 		}
 	}
+
+	/// If this task was defined directly in a project configuration, in which
+	/// case it's relative to the root of the project.
+	#[must_use]
+	pub fn is_defined_inline(&self) -> bool {
+		self.defined_inline
+	}
 }
 
 /// Represents the config that lives inside of a tasks configuration file.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct TaskConfFile {
 	/// The list of tasks to add to the global list of tasks.
 	tasks: Vec<TaskConf>,
@@ -592,7 +926,7 @@ impl TaskConfFile {
 }
 
 /// Represents the config that lives inside of a executor configuration file.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct ExecutorConfFile {
 	/// The list of executors to add to the global list of executors.
 	executors: Vec<ExecutorConf>,
@@ -5,7 +5,7 @@ use std::{
 	ffi::{CStr, OsString},
 	fs::set_permissions,
 	mem,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	ptr,
 };
 
@@ -17,16 +17,33 @@ cfg_if::cfg_if! {
   }
 }
 
-/// Rewrite the temporary directory.
+/// Rewrite a path inside of the host's temporary directory, to the same path
+/// inside of a container (where the temporary directory is mounted at `/tmp`).
+///
+/// Only a path that actually lives inside the temporary directory is
+/// rewritten, this is checked component by component so a `TMPDIR` with (or
+/// without) a trailing slash behaves the same, and a path that merely
+/// contains the temporary directory as a substring (e.g. `/tmp-other`, or
+/// `/home/user/tmp/...`) is left alone.
+#[must_use]
 pub fn rewrite_tmp_dir(host_tmp_dir: &str, path: &PathBuf) -> String {
-	let replacement_str = if host_tmp_dir.ends_with('/') {
-		"/tmp/"
-	} else {
-		"/tmp"
-	};
+	if host_tmp_dir.is_empty() {
+		return path.to_string_lossy().to_string();
+	}
 
-	path.to_string_lossy()
-		.replace(&host_tmp_dir, replacement_str)
+	match path.strip_prefix(host_tmp_dir) {
+		Ok(relative_path) => {
+			if relative_path.as_os_str().is_empty() {
+				"/tmp".to_owned()
+			} else {
+				Path::new("/tmp")
+					.join(relative_path)
+					.to_string_lossy()
+					.to_string()
+			}
+		}
+		Err(_) => path.to_string_lossy().to_string(),
+	}
 }
 
 #[cfg(target_family = "unix")]
@@ -64,6 +81,153 @@ pub fn mark_file_as_executable(path: &PathBuf) -> Result<()> {
 	Ok(())
 }
 
+/// Set the permissions of a directory, e.g. from the `mode` of an entry in
+/// `ensure_directories`.
+///
+/// `path`: the directory to set the permissions of.
+/// `mode`: the permissions to set, e.g. `0o755`.
+///
+/// # Errors
+///
+/// - When the permissions of the directory could not be set.
+#[cfg(target_family = "unix")]
+pub fn set_directory_mode(path: &Path, mode: u32) -> Result<()> {
+	use std::fs::Permissions;
+	use std::os::unix::fs::PermissionsExt;
+
+	set_permissions(path, Permissions::from_mode(mode))
+		.map(|_| ())
+		.wrap_err(format!(
+			"Failed to set the permissions of directory: [{:?}]",
+			path
+		))
+		.suggestion("Make sure you own the directory, or remove the `mode` for it.")
+}
+
+/// Set the permissions of a directory, which isn't supported on this
+/// platform so the directory is left as is.
+///
+/// `path`: the directory to set the permissions of.
+/// `_mode`: the permissions that would have been set.
+///
+/// # Errors
+///
+/// Never, this only warns that the permissions weren't set.
+#[cfg(not(target_family = "unix"))]
+pub fn set_directory_mode(path: &Path, _mode: u32) -> Result<()> {
+	tracing::warn!(
+		"Setting the `mode` of a directory isn't supported on this platform, leaving: [{:?}] as is.",
+		path,
+	);
+	Ok(())
+}
+
+/// Create a directory that only the current user can read, or write to.
+///
+/// Any missing parents are created with the default permissions, only the
+/// final directory is restricted.
+///
+/// `path`: the directory to create.
+///
+/// # Errors
+///
+/// - When the directory, or any of its parents could not be created.
+#[cfg(target_family = "unix")]
+pub fn create_private_dir(path: &Path) -> Result<()> {
+	use std::os::unix::fs::DirBuilderExt;
+
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)
+			.wrap_err(format!("Failed to create directory: [{:?}]", parent))?;
+	}
+	match std::fs::DirBuilder::new().mode(0o700).create(path) {
+		Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => {
+			Err(err).wrap_err(format!("Failed to create directory: [{:?}]", path))
+		}
+		_ => Ok(()),
+	}
+}
+
+/// Create a directory, permissions aren't supported on this platform so
+/// the directory is created as is.
+///
+/// `path`: the directory to create.
+///
+/// # Errors
+///
+/// - When the directory, or any of its parents could not be created.
+#[cfg(not(target_family = "unix"))]
+pub fn create_private_dir(path: &Path) -> Result<()> {
+	std::fs::create_dir_all(path).wrap_err(format!("Failed to create directory: [{:?}]", path))
+}
+
+/// Determine if a path is owned by the current user, and can't be written to
+/// by anyone else, so it's safe to trust its contents.
+///
+/// Symlinks are never trusted, since they could point anywhere.
+///
+/// `path`: the file, or directory to check.
+#[cfg(target_family = "unix")]
+#[must_use]
+pub fn is_private_to_current_user(path: &Path) -> bool {
+	use std::os::unix::fs::MetadataExt;
+
+	match std::fs::symlink_metadata(path) {
+		Ok(metadata) => {
+			!metadata.file_type().is_symlink()
+				&& metadata.uid() == users::get_effective_uid()
+				&& metadata.mode() & 0o022 == 0
+		}
+		Err(_) => false,
+	}
+}
+
+/// Determine if a path is owned by the current user, which can't be checked
+/// on this platform so only its existence is checked.
+///
+/// `path`: the file, or directory to check.
+#[cfg(not(target_family = "unix"))]
+#[must_use]
+pub fn is_private_to_current_user(path: &Path) -> bool {
+	path.exists()
+}
+
+/// Write a file that only the current user can read, or write to.
+///
+/// The contents are written to a temporary file next to `path` first, and
+/// then renamed into place, so a reader never sees a partially written file.
+///
+/// `path`: the file to write.
+/// `contents`: the contents to write.
+///
+/// # Errors
+///
+/// - When the temporary file could not be written, or renamed into place.
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+	use std::io::Write;
+
+	let mut tmp_name = path.file_name().map(OsString::from).unwrap_or_default();
+	tmp_name.push(format!(".{}.tmp", uuid::Uuid::new_v4()));
+	let tmp_path = path.with_file_name(tmp_name);
+
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(target_family = "unix")]
+	{
+		use std::os::unix::fs::OpenOptionsExt;
+		options.mode(0o600);
+	}
+
+	let write_res = options
+		.open(&tmp_path)
+		.and_then(|mut file| file.write_all(contents))
+		.and_then(|_| std::fs::rename(&tmp_path, path));
+	if write_res.is_err() {
+		let _ = std::fs::remove_file(&tmp_path);
+	}
+	write_res.wrap_err(format!("Failed to write file: [{:?}]", path))
+}
+
 /// Get the temporary directory for this host.
 #[must_use]
 pub fn get_tmp_dir() -> PathBuf {
@@ -85,6 +249,16 @@ pub fn get_tmp_dir() -> PathBuf {
 	}
 }
 
+/// Get the directory dev-loop caches things for the current user in, or
+/// `None` if there's no home directory to put it in.
+///
+/// This is never the temporary directory, since that is shared with every
+/// other user on the host.
+#[must_use]
+pub fn get_user_cache_dir() -> Option<PathBuf> {
+	home_dir().map(|home| home.join(".cache").join("dl"))
+}
+
 #[cfg(any(target_os = "android", target_os = "ios", target_os = "emscripten"))]
 unsafe fn home_dir_fallback() -> Option<OsString> {
 	None
@@ -119,6 +293,50 @@ unsafe fn home_dir_fallback() -> Option<OsString> {
 	}
 }
 
+/// A directory for a test to write files into, that is unique to the test,
+/// and removed once it's dropped (even if the test panics).
+#[cfg(test)]
+#[derive(Debug)]
+pub struct TestDir {
+	path: PathBuf,
+}
+
+#[cfg(test)]
+impl TestDir {
+	/// Create a new, empty, directory inside of the temporary directory.
+	///
+	/// `name`: a name for the directory, so it can be recognized.
+	#[must_use]
+	pub fn new(name: &str) -> Self {
+		static CREATED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+		let path = std::fs::canonicalize(get_tmp_dir())
+			.expect("Failed to find the temporary directory")
+			.join(format!(
+				"dl-{}-test-{}-{}",
+				name,
+				std::process::id(),
+				CREATED.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+			));
+		let _ = std::fs::remove_dir_all(&path);
+		std::fs::create_dir_all(&path).expect("Failed to create a test directory");
+		Self { path }
+	}
+
+	/// Get the path to the directory.
+	#[must_use]
+	pub fn get_path(&self) -> &Path {
+		&self.path
+	}
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.path);
+	}
+}
+
 /// Calculate the home directory of a user.
 #[must_use]
 pub fn home_dir() -> Option<PathBuf> {
@@ -142,4 +360,84 @@ mod tests {
 		let home_dir = home_dir.unwrap();
 		assert!(home_dir.is_dir());
 	}
+
+	#[test]
+	fn rewrites_tmp_dir() {
+		for tmp_dir in &["/tmp", "/tmp/"] {
+			assert_eq!(
+				rewrite_tmp_dir(tmp_dir, &PathBuf::from("/tmp/dl-abc/entrypoint.sh")),
+				"/tmp/dl-abc/entrypoint.sh",
+			);
+		}
+
+		for tmp_dir in &["/var/folders/xy/abc123/T", "/var/folders/xy/abc123/T/"] {
+			assert_eq!(
+				rewrite_tmp_dir(
+					tmp_dir,
+					&PathBuf::from("/var/folders/xy/abc123/T/dl-abc/entrypoint.sh")
+				),
+				"/tmp/dl-abc/entrypoint.sh",
+			);
+			assert_eq!(
+				rewrite_tmp_dir(tmp_dir, &PathBuf::from("/var/folders/xy/abc123/T/")),
+				"/tmp",
+			);
+			// Only the temporary directory as a prefix should ever be rewritten.
+			assert_eq!(
+				rewrite_tmp_dir(
+					tmp_dir,
+					&PathBuf::from("/home/user/var/folders/xy/abc123/T/file")
+				),
+				"/home/user/var/folders/xy/abc123/T/file",
+			);
+			assert_eq!(
+				rewrite_tmp_dir(
+					tmp_dir,
+					&PathBuf::from("/var/folders/xy/abc123/Tother/file")
+				),
+				"/var/folders/xy/abc123/Tother/file",
+			);
+		}
+
+		assert_eq!(
+			rewrite_tmp_dir("/tmp", &PathBuf::from("/tmp-other/file")),
+			"/tmp-other/file",
+		);
+		assert_eq!(
+			rewrite_tmp_dir("/tmp", &PathBuf::from("/home/user/tmp/file")),
+			"/home/user/tmp/file",
+		);
+		assert_eq!(
+			rewrite_tmp_dir("", &PathBuf::from("/tmp/file")),
+			"/tmp/file",
+		);
+	}
+
+	#[test]
+	fn writes_private_files() {
+		let test_dir = TestDir::new("private-files");
+		let dir = test_dir.get_path().join("private");
+		let file = dir.join("file.json");
+		create_private_dir(&dir).unwrap();
+		create_private_dir(&dir).unwrap();
+		write_private_file(&file, b"first").unwrap();
+		write_private_file(&file, b"second").unwrap();
+		assert_eq!(std::fs::read(&file).unwrap(), b"second");
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+		assert!(is_private_to_current_user(&dir));
+		assert!(is_private_to_current_user(&file));
+		assert!(!is_private_to_current_user(&dir.join("missing")));
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+
+			set_permissions(&file, std::fs::Permissions::from_mode(0o646)).unwrap();
+			assert!(!is_private_to_current_user(&file));
+			std::os::unix::fs::symlink(&dir, test_dir.get_path().join("link")).unwrap();
+			assert!(!is_private_to_current_user(
+				&test_dir.get_path().join("link")
+			));
+		}
+	}
 }
@@ -1,17 +1,23 @@
 use crate::{
 	config::types::{NeedsRequirement, ProvideConf},
-	dirs::{get_tmp_dir, rewrite_tmp_dir},
+	dirs::{get_tmp_dir, mark_as_world_editable, rewrite_tmp_dir},
 	executors::{
 		docker_engine::{
 			delete_container, delete_network, docker_version_check, ensure_docker_container,
-			ensure_network_attached, ensure_network_exists, execute_command_in_container_async,
+			ensure_network_attached, ensure_network_exists, ensure_tools_in_container,
+			execute_command_in_container, execute_command_in_container_async,
 			get_command_exit_code, has_command_finished, list_devloop_containers,
 			list_devloop_networks, DockerContainerInfo, SOCKET_PATH,
 		},
-		shared::{create_entrypoint, create_executor_shared_dir, create_log_proxy_files},
-		CompatibilityStatus, Executor as ExecutorTrait,
+		shared::{
+			build_provides, create_entrypoint, create_executor_shared_dir, create_log_proxy_files,
+			parse_version_from_output, provides_meet_requirements, shell_quote,
+			sorted_provided_tools, ExecutorHooks,
+		},
+		CompatibilityStatus, Executor as ExecutorTrait, ProvidedTools,
 	},
 	tasks::execution::preparation::ExecutableTask,
+	time_helper::is_older_than,
 };
 
 use color_eyre::{
@@ -25,12 +31,12 @@ use isahc::{
 	prelude::*,
 	Error as HttpError, HttpClient, HttpClientBuilder,
 };
-use semver::{Version, VersionReq};
+use semver::Version;
 use std::{
 	collections::HashMap,
 	fs::File,
 	io::{prelude::*, BufReader},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
@@ -39,6 +45,13 @@ use std::{
 };
 use tracing::{debug, error, info, warn};
 
+/// How long a task has to exit after being signaled to stop, before it is
+/// forcibly killed.
+const TASK_STOP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+/// The exit code of copying a path out of the container, when the path
+/// doesn't exist.
+const MISSING_PATH_RC: i32 = 3;
+
 /// Represents the actual `Executor` for docker, responsible for maintaining
 /// the lifecycle of a single docker container.
 #[derive(Debug)]
@@ -55,6 +68,10 @@ pub struct Executor {
 	container: DockerContainerInfo,
 	/// The temporary directory.
 	tmp_dir: String,
+	/// The `setup`/`teardown` hooks for this executor.
+	hooks: ExecutorHooks,
+	/// If the provided tools have been found in the container already.
+	provides_verified: AtomicBool,
 }
 
 impl Executor {
@@ -81,11 +98,12 @@ impl Executor {
 		let pr_as_string = project_root.to_str();
 		if pr_as_string.is_none() {
 			return Err(eyre!(
-				"Failed to turn the project directory: [{:?}] into a utf8-string.",
+				"The docker executor needs the project directory: [{:?}] to be a UTF-8 path.",
 				project_root,
 			))
+			.note("The project directory is mounted into the container by path, which the docker API only accepts as a UTF-8 string.")
 			.suggestion(
-				"Please move the project directory to somewhere that is a UTF-8 only file path.",
+				"Move the project directory to a UTF-8 only file path, or run these tasks with the host executor which doesn't have this requirement.",
 			);
 		}
 		let pr_as_string = pr_as_string.unwrap();
@@ -93,18 +111,7 @@ impl Executor {
 		// Next Generate the random name for the container to use that won't clash.
 		let random_str = format!("{}", uuid::Uuid::new_v4());
 
-		let mut provides = HashMap::new();
-		for provided in provided_conf {
-			let version_opt = if provided.get_version().is_empty() {
-				None
-			} else if let Ok(vs) = Version::parse(provided.get_version()) {
-				Some(vs)
-			} else {
-				None
-			};
-
-			provides.insert(provided.get_name().to_owned(), version_opt);
-		}
+		let provides = build_provides(provided_conf);
 
 		let mut default_headers = HeaderMap::new();
 		if executor_args.contains_key("docker_auth_username_env") {
@@ -168,15 +175,201 @@ impl Executor {
 			random_str,
 			container,
 			tmp_dir: get_tmp_dir().to_string_lossy().to_string(),
+			hooks: ExecutorHooks::default(),
+			provides_verified: AtomicBool::new(false),
 		})
 	}
 
+	/// Set the `setup`/`teardown` hooks for this executor.
+	#[must_use]
+	pub fn with_hooks(mut self, hooks: ExecutorHooks) -> Self {
+		self.hooks = hooks;
+		self
+	}
+
+	/// Run the `version_command` of every provided tool that has one inside of
+	/// the container, and use the version it outputs.
+	///
+	/// This starts the container if any tool has a `version_command`. If the
+	/// command can't be run, or its output can't be parsed the configured
+	/// `version` is kept (which when unset only meets requirements that don't
+	/// ask for a version).
+	///
+	/// `provided_conf`: the list of services this docker executor providers.
+	pub async fn with_probed_versions(mut self, provided_conf: &[ProvideConf]) -> Self {
+		for (idx, provided) in provided_conf.iter().enumerate() {
+			let version_command = if let Some(command) = provided.get_version_command() {
+				command
+			} else {
+				continue;
+			};
+
+			match self.run_version_command(idx, version_command).await {
+				Ok(output) => {
+					if let Some(version) = parse_version_from_output(&output) {
+						debug!(
+							"Docker container: [{}] provides: [{}] at version: [{}]",
+							self.container.get_container_name(),
+							provided.get_name(),
+							version,
+						);
+						self.provides
+							.insert(provided.get_name().to_owned(), Some(version));
+					} else {
+						warn!(
+							"Could not find a version in the output of the `version_command`: [{}] for: [{}], output was: [{}]. Will use the configured `version`.",
+							version_command,
+							provided.get_name(),
+							output.trim(),
+						);
+					}
+				}
+				Err(probe_err) => {
+					warn!(
+						"{:?}",
+						probe_err
+							.wrap_err(format!(
+								"Failed to run the `version_command`: [{}] for: [{}]",
+								version_command,
+								provided.get_name(),
+							))
+							.note("Will use the configured `version`.")
+					);
+				}
+			}
+		}
+
+		self
+	}
+
+	/// Run a `version_command` inside of the container, returning everything
+	/// it output.
+	async fn run_version_command(&self, idx: usize, command: &str) -> Result<String> {
+		ensure_docker_container(
+			&self.client,
+			&self.project_root,
+			&self.tmp_dir,
+			&self.container,
+			None,
+		)
+		.await?;
+
+		let mut output_path = get_tmp_dir();
+		output_path.push(format!("{}-version-probe-{}.log", self.random_str, idx));
+		File::create(&output_path)
+			.wrap_err("Failed to create file for `version_command` output")?;
+		mark_as_world_editable(&output_path)?;
+		let output_path_in_docker = rewrite_tmp_dir(&self.tmp_dir, &output_path);
+
+		// The braces (and newline) make sure the redirect covers the entire
+		// command, even a compound one, or one ending in a comment.
+		let exec_id = execute_command_in_container(
+			&self.client,
+			self.container.get_container_name(),
+			&self.container.get_shell_command(format!(
+				"{{ {}\n}} >{} 2>&1",
+				command,
+				String::from_utf8_lossy(&shell_quote(&output_path_in_docker)),
+			)),
+			self.container.get_environment_to_export(),
+			self.container.get_base_user(),
+			true,
+			self.container.get_cloned_proxy_user_id(),
+			self.container.get_cloned_proxy_group_id(),
+		)
+		.await?;
+		let rc = get_command_exit_code(&self.client, &exec_id).await?;
+
+		let output = std::fs::read_to_string(&output_path);
+		let _ = std::fs::remove_file(&output_path);
+		let output = output.wrap_err("Failed to read the output of the `version_command`")?;
+		if rc != 0 {
+			return Err(eyre!("The command exited with: [{}]", rc))
+				.note(format!("The command output: [{}]", output.trim()));
+		}
+
+		Ok(output)
+	}
+
+	/// Stop a task running inside of the container, by signaling the process
+	/// group of the task. Processes are given a grace period to exit after a
+	/// `SIGTERM`, before being sent a `SIGKILL`. The container itself is left
+	/// running, so it can be reused or cleaned up as normal.
+	///
+	/// `exec_id`: the docker execution id of the task.
+	/// `pid_path_in_docker`: the path to the file containing the pid of the task.
+	async fn stop_task(&self, exec_id: &str, pid_path_in_docker: &str) -> Result<()> {
+		for signal in &["TERM", "KILL"] {
+			if has_command_finished(&self.client, exec_id).await {
+				return Ok(());
+			}
+
+			debug!(
+				"Sending SIG{} to task in container: [{}]",
+				signal,
+				self.container.get_container_name()
+			);
+			execute_command_in_container(
+				&self.client,
+				self.container.get_container_name(),
+				&self.container.get_shell_command(format!(
+					"kill -{} -- -\"$(cat {})\" >/dev/null 2>&1",
+					signal,
+					String::from_utf8_lossy(&shell_quote(pid_path_in_docker)),
+				)),
+				self.container.get_environment_to_export(),
+				self.container.get_base_user(),
+				true,
+				self.container.get_cloned_proxy_user_id(),
+				self.container.get_cloned_proxy_group_id(),
+			)
+			.await?;
+
+			for _ in 0..(TASK_STOP_GRACE_PERIOD.as_millis() / 100) {
+				if has_command_finished(&self.client, exec_id).await {
+					return Ok(());
+				}
+				async_std::task::sleep(Duration::from_millis(100)).await;
+			}
+		}
+
+		if has_command_finished(&self.client, exec_id).await {
+			Ok(())
+		} else {
+			Err(eyre!("Task did not exit after being sent a SIGKILL."))
+		}
+	}
+
+	/// Run a hook command with the shell of the container, waiting for it
+	/// to finish.
+	async fn run_hook(&self, command: String) -> Result<i32> {
+		let exec_id = execute_command_in_container(
+			&self.client,
+			self.container.get_container_name(),
+			&self.container.get_shell_command(command),
+			self.container.get_environment_to_export(),
+			self.container.get_base_user(),
+			true,
+			self.container.get_cloned_proxy_user_id(),
+			self.container.get_cloned_proxy_group_id(),
+		)
+		.await
+		.wrap_err("Failed to run hook inside docker container.")?;
+
+		get_command_exit_code(&self.client, &exec_id).await
+	}
+
 	/// Attempt to clean up all resources left behind by the docker executor.
 	///
+	/// `older_than`: if specified only remove containers/networks that were
+	///               created at least this long ago.
+	/// `labeled_only`: only remove containers/networks labeled as managed by
+	///                 dev-loop, ignoring ones that merely have a `dl-` name.
+	///
 	/// # Errors
 	///
 	/// - when there is an issue talking to the docker api for containers.
-	pub async fn clean() -> Result<()> {
+	pub async fn clean(older_than: Option<Duration>, labeled_only: bool) -> Result<()> {
 		// Cleanup all things left behind by the docker executor.
 		if Self::is_compatible().await != CompatibilityStatus::Compatible {
 			info!("Docker is not listening on this host, won't clean!");
@@ -195,17 +388,23 @@ impl Executor {
 		}
 		.wrap_err("Failed to construct HTTP-Client to talk to Docker")?;
 
-		for container in list_devloop_containers(&client).await.wrap_err("Failed to list containers").note("Will not clean up docker containers due to this error.").suggestion("To manually clean up containers use `docker ps -a` to list containers, and `docker kill ${container name that starts with `dl-`}`")? {
+		for (container, created_at) in list_devloop_containers(&client, labeled_only).await.wrap_err("Failed to list containers").note("Will not clean up docker containers due to this error.").suggestion("To manually clean up containers use `docker ps -a` to list containers, and `docker kill ${container name that starts with `dl-`}`")? {
 			debug!("Found dev-loop container: [{}]", container);
+			if !Self::is_stale(&container, created_at, older_than) {
+				continue;
+			}
 			delete_container(&client, &container).await;
 		}
 
-		for network in list_devloop_networks(&client)
+		for (network, created_at) in list_devloop_networks(&client, labeled_only)
 			.await
 			.wrap_err("Failed to list networks")
 			.note("Will not delete docker networks due to this error.")?
 		{
 			debug!("Found dev-loop network: [{}]", network);
+			if !Self::is_stale(&network, created_at, older_than) {
+				continue;
+			}
 			delete_network(&client, &network).await;
 		}
 
@@ -213,6 +412,72 @@ impl Executor {
 		Ok(())
 	}
 
+	/// Find the dev-loop containers, and networks that exist right now. These
+	/// are normally left over from a run that crashed (or one that's still
+	/// running).
+	///
+	/// This is best-effort, if docker isn't available (or errors) `None` is
+	/// returned.
+	pub async fn list_leftover_resources() -> Option<(Vec<String>, Vec<String>)> {
+		if Self::is_compatible().await != CompatibilityStatus::Compatible {
+			return None;
+		}
+
+		let client = if cfg!(target_os = "windows") {
+			HttpClientBuilder::new()
+				.version_negotiation(VersionNegotiation::http11())
+				.build()
+		} else {
+			HttpClientBuilder::new()
+				.dial(SOCKET_PATH.parse::<Dialer>().ok()?)
+				.version_negotiation(VersionNegotiation::http11())
+				.build()
+		}
+		.ok()?;
+
+		let containers = list_devloop_containers(&client, false)
+			.await
+			.ok()?
+			.into_iter()
+			.map(|(container, _)| container.trim_start_matches('/').to_owned())
+			.collect::<Vec<String>>();
+		let networks = list_devloop_networks(&client, false)
+			.await
+			.ok()?
+			.into_iter()
+			.map(|(network, _)| network)
+			.collect::<Vec<String>>();
+
+		Some((containers, networks))
+	}
+
+	/// Determine if a docker resource is old enough to be cleaned up.
+	///
+	/// If docker didn't tell us when a resource was created, we play it safe
+	/// and only clean it when no age was requested.
+	fn is_stale(name: &str, created_at: Option<u64>, older_than: Option<Duration>) -> bool {
+		match (older_than, created_at) {
+			(None, _) => true,
+			(Some(age), Some(created)) => {
+				let stale = is_older_than(created, age);
+				if !stale {
+					debug!(
+						"Skipping: [{}] as it was created within the last: [{:?}]",
+						name, age,
+					);
+				}
+				stale
+			}
+			(Some(_), None) => {
+				debug!(
+					"Skipping: [{}] as docker did not report when it was created.",
+					name
+				);
+				false
+			}
+		}
+	}
+
 	/// Determines if this `Executor` is compatible with the system.
 	pub async fn is_compatible() -> CompatibilityStatus {
 		let client = if cfg!(target_os = "windows") {
@@ -292,31 +557,27 @@ impl Executor {
 impl ExecutorTrait for Executor {
 	#[must_use]
 	fn meets_requirements(&self, reqs: &[NeedsRequirement]) -> bool {
-		let mut met = true;
+		provides_meet_requirements(&self.provides, reqs)
+	}
 
-		for req in reqs {
-			if !self.provides.contains_key(req.get_name()) {
-				met = false;
-				break;
-			}
+	fn get_type_name(&self) -> &'static str {
+		"docker"
+	}
 
-			if let Some(matcher) = req.get_version_matcher() {
-				if let Ok(version_req) = VersionReq::parse(matcher) {
-					if let Some(version) = self.provides.get(req.get_name()).unwrap() {
-						if !version_req.matches(version) {
-							met = false;
-							break;
-						}
-					} else {
-						met = false;
-					}
-				} else {
-					continue;
-				}
-			}
+	fn get_provided_tools(&self) -> ProvidedTools {
+		sorted_provided_tools(&self.provides)
+	}
+
+	fn get_container_kept_on_failure(&self) -> Option<&str> {
+		if self.container.should_keep_on_failure() {
+			Some(self.container.get_container_name())
+		} else {
+			None
 		}
+	}
 
-		met
+	fn get_shell_path(&self) -> String {
+		self.container.get_shell_path()
 	}
 
 	#[must_use]
@@ -328,21 +589,45 @@ impl ExecutorTrait for Executor {
 		task: &ExecutableTask,
 		worker_count: usize,
 	) -> Result<i32> {
-		ensure_network_exists(&self.client, task.get_pipeline_id()).await?;
+		// When a network mode has been chosen, docker manages the network for us.
+		let uses_pipeline_network = self.container.get_network_mode().is_none();
+		if uses_pipeline_network {
+			ensure_network_exists(&self.client, task.get_pipeline_id()).await?;
+		}
 		ensure_docker_container(
 			&self.client,
 			&self.project_root,
 			&self.tmp_dir,
 			&self.container,
+			Some(task.get_pipeline_id()),
 		)
 		.await?;
-		ensure_network_attached(
-			&self.client,
-			self.container.get_container_name(),
-			self.container.get_hostname(),
-			task.get_pipeline_id(),
-		)
-		.await?;
+		if self.container.should_verify_provides()
+			&& !self.provides_verified.load(Ordering::Acquire)
+		{
+			let tools = sorted_provided_tools(&self.provides)
+				.into_iter()
+				.map(|(name, _)| name)
+				.collect::<Vec<_>>();
+			ensure_tools_in_container(&self.client, &self.container, &tools).await?;
+			self.provides_verified.store(true, Ordering::Release);
+		}
+		if uses_pipeline_network {
+			ensure_network_attached(
+				&self.client,
+				self.container.get_container_name(),
+				self.container.get_hostname(),
+				task.get_pipeline_id(),
+			)
+			.await?;
+		}
+		self.hooks
+			.ensure_setup(|command| self.run_hook(command))
+			.await
+			.note(format!(
+				"The hook runs inside the container: [{}]",
+				self.container.get_container_name()
+			))?;
 
 		let shared_dir = create_executor_shared_dir(task.get_pipeline_id())?;
 
@@ -351,21 +636,40 @@ impl ExecutorTrait for Executor {
 		let stdout_path_in_docker = rewrite_tmp_dir(&self.tmp_dir, &stdout_host_log_path);
 		let stderr_path_in_docker = rewrite_tmp_dir(&self.tmp_dir, &stderr_host_log_path);
 
+		// The PID of the task's process group gets written here, so if we're told
+		// to stop we can kill everything the task started.
+		let mut pid_host_path = shared_dir.clone();
+		pid_host_path.push(format!("{}-{}.pid", task.get_task_name(), worker_count));
+		File::create(&pid_host_path).wrap_err("Failed to create file for the task's pid")?;
+		mark_as_world_editable(&pid_host_path)?;
+		let pid_path_in_docker = rewrite_tmp_dir(&self.tmp_dir, &pid_host_path);
+
+		let mut working_dir = PathBuf::from(self.container.get_workdir_mount());
+		if let Some(task_working_dir) = task.get_working_dir() {
+			working_dir.push(task_working_dir);
+		}
 		let entrypoint = create_entrypoint(
-			"/mnt/dl-root",
+			&working_dir,
 			&self.tmp_dir,
 			shared_dir,
 			helper_src_line,
 			task,
+			worker_count,
 			true,
 			Some(stdout_path_in_docker),
 			Some(stderr_path_in_docker),
+			&self.container.get_shell_path(),
 		)?;
-		let entrypoint_as_str = entrypoint.to_string_lossy().to_string();
+		// `set -m` runs the entrypoint in its own process group, which is what lets
+		// us kill the entire process tree, since docker has no API to stop an exec.
 		let exec_id = execute_command_in_container_async(
 			&self.client,
 			self.container.get_container_name(),
-			&[entrypoint_as_str],
+			&self.container.get_shell_command(format!(
+				"set -m; {} & echo $! >{}; wait $!",
+				String::from_utf8_lossy(&shell_quote(&entrypoint)),
+				String::from_utf8_lossy(&shell_quote(&pid_path_in_docker)),
+			)),
 			self.container.get_environment_to_export(),
 			self.container.get_base_user(),
 			true,
@@ -427,6 +731,17 @@ impl ExecutorTrait for Executor {
 					warn!("Docker Executor was told to terminate! Stopping!");
 					rc = 0;
 				}
+				if let Err(stop_err) = self.stop_task(&exec_id, &pid_path_in_docker).await {
+					warn!(
+						"{:?}",
+						stop_err
+							.wrap_err("Failed to stop task running in docker container")
+							.note(format!(
+								"The task may still be running inside of the container: [{}]",
+								self.container.get_container_name()
+							))
+					);
+				}
 				break;
 			}
 
@@ -438,6 +753,61 @@ impl ExecutorTrait for Executor {
 
 		Ok(rc)
 	}
+
+	async fn copy_to_host(&self, path: &str, host_dir: &Path) -> Result<Option<PathBuf>> {
+		let file_name = Path::new(path).file_name().ok_or_else(|| {
+			eyre!(
+				"The path: [{}] doesn't name a file, or directory to copy.",
+				path
+			)
+		})?;
+		let host_dir_in_docker = rewrite_tmp_dir(&self.tmp_dir, &host_dir.to_path_buf());
+
+		// `cp -P` copies symlinks as links, and a path that doesn't exist gets
+		// it's own exit code so it isn't mistaken for failing to copy.
+		let exec_id = execute_command_in_container(
+			&self.client,
+			self.container.get_container_name(),
+			&self.container.get_shell_command(format!(
+				"if [ ! -e {path} ] && [ ! -L {path} ]; then exit {missing_rc}; fi\ncp -PR {path} {host_dir}",
+				path = String::from_utf8_lossy(&shell_quote(path)),
+				missing_rc = MISSING_PATH_RC,
+				host_dir = String::from_utf8_lossy(&shell_quote(&host_dir_in_docker)),
+			)),
+			self.container.get_environment_to_export(),
+			self.container.get_base_user(),
+			true,
+			self.container.get_cloned_proxy_user_id(),
+			self.container.get_cloned_proxy_group_id(),
+		)
+		.await?;
+		match get_command_exit_code(&self.client, &exec_id).await? {
+			0 => Ok(Some(host_dir.join(file_name))),
+			MISSING_PATH_RC => Ok(None),
+			rc => Err(eyre!(
+				"Failed to copy: [{}] out of the docker container: [{}], the copy exited with: [{}]",
+				path,
+				self.container.get_container_name(),
+				rc,
+			)),
+		}
+	}
+
+	async fn teardown(&self) -> Result<()> {
+		if let Some(teardown) = self.hooks.get_teardown_to_run().await {
+			let rc = self.run_hook(teardown.clone()).await?;
+			if rc != 0 {
+				return Err(eyre!(
+					"The `teardown` hook: [{}] for docker container: [{}] exited with: [{}]",
+					teardown,
+					self.container.get_container_name(),
+					rc,
+				));
+			}
+		}
+
+		Ok(())
+	}
 }
 
 #[cfg(test)]
@@ -1,16 +1,86 @@
+use super::SOCKET_PATH;
+use crate::executors::shared::DEFAULT_SHELL_PATH;
+
 use color_eyre::{eyre::eyre, Report, Result, Section};
-use std::{collections::HashMap, env::var as env_var, path::PathBuf};
+use std::{collections::HashMap, env::var as env_var, net::IpAddr, path::PathBuf, time::Duration};
 use tracing::warn;
 
 const CONTAINER_NAME_ARG: &str = "name_prefix";
+const GPUS_ARG: &str = "gpus";
+const HEALTHCHECK_COMMAND_ARG: &str = "healthcheck_command";
+const HEALTHCHECK_TIMEOUT_ARG: &str = "healthcheck_timeout_seconds";
 const HOSTNAME_ARG: &str = "hostname";
 const IMAGE_ARG: &str = "image";
+const KEEP_ON_FAILURE_ARG: &str = "keep_on_failure";
+const LABELS_ARG: &str = "labels";
 const ENV_TO_EXPORT_ARG: &str = "export_env";
+const ENV_FILE_ARG: &str = "env_file";
+const EXTRA_HOSTS_ARG: &str = "extra_hosts";
 const MOUNTS_ARG: &str = "extra_mounts";
+const MOUNT_DOCKER_SOCKET_ARG: &str = "mount_docker_socket";
+const NETWORK_MODE_ARG: &str = "network_mode";
 const PERMISSION_HELPER_ARG: &str = "experimental_permission_helper";
+const PERMISSION_HELPER_GID_ARG: &str = "permission_helper_gid";
+const PERMISSION_HELPER_UID_ARG: &str = "permission_helper_uid";
+const PERMISSION_MODE_ARG: &str = "permission_mode";
+const RESTART_POLICY_ARG: &str = "restart_policy";
+const SHELL_PATH_ARG: &str = "shell_path";
 const TCP_PORTS_TO_EXPOSE_ARG: &str = "tcp_ports_to_expose";
 const USER_ARG: &str = "user";
+const VERIFY_PROVIDES_ARG: &str = "verify_provides";
+const WORKDIR_MOUNT_ARG: &str = "workdir_mount";
+/// Where the project root gets mounted inside the container by default.
+const DEFAULT_WORKDIR_MOUNT: &str = "/mnt/dl-root";
+const DEFAULT_HEALTHCHECK_TIMEOUT_SECONDS: u64 = 60;
 const UDP_PORTS_TO_EXPOSE_ARG: &str = "udp_ports_to_expose";
+/// The prefix of every label dev-loop manages itself, users can't set these.
+pub const DEVLOOP_LABEL_PREFIX: &str = "dev-loop.";
+/// The label placed on every container dev-loop creates.
+pub const MANAGED_LABEL: &str = "dev-loop.managed";
+/// The label containing the pipeline a container was created for.
+pub const PIPELINE_ID_LABEL: &str = "dev-loop.pipeline-id";
+
+/// Represents an extra mount requested by the user for a container.
+#[derive(Debug, PartialEq)]
+pub struct ExtraMount {
+	/// The source of the mount, either a host path or the name of a volume.
+	source: String,
+	/// The path inside of the container to mount at.
+	target: String,
+	/// If this is a named docker volume, rather than a bind mount.
+	is_volume: bool,
+}
+
+impl ExtraMount {
+	pub fn get_source(&self) -> &str {
+		&self.source
+	}
+
+	pub fn get_target(&self) -> &str {
+		&self.target
+	}
+
+	/// Get the docker "Type" of this mount.
+	pub fn get_mount_type(&self) -> &str {
+		if self.is_volume {
+			"volume"
+		} else {
+			"bind"
+		}
+	}
+}
+
+/// How the files created inside of a container are given the same owner as
+/// the user running dev-loop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PermissionMode {
+	/// Create a `dl` user with the same user/group id (that can `sudo` if
+	/// sudo is installed), and run commands as it.
+	CreateUser,
+	/// Run commands as the user/group id directly, without creating a user.
+	/// This only works for images that are fine running as an arbitrary id.
+	RunAsUid,
+}
 
 /// Represents a `DockerContainer` managed by the docker-engine/docker executor.
 #[derive(Debug)]
@@ -22,15 +92,48 @@ pub struct DockerContainerInfo {
 	/// A list of environment variables to export.
 	environment_to_export: Vec<String>,
 	/// A list of extra mounts.
-	extra_mounts: Vec<String>,
+	extra_mounts: Vec<ExtraMount>,
+	/// A list of extra `name:ip` entries to add to the containers
+	/// `/etc/hosts`.
+	extra_hosts: Vec<String>,
 	/// The list of tcp ports to expose.
 	tcp_ports_to_expose: Vec<u32>,
 	/// The list of udp ports to expose.
 	udp_ports_to_expose: Vec<u32>,
 	/// The hostname of this container.
 	hostname: String,
+	/// Where the project root is mounted inside the container, this is also
+	/// the directory tasks run in.
+	workdir_mount: String,
+	/// The docker network mode to use (e.g. `host`), rather than attaching
+	/// to a network per pipeline.
+	network_mode: Option<String>,
+	/// The amount of GPUs to pass through to the container, where `-1` means
+	/// all of them.
+	gpu_count: Option<i64>,
+	/// A command to determine if the container is ready, overriding the
+	/// images `HEALTHCHECK`.
+	healthcheck_command: Option<String>,
+	/// How long to wait for a container to become healthy.
+	healthcheck_timeout: Duration,
+	/// If the container should be kept around after a failed run, so it can
+	/// be debugged.
+	keep_on_failure: bool,
+	/// The docker restart policy of the container, and the most times it may
+	/// be restarted (only used by `on-failure`).
+	restart_policy: Option<(String, u64)>,
+	/// Extra `key=value` labels to place on the container.
+	labels: Vec<(String, String)>,
+	/// If every provided tool should be checked to exist in the container,
+	/// before running anything.
+	verify_provides: bool,
 	/// The base user to use.
 	base_user: String,
+	/// The shell to run commands with (e.g. `/usr/bin/env bash`), split
+	/// on whitespace.
+	shell: Vec<String>,
+	/// How to give files created in the container the right owner, if at all.
+	permission_mode: Option<PermissionMode>,
 	/// The proxied user id.
 	proxy_user_id: Option<u32>,
 	/// The proxied group id.
@@ -43,17 +146,43 @@ impl DockerContainerInfo {
 		project_root_str: &str,
 		random_str: &str,
 	) -> Result<Self> {
-		let (proxy_user, proxy_group) = get_proxy_user_information(executor_args);
+		let permission_mode = get_permission_mode(executor_args)?;
+		let (proxy_user, proxy_group) = get_proxy_user_information(executor_args, permission_mode);
+		let keep_on_failure = matches!(
+			executor_args.get(KEEP_ON_FAILURE_ARG),
+			Some(keep) if keep.eq_ignore_ascii_case("true")
+		);
 
 		Ok(Self {
 			container_name: container_name_from_arg(executor_args, random_str)?,
 			image: image_from_arg(executor_args)?,
-			environment_to_export: get_env_vars_to_export(executor_args),
-			extra_mounts: get_extra_mounts(executor_args, project_root_str),
+			environment_to_export: get_env_vars_to_export(executor_args, project_root_str),
+			extra_mounts: get_extra_mounts(executor_args, project_root_str)
+				.into_iter()
+				.chain(get_docker_socket_mount(executor_args))
+				.collect(),
+			extra_hosts: get_extra_hosts(executor_args),
 			tcp_ports_to_expose: tcp_ports_to_expose(executor_args),
 			udp_ports_to_expose: udp_ports_to_expose(executor_args),
 			hostname: get_hostname(executor_args),
+			workdir_mount: get_workdir_mount(executor_args),
+			network_mode: executor_args
+				.get(NETWORK_MODE_ARG)
+				.map(|mode| mode.trim().to_owned())
+				.filter(|mode| !mode.is_empty()),
+			gpu_count: get_gpu_count(executor_args)?,
+			healthcheck_command: get_healthcheck_command(executor_args, keep_on_failure)?,
+			healthcheck_timeout: get_healthcheck_timeout(executor_args),
+			keep_on_failure,
+			restart_policy: get_restart_policy(executor_args, keep_on_failure)?,
+			labels: get_labels(executor_args),
+			verify_provides: matches!(
+				executor_args.get(VERIFY_PROVIDES_ARG),
+				Some(verify) if verify.eq_ignore_ascii_case("true")
+			),
 			base_user: get_user(executor_args),
+			shell: get_shell(executor_args),
+			permission_mode,
 			proxy_user_id: proxy_user,
 			proxy_group_id: proxy_group,
 		})
@@ -71,10 +200,14 @@ impl DockerContainerInfo {
 		&self.environment_to_export
 	}
 
-	pub fn get_extra_mounts(&self) -> &[String] {
+	pub fn get_extra_mounts(&self) -> &[ExtraMount] {
 		&self.extra_mounts
 	}
 
+	pub fn get_extra_hosts(&self) -> &[String] {
+		&self.extra_hosts
+	}
+
 	pub fn get_tcp_ports_to_expose(&self) -> &[u32] {
 		&self.tcp_ports_to_expose
 	}
@@ -83,14 +216,85 @@ impl DockerContainerInfo {
 		&self.udp_ports_to_expose
 	}
 
+	/// Get the network mode the user asked for, if none was asked for the
+	/// container should be attached to a network per pipeline.
+	pub fn get_network_mode(&self) -> Option<&str> {
+		self.network_mode.as_deref()
+	}
+
 	pub fn get_hostname(&self) -> &str {
 		&self.hostname
 	}
 
+	/// Get where the project root is mounted inside the container.
+	pub fn get_workdir_mount(&self) -> &str {
+		&self.workdir_mount
+	}
+
+	pub fn get_gpu_count(&self) -> Option<i64> {
+		self.gpu_count
+	}
+
+	pub fn get_healthcheck_command(&self) -> Option<&String> {
+		self.healthcheck_command.as_ref()
+	}
+
+	pub fn get_healthcheck_timeout(&self) -> Duration {
+		self.healthcheck_timeout
+	}
+
+	pub fn should_keep_on_failure(&self) -> bool {
+		self.keep_on_failure
+	}
+
+	/// If every provided tool should be checked to exist in the container.
+	#[must_use]
+	pub fn should_verify_provides(&self) -> bool {
+		self.verify_provides
+	}
+
+	/// Get the docker restart policy the user asked for, along with the most
+	/// times the container may be restarted.
+	pub fn get_restart_policy(&self) -> Option<(&str, u64)> {
+		self.restart_policy
+			.as_ref()
+			.map(|(name, max_retries)| (name.as_str(), *max_retries))
+	}
+
+	/// Get the labels the user asked to place on the container, this doesn't
+	/// include the labels dev-loop manages.
+	pub fn get_labels(&self) -> &[(String, String)] {
+		&self.labels
+	}
+
 	pub fn get_base_user(&self) -> &str {
 		&self.base_user
 	}
 
+	/// Get the shell commands are run with, e.g. `/usr/bin/env bash`.
+	#[must_use]
+	pub fn get_shell_path(&self) -> String {
+		self.shell.join(" ")
+	}
+
+	/// Get the command to run a script with the shell of this container.
+	///
+	/// The script is passed along as is, so any paths in it need to be quoted
+	/// with `shell_quote`.
+	#[must_use]
+	pub fn get_shell_command(&self, script: String) -> Vec<String> {
+		let mut command = self.shell.clone();
+		command.push("-c".to_owned());
+		command.push(script);
+		command
+	}
+
+	/// Get how files created in the container are given the right owner, if
+	/// at all.
+	pub fn get_permission_mode(&self) -> Option<PermissionMode> {
+		self.permission_mode
+	}
+
 	pub fn get_proxy_user_id(&self) -> Option<&u32> {
 		self.proxy_user_id.as_ref()
 	}
@@ -137,23 +341,84 @@ fn image_from_arg(args: &HashMap<String, String>) -> Result<String> {
 	Ok(image)
 }
 
-fn get_env_vars_to_export(args: &HashMap<String, String>) -> Vec<String> {
+/// Parse the contents of a `.env` style file into a list of `KEY=VALUE`
+/// strings.
+///
+/// Blank lines, and lines starting with `#` are ignored. Malformed lines are
+/// warned about, and skipped.
+fn parse_env_file_contents(contents: &str, env_file_path: &str) -> Vec<String> {
+	contents
+		.lines()
+		.enumerate()
+		.filter_map(|(line_idx, line)| {
+			let trimmed = line.trim();
+			if trimmed.is_empty() || trimmed.starts_with('#') {
+				return None;
+			}
+
+			let mut split = trimmed.splitn(2, '=');
+			let key = split.next().unwrap_or_default().trim();
+			if split.next().is_none() || key.is_empty() || key.contains(char::is_whitespace) {
+				warn!(
+					"{:?}",
+					eyre!(
+						"Line: [{}] of env file: [{}] is not a valid `KEY=VALUE` line. Will not export.",
+						line_idx + 1,
+						env_file_path,
+					)
+				);
+				return None;
+			}
+
+			Some(trimmed.to_owned())
+		})
+		.collect::<Vec<String>>()
+}
+
+fn get_env_vars_to_export(args: &HashMap<String, String>, project_root_str: &str) -> Vec<String> {
 	let mut env_vars = Vec::new();
 
+	if let Some(env_file) = args.get(ENV_FILE_ARG) {
+		let env_file_path = PathBuf::from(project_root_str).join(env_file);
+		match std::fs::read_to_string(&env_file_path) {
+			Ok(contents) => env_vars.extend(parse_env_file_contents(&contents, env_file)),
+			Err(read_err) => {
+				warn!(
+					"{:?}",
+					Err::<(), Report>(eyre!(
+						"Failed to read env file: [{:?}]: {}. Will not export any variables from it.",
+						env_file_path,
+						read_err,
+					))
+					.note("The `env_file` path should be relative to the root of the project.")
+					.unwrap_err()
+				);
+			}
+		}
+	}
+
 	if let Some(envs_to_export) = args.get(ENV_TO_EXPORT_ARG) {
-		env_vars = envs_to_export
-			.split(',')
-			.map(|the_str| {
-				env_var(the_str)
-					.map_or_else(|_| the_str.to_owned(), |val| format!("{}={}", the_str, val))
-			})
-			.collect::<Vec<String>>();
+		// Exported variables come last so they take precedence over the env file.
+		env_vars.extend(envs_to_export.split(',').map(|the_str| {
+			env_var(the_str)
+				.map_or_else(|_| the_str.to_owned(), |val| format!("{}={}", the_str, val))
+		}));
 	}
 
 	env_vars
 }
 
-fn get_extra_mounts(args: &HashMap<String, String>, project_root_str: &str) -> Vec<String> {
+/// Determine if a name is a valid docker volume name.
+fn is_valid_volume_name(name: &str) -> bool {
+	let mut chars = name.chars();
+	match chars.next() {
+		Some(first) if first.is_ascii_alphanumeric() => {}
+		_ => return false,
+	}
+	chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+fn get_extra_mounts(args: &HashMap<String, String>, project_root_str: &str) -> Vec<ExtraMount> {
 	let mut extra_mounts = Vec::new();
 
 	if let Some(mount_str_ref) = args.get(MOUNTS_ARG) {
@@ -161,6 +426,27 @@ fn get_extra_mounts(args: &HashMap<String, String>, project_root_str: &str) -> V
 			.split(',')
 			.filter_map(|item| {
 				let mounts = item.split(':').collect::<Vec<&str>>();
+				if mounts.len() == 3 && mounts[2] == "volume" {
+					if !is_valid_volume_name(mounts[0]) {
+						warn!(
+							"{:?}",
+							Err::<(), Report>(eyre!(
+								"Mount String for Docker Container: [{}] has an invalid volume name: [{}]. Will not mount.",
+								item,
+								mounts[0],
+							))
+							.note("Volume names must start with a letter or number, and only contain letters, numbers, `_`, `.`, or `-`.")
+							.unwrap_err()
+						);
+						return None;
+					}
+
+					return Some(ExtraMount {
+						source: mounts[0].to_owned(),
+						target: mounts[1].to_owned(),
+						is_volume: true,
+					});
+				}
 				if mounts.len() != 2 {
 					warn!(
 						"{:?}",
@@ -168,7 +454,7 @@ fn get_extra_mounts(args: &HashMap<String, String>, project_root_str: &str) -> V
 							"Mount String for Docker Container: [{}] is invalid, missing path for container. Will not mount.",
 							item,
 						))
-						.note("Mounts should be in the format: `host_path:path_in_container`")
+						.note("Mounts should be in the format: `host_path:path_in_container`, or `volume_name:path_in_container:volume`")
 						.unwrap_err()
 					);
 					return None;
@@ -225,14 +511,114 @@ fn get_extra_mounts(args: &HashMap<String, String>, project_root_str: &str) -> V
 					return None;
 				}
 
-					Some(format!("{}:{}", src, dest))
+					Some(ExtraMount {
+						source: src,
+						target: dest.to_owned(),
+						is_volume: false,
+					})
 				})
-				.collect::<Vec<String>>();
+				.collect::<Vec<ExtraMount>>();
 	}
 
 	extra_mounts
 }
 
+/// Get the mount for the docker socket, if the user asked for it with
+/// `mount_docker_socket`. The socket is mounted at the same path inside of
+/// the container, so tools like the docker cli find it without any changes.
+fn get_docker_socket_mount(args: &HashMap<String, String>) -> Option<ExtraMount> {
+	if !matches!(
+		args.get(MOUNT_DOCKER_SOCKET_ARG),
+		Some(mount) if mount.eq_ignore_ascii_case("true")
+	) {
+		return None;
+	}
+
+	let socket_path = if let Some(path) = SOCKET_PATH.strip_prefix("unix:") {
+		path
+	} else {
+		warn!("The docker socket can only be mounted on unix docker hosts. Will not mount.");
+		return None;
+	};
+	warn!(
+		"Mounting the docker socket: [{}] into the container, anything running in it has full control of the docker host (which is effectively root access to the host).",
+		socket_path,
+	);
+
+	Some(ExtraMount {
+		source: socket_path.to_owned(),
+		target: socket_path.to_owned(),
+		is_volume: false,
+	})
+}
+
+fn get_extra_hosts(args: &HashMap<String, String>) -> Vec<String> {
+	let mut extra_hosts = Vec::new();
+	if let Some(hosts) = args.get(EXTRA_HOSTS_ARG) {
+		extra_hosts = hosts
+			.split(',')
+			.map(str::trim)
+			.filter(|item| !item.is_empty())
+			.filter_map(|item| {
+				// IPv6 addresses contain `:` themselves, so only split on the first one.
+				let is_valid = match item.split_once(':') {
+					Some((name, address)) => {
+						!name.is_empty()
+							&& !name.contains(char::is_whitespace)
+							&& (address == "host-gateway" || address.parse::<IpAddr>().is_ok())
+					}
+					None => false,
+				};
+				if !is_valid {
+					warn!(
+						"Not adding extra host: [{}] as it is not in the format: `name:ip` (or `name:host-gateway`).",
+						item
+					);
+					return None;
+				}
+
+				Some(item.to_owned())
+			})
+			.collect::<Vec<String>>();
+	}
+
+	extra_hosts
+}
+
+fn get_labels(args: &HashMap<String, String>) -> Vec<(String, String)> {
+	let mut labels = Vec::new();
+	if let Some(labels_str) = args.get(LABELS_ARG) {
+		labels = labels_str
+			.split(',')
+			.map(str::trim)
+			.filter(|item| !item.is_empty())
+			.filter_map(|item| {
+				let (key, value) = match item.split_once('=') {
+					Some((key, value)) if !key.trim().is_empty() => (key.trim(), value.trim()),
+					_ => {
+						warn!(
+							"Not adding label: [{}] as it is not in the format: `key=value`.",
+							item
+						);
+						return None;
+					}
+				};
+				if key.starts_with(DEVLOOP_LABEL_PREFIX) {
+					warn!(
+						"Not adding label: [{}] as labels starting with: [{}] are managed by dev-loop.",
+						item, DEVLOOP_LABEL_PREFIX,
+					);
+					return None;
+				}
+
+				Some((key.to_owned(), value.to_owned()))
+			})
+			.collect::<Vec<(String, String)>>();
+	}
+
+	labels
+}
+
 fn tcp_ports_to_expose(args: &HashMap<String, String>) -> Vec<u32> {
 	let mut tcp_ports_to_expose = Vec::new();
 	if let Some(ports_to_expose) = args.get(TCP_PORTS_TO_EXPOSE_ARG) {
@@ -286,20 +672,480 @@ fn get_hostname(args: &HashMap<String, String>) -> String {
 	}
 }
 
+/// Get where the project root should be mounted inside the container.
+///
+/// The mount has to be an absolute path, and can't be (or be inside of)
+/// `/tmp` since that's where the temporary directory is mounted.
+fn get_workdir_mount(args: &HashMap<String, String>) -> String {
+	let workdir_mount = if let Some(mount) = args.get(WORKDIR_MOUNT_ARG) {
+		mount.trim()
+	} else {
+		return DEFAULT_WORKDIR_MOUNT.to_owned();
+	};
+	let normalized = workdir_mount.trim_end_matches('/');
+
+	if !workdir_mount.starts_with('/')
+		|| normalized.is_empty()
+		|| normalized == "/tmp"
+		|| normalized.starts_with("/tmp/")
+	{
+		warn!(
+			"{:?}",
+			Err::<(), Report>(eyre!(
+				"The `{}` param: [{}] for the docker executor is invalid, will use the default of: [{}].",
+				WORKDIR_MOUNT_ARG,
+				workdir_mount,
+				DEFAULT_WORKDIR_MOUNT,
+			))
+			.note(
+				"The mount must be an absolute path, other than `/`, that isn't inside of `/tmp`."
+			)
+			.unwrap_err()
+		);
+		return DEFAULT_WORKDIR_MOUNT.to_owned();
+	}
+
+	normalized.to_owned()
+}
+
+fn get_gpu_count(args: &HashMap<String, String>) -> Result<Option<i64>> {
+	let gpus = if let Some(gpus) = args.get(GPUS_ARG) {
+		gpus
+	} else {
+		return Ok(None);
+	};
+
+	if cfg!(target_os = "macos") {
+		return Err(eyre!(
+			"The docker executor was asked to pass through GPUs, but Docker for Mac does not support GPUs."
+		))
+		.suggestion("Remove the `gpus` param when running on macOS, or run on a linux host with the NVIDIA Container Toolkit installed.");
+	}
+
+	if gpus == "all" {
+		return Ok(Some(-1));
+	}
+	match gpus.parse::<i64>() {
+		Ok(count) if count > 0 => Ok(Some(count)),
+		_ => Err(eyre!(
+			"The `gpus` param: [{}] for the docker executor is invalid.",
+			gpus,
+		))
+		.suggestion("`gpus` should either be `all`, or a positive number of GPUs to pass through."),
+	}
+}
+
+/// Parse the `healthcheck_command` param.
+///
+/// # Errors
+///
+/// - When the container is removed automatically (`keep_on_failure` isn't
+///   set), see: [`ensure_container_is_kept`].
+fn get_healthcheck_command(
+	args: &HashMap<String, String>,
+	keep_on_failure: bool,
+) -> Result<Option<String>> {
+	let command = if let Some(command) = args.get(HEALTHCHECK_COMMAND_ARG) {
+		command
+	} else {
+		return Ok(None);
+	};
+	ensure_container_is_kept(HEALTHCHECK_COMMAND_ARG, command, keep_on_failure)?;
+
+	Ok(Some(command.to_owned()))
+}
+
+/// Ensure a param that only makes sense for a long lived container is only
+/// used on a container that is kept around.
+///
+/// The docker executor has no separate "reuse" mode, `keep_on_failure` is
+/// the only thing that turns off `AutoRemove` for a container, so it stands
+/// in for it here. An `AutoRemove` container is deleted the moment it stops,
+/// so docker can never restart it, and nobody is around to look at its
+/// health once the run is over.
+///
+/// # Errors
+///
+/// - When `keep_on_failure` isn't set.
+fn ensure_container_is_kept(arg: &str, value: &str, keep_on_failure: bool) -> Result<()> {
+	if keep_on_failure {
+		return Ok(());
+	}
+
+	Err(eyre!(
+		"The `{}` param: [{}] for the docker executor can only be used when `{}` is enabled.",
+		arg,
+		value,
+		KEEP_ON_FAILURE_ARG,
+	))
+	.note("Without `keep_on_failure` containers are removed as soon as they stop, so they aren't long lived.")
+	.suggestion(format!(
+		"Set `keep_on_failure` to `true` for this executor, or remove the `{}` param.",
+		arg,
+	))
+}
+
+fn get_healthcheck_timeout(args: &HashMap<String, String>) -> Duration {
+	let mut timeout_seconds = DEFAULT_HEALTHCHECK_TIMEOUT_SECONDS;
+	if let Some(timeout_str) = args.get(HEALTHCHECK_TIMEOUT_ARG) {
+		if let Ok(parsed) = timeout_str.parse::<u64>() {
+			timeout_seconds = parsed;
+		} else {
+			warn!(
+				"Healthcheck timeout: [{}] is not a valid positive number of seconds, using the default of: [{}].",
+				timeout_str,
+				DEFAULT_HEALTHCHECK_TIMEOUT_SECONDS,
+			);
+		}
+	}
+
+	Duration::from_secs(timeout_seconds)
+}
+
+/// Parse the `restart_policy` param, one of: `no`, `always`, `unless-stopped`,
+/// `on-failure`, or `on-failure:<max retries>`.
+///
+/// # Errors
+///
+/// - When the policy is not one docker understands.
+/// - When the container is removed automatically (`keep_on_failure` isn't
+///   set), since docker doesn't allow restarting those, see:
+///   [`ensure_container_is_kept`].
+fn get_restart_policy(
+	args: &HashMap<String, String>,
+	keep_on_failure: bool,
+) -> Result<Option<(String, u64)>> {
+	let policy = if let Some(policy) = args.get(RESTART_POLICY_ARG) {
+		policy.trim()
+	} else {
+		return Ok(None);
+	};
+
+	let parsed = match policy.split_once(':') {
+		Some(("on-failure", max_retries)) => max_retries
+			.parse::<u64>()
+			.ok()
+			.filter(|max_retries| *max_retries > 0)
+			.map(|max_retries| ("on-failure", max_retries)),
+		Some(_) => None,
+		None if ["no", "always", "unless-stopped", "on-failure"].contains(&policy) => {
+			Some((policy, 0))
+		}
+		None => None,
+	};
+	let (name, max_retries) = if let Some(parsed) = parsed {
+		parsed
+	} else {
+		return Err(eyre!(
+			"The `{}` param: [{}] for the docker executor is invalid.",
+			RESTART_POLICY_ARG,
+			policy,
+		))
+		.suggestion("`restart_policy` should be one of: `no`, `always`, `unless-stopped`, `on-failure`, or `on-failure:<max retries>`.");
+	};
+
+	if name != "no" {
+		ensure_container_is_kept(RESTART_POLICY_ARG, policy, keep_on_failure)?;
+	}
+
+	Ok(Some((name.to_owned(), max_retries)))
+}
+
 fn get_user(args: &HashMap<String, String>) -> String {
 	args.get(USER_ARG)
 		.map_or_else(|| "root".to_owned(), String::from)
 }
 
-fn get_proxy_user_information(args: &HashMap<String, String>) -> (Option<u32>, Option<u32>) {
-	let mut proxy_user_id = None;
-	let mut proxy_group_id = None;
-	if let Some(permission_helper_active) = args.get(PERMISSION_HELPER_ARG) {
-		if &permission_helper_active.to_ascii_lowercase() == "true" {
-			proxy_user_id = Some(users::get_effective_uid());
-			proxy_group_id = Some(users::get_effective_gid());
+/// Get the shell to run commands with inside the container, split on
+/// whitespace so it can be passed directly to docker.
+///
+/// The shell has to start with an absolute path, since it's also used as the
+/// shebang of the entrypoint.
+fn get_shell(args: &HashMap<String, String>) -> Vec<String> {
+	let default_shell = || {
+		DEFAULT_SHELL_PATH
+			.split_whitespace()
+			.map(String::from)
+			.collect()
+	};
+	let shell = if let Some(shell) = args.get(SHELL_PATH_ARG) {
+		shell.split_whitespace().map(String::from).collect::<Vec<_>>()
+	} else {
+		return default_shell();
+	};
+
+	if !shell.first().is_some_and(|path| path.starts_with('/')) {
+		warn!(
+			"{:?}",
+			Err::<(), Report>(eyre!(
+				"The `{}` param: [{}] for the docker executor is invalid, will use the default of: [{}].",
+				SHELL_PATH_ARG,
+				args.get(SHELL_PATH_ARG).unwrap(),
+				DEFAULT_SHELL_PATH,
+			))
+			.note("The shell must start with an absolute path, e.g. `/bin/sh`, or `/usr/bin/env bash`.")
+			.unwrap_err()
+		);
+		return default_shell();
+	}
+
+	shell
+}
+
+/// Get an explicitly provided id for the permission helper to use, falling
+/// back to `default` when it isn't provided (or isn't valid).
+///
+/// `args`: the executor params.
+/// `arg_name`: the name of the param with the id.
+/// `default`: the id to use when one isn't provided.
+fn get_proxy_id_override(args: &HashMap<String, String>, arg_name: &str, default: u32) -> u32 {
+	let id = if let Some(id) = args.get(arg_name) {
+		id.trim()
+	} else {
+		return default;
+	};
+
+	if let Ok(parsed) = id.parse::<u32>() {
+		parsed
+	} else {
+		warn!(
+			"The `{}` param: [{}] for the docker executor is not a valid id, using: [{}] instead.",
+			arg_name, id, default,
+		);
+		default
+	}
+}
+
+/// Get the `permission_mode` for the container: `create-user`, or
+/// `run-as-uid`. The older `experimental_permission_helper` is the same as
+/// `create-user`.
+///
+/// # Errors
+///
+/// - When the `permission_mode` is not one of the known modes.
+fn get_permission_mode(args: &HashMap<String, String>) -> Result<Option<PermissionMode>> {
+	if let Some(mode) = args.get(PERMISSION_MODE_ARG) {
+		return match mode.trim() {
+			"create-user" => Ok(Some(PermissionMode::CreateUser)),
+			"run-as-uid" => Ok(Some(PermissionMode::RunAsUid)),
+			"none" => Ok(None),
+			_ => Err(eyre!(
+				"The `{}` param: [{}] for the docker executor is invalid.",
+				PERMISSION_MODE_ARG,
+				mode,
+			))
+			.suggestion("`permission_mode` should be one of: `create-user`, `run-as-uid`, or `none`."),
+		};
+	}
+
+	Ok(match args.get(PERMISSION_HELPER_ARG) {
+		Some(active) if active.eq_ignore_ascii_case("true") => Some(PermissionMode::CreateUser),
+		_ => None,
+	})
+}
+
+/// Get the user, and group id files created in the container should be owned
+/// by, this is the effective user/group running dev-loop unless they're
+/// explicitly provided with `permission_helper_uid`/`permission_helper_gid`
+/// (e.g. in CI where the runner isn't the user that owns the files).
+///
+/// `args`: the executor params.
+/// `permission_mode`: the permission mode, no ids are used without one.
+fn get_proxy_user_information(
+	args: &HashMap<String, String>,
+	permission_mode: Option<PermissionMode>,
+) -> (Option<u32>, Option<u32>) {
+	if permission_mode.is_none() {
+		return (None, None);
+	}
+
+	(
+		Some(get_proxy_id_override(
+			args,
+			PERMISSION_HELPER_UID_ARG,
+			users::get_effective_uid(),
+		)),
+		Some(get_proxy_id_override(
+			args,
+			PERMISSION_HELPER_GID_ARG,
+			users::get_effective_gid(),
+		)),
+	)
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn parses_extra_hosts() {
+		let mut args = HashMap::new();
+		args.insert(
+			EXTRA_HOSTS_ARG.to_owned(),
+			"host.docker.internal:host-gateway, db:10.0.0.2,v6:::1,bad,:1.2.3.4,web:not-an-ip"
+				.to_owned(),
+		);
+		assert_eq!(
+			get_extra_hosts(&args),
+			vec!["host.docker.internal:host-gateway", "db:10.0.0.2", "v6:::1"],
+		);
+		assert!(get_extra_hosts(&HashMap::new()).is_empty());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn mounts_docker_socket() {
+		assert_eq!(get_docker_socket_mount(&HashMap::new()), None);
+
+		let mut args = HashMap::new();
+		args.insert(MOUNT_DOCKER_SOCKET_ARG.to_owned(), "false".to_owned());
+		assert_eq!(get_docker_socket_mount(&args), None);
+		args.insert(MOUNT_DOCKER_SOCKET_ARG.to_owned(), "true".to_owned());
+		assert_eq!(
+			get_docker_socket_mount(&args),
+			Some(ExtraMount {
+				source: "/var/run/docker.sock".to_owned(),
+				target: "/var/run/docker.sock".to_owned(),
+				is_volume: false,
+			}),
+		);
+	}
+
+	#[test]
+	fn parses_labels() {
+		let mut args = HashMap::new();
+		args.insert(
+			LABELS_ARG.to_owned(),
+			"team=infra, purpose = ci,empty=,bad,=value,dev-loop.managed=false".to_owned(),
+		);
+		assert_eq!(
+			get_labels(&args),
+			vec![
+				("team".to_owned(), "infra".to_owned()),
+				("purpose".to_owned(), "ci".to_owned()),
+				("empty".to_owned(), String::new()),
+			],
+		);
+		assert!(get_labels(&HashMap::new()).is_empty());
+	}
+
+	#[test]
+	fn parses_workdir_mount() {
+		assert_eq!(get_workdir_mount(&HashMap::new()), DEFAULT_WORKDIR_MOUNT);
+
+		let mut args = HashMap::new();
+		for (mount, expected) in &[
+			("/workspace", "/workspace"),
+			("/workspace/", "/workspace"),
+			("/", DEFAULT_WORKDIR_MOUNT),
+			("workspace", DEFAULT_WORKDIR_MOUNT),
+			("/tmp", DEFAULT_WORKDIR_MOUNT),
+			("/tmp/code", DEFAULT_WORKDIR_MOUNT),
+			("/tmpcode", "/tmpcode"),
+		] {
+			args.insert(WORKDIR_MOUNT_ARG.to_owned(), (*mount).to_owned());
+			assert_eq!(get_workdir_mount(&args), *expected);
 		}
 	}
 
-	(proxy_user_id, proxy_group_id)
+	#[test]
+	fn parses_restart_policy() {
+		assert_eq!(get_restart_policy(&HashMap::new(), false).unwrap(), None);
+
+		let mut args = HashMap::new();
+		for (policy, expected) in &[
+			("always", Some(("always", 0))),
+			("unless-stopped", Some(("unless-stopped", 0))),
+			("on-failure", Some(("on-failure", 0))),
+			("on-failure:5", Some(("on-failure", 5))),
+			("on-failure:0", None),
+			("no:5", None),
+			("sometimes", None),
+		] {
+			args.insert(RESTART_POLICY_ARG.to_owned(), (*policy).to_owned());
+			let parsed = get_restart_policy(&args, true).ok().flatten();
+			assert_eq!(
+				parsed
+					.as_ref()
+					.map(|(name, retries)| (name.as_str(), *retries)),
+				*expected
+			);
+		}
+
+		args.insert(RESTART_POLICY_ARG.to_owned(), "always".to_owned());
+		assert!(get_restart_policy(&args, false).is_err());
+		args.insert(RESTART_POLICY_ARG.to_owned(), "no".to_owned());
+		assert!(get_restart_policy(&args, false).is_ok());
+	}
+
+	#[test]
+	fn parses_healthcheck_command() {
+		assert_eq!(
+			get_healthcheck_command(&HashMap::new(), false).unwrap(),
+			None
+		);
+
+		let mut args = HashMap::new();
+		args.insert(HEALTHCHECK_COMMAND_ARG.to_owned(), "pg_isready".to_owned());
+		assert_eq!(
+			get_healthcheck_command(&args, true).unwrap(),
+			Some("pg_isready".to_owned())
+		);
+		assert!(get_healthcheck_command(&args, false).is_err());
+	}
+
+	#[test]
+	fn parses_shell() {
+		assert_eq!(get_shell(&HashMap::new()), vec!["/usr/bin/env", "bash"]);
+
+		let mut args = HashMap::new();
+		args.insert(SHELL_PATH_ARG.to_owned(), " /bin/sh ".to_owned());
+		assert_eq!(get_shell(&args), vec!["/bin/sh"]);
+		args.insert(SHELL_PATH_ARG.to_owned(), "sh".to_owned());
+		assert_eq!(get_shell(&args), vec!["/usr/bin/env", "bash"]);
+		args.insert(SHELL_PATH_ARG.to_owned(), "".to_owned());
+		assert_eq!(get_shell(&args), vec!["/usr/bin/env", "bash"]);
+	}
+
+	#[test]
+	fn parses_proxy_user_information() {
+		let mut args = HashMap::new();
+		args.insert(PERMISSION_HELPER_UID_ARG.to_owned(), "1001".to_owned());
+		assert_eq!(get_permission_mode(&args).unwrap(), None);
+		assert_eq!(get_proxy_user_information(&args, None), (None, None));
+
+		args.insert(PERMISSION_HELPER_ARG.to_owned(), "true".to_owned());
+		args.insert(PERMISSION_HELPER_GID_ARG.to_owned(), "not-a-gid".to_owned());
+		assert_eq!(
+			get_permission_mode(&args).unwrap(),
+			Some(PermissionMode::CreateUser)
+		);
+		assert_eq!(
+			get_proxy_user_information(&args, Some(PermissionMode::CreateUser)),
+			(Some(1001), Some(users::get_effective_gid()))
+		);
+
+		args.insert(PERMISSION_MODE_ARG.to_owned(), "run-as-uid".to_owned());
+		assert_eq!(
+			get_permission_mode(&args).unwrap(),
+			Some(PermissionMode::RunAsUid)
+		);
+		args.insert(PERMISSION_MODE_ARG.to_owned(), "none".to_owned());
+		assert_eq!(get_permission_mode(&args).unwrap(), None);
+		args.insert(PERMISSION_MODE_ARG.to_owned(), "root".to_owned());
+		assert!(get_permission_mode(&args).is_err());
+	}
+
+	#[test]
+	fn parses_env_files() {
+		let contents = "# A comment\n\nFOO=bar\n  BAZ=a=b  \nnot a valid line\n=novalue\nEMPTY=\n";
+		assert_eq!(
+			parse_env_file_contents(contents, ".env"),
+			vec![
+				"FOO=bar".to_owned(),
+				"BAZ=a=b".to_owned(),
+				"EMPTY=".to_owned()
+			],
+		);
+	}
 }
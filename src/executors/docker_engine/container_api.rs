@@ -1,7 +1,7 @@
 use super::{
 	docker_api_delete, docker_api_get, docker_api_post, download_image,
-	execute_command_in_container, get_command_exit_code, setup_permission_helper,
-	DockerContainerInfo,
+	execute_command_in_container, get_command_exit_code, label_filter_query,
+	setup_permission_helper, DockerContainerInfo, MANAGED_LABEL, PIPELINE_ID_LABEL,
 };
 
 use color_eyre::{
@@ -10,51 +10,75 @@ use color_eyre::{
 };
 use isahc::HttpClient;
 use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+use tracing::info;
 
 static CONTAINER_CREATION_LOCK: Lazy<async_std::sync::Mutex<()>> =
 	Lazy::new(|| async_std::sync::Mutex::new(()));
 
-/// List all the devloop containers.
-pub async fn list_devloop_containers(client: &HttpClient) -> Result<Vec<String>> {
-	let resp = docker_api_get(
+/// Get the name of a container from the docker API, preferring the one
+/// dev-loop gave it.
+fn get_container_name(container: &serde_json::Value) -> Option<String> {
+	let names = container.get("Names")?.as_array()?;
+	names
+		.iter()
+		.filter_map(serde_json::Value::as_str)
+		.find(|name| name.starts_with("/dl-"))
+		.or_else(|| names.first().and_then(serde_json::Value::as_str))
+		.map(String::from)
+}
+
+/// List all the devloop containers, along with when they were created (in
+/// epoch seconds) if docker told us.
+///
+/// Containers are found by the `dev-loop.managed` label, so renamed ones are
+/// still found. Unless `labeled_only` is set, containers whose name starts
+/// with `dl-` are also included, since containers created by older versions
+/// of dev-loop don't have any labels.
+pub async fn list_devloop_containers(
+	client: &HttpClient,
+	labeled_only: bool,
+) -> Result<Vec<(String, Option<u64>)>> {
+	let labeled = docker_api_get(
 		client,
-		"/containers/json?all=true",
-		"Taking awhile to query containers from docker. Will wait up to 30 seconds.".to_owned(),
+		&format!(
+			"/containers/json?all=true&{}",
+			label_filter_query(&format!("{}=true", MANAGED_LABEL))
+		),
+		"Taking awhile to query containers from docker.".to_owned(),
 		None,
 		true,
 	)
 	.await?;
 	let mut container_names = Vec::new();
 
-	if let Some(containers) = resp.as_array() {
-		for container in containers {
-			let names_opt = container.get("Names");
-			if names_opt.is_none() {
-				continue;
-			}
-
-			let names_untyped = names_opt.unwrap();
-			let names_typed_opt = names_untyped.as_array();
-			if names_typed_opt.is_none() {
-				continue;
-			}
-			let names = names_typed_opt.unwrap();
-
-			let mut dl_name = String::new();
-			for name in names {
-				if let Some(name_str) = name.as_str() {
-					if name_str.starts_with("/dl-") {
-						dl_name = name_str.to_owned();
-					}
-				}
-			}
-
-			if dl_name.is_empty() {
-				continue;
-			}
+	for container in labeled.as_array().into_iter().flatten() {
+		if let Some(name) = get_container_name(container) {
+			container_names.push((name, container.get("Created").and_then(|c| c.as_u64())));
+		}
+	}
+	if labeled_only {
+		return Ok(container_names);
+	}
 
-			container_names.push(dl_name);
+	let resp = docker_api_get(
+		client,
+		"/containers/json?all=true",
+		"Taking awhile to query containers from docker.".to_owned(),
+		None,
+		true,
+	)
+	.await?;
+	for container in resp.as_array().into_iter().flatten() {
+		let dl_name = match get_container_name(container) {
+			Some(name) if name.starts_with("/dl-") => name,
+			_ => continue,
+		};
+		if container_names.iter().any(|(name, _)| *name == dl_name) {
+			continue;
 		}
+
+		container_names.push((dl_name, container.get("Created").and_then(|c| c.as_u64())));
 	}
 
 	Ok(container_names)
@@ -64,8 +88,7 @@ pub async fn delete_container(client: &HttpClient, container_name: &str) {
 	let _ = docker_api_post(
 		client,
 		&format!("/containers{}/kill", container_name),
-		"Docker is not killing the container in a timely manner. Will wait up to 30 seconds."
-			.to_owned(),
+		"Docker is not killing the container in a timely manner.".to_owned(),
 		None,
 		None,
 		false,
@@ -74,7 +97,7 @@ pub async fn delete_container(client: &HttpClient, container_name: &str) {
 	let _ = docker_api_delete(
 		&client,
 		&format!("/containers{}?v=true&force=true&link=true", container_name),
-		"Docker is taking awhile to remove the container. Will wait up to 30 seconds.".to_owned(),
+		"Docker is taking awhile to remove the container.".to_owned(),
 		None,
 		None,
 		false,
@@ -82,7 +105,8 @@ pub async fn delete_container(client: &HttpClient, container_name: &str) {
 	.await;
 }
 
-/// Determine if the container is created, and then if it's wondering.
+/// Determine if the container is created, if it's running, and it's health
+/// status (if the container has a healthcheck).
 ///
 /// # Errors
 ///
@@ -90,17 +114,17 @@ pub async fn delete_container(client: &HttpClient, container_name: &str) {
 async fn is_container_created_and_running(
 	client: &HttpClient,
 	container_name: &str,
-) -> Result<(bool, bool)> {
+) -> Result<(bool, bool, Option<String>)> {
 	let url = format!("/containers/{}/json?size=false", container_name);
 	let mut is_created = false;
 	let mut is_running = false;
+	let mut health_status = None;
 
 	// Ignore errors since a 404 for no container is an Error.
 	if let Ok(value) = docker_api_get(
 		client,
 		&url,
-		"Taking awhile to query container status from docker. Will wait up to 30 seconds."
-			.to_owned(),
+		"Taking awhile to query container status from docker.".to_owned(),
 		None,
 		true,
 	)
@@ -111,13 +135,74 @@ async fn is_container_created_and_running(
 		if is_running_status.is_boolean() {
 			is_running = is_running_status.as_bool().unwrap();
 		}
+		health_status = value["State"]["Health"]["Status"]
+			.as_str()
+			.map(String::from);
 	}
 
-	Ok((is_created, is_running))
+	Ok((is_created, is_running, health_status))
+}
+
+/// Wait for a container to report itself as healthy.
+///
+/// Containers without a healthcheck are considered ready immediately.
+///
+/// # Errors
+///
+/// Errors when the docker api cannot be talked to, or the container does not
+/// become healthy before the timeout.
+async fn wait_for_container_healthy(
+	client: &HttpClient,
+	container: &DockerContainerInfo,
+) -> Result<()> {
+	let timeout = container.get_healthcheck_timeout();
+	let start = Instant::now();
+	let mut reported_waiting = false;
+
+	loop {
+		let (_, _, health_status) =
+			is_container_created_and_running(client, container.get_container_name()).await?;
+		let status = match health_status {
+			// No healthcheck, nothing to wait for.
+			None => return Ok(()),
+			Some(status) => status,
+		};
+		if status == "healthy" {
+			return Ok(());
+		}
+
+		if start.elapsed() >= timeout {
+			return Err(eyre!(
+				"Docker Container: [{}] did not become healthy within: [{}] seconds, last status was: [{}]",
+				container.get_container_name(),
+				timeout.as_secs(),
+				status,
+			))
+			.suggestion("If the container just needs more time, raise the `healthcheck_timeout_seconds` param for this executor.")
+			.note(format!(
+				"You can inspect the healthcheck results with: `docker inspect --format '{{{{json .State.Health}}}}' {}`",
+				container.get_container_name()
+			));
+		}
+
+		if !reported_waiting {
+			info!(
+				"Waiting for Docker Container: [{}] to become healthy. Will wait up to {} seconds.",
+				container.get_container_name(),
+				timeout.as_secs(),
+			);
+			reported_waiting = true;
+		}
+		async_std::task::sleep(Duration::from_secs(1)).await;
+	}
 }
 
 /// Creates the container, should only be called when it does not yet exist.
 ///
+/// The container is labeled as managed by dev-loop (along with the pipeline
+/// it was created for if there is one), on top of any labels the user asked
+/// for.
+///
 /// # Errors
 ///
 /// Errors when the docker socket cannot be talked too, or there is a conflict
@@ -127,11 +212,12 @@ async fn create_container(
 	project_root: &str,
 	tmp_dir: &str,
 	docker_container: &DockerContainerInfo,
+	pipeline_id: Option<&str>,
 ) -> Result<()> {
 	let mut mounts = Vec::new();
 	mounts.push(serde_json::json!({
 		"Source": project_root,
-		"Target": "/mnt/dl-root",
+		"Target": docker_container.get_workdir_mount(),
 		"Type": "bind",
 		"Consistency": "consistent",
 	}));
@@ -142,13 +228,10 @@ async fn create_container(
 		"Consistency": "consistent",
 	}));
 	for emount in docker_container.get_extra_mounts() {
-		let mut split = emount.split(':');
-		let source: &str = split.next().unwrap();
-		let target: &str = split.next().unwrap();
 		mounts.push(serde_json::json!({
-			"Source": source,
-			"Target": target,
-			"Type": "bind",
+			"Source": emount.get_source(),
+			"Target": emount.get_target(),
+			"Type": emount.get_mount_type(),
 			"Consistency": "consistent",
 		}));
 	}
@@ -171,46 +254,155 @@ async fn create_container(
 		);
 	}
 
+	let mut labels = serde_json::map::Map::<String, serde_json::Value>::new();
+	for (key, value) in docker_container.get_labels() {
+		labels.insert(key.clone(), serde_json::json!(value));
+	}
+	labels.insert(MANAGED_LABEL.to_owned(), serde_json::json!("true"));
+	if let Some(pipeline_id) = pipeline_id {
+		labels.insert(PIPELINE_ID_LABEL.to_owned(), serde_json::json!(pipeline_id));
+	}
+
 	let url = format!(
 		"/containers/create?name={}",
 		docker_container.get_container_name()
 	);
-	let body = serde_json::json!({
+	let mut body = serde_json::json!({
 		"Cmd": ["tail", "-f", "/dev/null"],
 		"Entrypoint": "",
 		"Image": docker_container.get_image(),
 		"Hostname": docker_container.get_hostname(),
 		"User": docker_container.get_base_user(),
 		"HostConfig": {
-			"AutoRemove": true,
+			"AutoRemove": !docker_container.should_keep_on_failure(),
 			"Mounts": mounts,
 			"Privileged": true,
 			"PortBindings": host_config_mapping,
 		},
-		"WorkingDir": "/mnt/dl-root",
+		"WorkingDir": docker_container.get_workdir_mount(),
 		"AttachStdout": true,
 		"AttachStderr": true,
 		"Privileged": true,
 		"Tty": true,
 		"ExposedPorts": port_mapping,
+		"Labels": labels,
 	});
-	let _ = docker_api_post(
+	if let Some(network_mode) = docker_container.get_network_mode() {
+		body["HostConfig"]["NetworkMode"] = serde_json::json!(network_mode);
+		// Docker doesn't allow setting a hostname when sharing the hosts network.
+		if network_mode == "host" {
+			if let Some(body_map) = body.as_object_mut() {
+				body_map.remove("Hostname");
+			}
+		}
+	}
+	if !docker_container.get_extra_hosts().is_empty() {
+		body["HostConfig"]["ExtraHosts"] = serde_json::json!(docker_container.get_extra_hosts());
+	}
+	if let Some(gpu_count) = docker_container.get_gpu_count() {
+		body["HostConfig"]["DeviceRequests"] = serde_json::json!([{
+			"Driver": "nvidia",
+			"Count": gpu_count,
+			"Capabilities": [["gpu"]],
+		}]);
+	}
+	if let Some((restart_policy, max_retries)) = docker_container.get_restart_policy() {
+		body["HostConfig"]["RestartPolicy"] = serde_json::json!({
+			"Name": restart_policy,
+			"MaximumRetryCount": max_retries,
+		});
+	}
+	if let Some(healthcheck_command) = docker_container.get_healthcheck_command() {
+		body["Healthcheck"] = serde_json::json!({
+			"Test": ["CMD-SHELL", healthcheck_command],
+			// Docker expects these in nanoseconds.
+			"Interval": 1_000_000_000_u64,
+			"Retries": 3,
+		});
+	}
+	let mut create_res = docker_api_post(
 		client,
 		&url,
-		"Docker is not creating the container in a timely manner. Will wait up to 30 seconds."
-			.to_owned(),
+		"Docker is not creating the container in a timely manner.".to_owned(),
 		Some(body),
 		None,
 		false,
 	)
 	.await
-	.wrap_err("Failed to create the docker container")?;
+	.wrap_err("Failed to create the docker container");
+	if docker_container.get_gpu_count().is_some() {
+		create_res = create_res
+			.note("This container requests GPUs, which requires an NVIDIA GPU, and the NVIDIA Container Toolkit to be installed on the docker host.");
+	}
+	create_res?;
+
+	Ok(())
+}
+
+/// Ensure every tool is available inside of the container, by running
+/// `hash <tool>` for each of them.
+///
+/// `tools`: the names of the tools to look for.
+///
+/// # Errors
+///
+/// If we cannot talk to the docker socket, or any of the tools are missing.
+pub async fn ensure_tools_in_container(
+	client: &HttpClient,
+	container: &DockerContainerInfo,
+	tools: &[String],
+) -> Result<()> {
+	let mut missing_tools = Vec::new();
+	for tool in tools {
+		// The tool is passed as an argument so it never needs to be escaped.
+		let execution_id = execute_command_in_container(
+			client,
+			container.get_container_name(),
+			&container
+				.get_shell_command("hash \"$1\"".to_owned())
+				.into_iter()
+				.chain(vec!["dev-loop".to_owned(), tool.clone()])
+				.collect::<Vec<_>>(),
+			&[],
+			container.get_base_user(),
+			false,
+			None,
+			None,
+		)
+		.await
+		.wrap_err(format!(
+			"Failed to check for existance of: [{}] in Docker container",
+			tool
+		))?;
+
+		if get_command_exit_code(client, &execution_id).await? != 0 {
+			missing_tools.push(tool.as_str());
+		}
+	}
+
+	if !missing_tools.is_empty() {
+		return Err(eyre!(
+			"Docker Image: [{}] does not have the tools: [{}], but they are listed in the executor's `provides`!",
+			container.get_image(),
+			missing_tools.join(", "),
+		))
+		.note(format!(
+			"To replicate you can run: `docker run --rm -it {} {} -c \"hash {}\"`",
+			container.get_image(),
+			container.get_shell_path(),
+			missing_tools[0],
+		))
+		.suggestion("Install the tools in the image, or remove them from `provides` (or turn off `verify_provides` if they aren't commands).");
+	}
 
 	Ok(())
 }
 
 /// Ensure the docker container exists.
 ///
+/// `pipeline_id`: the pipeline the container is needed for, if any. This is
+///                only used to label the container when it gets created.
+///
 /// # Errors
 ///
 /// If we cannot talk to the docker socket, or there is a conflict creating the container.
@@ -219,15 +411,15 @@ pub async fn ensure_docker_container(
 	project_root: &str,
 	tmp_dir: &str,
 	container: &DockerContainerInfo,
+	pipeline_id: Option<&str>,
 ) -> Result<()> {
-	let _guard = CONTAINER_CREATION_LOCK.lock().await;
+	let guard = CONTAINER_CREATION_LOCK.lock().await;
 
 	let image_exists_url = format!("/images/{}/json", container.get_image());
 	let image_exists = docker_api_get(
 		client,
 		&image_exists_url,
-		"Taking awhile to query if image is downloaded from docker. Will wait up to 30 seconds."
-			.to_owned(),
+		"Taking awhile to query if image is downloaded from docker.".to_owned(),
 		None,
 		false,
 	)
@@ -238,11 +430,11 @@ pub async fn ensure_docker_container(
 	if !image_exists {
 		download_image(client, container.get_image()).await?;
 	}
-	let (container_exists, container_running) =
+	let (container_exists, container_running, _) =
 		is_container_created_and_running(client, container.get_container_name()).await?;
 
 	if !container_exists {
-		create_container(client, project_root, tmp_dir, container).await?;
+		create_container(client, project_root, tmp_dir, container, pipeline_id).await?;
 	}
 
 	if !container_running {
@@ -250,8 +442,7 @@ pub async fn ensure_docker_container(
 		let _ = docker_api_post(
 			client,
 			&url,
-			"Docker is taking awhile to start the container. Will wait up to 30 seconds."
-				.to_owned(),
+			"Docker is taking awhile to start the container.".to_owned(),
 			None,
 			None,
 			false,
@@ -263,12 +454,7 @@ pub async fn ensure_docker_container(
 	let execution_id = execute_command_in_container(
 		client,
 		container.get_container_name(),
-		&[
-			"/usr/bin/env".to_owned(),
-			"bash".to_owned(),
-			"-c".to_owned(),
-			"hash bash".to_owned(),
-		],
+		&container.get_shell_command("true".to_owned()),
 		&[],
 		container.get_base_user(),
 		false,
@@ -276,18 +462,26 @@ pub async fn ensure_docker_container(
 		None,
 	)
 	.await
-	.wrap_err("Failed to check for existance of bash in Docker container")?;
+	.wrap_err(format!(
+		"Failed to check for existance of the shell: [{}] in Docker container",
+		container.get_shell_path()
+	))?;
 
-	let has_bash = get_command_exit_code(client, &execution_id).await?;
-	if has_bash != 0 {
+	let has_shell = get_command_exit_code(client, &execution_id).await?;
+	if has_shell != 0 {
 		return Err(eyre!(
-			"Docker Image: [{}] does not have bash! This is required for dev-loop!",
+			"Docker Image: [{}] does not have the shell: [{}]! This is required for dev-loop!",
 			container.get_image(),
+			container.get_shell_path(),
 		))
 		.note(format!(
-			"To replicate you can run: `docker run --rm -it {} /usr/bin/env bash -c \"hash bash\"`",
-			container.get_image()
+			"To replicate you can run: `docker run --rm -it {} {} -c true`",
+			container.get_image(),
+			container.get_shell_path(),
 		))
+		.suggestion(
+			"If the image has a shell somewhere else, set the `shell_path` param of the executor.",
+		)
 		.note(format!(
 			"The container is also still running with the name: [{}]",
 			container.get_container_name()
@@ -295,6 +489,10 @@ pub async fn ensure_docker_container(
 	}
 
 	setup_permission_helper(client, container).await?;
+	// Waiting on a healthcheck can take awhile, don't block other containers
+	// from being created while we wait.
+	drop(guard);
+	wait_for_container_healthy(client, container).await?;
 
 	Ok(())
 }
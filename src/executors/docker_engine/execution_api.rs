@@ -49,8 +49,7 @@ pub async fn execute_command_in_container_async(
 	let resp = docker_api_post(
 		client,
 		&url,
-		"Docker is taking awhile to start running a new command. Will wait up to 30 seconds."
-			.to_owned(),
+		"Docker is taking awhile to start running a new command.".to_owned(),
 		Some(body),
 		None,
 		true,
@@ -76,8 +75,7 @@ pub async fn execute_command_in_container_async(
 	let _ = docker_api_post(
 		client,
 		&start_url,
-		"Docker is taking awhile to start running a new command. Will wait up to 30 seconds."
-			.to_owned(),
+		"Docker is taking awhile to start running a new command.".to_owned(),
 		Some(start_body),
 		None,
 		false,
@@ -94,10 +92,11 @@ pub async fn has_command_finished(client: &HttpClient, execution_id: &str) -> bo
 	let resp_res = docker_api_get(
 		client,
 		&url,
-		"Taking awhile to determine if command has finished running in docker. Will wait up to 30 seconds.".to_owned(),
+		"Taking awhile to determine if command has finished running in docker.".to_owned(),
 		None,
-		true
-	).await;
+		true,
+	)
+	.await;
 	if resp_res.is_err() {
 		return false;
 	}
@@ -159,8 +158,7 @@ pub async fn get_command_exit_code(client: &HttpClient, execution_id: &str) -> R
 	let resp = docker_api_get(
 		client,
 		&url,
-		"Taking awhile to query exit code of command from docker. Will wait up to 30 seconds."
-			.to_owned(),
+		"Taking awhile to query exit code of command from docker.".to_owned(),
 		None,
 		true,
 	)
@@ -4,18 +4,25 @@ use color_eyre::{eyre::WrapErr, Result};
 use isahc::HttpClient;
 use std::time::Duration;
 
+/// Split an image into it's name, and tag (defaulting to `latest`).
+///
+/// Only a `:` after the last `/` separates the tag, so a registry with a port
+/// (e.g. `localhost:5000/image`) isn't mistaken for a tag.
+fn split_image_tag(image: &str) -> (&str, &str) {
+	let name_start = image.rfind('/').map_or(0, |idx| idx + 1);
+	match image[name_start..].rfind(':') {
+		Some(idx) => (&image[..name_start + idx], &image[name_start + idx + 1..]),
+		None => (image, "latest"),
+	}
+}
+
 /// Download the Image for this docker executor.
 ///
 /// # Errors
 ///
 /// Errors when it the docker api cannot be talked too, or the image cannot be downloaded.
 pub async fn download_image(client: &HttpClient, image: &str) -> Result<()> {
-	let image_tag_split = image.rsplitn(2, ':').collect::<Vec<&str>>();
-	let (image_name, tag_name) = if image_tag_split.len() == 2 {
-		(image_tag_split[1], image_tag_split[0])
-	} else {
-		(image_tag_split[0], "latest")
-	};
+	let (image_name, tag_name) = split_image_tag(image);
 	let url = format!("/images/create?fromImage={}&tag={}", image_name, tag_name);
 
 	let _ = docker_api_post(
@@ -37,3 +44,22 @@ pub async fn download_image(client: &HttpClient, image: &str) -> Result<()> {
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn splits_image_tags() {
+		assert_eq!(split_image_tag("ubuntu"), ("ubuntu", "latest"));
+		assert_eq!(split_image_tag("ubuntu:20.04"), ("ubuntu", "20.04"));
+		assert_eq!(
+			split_image_tag("localhost:5000/team/image"),
+			("localhost:5000/team/image", "latest")
+		);
+		assert_eq!(
+			split_image_tag("localhost:5000/team/image:v1"),
+			("localhost:5000/team/image", "v1")
+		);
+	}
+}
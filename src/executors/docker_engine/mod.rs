@@ -4,13 +4,13 @@ use crate::future_helper::timeout_with_log_msg;
 
 use color_eyre::{
 	eyre::{eyre, WrapErr},
-	Result, Section,
+	Report, Result, Section,
 };
 use isahc::{http::request::Request, prelude::*, Body, HttpClient};
 use once_cell::sync::Lazy;
 use serde_json::Value as JsonValue;
 use std::time::Duration;
-use tracing::debug;
+use tracing::{debug, warn};
 
 /// This is the api version we use for talking to the docker socket.
 ///
@@ -26,8 +26,104 @@ use tracing::debug;
 /// <https://success.docker.com/article/compatibility-matrix>
 ///
 /// We can bump this in the future when we know it won't run into anyone.
-const DOCKER_API_VERSION: &str = "/v1.40";
-const DOCKER_STATUS_CODES_ERR_NOTE: &str = "To find out what the status code means you can check the Docker documentation: https://docs.docker.com/engine/api/v1.40/.";
+///
+/// Advanced users can opt into a newer version with `DL_DOCKER_API_VERSION`.
+const DEFAULT_DOCKER_API_VERSION: &str = "v1.40";
+
+/// The docker API version to use, `DL_DOCKER_API_VERSION` if it's set to
+/// a valid version, otherwise `DEFAULT_DOCKER_API_VERSION`.
+static DOCKER_API_VERSION: Lazy<String> =
+	Lazy::new(|| match std::env::var("DL_DOCKER_API_VERSION") {
+		Ok(requested_version) => match parse_docker_api_version(&requested_version) {
+			Ok(version) => version,
+			Err(version_err) => {
+				warn!(
+					"{:?}",
+					version_err.note(format!(
+						"Will use the default docker API version: [{}]",
+						DEFAULT_DOCKER_API_VERSION,
+					))
+				);
+				DEFAULT_DOCKER_API_VERSION.to_owned()
+			}
+		},
+		Err(_) => DEFAULT_DOCKER_API_VERSION.to_owned(),
+	});
+
+/// Validate a user provided docker API version looks like: `vX.YY`.
+///
+/// The leading `v` is optional, and will be added if it's missing.
+///
+/// # Errors
+///
+/// If the version isn't a major, and minor version number separated by `.`.
+fn parse_docker_api_version(version: &str) -> Result<String> {
+	let trimmed = version.trim();
+	let without_v = trimmed.strip_prefix('v').unwrap_or(trimmed);
+	let is_plausible = match without_v.split_once('.') {
+		Some((major, minor)) => {
+			!major.is_empty()
+				&& major.len() <= 2
+				&& major.chars().all(|character| character.is_ascii_digit())
+				&& !minor.is_empty()
+				&& minor.len() <= 3
+				&& minor.chars().all(|character| character.is_ascii_digit())
+		}
+		None => false,
+	};
+
+	if is_plausible {
+		Ok(format!("v{}", without_v))
+	} else {
+		Err(eyre!(
+			"The docker API version: [{}] in `DL_DOCKER_API_VERSION` is not valid.",
+			version,
+		))
+		.suggestion("Docker API versions look like: `v1.41`.")
+	}
+}
+
+/// How long a call to docker can take when no explicit timeout is given.
+///
+/// Users behind a slow proxy can change this with `DL_DOCKER_TIMEOUT_SECS`.
+const DEFAULT_DOCKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The timeout for docker calls without an explicit timeout,
+/// `DL_DOCKER_TIMEOUT_SECS` if it's set to a valid timeout, otherwise
+/// `DEFAULT_DOCKER_TIMEOUT`.
+static DOCKER_TIMEOUT: Lazy<Duration> =
+	Lazy::new(|| match std::env::var("DL_DOCKER_TIMEOUT_SECS") {
+		Ok(requested_timeout) => match parse_docker_timeout(&requested_timeout) {
+			Ok(timeout) => timeout,
+			Err(timeout_err) => {
+				warn!(
+					"{:?}",
+					timeout_err.note(format!(
+						"Will use the default docker timeout of: [{}] seconds",
+						DEFAULT_DOCKER_TIMEOUT.as_secs(),
+					))
+				);
+				DEFAULT_DOCKER_TIMEOUT
+			}
+		},
+		Err(_) => DEFAULT_DOCKER_TIMEOUT,
+	});
+
+/// Validate a user provided docker timeout is a positive number of seconds.
+///
+/// # Errors
+///
+/// If the timeout is not a number, or is zero.
+fn parse_docker_timeout(timeout: &str) -> Result<Duration> {
+	match timeout.trim().parse::<u64>() {
+		Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+		_ => Err(eyre!(
+			"The docker timeout: [{}] in `DL_DOCKER_TIMEOUT_SECS` is not valid.",
+			timeout,
+		))
+		.suggestion("The docker timeout should be a positive number of seconds, like: `60`."),
+	}
+}
 
 cfg_if::cfg_if! {
   if #[cfg(unix)] {
@@ -47,6 +143,53 @@ cfg_if::cfg_if! {
 static DOCK_SOCK_LOCK: Lazy<async_std::sync::Mutex<()>> =
 	Lazy::new(|| async_std::sync::Mutex::new(()));
 
+/// How many times to retry a GET request to docker that failed in a way that
+/// is most likely temporary.
+const DOCKER_GET_RETRIES: u32 = 3;
+/// How long to wait before the first retry, this doubles for every retry.
+const DOCKER_GET_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Docker responded with a non successful status code.
+#[derive(Debug)]
+struct DockerStatusError(u16);
+
+impl std::fmt::Display for DockerStatusError {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			formatter,
+			"Docker responded with a status code: [{}] which is not in the 200-300 range.",
+			self.0,
+		)
+	}
+}
+
+impl std::error::Error for DockerStatusError {}
+
+/// Determine if an error talking to docker is most likely temporary, e.g.
+/// we couldn't connect, or docker had an internal error.
+fn is_transient_docker_error(err: &Report) -> bool {
+	if let Some(status_err) = err.downcast_ref::<DockerStatusError>() {
+		return status_err.0 >= 500;
+	}
+
+	matches!(
+		err.downcast_ref::<isahc::Error>(),
+		Some(isahc::Error::ConnectFailed) | Some(isahc::Error::Io(_))
+	)
+}
+
+/// Get the `message` out of an error response body from docker.
+///
+/// `body`: the raw body docker responded with.
+fn get_docker_error_message(body: &str) -> Option<String> {
+	serde_json::from_str::<JsonValue>(body)
+		.ok()?
+		.get("message")?
+		.as_str()
+		.map(|message| message.trim().to_owned())
+		.filter(|message| !message.is_empty())
+}
+
 async fn docker_api_call<B: Into<Body>>(
 	client: &HttpClient,
 	req: Request<B>,
@@ -58,7 +201,18 @@ async fn docker_api_call<B: Into<Body>>(
 	let uri = req.uri().to_string();
 
 	let log_timeout = Duration::from_secs(3);
-	let timeout_frd = timeout.unwrap_or_else(|| Duration::from_secs(30));
+	let (timeout_frd, long_call_msg) = if let Some(explicit_timeout) = timeout {
+		(explicit_timeout, long_call_msg)
+	} else {
+		(
+			*DOCKER_TIMEOUT,
+			format!(
+				"{} Will wait up to {} seconds.",
+				long_call_msg,
+				DOCKER_TIMEOUT.as_secs()
+			),
+		)
+	};
 	let mut resp = timeout_with_log_msg(
 		long_call_msg.clone(),
 		log_timeout,
@@ -69,11 +223,30 @@ async fn docker_api_call<B: Into<Body>>(
 
 	let status = resp.status().as_u16();
 	if status < 200 || status > 299 {
-		return Err(eyre!(
-			"Docker responded with a status code: [{}] which is not in the 200-300 range.",
-			status,
+		// Docker returns a JSON body like: `{"message": "..."}` explaining what
+		// went wrong, this is best effort since it's purely to help debug.
+		let docker_message = timeout_with_log_msg(
+			long_call_msg.clone(),
+			log_timeout,
+			timeout_frd,
+			resp.text_async(),
+		)
+		.await
+		.ok()
+		.and_then(|body_res| body_res.ok())
+		.and_then(|body| get_docker_error_message(&body));
+
+		let err: Result<JsonValue> = Err(DockerStatusError(status).into());
+		let err = if let Some(message) = docker_message {
+			err.note(format!("Docker said: [{}]", message))
+		} else {
+			err
+		};
+		return err
+		.note(format!(
+			"To find out what the status code means you can check the Docker documentation: https://docs.docker.com/engine/api/{}/.",
+			*DOCKER_API_VERSION,
 		))
-		.note(DOCKER_STATUS_CODES_ERR_NOTE)
 		.context(uri);
 	}
 
@@ -106,8 +279,11 @@ async fn docker_api_call<B: Into<Body>>(
 /// `client`: the http client to use.
 /// `path`: the path to call (along with Query Args).
 /// `long_call_msg`: the message to print when docker is taking awhile to respond.
-/// `timeout`: The optional timeout. Defaults to 30 seconds.
+/// `timeout`: The optional timeout. Defaults to `DL_DOCKER_TIMEOUT_SECS`, or 30 seconds.
 /// `is_json`: whether or not to parse the response as json.
+///
+/// Since GETs are idempotent, failing to connect, or a 5xx response from
+/// docker is retried a few times with a short backoff.
 pub(self) async fn docker_api_get(
 	client: &HttpClient,
 	path: &str,
@@ -115,18 +291,72 @@ pub(self) async fn docker_api_get(
 	timeout: Option<Duration>,
 	is_json: bool,
 ) -> Result<JsonValue> {
-	let url = format!("http://localhost{}{}", DOCKER_API_VERSION, path);
+	docker_api_get_with_retries(
+		client,
+		path,
+		long_call_msg,
+		timeout,
+		is_json,
+		DOCKER_GET_RETRIES,
+	)
+	.await
+}
+
+/// Call the docker engine api using the GET http method, without retrying.
+///
+/// This is useful for checking if docker is running at all, where retrying
+/// would only slow down finding out that it isn't.
+///
+/// `client`: the http client to use.
+/// `path`: the path to call (along with Query Args).
+/// `long_call_msg`: the message to print when docker is taking awhile to respond.
+/// `timeout`: The optional timeout. Defaults to `DL_DOCKER_TIMEOUT_SECS`, or 30 seconds.
+/// `is_json`: whether or not to parse the response as json.
+async fn docker_api_get_once(
+	client: &HttpClient,
+	path: &str,
+	long_call_msg: String,
+	timeout: Option<Duration>,
+	is_json: bool,
+) -> Result<JsonValue> {
+	docker_api_get_with_retries(client, path, long_call_msg, timeout, is_json, 0).await
+}
+
+/// Call the docker engine api using the GET http method, retrying transient
+/// failures up to `retries` times.
+async fn docker_api_get_with_retries(
+	client: &HttpClient,
+	path: &str,
+	long_call_msg: String,
+	timeout: Option<Duration>,
+	is_json: bool,
+	retries: u32,
+) -> Result<JsonValue> {
+	let url = format!("http://localhost/{}{}", *DOCKER_API_VERSION, path);
 	debug!("URL for get will be: {}", url);
-	let req = Request::get(url)
-		.header("Accept", "application/json; charset=UTF-8")
-		.header("Content-Type", "application/json; charset=UTF-8")
-		.body(())
-		.wrap_err("Internal-Error: Failed to construct http request.")
-		.suggestion("Please report this as an issue so it can be fixed.")?;
 
-	docker_api_call(client, req, long_call_msg, timeout, is_json)
-		.await
-		.context(format!("URL: {}", path))
+	let mut attempt = 0;
+	loop {
+		let req = Request::get(url.clone())
+			.header("Accept", "application/json; charset=UTF-8")
+			.header("Content-Type", "application/json; charset=UTF-8")
+			.body(())
+			.wrap_err("Internal-Error: Failed to construct http request.")
+			.suggestion("Please report this as an issue so it can be fixed.")?;
+
+		match docker_api_call(client, req, long_call_msg.clone(), timeout, is_json).await {
+			Err(err) if attempt < retries && is_transient_docker_error(&err) => {
+				let backoff = DOCKER_GET_RETRY_BACKOFF * 2_u32.pow(attempt);
+				attempt += 1;
+				debug!(
+					"GET: [{}] failed with what looks like a temporary error, retrying ({}/{}) in {:?}: {}",
+					url, attempt, retries, backoff, err,
+				);
+				async_std::task::sleep(backoff).await;
+			}
+			result => return result.context(format!("URL: {}", path)),
+		}
+	}
 }
 
 /// Call the docker engine api using the POST http method.
@@ -135,7 +365,7 @@ pub(self) async fn docker_api_get(
 /// `path`: the path to call (along with Query Args).
 /// `long_call_msg`: the message to print when docker is taking awhile to respond.
 /// `body`: The body to send to the remote endpoint.
-/// `timeout`: the optional timeout. Defaults to 30 seconds.
+/// `timeout`: the optional timeout. Defaults to `DL_DOCKER_TIMEOUT_SECS`, or 30 seconds.
 /// `is_json`: whether to attempt to read the response body as json.
 pub(self) async fn docker_api_post(
 	client: &HttpClient,
@@ -145,7 +375,7 @@ pub(self) async fn docker_api_post(
 	timeout: Option<Duration>,
 	is_json: bool,
 ) -> Result<JsonValue> {
-	let url = format!("http://localhost{}{}", DOCKER_API_VERSION, path);
+	let url = format!("http://localhost/{}{}", *DOCKER_API_VERSION, path);
 	debug!("URL for post will be: {}", url);
 	let req_part = Request::post(url)
 		.header("Accept", "application/json; charset=UTF-8")
@@ -189,7 +419,7 @@ pub(self) async fn docker_api_delete(
 	timeout: Option<Duration>,
 	is_json: bool,
 ) -> Result<JsonValue> {
-	let url = format!("http://localhost{}{}", DOCKER_API_VERSION, path);
+	let url = format!("http://localhost/{}{}", *DOCKER_API_VERSION, path);
 	debug!("URL for delete will be: {}", url);
 	let req_part = Request::delete(url)
 		.header("Accept", "application/json; charset=UTF-8")
@@ -216,6 +446,16 @@ pub(self) async fn docker_api_delete(
 		.context(format!("URL: {}", path))
 }
 
+/// Build the `filters` query parameter for listing docker resources that
+/// have a particular label, e.g. `dev-loop.managed=true`.
+fn label_filter_query(label: &str) -> String {
+	let filters = serde_json::json!({ "label": [label] }).to_string();
+	format!(
+		"filters={}",
+		url::form_urlencoded::byte_serialize(filters.as_bytes()).collect::<String>()
+	)
+}
+
 pub(crate) mod container;
 pub(crate) mod container_api;
 pub(crate) mod execution_api;
@@ -231,3 +471,59 @@ pub use image_api::*;
 pub use network_api::*;
 pub use permissions_helper::*;
 pub use version_api::*;
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn parses_docker_api_versions() {
+		assert_eq!(parse_docker_api_version("v1.41").unwrap(), "v1.41");
+		assert_eq!(parse_docker_api_version("1.43").unwrap(), "v1.43");
+		assert!(parse_docker_api_version("").is_err());
+		assert!(parse_docker_api_version("v1").is_err());
+		assert!(parse_docker_api_version("latest").is_err());
+		assert!(parse_docker_api_version("v1.41/../").is_err());
+	}
+
+	#[test]
+	fn builds_label_filter_queries() {
+		assert_eq!(
+			label_filter_query("dev-loop.managed=true"),
+			"filters=%7B%22label%22%3A%5B%22dev-loop.managed%3Dtrue%22%5D%7D",
+		);
+	}
+
+	#[test]
+	fn parses_docker_timeouts() {
+		assert_eq!(parse_docker_timeout("60").unwrap(), Duration::from_secs(60));
+		assert_eq!(parse_docker_timeout(" 5 ").unwrap(), Duration::from_secs(5));
+		assert!(parse_docker_timeout("0").is_err());
+		assert!(parse_docker_timeout("-1").is_err());
+		assert!(parse_docker_timeout("1m").is_err());
+	}
+
+	#[test]
+	fn gets_docker_error_messages() {
+		assert_eq!(
+			get_docker_error_message(
+				r#"{"message": "Conflict. The container name is already in use."}"#
+			),
+			Some("Conflict. The container name is already in use.".to_owned()),
+		);
+		assert_eq!(get_docker_error_message(r#"{"message": ""}"#), None);
+		assert_eq!(get_docker_error_message("page not found"), None);
+	}
+
+	#[test]
+	fn detects_transient_docker_errors() {
+		assert!(is_transient_docker_error(&DockerStatusError(500).into()));
+		assert!(is_transient_docker_error(&DockerStatusError(503).into()));
+		assert!(!is_transient_docker_error(&DockerStatusError(404).into()));
+		assert!(!is_transient_docker_error(&DockerStatusError(409).into()));
+		assert!(is_transient_docker_error(
+			&isahc::Error::ConnectFailed.into()
+		));
+		assert!(!is_transient_docker_error(&eyre!("Something else")));
+	}
+}
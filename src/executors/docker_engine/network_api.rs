@@ -1,4 +1,8 @@
-use super::{docker_api_delete, docker_api_get, docker_api_post};
+use super::{
+	docker_api_delete, docker_api_get, docker_api_post, label_filter_query, MANAGED_LABEL,
+	PIPELINE_ID_LABEL,
+};
+use crate::time_helper::parse_rfc3339_to_epoch;
 
 use color_eyre::{eyre::WrapErr, Result, Section};
 use isahc::HttpClient;
@@ -10,26 +14,65 @@ static NETWORK_CREATION_LOCK: Lazy<async_std::sync::Mutex<()>> =
 static NETWORK_ATTACH_LOCK: Lazy<async_std::sync::Mutex<()>> =
 	Lazy::new(|| async_std::sync::Mutex::new(()));
 
-pub async fn list_devloop_networks(client: &HttpClient) -> Result<Vec<String>> {
+/// Get the name of a network, along with when it was created (in epoch
+/// seconds) if docker told us.
+fn get_network_name_and_created(network: &serde_json::Value) -> Option<(String, Option<u64>)> {
+	let name = network.get("Name")?.as_str()?;
+	let created_at = network
+		.get("Created")
+		.and_then(|c| c.as_str())
+		.and_then(parse_rfc3339_to_epoch);
+	Some((name.to_owned(), created_at))
+}
+
+/// List all the devloop networks, along with when they were created (in
+/// epoch seconds) if docker told us.
+///
+/// Networks are found by the `dev-loop.managed` label. Unless `labeled_only`
+/// is set, networks whose name starts with `dl-` are also included, since
+/// networks created by older versions of dev-loop don't have any labels.
+pub async fn list_devloop_networks(
+	client: &HttpClient,
+	labeled_only: bool,
+) -> Result<Vec<(String, Option<u64>)>> {
+	let labeled = docker_api_get(
+		client,
+		&format!(
+			"/networks?{}",
+			label_filter_query(&format!("{}=true", MANAGED_LABEL))
+		),
+		"Taking ahwile to query networks from docker.".to_owned(),
+		None,
+		true,
+	)
+	.await?;
+
+	let mut devloop_networks = labeled
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter_map(get_network_name_and_created)
+		.collect::<Vec<_>>();
+	if labeled_only {
+		return Ok(devloop_networks);
+	}
+
 	let json_networks = docker_api_get(
-		&client,
+		client,
 		"/networks",
-		"Taking ahwile to query networks from docker. Will wait up til 30 seconds.".to_owned(),
+		"Taking ahwile to query networks from docker.".to_owned(),
 		None,
 		true,
 	)
 	.await?;
-
-	let mut devloop_networks = Vec::new();
-	if let Some(networks) = json_networks.as_array() {
-		for network in networks {
-			if let Some(name_untyped) = network.get("Name") {
-				if let Some(name_str) = name_untyped.as_str() {
-					if name_str.starts_with("dl-") {
-						devloop_networks.push(name_str.to_owned());
-					}
-				}
-			}
+	for (name, created_at) in json_networks
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter_map(get_network_name_and_created)
+	{
+		if name.starts_with("dl-") && !devloop_networks.iter().any(|(known, _)| *known == name) {
+			devloop_networks.push((name, created_at));
 		}
 	}
 
@@ -40,8 +83,7 @@ pub async fn delete_network(client: &HttpClient, network: &str) {
 	let err = docker_api_delete(
 		&client,
 		&format!("/networks/{}", network),
-		"Docker is taking awhile to delete a docker network. Will wait up to 30 seconds."
-			.to_owned(),
+		"Docker is taking awhile to delete a docker network.".to_owned(),
 		None,
 		None,
 		false,
@@ -74,8 +116,7 @@ pub async fn ensure_network_exists(client: &HttpClient, pipeline_id: &str) -> Re
 	let res = docker_api_get(
 		client,
 		&network_url,
-		"Taking awhile to query network existance status from docker. Will wait up to 30 seconds."
-			.to_owned(),
+		"Taking awhile to query network existance status from docker.".to_owned(),
 		None,
 		true,
 	)
@@ -88,13 +129,16 @@ pub async fn ensure_network_exists(client: &HttpClient, pipeline_id: &str) -> Re
 	if res.is_err() {
 		let json_body = serde_json::json!({
 			"Name": network_id,
+			"Labels": {
+				MANAGED_LABEL: "true",
+				PIPELINE_ID_LABEL: pipeline_id,
+			},
 		});
 
 		let _ = docker_api_post(
 			client,
 			"/networks/create",
-			"Docker is not creating the network in a timely manner. Will wait up to 30 seconds."
-				.to_owned(),
+			"Docker is not creating the network in a timely manner.".to_owned(),
 			Some(json_body),
 			None,
 			false,
@@ -114,7 +158,7 @@ async fn is_network_attached(client: &HttpClient, container_name: &str, pipeline
 	let body_res = docker_api_get(
 		&client,
 		&url,
-		"Taking awhile to get container status from docker. Will wait up to 30 seconds.".to_owned(),
+		"Taking awhile to get container status from docker.".to_owned(),
 		None,
 		true,
 	)
@@ -133,7 +177,7 @@ async fn is_network_attached(client: &HttpClient, container_name: &str, pipeline
 	let network_body_res = docker_api_get(
 		client,
 		&network_url,
-		"Taking awhile to get network status from docker. Will wait up to 30 seconds.".to_owned(),
+		"Taking awhile to get network status from docker.".to_owned(),
 		None,
 		true,
 	)
@@ -176,12 +220,13 @@ pub async fn ensure_network_attached(
 		let _ = docker_api_post(
 			client,
 			&url,
-			"Docker is taking awhile to attach a network to the container. Will wait up to 30 seconds.".to_owned(),
+			"Docker is taking awhile to attach a network to the container.".to_owned(),
 			Some(body),
 			None,
-			false
-		).await
-		 .wrap_err("Failed to attach network to Docker Container.")?;
+			false,
+		)
+		.await
+		.wrap_err("Failed to attach network to Docker Container.")?;
 	}
 
 	Ok(())
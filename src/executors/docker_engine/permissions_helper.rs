@@ -1,4 +1,6 @@
-use super::{execute_command_in_container, get_command_exit_code, DockerContainerInfo};
+use super::{
+	execute_command_in_container, get_command_exit_code, DockerContainerInfo, PermissionMode,
+};
 
 use color_eyre::{
 	eyre::{eyre, WrapErr},
@@ -9,10 +11,13 @@ use once_cell::sync::Lazy;
 
 static DOCK_USER_LOCK: Lazy<async_std::sync::Mutex<()>> =
 	Lazy::new(|| async_std::sync::Mutex::new(()));
-const _PERMISSIONS_HELPER_EXPERIMENTAL_SUGGESTION: &str = "The permissions helper is still experimental. Please report this so it can be fixed before stabilization.";
+const PERMISSIONS_HELPER_SUGGESTION: &str = "The `create-user` permission mode needs to create a user inside of the container, if the image can't support that try the `run-as-uid` permission mode instead.";
 
 /// Setup the permission helper for this docker container if it's been configured.
 ///
+/// Only the `create-user` permission mode needs any setup, `run-as-uid` runs
+/// commands as the user/group id directly.
+///
 /// # Errors
 ///
 /// If we cannot talk to the docker socket, or cannot create the user.
@@ -20,6 +25,9 @@ pub async fn setup_permission_helper(
 	client: &HttpClient,
 	container: &DockerContainerInfo,
 ) -> Result<()> {
+	if container.get_permission_mode() != Some(PermissionMode::CreateUser) {
+		return Ok(());
+	}
 	let _guard = DOCK_USER_LOCK.lock().await;
 	if container.get_proxy_user_id().is_none() || container.get_proxy_group_id().is_none() {
 		return Ok(());
@@ -27,61 +35,48 @@ pub async fn setup_permission_helper(
 
 	let forced_user_id = container.get_proxy_user_id().unwrap();
 	let forced_group_id = container.get_proxy_group_id().unwrap();
-	let has_sudo = container_has_sudo(
-		client,
-		container.get_container_name(),
-		container.get_base_user(),
-	)
-	.await
-	.suggestion(_PERMISSIONS_HELPER_EXPERIMENTAL_SUGGESTION)?;
-	if has_created_proxy_user_before(
-		client,
-		container.get_container_name(),
-		container.get_base_user(),
-	)
-	.await
-	.suggestion(_PERMISSIONS_HELPER_EXPERIMENTAL_SUGGESTION)?
+	let has_sudo = container_has_sudo(client, container)
+		.await
+		.suggestion(PERMISSIONS_HELPER_SUGGESTION)?;
+	if has_created_proxy_user_before(client, container)
+		.await
+		.suggestion(PERMISSIONS_HELPER_SUGGESTION)?
 	{
 		return Ok(());
 	}
 	create_permissions_proxy_user(
 		client,
-		container.get_container_name(),
-		container.get_base_user(),
+		container,
 		*forced_user_id,
 		*forced_group_id,
 		has_sudo,
 	)
 	.await
-	.suggestion(_PERMISSIONS_HELPER_EXPERIMENTAL_SUGGESTION)?;
+	.suggestion(PERMISSIONS_HELPER_SUGGESTION)?;
 
 	// Allow the user to sudo, if sudo is installed.
 	if has_sudo {
-		allow_proxy_user_to_sudo(
-			client,
-			container.get_container_name(),
-			container.get_base_user(),
-		)
-		.await
-		.suggestion(_PERMISSIONS_HELPER_EXPERIMENTAL_SUGGESTION)?;
+		allow_proxy_user_to_sudo(client, container)
+			.await
+			.suggestion(PERMISSIONS_HELPER_SUGGESTION)?;
 	}
 
 	Ok(())
 }
 
-/// Perform a very simple execute and wait for command to finish.
+/// Perform a very simple execute of a script with the containers shell, and
+/// wait for command to finish.
 async fn execute_and_wait_simple(
 	client: &HttpClient,
-	container_name: &str,
-	user: &str,
-	command: &[String],
+	container: &DockerContainerInfo,
+	script: String,
 ) -> Result<String> {
 	execute_command_in_container(
 		client,
-		container_name,
-		command,
+		container.get_container_name(),
+		&container.get_shell_command(script),
 		&[],
-		user,
+		container.get_base_user(),
 		false,
 		None,
 		None,
@@ -90,22 +85,12 @@ async fn execute_and_wait_simple(
 }
 
 /// Check if a container has sudo installed.
-async fn container_has_sudo(client: &HttpClient, container_name: &str, user: &str) -> Result<bool> {
-	let sudo_execution_id = execute_and_wait_simple(
-		client,
-		container_name,
-		user,
-		&[
-			"/usr/bin/env".to_owned(),
-			"bash".to_owned(),
-			"-c".to_owned(),
-			"hash sudo".to_owned(),
-		],
-	)
-	.await
-	.wrap_err(
-		"Failure Checking for sudo existance inside docker container for permissions helper.",
-	)?;
+async fn container_has_sudo(client: &HttpClient, container: &DockerContainerInfo) -> Result<bool> {
+	let sudo_execution_id = execute_and_wait_simple(client, container, "hash sudo".to_owned())
+		.await
+		.wrap_err(
+			"Failure Checking for sudo existance inside docker container for permissions helper.",
+		)?;
 
 	Ok(get_command_exit_code(client, &sudo_execution_id)
 		.await
@@ -117,22 +102,11 @@ async fn container_has_sudo(client: &HttpClient, container_name: &str, user: &st
 /// Check if this user has already created the dev-loop permissions helper user.
 async fn has_created_proxy_user_before(
 	client: &HttpClient,
-	container_name: &str,
-	user: &str,
+	container: &DockerContainerInfo,
 ) -> Result<bool> {
-	let user_exist_id = execute_and_wait_simple(
-		client,
-		container_name,
-		user,
-		&[
-			"/usr/bin/env".to_owned(),
-			"bash".to_owned(),
-			"-c".to_owned(),
-			"getent passwd dl".to_owned(),
-		],
-	)
-	.await
-	.wrap_err("Failure checking if user has already been created for permissions helper.")?;
+	let user_exist_id = execute_and_wait_simple(client, container, "getent passwd dl".to_owned())
+		.await
+		.wrap_err("Failure checking if user has already been created for permissions helper.")?;
 
 	if get_command_exit_code(client, &user_exist_id)
 		.await
@@ -149,42 +123,29 @@ async fn has_created_proxy_user_before(
 /// as the user on the host so we can proxy permissions.
 async fn create_permissions_proxy_user(
 	client: &HttpClient,
-	container_name: &str,
-	user: &str,
+	container: &DockerContainerInfo,
 	forced_user_id: u32,
 	forced_group_id: u32,
 	has_sudo: bool,
 ) -> Result<()> {
-	let creation_execution_id = match (user == "root", has_sudo) {
+	let creation_execution_id = match (container.get_base_user() == "root", has_sudo) {
 		(true, _) | (false, false) => execute_and_wait_simple(
 			client,
-			container_name,
-			user,
-			&[
-				"/usr/bin/env".to_owned(),
-				"bash".to_owned(),
-				"-c".to_owned(),
-				format!(
-					"groupadd -g {} -o dl && useradd -u {} -g {} -o -c '' -m dl",
-					forced_group_id, forced_user_id, forced_group_id
-				),
-			],
+			container,
+			format!(
+				"groupadd -g {} -o dl && useradd -u {} -g {} -o -c '' -m dl",
+				forced_group_id, forced_user_id, forced_group_id
+			),
 		)
 		.await
 		.wrap_err("Failure creating user for permissions helper")?,
 		(false, true) => execute_and_wait_simple(
 			client,
-			container_name,
-			user,
-			&[
-				"/usr/bin/env".to_owned(),
-				"bash".to_owned(),
-				"-c".to_owned(),
-				format!(
-					"sudo -n groupadd -g {} -o dl && sudo -n useradd -u {} -g {} -o -c '' -m dl",
-					forced_group_id, forced_user_id, forced_group_id
-				),
-			],
+			container,
+			format!(
+				"sudo -n groupadd -g {} -o dl && sudo -n useradd -u {} -g {} -o -c '' -m dl",
+				forced_group_id, forced_user_id, forced_group_id
+			),
 		)
 		.await
 		.wrap_err("Failure creating user for permissions helper")?,
@@ -202,33 +163,24 @@ async fn create_permissions_proxy_user(
 /// Allow the permissions proxy user to sudo.
 async fn allow_proxy_user_to_sudo(
 	client: &HttpClient,
-	container_name: &str,
-	user: &str,
+	container: &DockerContainerInfo,
 ) -> Result<()> {
-	let sudo_user_creation_id = if user == "root" {
+	let sudo_user_creation_id = if container.get_base_user() == "root" {
 		execute_and_wait_simple(
-      client,
-  container_name,
-  user,
-  &[
-      "/usr/bin/env".to_owned(),
-      "bash".to_owned(),
-      "-c".to_owned(),
-      "mkdir -p /etc/sudoers.d && echo \"dl ALL=(root) NOPASSWD:ALL\" > /etc/sudoers.d/dl && chmod 0440 /etc/sudoers.d/dl".to_owned()
-    ],
-    ).await.wrap_err("Failure adding user to sudoers for permissions helper")?
+			client,
+			container,
+			"mkdir -p /etc/sudoers.d && echo \"dl ALL=(root) NOPASSWD:ALL\" > /etc/sudoers.d/dl && chmod 0440 /etc/sudoers.d/dl".to_owned(),
+		)
+		.await
+		.wrap_err("Failure adding user to sudoers for permissions helper")?
 	} else {
 		execute_and_wait_simple(
-      client,
-      container_name,
-      user,
-      &[
-        "/usr/bin/env".to_owned(),
-        "bash".to_owned(),
-        "-c".to_owned(),
-        "sudo -n mkdir -p /etc/sudoers.d && echo \"dl ALL=(root) NOPASSWD:ALL\" | sudo -n tee /etc/sudoers.d/dl && sudo -n chmod 0440 /etc/sudoers.d/dl".to_owned()
-      ],
-    ).await.wrap_err("Failure adding user to sudoers for permissions helper")?
+			client,
+			container,
+			"sudo -n mkdir -p /etc/sudoers.d && echo \"dl ALL=(root) NOPASSWD:ALL\" | sudo -n tee /etc/sudoers.d/dl && sudo -n chmod 0440 /etc/sudoers.d/dl".to_owned(),
+		)
+		.await
+		.wrap_err("Failure adding user to sudoers for permissions helper")?
 	};
 
 	if get_command_exit_code(client, &sudo_user_creation_id)
@@ -1,14 +1,14 @@
-use super::docker_api_get;
+use super::docker_api_get_once;
 
 use color_eyre::Result;
 use isahc::HttpClient;
 use serde_json::Value as JsonValue;
 
 pub async fn docker_version_check(client: &HttpClient) -> Result<JsonValue> {
-	docker_api_get(
+	docker_api_get_once(
 		client,
 		"/version",
-		"Taking awhile to query version from docker. Will wait up to 30 seconds.".to_owned(),
+		"Taking awhile to query version from docker.".to_owned(),
 		None,
 		true,
 	)
@@ -2,11 +2,15 @@
 //! that just uses the Host System.
 
 use crate::{
-	config::types::NeedsRequirement,
+	config::types::{NeedsRequirement, ProvideConf},
 	dirs::get_tmp_dir,
 	executors::{
-		shared::{create_entrypoint, create_executor_shared_dir},
-		CompatibilityStatus, Executor as ExecutorTrait,
+		shared::{
+			build_provides, create_entrypoint, create_executor_shared_dir,
+			parse_version_from_output, provides_meet_requirements, sorted_provided_tools,
+			ExecutorHooks, DEFAULT_SHELL_PATH,
+		},
+		CompatibilityStatus, Executor as ExecutorTrait, ProvidedTools,
 	},
 	tasks::execution::preparation::ExecutableTask,
 };
@@ -16,18 +20,24 @@ use color_eyre::{
 	Result, Section,
 };
 use crossbeam_channel::Sender;
+use semver::Version;
 use std::{
+	collections::HashMap,
 	fs::{read_dir, remove_dir_all},
 	io::{BufRead, BufReader, Error as IoError},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	process::{Command, Stdio},
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
 	},
+	time::Duration,
 };
 use tracing::{debug, error, warn};
 
+/// The param to set the shell the host executor runs tasks with.
+pub const SHELL_ARG: &str = "shell";
+
 /// Determine if an error is an "ETXTFILEBUSY" error, e.g. someone
 /// else is actively executing bash.
 #[cfg(any(
@@ -77,38 +87,180 @@ fn is_etxtfilebusy(os_err: &IoError) -> bool {
 	false
 }
 
+/// Determine if a path in the temporary directory was created by dev-loop.
+///
+/// Both pipeline directories (`<pipeline_id>-dl-host`), and helper
+/// directories (`<epoch>-<id>-helpers-dl-host`) end with `-dl-host`.
+fn is_dev_loop_tmp_dir(path: &str) -> bool {
+	path.trim_end_matches('/').ends_with("-dl-host")
+}
+
 /// Represents the actual `Executor` for the host system.
 #[derive(Debug)]
 pub struct Executor {
-	/// The root of the project, so we know where to "cd" into.
-	project_root: String,
+	/// The root of the project, so we know where to "cd" into. This doesn't
+	/// need to be UTF-8.
+	project_root: PathBuf,
+	/// The `setup`/`teardown` hooks for this executor.
+	hooks: ExecutorHooks,
+	/// The list of things this provides, on top of `host` itself.
+	provides: HashMap<String, Option<Version>>,
+	/// The shell to run tasks, and hooks with. When unset tasks are run
+	/// through their shebang, and hooks are run with `bash`.
+	shell: Option<String>,
 }
 
 impl Executor {
 	/// Create a new host executor, with nothing but the project root.
+	#[must_use]
+	pub fn new(project_root: &Path) -> Self {
+		Self {
+			project_root: project_root.to_path_buf(),
+			hooks: ExecutorHooks::default(),
+			provides: HashMap::new(),
+			shell: None,
+		}
+	}
+
+	/// Set the shell to run tasks, and hooks with.
 	///
-	/// # Errors
+	/// `shell`: the shell to use, e.g. `/usr/local/bin/bash`.
+	#[must_use]
+	pub fn with_shell(mut self, shell: Option<String>) -> Self {
+		self.shell = shell;
+		self
+	}
+
+	/// Get the shell to run commands with, defaulting to `bash`.
+	fn get_shell(&self) -> &str {
+		self.shell.as_deref().unwrap_or("bash")
+	}
+
+	/// Build the command that runs an entrypoint script.
+	fn entrypoint_command(&self, entrypoint: &Path) -> Command {
+		if let Some(shell) = &self.shell {
+			let mut command = Command::new(shell);
+			command.arg(entrypoint);
+			command
+		} else {
+			Command::new(entrypoint)
+		}
+	}
+
+	/// Set the tools this executor provides, on top of `host` itself.
 	///
-	/// If the project root is not on a valid utf-8 string path.
-	pub fn new(project_root: &PathBuf) -> Result<Self> {
-		let pr_as_string = project_root.to_str();
-		if pr_as_string.is_none() {
-			return Err(eyre!(
-				"Failed to turn the project directory: [{:?}] into a utf8-string.",
-				project_root,
-			))
-			.suggestion(
-				"Please move the project directory to somewhere that is a UTF-8 only file path.",
+	/// `provided_conf`: the list of tools the host provides.
+	#[must_use]
+	pub fn with_provides(mut self, provided_conf: &[ProvideConf]) -> Self {
+		self.provides = build_provides(provided_conf);
+		self
+	}
+
+	/// Run the `version_command` of every provided tool that has one in the
+	/// root of the project, and use the version it outputs.
+	///
+	/// If the command can't be run, or its output can't be parsed the
+	/// configured `version` is kept (which when unset only meets requirements
+	/// that don't ask for a version).
+	///
+	/// `provided_conf`: the list of tools the host provides.
+	#[must_use]
+	pub fn with_probed_versions(mut self, provided_conf: &[ProvideConf]) -> Self {
+		for provided in provided_conf {
+			let version_command = if let Some(command) = provided.get_version_command() {
+				command
+			} else {
+				continue;
+			};
+
+			let output = match Command::new(self.get_shell())
+				.arg("-c")
+				.arg(version_command)
+				.current_dir(&self.project_root)
+				.stdin(Stdio::null())
+				.output()
+			{
+				Ok(output) => output,
+				Err(probe_err) => {
+					warn!(
+						"Failed to run the `version_command`: [{}] for: [{}] due to: [{:?}]. Will use the configured `version`.",
+						version_command,
+						provided.get_name(),
+						probe_err,
+					);
+					continue;
+				}
+			};
+			let combined_output = format!(
+				"{}\n{}",
+				String::from_utf8_lossy(&output.stdout),
+				String::from_utf8_lossy(&output.stderr),
 			);
+
+			if !output.status.success() {
+				warn!(
+					"The `version_command`: [{}] for: [{}] exited with: [{}], output was: [{}]. Will use the configured `version`.",
+					version_command,
+					provided.get_name(),
+					output.status.code().unwrap_or(10),
+					combined_output.trim(),
+				);
+			} else if let Some(version) = parse_version_from_output(&combined_output) {
+				debug!(
+					"Host provides: [{}] at version: [{}]",
+					provided.get_name(),
+					version,
+				);
+				self.provides
+					.insert(provided.get_name().to_owned(), Some(version));
+			} else {
+				warn!(
+					"Could not find a version in the output of the `version_command`: [{}] for: [{}], output was: [{}]. Will use the configured `version`.",
+					version_command,
+					provided.get_name(),
+					combined_output.trim(),
+				);
+			}
 		}
 
-		Ok(Self {
-			project_root: pr_as_string.unwrap().to_owned(),
-		})
+		self
+	}
+
+	/// Set the `setup`/`teardown` hooks for this executor.
+	#[must_use]
+	pub fn with_hooks(mut self, hooks: ExecutorHooks) -> Self {
+		self.hooks = hooks;
+		self
+	}
+
+	/// Run a hook command with the executor's shell in the root of the project, waiting for it
+	/// to finish.
+	async fn run_hook(shell: String, project_root: PathBuf, command: String) -> Result<i32> {
+		let mut child = Command::new(shell)
+			.arg("-c")
+			.arg(&command)
+			.current_dir(project_root)
+			.stdin(Stdio::null())
+			.spawn()
+			.wrap_err("Failed to run hook on the host system")?;
+
+		loop {
+			if let Some(status) = child
+				.try_wait()
+				.wrap_err("Failed to read the status of hook on the host system")?
+			{
+				return Ok(status.code().unwrap_or(10));
+			}
+
+			async_std::task::sleep(Duration::from_millis(10)).await;
+		}
 	}
 
 	/// Performs a clean up of all host resources.
-	pub async fn clean() {
+	///
+	/// `older_than`: if specified only remove directories that were last
+	///               modified at least this long ago.
+	pub async fn clean(older_than: Option<Duration>) {
 		// To clean all we would possibly have leftover is files in $TMPDIR.
 		// So we iterate through everything in the temporary directory...
 		if let Ok(entries) = read_dir(get_tmp_dir()) {
@@ -138,7 +290,7 @@ impl Executor {
 
 					// Is it a directory that ends with `-dl-host` the
 					// identifier of dev-loop host executor?
-					if !entry_str.ends_with("-dl-host") {
+					if !is_dev_loop_tmp_dir(entry_str) {
 						debug!(
 							"Skipping entry: [{:?}] does not appear to be a dev-loop temporary directory (dev-loop dirs end with -dl-host)",
 							entry,
@@ -146,6 +298,26 @@ impl Executor {
 						continue;
 					}
 
+					// If we were asked to only clean stale directories, make sure this one
+					// hasn't been touched recently. A concurrently running dev-loop would
+					// still be writing to it.
+					if let Some(age) = older_than {
+						let elapsed = entry
+							.metadata()
+							.and_then(|metadata| metadata.modified())
+							.ok()
+							.and_then(|modified| modified.elapsed().ok());
+						let is_stale =
+							matches!(elapsed, Some(since_modified) if since_modified >= age);
+						if !is_stale {
+							debug!(
+								"Skipping entry: [{:?}] as it has been modified within the last: [{:?}]",
+								entry, age,
+							);
+							continue;
+						}
+					}
+
 					// If it is... remove the directory and everything underneath it.
 					if let Err(remove_err) = remove_dir_all(&entry) {
 						let formatted_err = Err::<(), IoError>(remove_err)
@@ -177,8 +349,11 @@ impl Executor {
 		//  scripts potentially. if they do try to do this the "hash" command
 		//  will not properly since it is not an actual binary.
 
-		match Command::new("bash").args(&["-c", "\"hash bash\""]).output() {
-			Ok(_) => CompatibilityStatus::Compatible,
+		match Command::new("bash").args(&["-c", "hash bash"]).output() {
+			Ok(output) if output.status.success() => CompatibilityStatus::Compatible,
+			Ok(_) => CompatibilityStatus::CouldBeCompatible(
+				"make sure `bash` can be found on your `PATH`".to_owned(),
+			),
 			Err(os_err) => {
 				if is_etxtfilebusy(&os_err) {
 					// Tail recurse.
@@ -195,16 +370,29 @@ impl Executor {
 impl ExecutorTrait for Executor {
 	#[must_use]
 	fn meets_requirements(&self, reqs: &[NeedsRequirement]) -> bool {
-		let mut meets_reqs = true;
+		// `host` itself is always provided, everything else has to be declared in
+		// `provides`.
+		reqs.iter()
+			.filter(|req| req.get_name() != "host")
+			.all(|req| provides_meet_requirements(&self.provides, std::slice::from_ref(req)))
+	}
 
-		for req in reqs {
-			if req.get_name() != "host" {
-				meets_reqs = false;
-				break;
-			}
-		}
+	fn get_type_name(&self) -> &'static str {
+		"host"
+	}
+
+	fn get_provided_tools(&self) -> ProvidedTools {
+		let mut tools = vec![("host".to_owned(), None)];
+		tools.extend(sorted_provided_tools(&self.provides));
+		tools
+	}
+
+	fn get_container_kept_on_failure(&self) -> Option<&str> {
+		None
+	}
 
-		meets_reqs
+	fn get_shell_path(&self) -> String {
+		self.get_shell().to_owned()
 	}
 
 	#[must_use]
@@ -218,6 +406,16 @@ impl ExecutorTrait for Executor {
 	) -> Result<i32> {
 		debug!("Host Executor executing task: [{}]", task.get_task_name());
 
+		self.hooks
+			.ensure_setup(|command| {
+				Self::run_hook(
+					self.get_shell().to_owned(),
+					self.project_root.clone(),
+					command,
+				)
+			})
+			.await?;
+
 		// Write out the small wrapper script that sources in the helpers, and runs the task.
 		let shared_dir = create_executor_shared_dir(task.get_pipeline_id())
 			.wrap_err("Failed to create pipeline directory")?;
@@ -225,21 +423,28 @@ impl ExecutorTrait for Executor {
 			"Host Executor will be using temporary directory: [{:?}]",
 			shared_dir
 		);
+		let working_dir = if let Some(working_dir) = task.get_working_dir() {
+			self.project_root.join(working_dir)
+		} else {
+			self.project_root.clone()
+		};
 		let entrypoint_path = create_entrypoint(
-			&self.project_root,
+			&working_dir,
 			&get_tmp_dir().to_string_lossy().to_string(),
 			shared_dir,
 			helper_src_line,
 			task,
+			worker_count,
 			false,
 			None,
 			None,
+			DEFAULT_SHELL_PATH,
 		)?;
-		let entrypoint_as_str = entrypoint_path.to_str().unwrap();
 
 		// Spawn the command itself, retry if we get an ETXTFILEBUSY error incase we try to start two
 		// bash processes at the same time.
-		let mut command_res = Command::new(entrypoint_as_str)
+		let mut command_res = self
+			.entrypoint_command(&entrypoint_path)
 			.stdin(Stdio::null())
 			.stdout(Stdio::piped())
 			.stderr(Stdio::piped())
@@ -247,7 +452,8 @@ impl ExecutorTrait for Executor {
 		while let Err(command_err) = command_res {
 			if is_etxtfilebusy(&command_err) {
 				// Respawn the command again!
-				command_res = Command::new(entrypoint_as_str)
+				command_res = self
+					.entrypoint_command(&entrypoint_path)
 					.stdin(Stdio::null())
 					.stdout(Stdio::piped())
 					.stderr(Stdio::piped())
@@ -255,7 +461,7 @@ impl ExecutorTrait for Executor {
 			} else {
 				return Err(command_err)
 					.wrap_err("Failed to run bash script on the host system")
-					.note(format!("The script is located at: [{}]", entrypoint_as_str,));
+					.note(format!("The script is located at: [{:?}]", entrypoint_path));
 			}
 		}
 		let mut command_pid = command_res.unwrap();
@@ -335,12 +541,52 @@ impl ExecutorTrait for Executor {
 
 		Ok(rc)
 	}
+
+	async fn copy_to_host(&self, path: &str, _host_dir: &Path) -> Result<Option<PathBuf>> {
+		Err(eyre!(
+			"The path: [{}] is outside of the project, and the host executor runs tasks on the host so there's nothing to copy it from.",
+			path,
+		))
+		.suggestion("Paths for tasks run by the host executor need to be relative to the root of the project.")
+	}
+
+	async fn teardown(&self) -> Result<()> {
+		if let Some(teardown) = self.hooks.get_teardown_to_run().await {
+			let rc = Self::run_hook(
+				self.get_shell().to_owned(),
+				self.project_root.clone(),
+				teardown.clone(),
+			)
+			.await?;
+			if rc != 0 {
+				return Err(eyre!(
+					"The `teardown` hook: [{}] for the host executor exited with: [{}]",
+					teardown,
+					rc,
+				));
+			}
+		}
+
+		Ok(())
+	}
 }
 
 #[cfg(test)]
 mod unit_tests {
 	use super::*;
 
+	#[test]
+	fn matches_dev_loop_tmp_dirs() {
+		assert!(is_dev_loop_tmp_dir(
+			"/tmp/1b4e28ba-2fa1-11d2-883f-0016d3cca427-dl-host"
+		));
+		assert!(is_dev_loop_tmp_dir(
+			"/tmp/1600000000-1b4e28ba-2fa1-11d2-883f-0016d3cca427-helpers-dl-host"
+		));
+		assert!(is_dev_loop_tmp_dir("/tmp/1600000000-helpers-dl-host/"));
+		assert!(!is_dev_loop_tmp_dir("/tmp/some-other-dir"));
+	}
+
 	#[test]
 	fn is_compatible() {
 		let compat = Executor::is_compatible();
@@ -350,7 +596,7 @@ mod unit_tests {
 	#[test]
 	fn meets_requirements() {
 		let pb = PathBuf::from("/tmp/non-existant");
-		let he = Executor::new(&pb).expect("Should always be able to construct Executor for host.");
+		let he = Executor::new(&pb);
 
 		assert!(
 			he.meets_requirements(&vec![crate::config::types::NeedsRequirement::new(
@@ -375,4 +621,23 @@ mod unit_tests {
 			crate::config::types::NeedsRequirement::new("another-service".to_owned(), None)
 		]));
 	}
+
+	#[test]
+	fn meets_provided_requirements() {
+		let pb = PathBuf::from("/tmp/non-existant");
+		let he = Executor::new(&pb).with_provides(&[ProvideConf::new(
+			"node".to_owned(),
+			Some("18.2.0".to_owned()),
+		)]);
+
+		assert!(he.meets_requirements(&[
+			NeedsRequirement::new("host".to_owned(), None),
+			NeedsRequirement::new("node".to_owned(), Some(">=18".to_owned())),
+		]));
+		assert!(!he.meets_requirements(&[NeedsRequirement::new(
+			"node".to_owned(),
+			Some(">=19".to_owned())
+		)]));
+		assert!(!he.meets_requirements(&[NeedsRequirement::new("python".to_owned(), None)]));
+	}
 }
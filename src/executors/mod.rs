@@ -4,13 +4,16 @@
 //! a docker executor the code that spins up/down the container will be here.
 
 use crate::{
-	config::types::{
-		ExecutorConf, ExecutorConfFile, ExecutorType, LocationType, NeedsRequirement, TaskConf,
-		TopLevelConf,
+	config::{
+		types::{
+			ExecutorConf, ExecutorConfFile, ExecutorType, LocationType, NeedsRequirement, TaskConf,
+			TopLevelConf,
+		},
+		warn_about_unknown_list_fields,
 	},
 	fetch::FetcherRepository,
 	tasks::execution::preparation::ExecutableTask,
-	yaml_err::contextualize,
+	yaml_err::contextualize_list,
 };
 
 use color_eyre::{
@@ -18,11 +21,13 @@ use color_eyre::{
 	Result, Section,
 };
 use crossbeam_channel::Sender;
+use futures_util::future::join_all;
+use semver::Version;
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{hash_map::Entry, HashMap, HashSet},
 	fmt::{Debug, Formatter},
 	hash::{BuildHasher, Hasher},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	sync::{atomic::AtomicBool, Arc, RwLock},
 };
 use tracing::{debug, warn};
@@ -31,6 +36,13 @@ use twox_hash::{RandomXxHashBuilder64, XxHash64};
 type ExecutorMapType = dyn Executor + Send + Sync;
 type AtomicRefExecutorMapType = Arc<ExecutorMapType>;
 type ExecutorHashMapType = HashMap<String, AtomicRefExecutorMapType>;
+/// A map of executor label, to the ID, and configuration of the executor.
+type LabelMapType = HashMap<String, (String, ExecutorConf)>;
+/// Describes an executor: the ID, the label (if any), the type of executor,
+/// and the tools it provides.
+pub type ExecutorDescription = (String, Option<String>, &'static str, ProvidedTools);
+/// A list of tools an executor provides, and the version provided if known.
+pub type ProvidedTools = Vec<(String, Option<Version>)>;
 
 /// Describes the compatibility status of a particular Executor.
 #[derive(Debug, PartialEq)]
@@ -55,6 +67,24 @@ pub trait Executor {
 	#[must_use]
 	fn meets_requirements(&self, reqs: &[NeedsRequirement]) -> bool;
 
+	/// The type of this executor, e.g. `host`, or `docker`.
+	#[must_use]
+	fn get_type_name(&self) -> &'static str;
+
+	/// The tools this executor provides sorted by name, along with the version
+	/// provided if one is known.
+	#[must_use]
+	fn get_provided_tools(&self) -> ProvidedTools;
+
+	/// The name of the container this executor keeps around after a failed
+	/// run for debugging, if it has been asked to.
+	#[must_use]
+	fn get_container_kept_on_failure(&self) -> Option<&str>;
+
+	/// The shell this executor runs tasks with, e.g. `bash`, or `/bin/sh`.
+	#[must_use]
+	fn get_shell_path(&self) -> String;
+
 	/// Execute a task.
 	///
 	/// `log_channel`: The channel to send log updates over.
@@ -71,6 +101,30 @@ pub trait Executor {
 		task: &ExecutableTask,
 		worker_count: usize,
 	) -> Result<i32>;
+
+	/// Copy a path that only exists where this executor runs tasks (like a
+	/// path inside of a docker container that isn't in the project) into a
+	/// directory on the host, e.g. to collect it as an artifact.
+	///
+	/// Symlinks are copied as links, rather than followed.
+	///
+	/// `path`: the absolute path to copy, where tasks are run.
+	/// `host_dir`: the directory on the host to copy it into, it must be
+	///             inside of the temporary directory, and world writable.
+	///
+	/// Returns the path on the host it was copied to, or `None` if it doesn't
+	/// exist.
+	///
+	/// # Errors
+	///
+	/// - When this executor runs tasks on the host, so there's nowhere else to
+	///   copy from.
+	/// - When the path could not be copied.
+	async fn copy_to_host(&self, path: &str, host_dir: &Path) -> Result<Option<PathBuf>>;
+
+	/// Run the `teardown` hook for this executor, if it has one, and the
+	/// executor was setup.
+	async fn teardown(&self) -> Result<()>;
 }
 
 pub(crate) mod docker;
@@ -78,6 +132,8 @@ pub(crate) mod docker_engine;
 pub(crate) mod host;
 pub(crate) mod shared;
 
+use shared::ExecutorHooks;
+
 /// Describes a "repository" of executors, or more accurately a set of all
 /// the executors that could potentially run, or are running right now.
 ///
@@ -88,8 +144,20 @@ pub struct ExecutorRepository {
 	active_executors: RwLock<HashSet<String>>,
 	/// Defines the repository of executors that are compatible with this system.
 	repo: RwLock<ExecutorHashMapType>,
+	/// The human readable labels of executors, mapped to their IDs.
+	labels: LabelMapType,
+	/// The IDs of executors in the order they were declared (or created for
+	/// a `custom_executor`), used to choose between executors that meet the
+	/// needs of a task equally. IDs can be random, but this order never is.
+	declaration_order: Vec<String>,
 	/// The root project directory.
 	root_dir: PathBuf,
+	/// The shell the host executor runs tasks with, unless it sets its own
+	/// `shell` param.
+	default_shell: Option<String>,
+	/// An executor ID the user asked to run every task on, e.g. with
+	/// `--executor`.
+	executor_override: Option<String>,
 }
 
 impl Debug for ExecutorRepository {
@@ -141,6 +209,9 @@ impl ExecutorRepository {
 		let mut executors = HashMap::new();
 		// The hasher is used to assign global unique IDs.
 		let hash_builder = RandomXxHashBuilder64::default();
+		// Labels must be unique, so keep track of the ones we've seen.
+		let mut labels = LabelMapType::new();
+		let mut declaration_order = Vec::new();
 
 		// First try to create the default executor.
 		//
@@ -148,7 +219,7 @@ impl ExecutorRepository {
 		// If someone tries to use it will fail, but if they don't it won't impact
 		// their work if someone else breaks it somehow.
 		if let Some(econf) = tlc.get_default_executor() {
-			match Self::instantiate_executor(rd, econf)
+			match Self::instantiate_executor(rd, econf, tlc.get_default_shell())
 				.await
 				.wrap_err(
 					"Error attempting to instantiate `default_executor` defined in `.dl/config.yml`",
@@ -159,21 +230,33 @@ impl ExecutorRepository {
 					// The default executor will never conflict because nothing else is in the map.
 					// As such we don't need to check for colissions.
 					executors.insert("default".to_owned(), executor);
+					declaration_order.push("default".to_owned());
 					debug!("Inserted 'default' executor.");
+					Self::register_label(&mut labels, econf, "default").wrap_err(
+						"Failed to load `default_executor` defined in `.dl/config.yml`",
+					)?;
 				}
 				Err(err) => warn!("{:?}", err),
 			}
 		}
 
 		if let Some(executor_locations) = tlc.get_executor_locations() {
-			for (eloc_idx, exec_location) in executor_locations.iter().enumerate() {
-				// Go fetch all the executors that we can.
-				// If search in folders look for: `dl-executors.yml`.
-				let resulting_fetched_executors = fr
-					.fetch_filter(exec_location, Some("dl-executors.yml".to_owned()))
-					.await
-					.wrap_err(format!("Error while grabbing location specified at `.dl/config.yml:executor_locations:{}`", eloc_idx));
-
+			// Go fetch all the executors that we can, all at once.
+			// If search in folders look for: `dl-executors.yml`.
+			//
+			// Results come back in the same order as the locations were specified.
+			let all_fetched_executors = join_all(executor_locations.iter().enumerate().map(
+				|(eloc_idx, exec_location)| async move {
+					fr.fetch_filter(exec_location, Some("dl-executors.yml".to_owned()))
+						.await
+						.wrap_err(format!("Error while grabbing location specified at `.dl/config.yml:executor_locations:{}`", eloc_idx))
+				},
+			))
+			.await;
+
+			for (exec_location, resulting_fetched_executors) in
+				executor_locations.iter().zip(all_fetched_executors)
+			{
 				// For HTTP errors we're going to try to continue, if your FS fails
 				// well than something really bad is going on, and further FS
 				// operations are most likely to fail, so just fail fast.
@@ -195,14 +278,22 @@ impl ExecutorRepository {
 					let exec_yaml_res =
 						serde_yaml::from_slice::<ExecutorConfFile>(&exec_conf_file.get_contents());
 					if let Err(exec_err) = exec_yaml_res {
-						return contextualize(
+						return contextualize_list::<_, ExecutorConf>(
 							Err(exec_err),
 							exec_conf_file.get_source(),
-							&String::from_utf8_lossy(exec_conf_file.get_contents()).to_string()
+							&String::from_utf8_lossy(exec_conf_file.get_contents()).to_string(),
+							"executors",
+							"Executor",
 						).wrap_err("Failed to parse executor file as yaml")
 						 .note("Full types, and supported values are documented at: https://dev-loop.kungfury.dev/docs/schemas/executor-conf-file");
 					}
 					let exec_yaml = exec_yaml_res.unwrap();
+					warn_about_unknown_list_fields::<ExecutorConfFile, ExecutorConf>(
+						exec_conf_file.get_contents(),
+						exec_conf_file.get_source(),
+						"executors",
+						"Executor",
+					);
 
 					for (idx, econf) in exec_yaml
 						.consume_and_get_executors()
@@ -216,7 +307,8 @@ impl ExecutorRepository {
 						);
 						let _guard = span.enter();
 
-						let exec_res = Self::instantiate_executor(rd, &econf).await;
+						let exec_res =
+							Self::instantiate_executor(rd, &econf, tlc.get_default_shell()).await;
 						if let Err(exec_init_err) = exec_res {
 							warn!(
 								"Failed to initialize executor due to: {:?}. Will not be choosing.",
@@ -226,15 +318,32 @@ impl ExecutorRepository {
 						}
 
 						let (mut potential_id, executor) = exec_res.unwrap();
+						if &potential_id != "host" {
+							while executors.contains_key(&potential_id) {
+								potential_id =
+									Self::hash_string(&potential_id, hash_builder.build_hasher());
+							}
+						}
+						Self::register_label(&mut labels, &econf, &potential_id).wrap_err(
+							format!(
+								"Failed to load executor #{} defined in: [{}]",
+								idx + 1,
+								exec_conf_file.get_source(),
+							),
+						)?;
 						if &potential_id == "host" {
-							executors.entry(potential_id).or_insert(executor);
+							if let Entry::Vacant(entry) = executors.entry(potential_id) {
+								declaration_order.push(entry.key().clone());
+								entry.insert(executor);
+							}
 							continue;
 						}
-						while executors.contains_key(&potential_id) {
-							potential_id =
-								Self::hash_string(&potential_id, hash_builder.build_hasher());
-						}
-						debug!("Executor has been assigned ID: [{}]", potential_id);
+						debug!(
+							"Executor has been assigned ID: [{}] label: [{}]",
+							potential_id,
+							econf.get_label().unwrap_or_default(),
+						);
+						declaration_order.push(potential_id.clone());
 						executors.insert(potential_id, executor);
 					}
 				}
@@ -244,10 +353,121 @@ impl ExecutorRepository {
 		Ok(Self {
 			active_executors: RwLock::new(HashSet::new()),
 			repo: RwLock::new(executors),
+			labels,
+			declaration_order,
 			root_dir: rd.clone(),
+			default_shell: tlc.get_default_shell().map(ToOwned::to_owned),
+			executor_override: None,
 		})
 	}
 
+	/// Force every task to run on a particular executor ID, rather than
+	/// going through normal executor selection.
+	#[must_use]
+	pub fn with_executor_override(mut self, executor_override: Option<String>) -> Self {
+		self.executor_override = executor_override;
+		self
+	}
+
+	/// Describe every executor in the repository sorted by ID: the ID, the
+	/// label, the type of executor, and the tools it provides.
+	#[must_use]
+	pub fn describe_executors(&self) -> Vec<ExecutorDescription> {
+		let mut descriptions = if let Ok(repo) = self.repo.read() {
+			repo.iter()
+				.map(|(id, executor)| {
+					(
+						id.clone(),
+						self.get_label_for_id(id).map(ToOwned::to_owned),
+						executor.get_type_name(),
+						executor.get_provided_tools(),
+					)
+				})
+				.collect::<Vec<_>>()
+		} else {
+			warn!("Unknown State describing executors, please report as an issue. Maintainer Info: [repo_read_mutex_failure]");
+			Vec::new()
+		};
+		descriptions.sort_by(|left, right| left.0.cmp(&right.0));
+
+		descriptions
+	}
+
+	/// Get the ID of an executor that was selected from this repository.
+	///
+	/// `executor`: the executor to find the ID of.
+	#[must_use]
+	pub fn get_executor_id(&self, executor: &Arc<dyn Executor + Sync + Send>) -> Option<String> {
+		let repo = self.repo.read().ok()?;
+		repo.iter()
+			.find(|(_, repo_executor)| Arc::ptr_eq(repo_executor, executor))
+			.map(|(id, _)| id.clone())
+	}
+
+	/// Get the names of the containers that ran tasks, and are being kept
+	/// around after a failure so they can be debugged, along with the shell
+	/// to enter them with.
+	#[must_use]
+	pub fn get_containers_kept_on_failure(&self) -> Vec<(String, String)> {
+		let (repo, active_executors) = if let Some(locks) = self.map_read_locks() {
+			locks
+		} else {
+			return Vec::new();
+		};
+
+		let mut containers = active_executors
+			.iter()
+			.filter_map(|id| repo.get(id))
+			.filter_map(|executor| {
+				executor
+					.get_container_kept_on_failure()
+					.map(|container_name| (container_name.to_owned(), executor.get_shell_path()))
+			})
+			.collect::<Vec<(String, String)>>();
+		containers.sort();
+		containers.dedup();
+		containers
+	}
+
+	/// Get the label of an executor by its ID, if it has one.
+	fn get_label_for_id(&self, id: &str) -> Option<&str> {
+		self.labels
+			.iter()
+			.find(|(_, (labeled_id, _))| labeled_id == id)
+			.map(|(label, _)| label.as_str())
+	}
+
+	/// Remember the label of an executor, if it has one.
+	///
+	/// # Errors
+	///
+	/// If the label is already being used by another executor.
+	fn register_label(labels: &mut LabelMapType, conf: &ExecutorConf, id: &str) -> Result<()> {
+		let label = if let Some(label) = conf.get_label() {
+			label
+		} else {
+			return Ok(());
+		};
+
+		if let Some((existing_id, _)) = labels.get(label) {
+			return Err(eyre!(
+				"The executor label: [{}] is used by more than one executor, it is already the label of executor: [{}].",
+				label,
+				existing_id,
+			))
+			.suggestion("Executor labels must be unique, please rename one of them.");
+		}
+		labels.insert(label.to_owned(), (id.to_owned(), conf.clone()));
+
+		Ok(())
+	}
+
+	/// Get the executor ID every task has been forced to run on, if any.
+	#[must_use]
+	pub fn get_executor_override(&self) -> Option<&str> {
+		self.executor_override.as_deref()
+	}
+
 	fn map_write_locks(
 		&self,
 	) -> Option<(
@@ -306,15 +526,94 @@ impl ExecutorRepository {
 		Some((repo, active_executors))
 	}
 
+	/// Select the executor a user explicitly asked for, making sure it can
+	/// actually run the task.
+	///
+	/// `task`: The actual task configuration.
+	/// `executor_id`: The ID of the executor the user asked for.
+	fn select_overridden_executor(
+		&self,
+		task: &TaskConf,
+		executor_id: &str,
+	) -> Option<Arc<dyn Executor + Sync + Send>> {
+		// Labels are resolved to the ID they were given to.
+		let executor_id = self
+			.labels
+			.get(executor_id)
+			.map_or(executor_id, |(id, _)| id.as_str());
+		let (mut repo, mut active_executors) = self.map_write_locks()?;
+
+		// The host executor may not have been referenced by any configuration,
+		// but it's always possible to create.
+		if executor_id == "host" && !repo.contains_key(executor_id) {
+			if host::Executor::is_compatible() != CompatibilityStatus::Compatible {
+				warn!("The host executor is not compatible with this system, and cannot be used with `--executor`.");
+				return None;
+			}
+			repo.insert(
+				executor_id.to_owned(),
+				Arc::new(
+					host::Executor::new(&self.root_dir).with_shell(self.default_shell.clone()),
+				),
+			);
+		}
+
+		let executor = if let Some(executor) = repo.get(executor_id) {
+			executor.clone()
+		} else {
+			let mut known_ids = repo.keys().map(String::as_str).collect::<Vec<&str>>();
+			known_ids.sort_unstable();
+			let mut known_labels = self
+				.labels
+				.keys()
+				.map(String::as_str)
+				.collect::<Vec<&str>>();
+			known_labels.sort_unstable();
+			warn!(
+				"Executor: [{}] passed with `--executor` does not exist. Known executor IDs are: [{}], known labels are: [{}]",
+				executor_id,
+				known_ids.join(", "),
+				known_labels.join(", "),
+			);
+			return None;
+		};
+
+		if let Some(needs) = task.get_execution_needs() {
+			if !executor.meets_requirements(needs) {
+				warn!(
+					"Executor: [{}] passed with `--executor` does not meet the `execution_needs` of task: [{}] defined in: [{}]",
+					executor_id,
+					task.get_name(),
+					task.get_source_path(),
+				);
+				return None;
+			}
+		}
+
+		debug!(
+			"Task: [{}] is being forced onto executor: [{}]",
+			task.get_name(),
+			executor_id,
+		);
+		if !active_executors.contains(executor_id) {
+			active_executors.insert(executor_id.to_owned());
+		}
+		Some(executor)
+	}
+
 	/// Perform selection of a particular executor for a task.
 	///
 	/// `task`: The actual task configuration.
+	/// `executor_override`: The ID of an executor that must be used, ignoring
+	///                      the normal selection precedence.
 	pub async fn select_executor(
 		&mut self,
 		task: &TaskConf,
+		executor_override: Option<&str>,
 	) -> Option<Arc<dyn Executor + Sync + Send>> {
 		// How dev-loop chooses an executor (precedence):
 		//
+		// 0. If the user has explicitly asked for an executor, use that or nothing.
 		// 1. If a custom_executor is specified, use that.
 		// 2. Next try to select an existing executor based off of the execution_needs
 		//    field. This checks "active" executors first (even custom ones!), and then
@@ -326,6 +625,10 @@ impl ExecutorRepository {
 		// incase a custom executor is specified.
 		let hash_builder = RandomXxHashBuilder64::default();
 
+		if let Some(executor_id) = executor_override {
+			return self.select_overridden_executor(task, executor_id);
+		}
+
 		// If a user has specified a custom executor.
 		// This must be used.
 		if let Some(custom_executor_config) = task.get_custom_executor() {
@@ -333,8 +636,36 @@ impl ExecutorRepository {
 				"Task: [{}] has specified custom executor... using",
 				task.get_name()
 			);
-			let resulting_executor =
-				Self::instantiate_executor(&self.root_dir, custom_executor_config).await;
+
+			// A labeled custom executor that has already been created (e.g. by
+			// another task sharing it) is reused rather than created again.
+			if let Some(label) = custom_executor_config.get_label() {
+				if let Some((labeled_id, labeled_conf)) = self.labels.get(label) {
+					if labeled_conf != custom_executor_config {
+						warn!(
+							"The label: [{}] of the custom executor for task: [{}] defined in: [{}] is already the label of executor: [{}]. Executor labels must be unique.",
+							label,
+							task.get_name(),
+							task.get_source_path(),
+							labeled_id,
+						);
+						return None;
+					}
+
+					let labeled_id = labeled_id.clone();
+					let (repo, mut active_executors) = self.map_write_locks()?;
+					let executor = repo.get(&labeled_id)?.clone();
+					active_executors.insert(labeled_id);
+					return Some(executor);
+				}
+			}
+
+			let resulting_executor = Self::instantiate_executor(
+				&self.root_dir,
+				custom_executor_config,
+				self.default_shell.as_deref(),
+			)
+			.await;
 
 			if let Err(resulting_err) = resulting_executor {
 				warn!(
@@ -371,7 +702,19 @@ impl ExecutorRepository {
 			if !active_executors.contains(&potential_id) {
 				active_executors.insert(potential_id.clone());
 			}
-			return Some(repo.get(&potential_id).unwrap().clone());
+			let executor = repo.get(&potential_id).unwrap().clone();
+			drop(repo);
+			drop(active_executors);
+			if !self.declaration_order.contains(&potential_id) {
+				self.declaration_order.push(potential_id.clone());
+			}
+			if let Some(label) = custom_executor_config.get_label() {
+				self.labels.insert(
+					label.to_owned(),
+					(potential_id, custom_executor_config.clone()),
+				);
+			}
+			return Some(executor);
 		}
 
 		let (repo, active_executors) = self.map_read_locks()?;
@@ -382,28 +725,27 @@ impl ExecutorRepository {
 			);
 
 			// Check active executors first
-			for id in &*active_executors {
-				let executor = repo.get(id).unwrap();
-				if executor.meets_requirements(needs) {
-					debug!(
-						"Task: [{}] has it's requirements met by already active executor: [{}]",
-						task.get_name(),
-						id,
-					);
-					return Some(executor.clone());
-				}
+			if let Some(executor) = Self::select_by_needs(
+				task,
+				needs,
+				&repo,
+				&self.declaration_order,
+				active_executors.iter(),
+				"already active executor",
+			) {
+				return Some(executor);
 			}
 
 			// Check all again, yes this means we will check some twice.
-			for (id, exec) in &*repo {
-				if exec.meets_requirements(needs) {
-					debug!(
-						"Task: [{}] has it's requirements met by executor: [{}]",
-						task.get_name(),
-						id,
-					);
-					return Some(exec.clone());
-				}
+			if let Some(executor) = Self::select_by_needs(
+				task,
+				needs,
+				&repo,
+				&self.declaration_order,
+				repo.keys(),
+				"executor",
+			) {
+				return Some(executor);
 			}
 
 			warn!(
@@ -428,6 +770,87 @@ impl ExecutorRepository {
 		}
 	}
 
+	/// Run the `teardown` hooks for every executor that has been setup.
+	///
+	/// Failures are logged, but don't stop other executors from being torn down.
+	pub async fn teardown(&self) {
+		let executors = if let Ok(repo) = self.repo.read() {
+			repo.values().cloned().collect::<Vec<_>>()
+		} else {
+			warn!("Unknown State tearing down executors, please report as an issue. Maintainer Info: [repo_read_mutex_failure]");
+			return;
+		};
+
+		for executor in executors {
+			if let Err(teardown_err) = executor.teardown().await {
+				warn!(
+					"{:?}",
+					teardown_err.wrap_err("Failed to run `teardown` hook for executor")
+				);
+			}
+		}
+	}
+
+	/// Select the executor that meets the needs of a task out of a series of
+	/// candidates.
+	///
+	/// The candidates come out of maps/sets, and the IDs of docker executors
+	/// are random, so when multiple executors meet the needs the one declared
+	/// first is chosen. This keeps selection the same from run to run.
+	///
+	/// `task`: the task being selected for.
+	/// `needs`: the execution needs of the task.
+	/// `repo`: the repository of executors.
+	/// `declaration_order`: the IDs of executors in the order they were declared.
+	/// `candidate_ids`: the ids of the executors to choose from.
+	/// `kind`: what kind of executors are being chosen from, used for logging.
+	fn select_by_needs<'a>(
+		task: &TaskConf,
+		needs: &[NeedsRequirement],
+		repo: &ExecutorHashMapType,
+		declaration_order: &[String],
+		candidate_ids: impl Iterator<Item = &'a String>,
+		kind: &str,
+	) -> Option<AtomicRefExecutorMapType> {
+		let mut matching_ids = candidate_ids
+			.filter(|id| {
+				repo.get(*id)
+					.is_some_and(|executor| executor.meets_requirements(needs))
+			})
+			.collect::<Vec<_>>();
+		matching_ids.sort_by_key(|id| {
+			(
+				declaration_order
+					.iter()
+					.position(|declared_id| declared_id == *id)
+					.unwrap_or(usize::MAX),
+				*id,
+			)
+		});
+
+		let selected_id = matching_ids.first()?;
+		if matching_ids.len() > 1 {
+			debug!(
+				"Task: [{}] has it's requirements met equally by the executors: [{}], choosing: [{}] as it was declared first.",
+				task.get_name(),
+				matching_ids
+					.iter()
+					.map(|id| id.as_str())
+					.collect::<Vec<_>>()
+					.join(", "),
+				selected_id,
+			);
+		}
+		debug!(
+			"Task: [{}] has it's requirements met by {}: [{}]",
+			task.get_name(),
+			kind,
+			selected_id,
+		);
+
+		repo.get(*selected_id).cloned()
+	}
+
 	// Hash a particular string with an XxHash instance.
 	fn hash_string(to_hash: &str, mut hasher: XxHash64) -> String {
 		hasher.write(to_hash.as_bytes());
@@ -449,10 +872,11 @@ impl ExecutorRepository {
 	///
 	/// `rd`: The root directory for dev-loop.
 	/// `conf`: The configuration for the executor to create.
-	/// `hasher`: used to create unique ids.
+	/// `default_shell`: the shell for a host executor that doesn't set its own.
 	async fn instantiate_executor(
 		rd: &PathBuf,
 		conf: &ExecutorConf,
+		default_shell: Option<&str>,
 	) -> Result<(String, Arc<dyn Executor + Send + Sync>)> {
 		// Help the type checker out.
 		let ret_v: Result<(String, Arc<dyn Executor + Send + Sync>)> = match *conf.get_type() {
@@ -479,7 +903,17 @@ impl ExecutorRepository {
 						));
 					}
 				}
-				let he = host::Executor::new(rd)?;
+				let provides = conf.get_provided();
+				let shell = conf
+					.get_parameters()
+					.get(host::SHELL_ARG)
+					.cloned()
+					.or_else(|| default_shell.map(ToOwned::to_owned));
+				let he = host::Executor::new(rd)
+					.with_shell(shell)
+					.with_hooks(ExecutorHooks::new(conf))
+					.with_provides(&provides)
+					.with_probed_versions(&provides);
 				Ok(("host".to_owned(), Arc::new(he)))
 			}
 			ExecutorType::Docker => {
@@ -508,7 +942,10 @@ impl ExecutorRepository {
 
 				let params = conf.get_parameters();
 				let provides = conf.get_provided();
-				let de = docker::Executor::new(rd, &params, &provides, None)?;
+				let de = docker::Executor::new(rd, &params, &provides, None)?
+					.with_hooks(ExecutorHooks::new(conf))
+					.with_probed_versions(&provides)
+					.await;
 				Ok((de.get_container_name().to_owned(), Arc::new(de)))
 			}
 		};
@@ -1,15 +1,235 @@
 use crate::{
+	config::types::{ExecutorConf, NeedsRequirement, ProvideConf},
 	dirs::{get_tmp_dir, mark_as_world_editable, mark_file_as_executable, rewrite_tmp_dir},
-	executors::ExecutableTask,
+	executors::{ExecutableTask, ProvidedTools},
 };
 
-use color_eyre::{eyre::WrapErr, Result, Section};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
+use semver::{Version, VersionReq};
 use std::{
+	collections::HashMap,
+	ffi::OsStr,
 	fs::{create_dir_all, write as write_file, File},
-	path::PathBuf,
+	future::Future,
+	path::{Path, PathBuf},
 	time::{SystemTime, UNIX_EPOCH},
 };
-use tracing::warn;
+use tracing::{info, warn};
+
+cfg_if::cfg_if! {
+  if #[cfg(unix)] {
+		use std::os::unix::ffi::OsStrExt;
+  }
+}
+
+/// The shell tasks are run with when one isn't chosen.
+pub const DEFAULT_SHELL_PATH: &str = "/usr/bin/env bash";
+
+/// The `setup`/`teardown` hooks for an executor, along with whether or not
+/// setup has been run yet.
+#[derive(Debug, Default)]
+pub struct ExecutorHooks {
+	/// The command to run the first time the executor runs a task.
+	setup: Option<String>,
+	/// The command to run when the executor is cleaned up.
+	teardown: Option<String>,
+	/// `None` if the executor has not run a task yet, otherwise if the setup
+	/// hook succeeded.
+	setup_status: async_std::sync::Mutex<Option<bool>>,
+}
+
+impl ExecutorHooks {
+	/// Create the hooks for a particular executor configuration.
+	#[must_use]
+	pub fn new(conf: &ExecutorConf) -> Self {
+		Self {
+			setup: conf.get_setup().cloned(),
+			teardown: conf.get_teardown().cloned(),
+			setup_status: async_std::sync::Mutex::new(None),
+		}
+	}
+
+	/// Ensure the setup hook has been run, running it with `run_hook` if this
+	/// is the first time we've been called.
+	///
+	/// Tasks running at the same time will wait for setup to finish.
+	///
+	/// # Errors
+	///
+	/// - If the setup hook fails (now, or previously).
+	pub async fn ensure_setup<F, Fut>(&self, run_hook: F) -> Result<()>
+	where
+		F: FnOnce(String) -> Fut,
+		Fut: Future<Output = Result<i32>>,
+	{
+		let mut status = self.setup_status.lock().await;
+		match *status {
+			Some(true) => return Ok(()),
+			Some(false) => {
+				return Err(eyre!(
+					"The `setup` hook for this executor failed, not running any tasks on it."
+				));
+			}
+			None => {}
+		}
+
+		let result = if let Some(setup) = &self.setup {
+			info!("Running `setup` hook for executor: [{}]", setup);
+			match run_hook(setup.clone()).await {
+				Ok(0) => Ok(()),
+				Ok(rc) => Err(eyre!(
+					"The `setup` hook: [{}] for this executor exited with: [{}]",
+					setup,
+					rc,
+				))
+				.note("Any task that uses this executor will not be run."),
+				Err(err) => Err(err.wrap_err(format!(
+					"Failed to run the `setup` hook: [{}] for this executor",
+					setup
+				))),
+			}
+		} else {
+			Ok(())
+		};
+
+		*status = Some(result.is_ok());
+		result
+	}
+
+	/// Get the teardown hook to run, only if this executor was setup
+	/// successfully.
+	pub async fn get_teardown_to_run(&self) -> Option<String> {
+		if *self.setup_status.lock().await == Some(true) {
+			self.teardown.clone()
+		} else {
+			None
+		}
+	}
+}
+
+/// Turn the configured list of provided tools into a map of tool name, to the
+/// version provided (if a valid one was configured).
+///
+/// Versions that fail to parse are warned about, and treated as if no version
+/// was configured.
+///
+/// `provided_conf`: the list of tools an executor provides.
+#[must_use]
+pub fn build_provides(provided_conf: &[ProvideConf]) -> HashMap<String, Option<Version>> {
+	let mut provides = HashMap::new();
+	for provided in provided_conf {
+		let version_opt = if provided.get_version().is_empty() {
+			None
+		} else {
+			match Version::parse(provided.get_version()) {
+				Ok(version) => Some(version),
+				Err(parse_err) => {
+					warn!(
+						"Tool: [{}] is provided with version: [{}] which is not a valid semver version ({}), so it will not meet any requirement that asks for a version.",
+						provided.get_name(),
+						provided.get_version(),
+						parse_err,
+					);
+					None
+				}
+			}
+		};
+
+		provides.insert(provided.get_name().to_owned(), version_opt);
+	}
+
+	provides
+}
+
+/// Get the provided tools sorted by their name.
+///
+/// `provides`: the tools provided, built with `build_provides`.
+#[must_use]
+pub fn sorted_provided_tools(provides: &HashMap<String, Option<Version>>) -> ProvidedTools {
+	let mut tools = provides
+		.iter()
+		.map(|(name, version)| (name.clone(), version.clone()))
+		.collect::<Vec<_>>();
+	tools.sort_by(|left, right| left.0.cmp(&right.0));
+	tools
+}
+
+/// Determine if a set of provided tools meets all of the requirements.
+///
+/// A requirement with a version matcher is only met when the tool provides a
+/// version that matches. Matchers that aren't valid are ignored.
+///
+/// `provides`: the tools provided, built with `build_provides`.
+/// `reqs`: the requirements to check.
+#[must_use]
+pub fn provides_meet_requirements(
+	provides: &HashMap<String, Option<Version>>,
+	reqs: &[NeedsRequirement],
+) -> bool {
+	let mut met = true;
+
+	for req in reqs {
+		if !provides.contains_key(req.get_name()) {
+			met = false;
+			break;
+		}
+
+		if let Some(matcher) = req.get_version_matcher() {
+			if let Ok(version_req) = VersionReq::parse(matcher) {
+				if let Some(version) = provides.get(req.get_name()).unwrap() {
+					if !version_req.matches(version) {
+						met = false;
+						break;
+					}
+				} else {
+					met = false;
+				}
+			} else {
+				continue;
+			}
+		}
+	}
+
+	met
+}
+
+/// Parse a version out of the output of a `version_command`, e.g.
+/// `Python 3.9.1`, or `v18.2.0`.
+///
+/// The first word that looks like a version is used. Missing minor/patch
+/// versions are treated as `0`, so `go1.16` becomes `1.16.0`.
+#[must_use]
+pub fn parse_version_from_output(output: &str) -> Option<Version> {
+	for word in output.split(|c: char| c.is_whitespace() || c == ',') {
+		let version_start = if let Some(idx) = word.find(|c: char| c.is_ascii_digit()) {
+			idx
+		} else {
+			continue;
+		};
+		let version_str: String = word[version_start..]
+			.chars()
+			.take_while(|c| c.is_ascii_digit() || *c == '.')
+			.collect();
+
+		let mut parts = version_str
+			.split('.')
+			.filter(|part| !part.is_empty())
+			.map(str::parse::<u64>);
+		let major = match parts.next() {
+			Some(Ok(major)) => major,
+			_ => continue,
+		};
+		let minor = parts.next().and_then(std::result::Result::ok).unwrap_or(0);
+		let patch = parts.next().and_then(std::result::Result::ok).unwrap_or(0);
+
+		return Some(Version::new(major, minor, patch));
+	}
+
+	None
+}
 
 /// Create the shared directory to execute in.
 pub fn create_executor_shared_dir(pipeline_id: &str) -> Result<PathBuf> {
@@ -53,17 +273,72 @@ pub fn create_log_proxy_files(
 	Ok((stdout_log_path, stderr_log_path))
 }
 
+/// Get the raw bytes of a value, which on unix don't need to be UTF-8.
+#[must_use]
+fn os_str_as_bytes(value: &OsStr) -> Vec<u8> {
+	cfg_if::cfg_if! {
+		if #[cfg(unix)] {
+			value.as_bytes().to_vec()
+		} else {
+			value.to_string_lossy().as_bytes().to_vec()
+		}
+	}
+}
+
+/// Quote a value (like a path) so it's a single word in a shell script, this
+/// works on the raw bytes of the value so it doesn't need to be UTF-8.
+///
+/// Quoting a UTF-8 value always produces UTF-8.
+#[must_use]
+pub fn shell_quote<V: AsRef<OsStr> + ?Sized>(value: &V) -> Vec<u8> {
+	let mut quoted = vec![b'\''];
+	for byte in os_str_as_bytes(value.as_ref()) {
+		if byte == b'\'' {
+			quoted.extend_from_slice(b"'\\''");
+		} else {
+			quoted.push(byte);
+		}
+	}
+	quoted.push(b'\'');
+	quoted
+}
+
 /// Create an entrypoint to run for tasks.
+///
+/// If the task has standard input, it is written next to the task script,
+/// and redirected into the task by the entrypoint. This works the same for
+/// every executor, including ones (like docker) where we don't attach to the
+/// processes stdin.
+///
+/// The entrypoint exports `DL_PIPELINE_ID`, `DL_WORKER_INDEX`, and
+/// `DL_TASK_NAME` so a task knows the context it's being executed in.
+///
+/// Tasks with an explicit interpreter are run through it. The helper line is
+/// still sourced (it also exports any configured variables), but helper
+/// functions are only exported to bash tasks since nothing else can use them.
+///
+/// Everything the entrypoint runs itself is POSIX, so it works when
+/// `shell_path` isn't bash (e.g. `/bin/sh` on alpine). Helper functions are
+/// only exported when the entrypoint is actually running in bash. The shell
+/// is stored in `DL_SHELL` so helpers can be checked with it before they are
+/// sourced.
+///
+/// The directory to run in (usually the project root) is written into the
+/// entrypoint as raw bytes, so it does not need to be UTF-8.
+///
+/// `shell_path`: the shell the entrypoint is run with (it's shebang).
 #[allow(clippy::too_many_arguments)]
 pub fn create_entrypoint(
-	project_root: &str,
+	working_dir: &Path,
 	tmp_dir: &str,
 	shared_dir: PathBuf,
 	helper_src_line: &str,
 	task: &ExecutableTask,
+	worker_count: usize,
 	rewrite_tmp: bool,
 	stdout_log_path: Option<String>,
 	stderr_log_path: Option<String>,
+	shell_path: &str,
 ) -> Result<PathBuf> {
 	let mut task_path = shared_dir.clone();
 	task_path.push(format!("{}.sh", task.get_task_name()));
@@ -80,43 +355,75 @@ pub fn create_entrypoint(
 	write_file(&task_path, task.get_contents().get_contents())
 		.wrap_err("Failed to copy your task script to temporary directory")?;
 
-	let mut entrypoint_script = format!(
-		"#!/usr/bin/env bash
+	let stdin_path = if let Some(stdin) = task.get_stdin() {
+		let mut stdin_path = entrypoint_path.clone();
+		stdin_path.set_file_name(format!("{}-stdin", task.get_task_name()));
+		write_file(&stdin_path, stdin)
+			.wrap_err("Failed to write your tasks stdin to temporary directory")?;
 
-{opening_bracket}
+		Some(if rewrite_tmp {
+			rewrite_tmp_dir(tmp_dir, &stdin_path)
+		} else {
+			stdin_path.to_string_lossy().to_string()
+		})
+	} else {
+		None
+	};
 
-cd {project_root}
+	let quoted_script = String::from_utf8_lossy(&shell_quote(&script_to_run)).to_string();
+	let (export_helpers, script_command) = if let Some(interpreter) = task.get_interpreter() {
+		(
+			"# Helper functions are only available to bash tasks.",
+			format!("{} {}", interpreter, quoted_script),
+		)
+	} else {
+		(
+			"[ -z \"${BASH_VERSION:-}\" ] || eval \"$(declare -F | sed -e 's/-f /-fx /')\"",
+			quoted_script,
+		)
+	};
+
+	let mut entrypoint_script = format!("#!{}\n\n{{\n\ncd ", shell_path).into_bytes();
+	entrypoint_script.extend(shell_quote(working_dir));
+	entrypoint_script.extend_from_slice(b"\n\nDL_SHELL=");
+	entrypoint_script.extend(shell_quote(shell_path));
+	entrypoint_script.extend_from_slice(
+		format!(
+			"
+export DL_PIPELINE_ID='{pipeline_id}'
+export DL_WORKER_INDEX='{worker_index}'
+export DL_TASK_NAME='{task_name}'
 
 # Source Helpers
 {helper}
 
-eval \"$(declare -F | sed -e 's/-f /-fx /')\"
+{export_helpers}
 
 {script} {arg_str}
 
 {closing_bracket}",
-		opening_bracket = "{",
-		project_root = project_root,
-		helper = helper_src_line,
-		script = script_to_run,
-		arg_str = task.get_arg_string(),
-		closing_bracket = "}",
+			pipeline_id = task.get_pipeline_id(),
+			worker_index = worker_count,
+			task_name = task.get_task_name(),
+			helper = helper_src_line,
+			export_helpers = export_helpers,
+			script = script_command,
+			arg_str = task.get_arg_string(),
+			closing_bracket = "}",
+		)
+		.as_bytes(),
 	);
-	match (stdout_log_path.is_some(), stderr_log_path.is_some()) {
-		(true, true) => {
-			entrypoint_script += &format!(
-				" >{} 2>{}",
-				stdout_log_path.unwrap(),
-				stderr_log_path.unwrap()
-			);
-		}
-		(true, false) => {
-			entrypoint_script += &format!(" >{}", stdout_log_path.unwrap());
-		}
-		(false, true) => {
-			entrypoint_script += &format!(" 2>{}", stderr_log_path.unwrap());
-		}
-		(false, false) => {}
+	if let Some(stdout_log_path) = stdout_log_path {
+		entrypoint_script.extend_from_slice(b" >");
+		entrypoint_script.extend(shell_quote(&stdout_log_path));
+	}
+	if let Some(stderr_log_path) = stderr_log_path {
+		entrypoint_script.extend_from_slice(b" 2>");
+		entrypoint_script.extend(shell_quote(&stderr_log_path));
+	}
+	if let Some(stdin_path) = stdin_path {
+		entrypoint_script.extend_from_slice(b" <");
+		entrypoint_script.extend(shell_quote(&stdin_path));
 	}
 
 	write_file(&entrypoint_path, entrypoint_script).wrap_err("Failed to write entrypoint file")?;
@@ -130,3 +437,119 @@ eval \"$(declare -F | sed -e 's/-f /-fx /')\"
 		Ok(entrypoint_path)
 	}
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+	use crate::{dirs::TestDir, executors::host::Executor as HostExecutor, fetch::FetchedItem};
+	use std::{fs::read_to_string, sync::Arc};
+
+	#[test]
+	fn invalid_versions_only_meet_unversioned_requirements() {
+		let provides = build_provides(&[ProvideConf::new(
+			"node".to_owned(),
+			Some("eighteen".to_owned()),
+		)]);
+		assert_eq!(provides.get("node"), Some(&None));
+		assert!(provides_meet_requirements(
+			&provides,
+			&[NeedsRequirement::new("node".to_owned(), None)],
+		));
+		assert!(!provides_meet_requirements(
+			&provides,
+			&[NeedsRequirement::new(
+				"node".to_owned(),
+				Some(">= 1.0.0".to_owned()),
+			)],
+		));
+	}
+
+	#[test]
+	fn parses_versions_from_output() {
+		assert_eq!(
+			parse_version_from_output("Python 3.9.1\n"),
+			Some(Version::new(3, 9, 1))
+		);
+		assert_eq!(
+			parse_version_from_output("v18.2.0"),
+			Some(Version::new(18, 2, 0))
+		);
+		assert_eq!(
+			parse_version_from_output("go version go1.16 linux/amd64"),
+			Some(Version::new(1, 16, 0))
+		);
+		assert_eq!(
+			parse_version_from_output("rustc 1.50.0 (cb75ad5db 2021-02-10)"),
+			Some(Version::new(1, 50, 0))
+		);
+		assert_eq!(parse_version_from_output("command not found"), None);
+		assert_eq!(parse_version_from_output(""), None);
+	}
+
+	#[test]
+	fn quotes_paths() {
+		assert_eq!(
+			shell_quote(Path::new("/home/me/my project")),
+			b"'/home/me/my project'".to_vec()
+		);
+		assert_eq!(
+			shell_quote(Path::new("/home/me/it's")),
+			b"'/home/me/it'\\''s'".to_vec()
+		);
+		assert_eq!(shell_quote("it's me"), b"'it'\\''s me'".to_vec());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn quotes_non_utf8_paths() {
+		let path = Path::new(OsStr::from_bytes(b"/home/me/caf\xe9"));
+		assert_eq!(shell_quote(path), b"'/home/me/caf\xe9'".to_vec());
+	}
+
+	#[test]
+	fn runs_entrypoints_in_paths_with_spaces() {
+		let test_dir = TestDir::new("entrypoint");
+		let project_root = test_dir.get_path().join("my project");
+		let shared_dir = test_dir.get_path().join("it's shared");
+		create_dir_all(&project_root).unwrap();
+		create_dir_all(&shared_dir).unwrap();
+		let stdout_log_path = shared_dir.join("out log");
+		let stderr_log_path = shared_dir.join("err log");
+
+		let task = ExecutableTask::new(
+			Vec::new(),
+			Arc::new(HostExecutor::new(&project_root)),
+			FetchedItem::new(
+				b"#!/usr/bin/env bash\npwd\ncat\necho err >&2\n".to_vec(),
+				"test.sh".to_owned(),
+			),
+			false,
+			"pipeline".to_owned(),
+			"test".to_owned(),
+			Some(b"from stdin".to_vec()),
+		);
+		let entrypoint = create_entrypoint(
+			&project_root,
+			"",
+			shared_dir,
+			"",
+			&task,
+			0,
+			false,
+			Some(stdout_log_path.to_string_lossy().to_string()),
+			Some(stderr_log_path.to_string_lossy().to_string()),
+			DEFAULT_SHELL_PATH,
+		)
+		.unwrap();
+		assert!(std::process::Command::new(&entrypoint)
+			.status()
+			.unwrap()
+			.success());
+
+		assert_eq!(
+			read_to_string(&stdout_log_path).unwrap(),
+			format!("{}\nfrom stdin", project_root.display())
+		);
+		assert_eq!(read_to_string(&stderr_log_path).unwrap(), "err\n");
+	}
+}
@@ -6,48 +6,296 @@ use crate::{
 };
 use color_eyre::{eyre::eyre, Result, Section};
 use std::{
-	fs::{canonicalize, read_dir, File},
+	ffi::OsStr,
+	fs::{canonicalize, read_dir, read_to_string, File},
 	io::Read,
-	path::PathBuf,
+	path::{Path, PathBuf},
 };
-use tracing::trace;
+use tracing::{trace, warn};
 
 /// Deteremines if a path is a child of a parent.
 ///
+/// This compares path components, rather than strings so neither path needs
+/// to be UTF-8.
+///
 /// `parent` - the parent path.
 /// `child` - the child to check if a child of the parent.
 #[must_use]
-fn path_is_child_of_parent(parent: &PathBuf, child: &PathBuf) -> bool {
-	let parent_as_str = parent.to_str();
-	let child_as_str = child.to_str();
+fn path_is_child_of_parent(parent: &Path, child: &Path) -> bool {
+	child.starts_with(parent)
+}
+
+/// Determine if a path fetched for a location is allowed, paths have to be
+/// inside of the project unless the location explicitly opted out with
+/// `allow_outside_root`.
+///
+/// `location`: the location being fetched.
+/// `project_root`: the root of the project.
+/// `path`: the (canonicalized) path being fetched.
+///
+/// # Errors
+///
+/// - When the path is outside of the project, and the location didn't opt in.
+fn ensure_path_allowed(location: &LocationConf, project_root: &Path, path: &Path) -> Result<()> {
+	if path_is_child_of_parent(project_root, path) {
+		return Ok(());
+	}
 
-	if parent_as_str.is_none() || child_as_str.is_none() {
-		return false;
+	if location.allows_outside_root() {
+		warn!(
+			"Fetching: [{:?}] from outside of the project directory: [{:?}] because the location: [{}] has `allow_outside_root` set. This may not exist on other machines.",
+			path,
+			project_root,
+			location.get_at(),
+		);
+		return Ok(());
+	}
+
+	Err(eyre!(
+		"Path: [{:?}] is not part of the project directory: [{:?}]",
+		path,
+		project_root,
+	))
+		.note("This is required so other people running your project who may not have the same directories as you can use your project.")
+		.suggestion("Keep all project files inside the project, or set `allow_outside_root: true` on the location if this is intentional.")
+}
+
+/// Determine if the name of a file matches a filename filter.
+///
+/// A filter starting with `.` (like `.sh`) matches files with that extension,
+/// anything else has to be the exact name of the file. This way a filter of
+/// `dl-tasks.yml` doesn't also match a file like `my-dl-tasks.yml`.
+///
+/// `file_name`: the name of the file (not the full path).
+/// `filter`: the filter to match against.
+#[must_use]
+fn file_name_matches_filter(file_name: &str, filter: &str) -> bool {
+	if let Some(extension) = filter.strip_prefix('.') {
+		Path::new(file_name).extension().and_then(OsStr::to_str) == Some(extension)
+	} else {
+		file_name == filter
 	}
+}
+
+/// Determine if a location string contains any glob characters.
+#[must_use]
+pub fn is_glob_pattern(location: &str) -> bool {
+	location.contains('*') || location.contains('?')
+}
 
-	let parent_str = parent_as_str.unwrap();
-	let child_str = child_as_str.unwrap();
+/// Determine if a single path component matches a single glob component.
+///
+/// Supports `*` (any amount of characters), and `?` (exactly one character).
+#[must_use]
+fn glob_component_matches(pattern: &[char], component: &[char]) -> bool {
+	match (pattern.first(), component.first()) {
+		(None, None) => true,
+		(Some('*'), _) => {
+			glob_component_matches(&pattern[1..], component)
+				|| (!component.is_empty() && glob_component_matches(pattern, &component[1..]))
+		}
+		(Some('?'), Some(_)) => glob_component_matches(&pattern[1..], &component[1..]),
+		(Some(pattern_char), Some(component_char)) => {
+			pattern_char == component_char && glob_component_matches(&pattern[1..], &component[1..])
+		}
+		_ => false,
+	}
+}
 
-	child_str.starts_with(parent_str)
+/// Determine if a series of path components match a series of glob components.
+///
+/// A glob component of `**` matches any amount of directories (including none).
+#[must_use]
+pub fn glob_matches(pattern: &[&str], path: &[&str]) -> bool {
+	match pattern.first() {
+		None => path.is_empty(),
+		Some(&"**") => {
+			glob_matches(&pattern[1..], path)
+				|| (!path.is_empty() && glob_matches(pattern, &path[1..]))
+		}
+		Some(component_pattern) => {
+			!path.is_empty()
+				&& glob_component_matches(
+					&component_pattern.chars().collect::<Vec<char>>(),
+					&path[0].chars().collect::<Vec<char>>(),
+				) && glob_matches(&pattern[1..], &path[1..])
+		}
+	}
 }
 
-/// Iterate a directory, getting all possible directory entries.
+/// Split a glob location into the directory we should start searching at
+/// (everything before the first glob component), and the remaining pattern.
 ///
+/// A leading `/` is kept on the base, so an absolute location stays absolute
+/// when it's pushed onto a root directory.
+#[must_use]
+pub fn split_glob_location(location: &str) -> (String, Vec<&str>) {
+	let mut base = Vec::new();
+	let mut pattern = Vec::new();
+
+	for component in location.split('/').filter(|c| !c.is_empty()) {
+		if pattern.is_empty() && !is_glob_pattern(component) {
+			base.push(component);
+		} else {
+			pattern.push(component);
+		}
+	}
+
+	let base = base.join("/");
+	if location.starts_with('/') {
+		(format!("/{}", base), pattern)
+	} else {
+		(base, pattern)
+	}
+}
+
+/// The name of the file that lists what to ignore when fetching a directory.
+const IGNORE_FILE_NAME: &str = ".dlignore";
+
+/// A single line of a `.dlignore` file.
+#[derive(Debug, PartialEq)]
+struct IgnorePattern {
+	/// The glob components to match.
+	components: Vec<String>,
+	/// If the pattern is relative to the root (it contained a `/`), rather
+	/// than matching a file name at any depth.
+	anchored: bool,
+	/// If the pattern only matches directories (it ended with a `/`).
+	directory_only: bool,
+	/// If the pattern re-includes a path (it started with a `!`).
+	negated: bool,
+}
+
+/// The patterns from a `.dlignore` file, which uses the same syntax as a
+/// `.gitignore` file. Patterns are relative to the directory being fetched.
+#[derive(Debug, Default)]
+pub struct DlIgnore {
+	patterns: Vec<IgnorePattern>,
+}
+
+impl DlIgnore {
+	/// Parse the contents of a `.dlignore` file.
+	#[must_use]
+	pub fn parse(contents: &str) -> Self {
+		let patterns = contents
+			.lines()
+			.filter_map(|line| {
+				let line = line.trim_end();
+				if line.is_empty() || line.starts_with('#') {
+					return None;
+				}
+
+				let (negated, line) = if let Some(stripped) = line.strip_prefix('!') {
+					(true, stripped)
+				} else {
+					(false, line.strip_prefix('\\').unwrap_or(line))
+				};
+				let directory_only = line.ends_with('/');
+				let line = line.trim_end_matches('/');
+				let anchored = line.contains('/');
+				let components = line
+					.split('/')
+					.filter(|component| !component.is_empty())
+					.map(String::from)
+					.collect::<Vec<String>>();
+				if components.is_empty() {
+					return None;
+				}
+
+				Some(IgnorePattern {
+					components,
+					anchored,
+					directory_only,
+					negated,
+				})
+			})
+			.collect();
+
+		Self { patterns }
+	}
+
+	/// Load the `.dlignore` file at the root of a directory, if there is one.
+	#[must_use]
+	pub fn load(dir: &Path) -> Self {
+		read_to_string(dir.join(IGNORE_FILE_NAME))
+			.map(|contents| Self::parse(&contents))
+			.unwrap_or_default()
+	}
+
+	/// Determine if a path (relative to the directory being fetched) should
+	/// be ignored. Like a `.gitignore` the last pattern that matches wins.
+	#[must_use]
+	pub fn is_ignored(&self, relative_path: &Path, is_dir: bool) -> bool {
+		let components = relative_path
+			.components()
+			.filter_map(|component| component.as_os_str().to_str())
+			.collect::<Vec<&str>>();
+		let file_name = if let Some(file_name) = components.last() {
+			file_name
+		} else {
+			return false;
+		};
+
+		let mut ignored = false;
+		for pattern in &self.patterns {
+			if pattern.directory_only && !is_dir {
+				continue;
+			}
+			let pattern_components = pattern
+				.components
+				.iter()
+				.map(String::as_str)
+				.collect::<Vec<&str>>();
+			let matches = if pattern.anchored {
+				glob_matches(&pattern_components, &components)
+			} else {
+				glob_matches(&pattern_components, &[file_name])
+			};
+			if matches {
+				ignored = !pattern.negated;
+			}
+		}
+
+		ignored
+	}
+}
+
+/// Iterate a directory, getting all possible directory entries that aren't
+/// ignored by a `.dlignore` file.
+///
+/// Ignored directories are never descended into, and the `.dlignore` file
+/// itself is never returned.
+///
+/// `root`: the directory being fetched, that ignore patterns are relative to.
 /// `dir`: the directory to iterate over.
 /// `should_recurse`: if we should recursively look at this directory.
+/// `ignore`: the patterns of paths to ignore.
 ///
 /// # Errors
 ///
 /// If we fail to open up the directory, and iterate over the files.
-fn iterate_directory(dir: &PathBuf, should_recurse: bool) -> Result<Vec<PathBuf>> {
+fn iterate_directory_ignoring(
+	root: &PathBuf,
+	dir: &PathBuf,
+	should_recurse: bool,
+	ignore: &DlIgnore,
+) -> Result<Vec<PathBuf>> {
 	let mut results = Vec::new();
 
 	let entries = read_dir(dir)?;
 	for entry in entries {
 		let found_path = entry?.path();
+		let relative_path = found_path.strip_prefix(root).unwrap_or(&found_path);
+		if relative_path == Path::new(IGNORE_FILE_NAME)
+			|| ignore.is_ignored(relative_path, found_path.is_dir())
+		{
+			trace!("Skipping path ignored by `.dlignore`: [{:?}]", found_path);
+			continue;
+		}
 
 		if found_path.is_dir() && should_recurse {
-			let new_results = iterate_directory(&found_path, should_recurse)?;
+			let new_results =
+				iterate_directory_ignoring(root, &found_path, should_recurse, ignore)?;
 			results.extend(new_results);
 		} else if found_path.is_file() {
 			results.push(found_path);
@@ -94,7 +342,7 @@ fn read_path_as_item_blocking(file: &PathBuf, project_root: &PathBuf) -> Result<
 /// immediately break on someone elses machine (who doesn't have the
 /// same path). This does mean it may be harder to get stood up in some cases,
 /// but the end result will be better.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct PathFetcher {}
 
 impl PathFetcher {
@@ -132,28 +380,33 @@ impl PathFetcher {
 		// Running say a script from /usr/bin/blah is inherently un-repeatable.
 		// Within an actual bash script it's okay because that bash script may
 		// be running in docker or remotely which may always have that tool there.
+		//
+		// A location can still opt out with `allow_outside_root`, for things like
+		// a sibling directory in a monorepo.
+		if is_glob_pattern(location.get_at()) {
+			return Self::fetch_glob_from_fs(location, project_root, root_dir);
+		}
+
 		let mut built_path = root_dir.clone();
 		built_path.push(location.get_at());
 		let canonicalized = canonicalize(built_path)?;
-		if !path_is_child_of_parent(project_root, &canonicalized) {
-			return Err(eyre!(
-				"Path: [{:?}] is not part of the project directory: [{:?}]",
-				&canonicalized,
-				project_root,
-			))
-				.note("This is required so other people running your project who may not have the same directories as you can use your project.")
-				.suggestion("Keep all project files inside the project.");
-		}
+		ensure_path_allowed(location, project_root, &canonicalized)?;
 
 		let mut results = Vec::new();
 
 		if canonicalized.is_dir() {
-			let path_entries = iterate_directory(&canonicalized, location.get_recurse())?;
+			let path_entries = iterate_directory_ignoring(
+				&canonicalized,
+				&canonicalized,
+				location.get_recurse(),
+				&DlIgnore::load(&canonicalized),
+			)?;
 
 			if let Some(ffn) = filter_filename {
 				for file_to_read in path_entries {
-					if let Some(utf8_str) = file_to_read.to_str() {
-						if utf8_str.ends_with(&ffn) {
+					// Only the file name needs to be UTF-8, the project root may not be.
+					if let Some(file_name) = file_to_read.file_name().and_then(OsStr::to_str) {
+						if file_name_matches_filter(file_name, &ffn) {
 							results.push(read_path_as_item_blocking(&file_to_read, project_root)?);
 						}
 					}
@@ -174,4 +427,235 @@ impl PathFetcher {
 
 		Ok(results)
 	}
+
+	/// Fetch all the files matching a glob pattern like: `scripts/**/*.sh`.
+	///
+	/// A glob location is explicit about what it wants, so no filename filter
+	/// is applied on top of it.
+	///
+	/// `location`: the location to fetch from.
+	/// `project_root`: the root of the project, all matches must be inside of it.
+	/// `root_dir`: the directory the glob is relative to.
+	///
+	/// # Errors
+	///
+	/// - When the non-glob part of the path is outside the project directory.
+	/// - When there is an issue reading from the filesystem.
+	fn fetch_glob_from_fs(
+		location: &LocationConf,
+		project_root: &PathBuf,
+		root_dir: &PathBuf,
+	) -> Result<Vec<FetchedItem>> {
+		let (base, pattern) = split_glob_location(location.get_at());
+
+		let mut built_path = root_dir.clone();
+		built_path.push(&base);
+		let canonicalized_base = canonicalize(built_path)?;
+		ensure_path_allowed(location, project_root, &canonicalized_base)?;
+		if !canonicalized_base.is_dir() {
+			return Err(eyre!(
+				"The glob: [{}] starts at: [{:?}] which is not a directory.",
+				location.get_at(),
+				canonicalized_base,
+			));
+		}
+
+		let mut results = Vec::new();
+		let ignore = DlIgnore::load(&canonicalized_base);
+		for file_to_read in
+			iterate_directory_ignoring(&canonicalized_base, &canonicalized_base, true, &ignore)?
+		{
+			let relative_path = if let Ok(stripped) = file_to_read.strip_prefix(&canonicalized_base)
+			{
+				stripped
+			} else {
+				continue;
+			};
+			let relative_components = relative_path
+				.components()
+				.filter_map(|component| component.as_os_str().to_str())
+				.collect::<Vec<&str>>();
+			if !glob_matches(&pattern, &relative_components) {
+				continue;
+			}
+
+			// A symlink could still point outside of the project.
+			let canonicalized = canonicalize(&file_to_read)?;
+			if !location.allows_outside_root()
+				&& !path_is_child_of_parent(project_root, &canonicalized)
+			{
+				trace!(
+					"Skipping glob match: [{:?}] as it is not part of the project directory.",
+					canonicalized
+				);
+				continue;
+			}
+
+			results.push(read_path_as_item_blocking(&canonicalized, project_root)?);
+		}
+
+		Ok(results)
+	}
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn glob_matching() {
+		assert!(glob_matches(&["*.sh"], &["build.sh"]));
+		assert!(!glob_matches(&["*.sh"], &["nested", "build.sh"]));
+		assert!(glob_matches(&["**", "*.sh"], &["build.sh"]));
+		assert!(glob_matches(&["**", "*.sh"], &["a", "b", "build.sh"]));
+		assert!(!glob_matches(&["**", "*.sh"], &["a", "b", "build.yml"]));
+		assert!(glob_matches(&["a", "**", "c"], &["a", "c"]));
+		assert!(glob_matches(&["a", "**", "c"], &["a", "b", "b", "c"]));
+		assert!(glob_matches(&["build-?.sh"], &["build-1.sh"]));
+		assert!(!glob_matches(&["build-?.sh"], &["build-10.sh"]));
+	}
+
+	#[test]
+	fn filename_filter_matching() {
+		assert!(file_name_matches_filter("dl-tasks.yml", "dl-tasks.yml"));
+		assert!(!file_name_matches_filter("my-dl-tasks.yml", "dl-tasks.yml"));
+		assert!(!file_name_matches_filter(
+			"dl-tasks.yml.bak",
+			"dl-tasks.yml"
+		));
+		assert!(file_name_matches_filter("build.sh", ".sh"));
+		assert!(file_name_matches_filter("build.test.sh", ".sh"));
+		assert!(!file_name_matches_filter("build.bash", ".sh"));
+		assert!(!file_name_matches_filter("build-sh", ".sh"));
+		assert!(!file_name_matches_filter(".sh", ".sh"));
+	}
+
+	#[test]
+	fn fetches_only_filtered_files() {
+		let test_dir = crate::dirs::TestDir::new("fetch-filter");
+		let project_root = test_dir.get_path().to_path_buf();
+		let tasks_dir = project_root.join("tasks");
+		std::fs::create_dir_all(tasks_dir.join("nested")).unwrap();
+		for file_name in &[
+			"dl-tasks.yml",
+			"nested/dl-tasks.yml",
+			"evil-dl-tasks.yml",
+			"dl-tasks.yml.orig",
+			"nested/xdl-tasks.yml",
+		] {
+			std::fs::write(tasks_dir.join(file_name), "tasks: []\n").unwrap();
+		}
+		let project_root = canonicalize(&project_root).unwrap();
+
+		let location =
+			serde_yaml::from_str::<LocationConf>("type: path\nat: tasks\nrecurse: true\n").unwrap();
+		let mut fetched = async_std::task::block_on(PathFetcher::default().fetch_from_fs(
+			&location,
+			&project_root,
+			&project_root,
+			Some("dl-tasks.yml".to_owned()),
+		))
+		.unwrap()
+		.iter()
+		.map(|item| item.get_source().to_owned())
+		.collect::<Vec<_>>();
+		fetched.sort();
+		assert_eq!(fetched.len(), 2);
+		assert!(fetched[0].ends_with("tasks/dl-tasks.yml"));
+		assert!(fetched[1].ends_with("tasks/nested/dl-tasks.yml"));
+	}
+
+	#[test]
+	fn fetches_outside_root_only_when_allowed() {
+		let test_dir = crate::dirs::TestDir::new("outside-root");
+		let test_root = test_dir.get_path();
+		std::fs::create_dir_all(test_root.join("project")).unwrap();
+		std::fs::create_dir_all(test_root.join("shared")).unwrap();
+		std::fs::write(test_root.join("shared").join("dl-tasks.yml"), "tasks: []\n").unwrap();
+		let project_root = canonicalize(test_root.join("project")).unwrap();
+
+		let fetch = |location: &str| {
+			let location = serde_yaml::from_str::<LocationConf>(location).unwrap();
+			async_std::task::block_on(PathFetcher::default().fetch_from_fs(
+				&location,
+				&project_root,
+				&project_root,
+				Some("dl-tasks.yml".to_owned()),
+			))
+		};
+		assert!(fetch("type: path\nat: ../shared\n").is_err());
+		assert_eq!(
+			fetch("type: path\nat: ../shared\nallow_outside_root: true\n")
+				.unwrap()
+				.len(),
+			1
+		);
+	}
+
+	#[test]
+	fn dlignore_matching() {
+		let ignore = DlIgnore::parse(
+			"# Build outputs\ntarget/\n*.bak\n!keep.bak\n/vendor/lib\nscripts/**/generated-*\n",
+		);
+		assert!(ignore.is_ignored(Path::new("target"), true));
+		assert!(ignore.is_ignored(Path::new("nested/target"), true));
+		assert!(!ignore.is_ignored(Path::new("target"), false));
+		assert!(ignore.is_ignored(Path::new("a/old.bak"), false));
+		assert!(!ignore.is_ignored(Path::new("a/keep.bak"), false));
+		assert!(ignore.is_ignored(Path::new("vendor/lib"), true));
+		assert!(!ignore.is_ignored(Path::new("nested/vendor/lib"), true));
+		assert!(ignore.is_ignored(Path::new("scripts/a/generated-1.sh"), false));
+		assert!(!ignore.is_ignored(Path::new("scripts/a/build.sh"), false));
+		assert!(!DlIgnore::default().is_ignored(Path::new("target"), true));
+	}
+
+	#[test]
+	fn glob_location_splitting() {
+		assert_eq!(
+			split_glob_location("scripts/**/*.sh"),
+			("scripts".to_owned(), vec!["**", "*.sh"])
+		);
+		assert_eq!(split_glob_location("*.sh"), (String::new(), vec!["*.sh"]));
+		assert_eq!(
+			split_glob_location("a/b/c-*/d"),
+			("a/b".to_owned(), vec!["c-*", "d"])
+		);
+		assert_eq!(
+			split_glob_location("/shared/scripts/*.sh"),
+			("/shared/scripts".to_owned(), vec!["*.sh"])
+		);
+		assert_eq!(split_glob_location("/*.sh"), ("/".to_owned(), vec!["*.sh"]));
+	}
+
+	#[test]
+	fn fetches_absolute_globs_outside_root() {
+		let test_dir = crate::dirs::TestDir::new("absolute-glob");
+		let test_root = test_dir.get_path();
+		std::fs::create_dir_all(test_root.join("project")).unwrap();
+		std::fs::create_dir_all(test_root.join("shared").join("scripts")).unwrap();
+		std::fs::write(
+			test_root.join("shared").join("scripts").join("build.sh"),
+			"#!/usr/bin/env bash\n",
+		)
+		.unwrap();
+		let project_root = canonicalize(test_root.join("project")).unwrap();
+		let shared_scripts = canonicalize(test_root.join("shared").join("scripts")).unwrap();
+
+		let location = serde_yaml::from_str::<LocationConf>(&format!(
+			"type: path\nat: \"{}/*.sh\"\nallow_outside_root: true\n",
+			shared_scripts.to_string_lossy(),
+		))
+		.unwrap();
+		let fetched = async_std::task::block_on(PathFetcher::default().fetch_from_fs(
+			&location,
+			&project_root,
+			&project_root,
+			None,
+		))
+		.unwrap();
+		assert_eq!(fetched.len(), 1);
+		assert!(fetched[0]
+			.get_source()
+			.starts_with(&shared_scripts.to_string_lossy().to_string()));
+	}
 }
@@ -51,10 +51,16 @@ pub(crate) mod remote;
 
 /// A wrapper around all the fetchers at once, so you just have one type to
 /// deal with.
+///
+/// Cloning is cheap, the HTTP client is shared between every clone.
+#[derive(Clone)]
 pub struct FetcherRepository {
 	http_fetcher: remote::HttpFetcher,
 	path_fetcher: fs::PathFetcher,
 	project_root: PathBuf,
+	/// The directory (relative to the project root) task scripts are fetched
+	/// relative to, and run in, from `exec --cwd`.
+	cwd: Option<PathBuf>,
 }
 
 impl Debug for FetcherRepository {
@@ -70,16 +76,34 @@ impl FetcherRepository {
 	///
 	/// If creating any of the underlying fetchers fails.
 	pub fn new(project_root: PathBuf) -> Result<Self> {
-		let http_fetcher = remote::HttpFetcher::default();
+		let http_fetcher = remote::HttpFetcher::new()?;
 		let path_fetcher = fs::PathFetcher::default();
 
 		Ok(Self {
 			http_fetcher,
 			path_fetcher,
 			project_root,
+			cwd: None,
 		})
 	}
 
+	/// Fetch task scripts relative to a directory inside of the project (and
+	/// run tasks in it), rather than relative to where the task is defined.
+	///
+	/// `cwd`: the directory, relative to the project root.
+	#[must_use]
+	pub fn with_cwd(mut self, cwd: Option<PathBuf>) -> Self {
+		self.cwd = cwd;
+		self
+	}
+
+	/// Get the directory (relative to the project root) task scripts should
+	/// be fetched relative to, and run in, if one has been set.
+	#[must_use]
+	pub fn get_cwd(&self) -> Option<&PathBuf> {
+		self.cwd.as_ref()
+	}
+
 	/// Fetch from a particular location, while filtering on filename.
 	///
 	/// # Errors
@@ -5,15 +5,43 @@ use crate::{
 	fetch::FetchedItem,
 	future_helper::timeout_with_log_msg,
 };
-use color_eyre::{eyre::eyre, Result, Section};
-use isahc::prelude::*;
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
+use isahc::{prelude::*, HttpClient, HttpClientBuilder};
 use std::time::Duration;
 
+/// The longest we'll ever wait on a remote endpoint to respond.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// A fetcher that is capable of fetching from an http like endpoint.
-#[derive(Default)]
-pub struct HttpFetcher {}
+///
+/// The underlying client is built once, and shared for every fetch so
+/// connections (and TLS sessions) can be reused when fetching multiple files
+/// from the same host.
+#[derive(Clone)]
+pub struct HttpFetcher {
+	/// The client used to issue every request.
+	client: HttpClient,
+}
 
 impl HttpFetcher {
+	/// Construct a new `HttpFetcher`, along with the client it uses.
+	///
+	/// # Errors
+	///
+	/// - When the underlying HTTP Client fails to be constructed.
+	pub fn new() -> Result<Self> {
+		let client = HttpClientBuilder::new()
+			.timeout(HTTP_TIMEOUT)
+			.build()
+			.wrap_err("Failed to construct HTTP-Client for fetching remote locations")
+			.suggestion("This is an internal error, please file an issue.")?;
+
+		Ok(Self { client })
+	}
+
 	/// Fetch a HTTP Location.
 	///
 	/// # Errors
@@ -32,7 +60,6 @@ impl HttpFetcher {
 		}
 
 		let log_dur = Duration::from_secs(3);
-		let dur = Duration::from_secs(30);
 
 		let mut resp = timeout_with_log_msg(
 			format!(
@@ -40,8 +67,8 @@ impl HttpFetcher {
 				location.get_at()
 			),
 			log_dur,
-			dur,
-			isahc::get_async(location.get_at()),
+			HTTP_TIMEOUT,
+			self.client.get_async(location.get_at()),
 		)
 		.await
 		.map_err(|_| {
@@ -1,6 +1,11 @@
 //! Handles any logging utilities that we need in our crate for dev-loop.
 
-use color_eyre::{config::HookBuilder, Result};
+use crate::terminal::ColourMode;
+
+use color_eyre::{
+	config::{HookBuilder, Theme},
+	Result,
+};
 use lazy_static::lazy_static;
 use std::sync::{
 	atomic::{AtomicBool, Ordering},
@@ -44,11 +49,14 @@ enum Format {
 
 /// Initialize the logging for this crate. Should be called at startup.
 ///
+/// `colour_mode`: the `--color` passed on the command line, `never` turns off
+///                colour in logs, and errors.
+///
 /// # Errors
 ///
 /// - If we fail to initialize the log tracer.
 /// - If `color_eyre` fails to initialize.
-pub fn initialize_crate_logging() -> Result<()> {
+pub fn initialize_crate_logging(colour_mode: ColourMode) -> Result<()> {
 	let chosen_format = match std::env::var("RUST_LOG_FORMAT")
 		.as_ref()
 		.map(String::as_str)
@@ -75,13 +83,18 @@ pub fn initialize_crate_logging() -> Result<()> {
 			.unwrap_or_default()
 			.is_empty();
 
-	HookBuilder::new()
+	let mut hook_builder = HookBuilder::new()
 		.capture_span_trace_by_default(add_spantrace)
-		.display_env_section(false)
-		.install()?;
+		.display_env_section(false);
+	if colour_mode == ColourMode::Never {
+		hook_builder = hook_builder.theme(Theme::new());
+	}
+	hook_builder.install()?;
 
 	let filter_layer = EnvFilter::from_default_env().add_directive(chosen_level.into());
-	let fmt_layer = fmt_layer().with_target(false);
+	let fmt_layer = fmt_layer()
+		.with_target(false)
+		.with_ansi(colour_mode != ColourMode::Never);
 	let tracing_layer = TracingSubscriber {};
 
 	match chosen_format {
@@ -14,38 +14,61 @@ pub(crate) mod sigint;
 pub(crate) mod strsim;
 pub(crate) mod tasks;
 pub(crate) mod terminal;
+pub(crate) mod time_helper;
 pub(crate) mod yaml_err;
 
 /// The entrypoint to the application.
 ///
 /// Gets called at the beginning, and performs setup.
 fn main() -> Result<(), Report> {
-	log::initialize_crate_logging()?;
+	// Skip the program name.
+	let all_arguments = std::env::args().skip(1).collect::<Vec<String>>();
+	// Colour is decided before anything else so `--color` applies to
+	// everything, including errors from parsing the rest of the flags.
+	let colour_mode = commands::peek_colour_mode(&all_arguments);
+	terminal::set_colour_mode(colour_mode);
+
+	log::initialize_crate_logging(colour_mode)?;
 	sigint::setup_global_ctrlc_handler()?;
+	sigint::setup_terminal_resize_handler();
 
 	let span = tracing::info_span!("dev-loop");
 	let _span_guard = span.enter();
 
-	let mut program_name = String::new();
-	let mut action = String::new();
-	let mut arguments: Vec<String> = Vec::new();
-
-	for arg in std::env::args() {
-		if program_name.is_empty() {
-			program_name = arg.to_owned();
-		} else if action.is_empty() {
-			action = arg.to_owned();
-		} else {
-			arguments.push(arg.to_owned());
-		}
-	}
+	let (global_flags, command_arguments) = commands::parse_global_flags(&all_arguments)?;
+	// List is the "help" page or the default command.
+	let action = command_arguments
+		.first()
+		.cloned()
+		.unwrap_or_else(|| "list".to_owned());
+	let arguments = command_arguments
+		.iter()
+		.skip(1)
+		.cloned()
+		.collect::<Vec<String>>();
 
-	if action.is_empty() {
-		// List is the "help" page or the default command.
-		action = "list".to_owned();
+	// The version should always be printable, even without a valid configuration.
+	if action == "version" || action == "--version" {
+		commands::version::handle_version_command();
+		return Ok(());
+	}
+	if action == "schema" {
+		return commands::schema::handle_schema_command(&arguments);
+	}
+	let project_root_override =
+		config::resolve_project_root_override(global_flags.project_root.as_deref())?;
+	let config_override = config::resolve_config_override(
+		global_flags.config.as_deref(),
+		project_root_override.as_deref(),
+	)?;
+	// Doctor checks the configuration itself, so it also runs before loading it.
+	if action == "doctor" {
+		return async_std::task::block_on(async {
+			commands::doctor::handle_doctor_command(config_override.as_deref()).await
+		});
 	}
 
-	let tlc_res = config::get_top_level();
+	let tlc_res = config::get_top_level(config_override.as_deref());
 	let errord_on_tlc = tlc_res.is_err();
 	let tlc = if let Err(tlc_err) = tlc_res {
 		// NOTE(cynthia): if you change this print statement, make sure it looks
@@ -64,7 +87,8 @@ fn main() -> Result<(), Report> {
 			.unwrap_or_else(TopLevelConf::create_empty_config)
 	};
 
-	let root_dir_opt = config::get_project_root();
+	let root_dir_opt =
+		project_root_override.or_else(|| config::get_project_root(config_override.as_deref()));
 	let root_dir = if let Some(dir) = root_dir_opt {
 		dir
 	} else if let Ok(dir) = std::env::current_dir() {
@@ -81,7 +105,7 @@ fn main() -> Result<(), Report> {
 	}
 	let fetcher = fetcher_res.unwrap();
 
-	Ok(match action.as_str() {
+	let result = match action.as_str() {
 		"list" => async_std::task::block_on(async {
 			commands::list::handle_list_command(&tlc, &fetcher, &arguments).await
 		}),
@@ -103,22 +127,94 @@ fn main() -> Result<(), Report> {
 				commands::run::handle_run_command(&tlc, &fetcher, &arguments, &root_dir).await
 			})
 		}
-		"clean" => {
-			async_std::task::block_on(async { commands::clean::handle_clean_command().await })
+		"clean" => async_std::task::block_on(async {
+			commands::clean::handle_clean_command(&arguments).await
+		}),
+		"explain" => {
+			if errord_on_tlc {
+				std::process::exit(10);
+			}
+
+			async_std::task::block_on(async {
+				commands::explain::handle_explain_command(&tlc, &fetcher, &arguments, &root_dir)
+					.await
+			})
+		}
+		"--list-executors" => {
+			if errord_on_tlc {
+				std::process::exit(10);
+			}
+
+			async_std::task::block_on(async {
+				commands::list::handle_list_executors_command(&tlc, &fetcher, &root_dir).await
+			})
 		}
 		&_ => {
-			let err = Err(eyre!(
-				"The sub-command: [{}] is not known to dev-loop.",
-				action,
-			));
-
-			strsim::add_did_you_mean_text(
-				err,
-				&action,
-				&["clean", "list", "exec", "run"],
-				2,
-				Some("You can use the `list` sub-command to get a list of commands to run."),
-			)
+			// People often type a task name expecting it to run, so (best-effort)
+			// treat a task name as shorthand for `exec`. Sub-commands always take
+			// precedence, since they've already been matched above.
+			let task_names = if errord_on_tlc {
+				Vec::new()
+			} else {
+				async_std::task::block_on(async {
+					commands::exec::get_runnable_task_names(&tlc, &fetcher).await
+				})
+			};
+			if task_names.contains(&action) {
+				let mut exec_arguments = vec![action];
+				exec_arguments.extend(arguments);
+
+				async_std::task::block_on(async {
+					commands::exec::handle_exec_command(&tlc, &fetcher, &exec_arguments, &root_dir)
+						.await
+				})
+			} else {
+				let mut err = Err(eyre!(
+					"The sub-command: [{}] is not known to dev-loop.",
+					action,
+				));
+				for task_name in &task_names {
+					if strsim::string_differences(&action, task_name)
+						<= strsim::adaptive_distance(task_name)
+					{
+						err = err.suggestion(format!(
+							"Instead of: \"{}\", Did you mean to run the task: `dev-loop exec {}`",
+							action, task_name,
+						));
+					}
+				}
+
+				strsim::add_did_you_mean_text(
+					err,
+					&action,
+					&[
+						"clean",
+						"doctor",
+						"list",
+						"exec",
+						"explain",
+						"run",
+						"schema",
+						"version",
+						"--list-executors",
+					],
+					2,
+					Some("You can use the `list` sub-command to get a list of commands to run."),
+				)
+			}
+		}
+	};
+
+	// Some failures (like being interrupted) have their own exit code, so
+	// scripts can tell them apart from a task failing.
+	if let Err(err) = result {
+		let exit_code = commands::get_failing_exit_code();
+		if exit_code != 1 {
+			eprintln!("Error: {:?}", err);
+			std::process::exit(exit_code);
 		}
-	}?)
+		return Err(err);
+	}
+
+	Ok(())
 }
@@ -10,7 +10,11 @@ use std::sync::{
 	Arc,
 };
 
-/// Determines if Ctrl-C has been hit.
+/// Set when the terminal has been resized, and not yet handled.
+static TERMINAL_RESIZED: AtomicBool = AtomicBool::new(false);
+
+/// Determines if Ctrl-C has been hit (or on unix, if we've been asked to
+/// terminate with `SIGTERM`/`SIGHUP`).
 #[must_use]
 pub fn has_ctrlc_been_hit() -> bool {
 	!RUNNING.clone().load(Ordering::Acquire)
@@ -19,7 +23,9 @@ pub fn has_ctrlc_been_hit() -> bool {
 /// Setup the CTRL-C Handler.
 ///
 /// Watches for Ctrl-C, and properly handles shutdown for an application so
-/// we don't leave junk everywhere.
+/// we don't leave junk everywhere. On unix this also catches `SIGTERM`, and
+/// `SIGHUP` (what CI systems, and container orchestrators send to stop a
+/// process) so they get the same graceful shutdown. Windows only handles Ctrl-C.
 ///
 /// # Errors
 ///
@@ -34,3 +40,30 @@ pub fn setup_global_ctrlc_handler() -> Result<()> {
 
 	Ok(())
 }
+
+#[cfg(unix)]
+extern "C" fn handle_sigwinch(_signal: libc::c_int) {
+	TERMINAL_RESIZED.store(true, Ordering::Release);
+}
+
+/// Setup the terminal resize handler.
+///
+/// On unix this watches for `SIGWINCH` so anything rendering to the terminal
+/// can react to a resize immediately. Other platforms have no such signal,
+/// and just keep polling the size of the terminal.
+pub fn setup_terminal_resize_handler() {
+	#[cfg(unix)]
+	unsafe {
+		libc::signal(
+			libc::SIGWINCH,
+			handle_sigwinch as extern "C" fn(libc::c_int) as libc::sighandler_t,
+		);
+	}
+}
+
+/// Determines if the terminal has been resized since the last time this was
+/// called.
+#[must_use]
+pub fn has_terminal_been_resized() -> bool {
+	TERMINAL_RESIZED.swap(false, Ordering::AcqRel)
+}
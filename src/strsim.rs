@@ -2,6 +2,7 @@
 
 use color_eyre::{Report, Section};
 use std::collections::HashMap;
+use unicode_normalization::UnicodeNormalization;
 
 /// Returns the final index for a value in a single vector that represents a fixed
 /// 2d grid.
@@ -78,12 +79,73 @@ where
 
 /// Determine the "distance" between two strings.
 /// Uses a Damerau Levenshtein distance.
+///
+/// Both strings are NFC normalized first, so the same text typed with
+/// composed, or decomposed characters (e.g. `é` vs `e` + `◌́`) is equal.
 #[must_use]
 pub fn string_differences(a: &str, b: &str) -> usize {
-	let (x, y): (Vec<_>, Vec<_>) = (a.chars().collect(), b.chars().collect());
+	let (x, y): (Vec<_>, Vec<_>) = (a.nfc().collect(), b.nfc().collect());
 	generic_string_differences(x.as_slice(), y.as_slice())
 }
 
+/// The largest distance `adaptive_distance` will ever return, so a long name
+/// isn't suggested for something that barely resembles it.
+const MAX_ADAPTIVE_DISTANCE: usize = 4;
+
+/// Calculate how "far" a typo can be from a candidate while still being
+/// suggested, scaled to the length of the candidate.
+///
+/// This is a quarter of the candidates length (rounded up), capped to
+/// `MAX_ADAPTIVE_DISTANCE`. So short names only suggest on a single typo,
+/// while longer names can have a couple.
+#[must_use]
+pub fn adaptive_distance(candidate: &str) -> usize {
+	let length = candidate.nfc().count();
+	length.div_ceil(4).clamp(1, MAX_ADAPTIVE_DISTANCE)
+}
+
+/// Calculate the suggestions for a typo, using `distance_for` to determine
+/// the distance allowed for each possibility.
+fn calculate_suggestions<'a>(
+	potentially_typod_thing: &str,
+	typo_possibilities: impl Iterator<Item = &'a str>,
+	distance_for: impl Fn(&str) -> usize,
+) -> Vec<String> {
+	typo_possibilities
+		.filter(|typo_possibility| {
+			string_differences(potentially_typod_thing, typo_possibility)
+				<= distance_for(typo_possibility)
+		})
+		.map(|typo_possibility| {
+			format!(
+				"Instead of: \"{}\", Did you mean: \"{}\"",
+				potentially_typod_thing, typo_possibility
+			)
+		})
+		.collect()
+}
+
+/// Add suggestions to an error, or the default message if there are no
+/// suggestions.
+fn add_suggestions<T>(
+	mut result: Result<T, Report>,
+	suggestions: Vec<String>,
+	default_msg: Option<&'static str>,
+) -> Result<T, Report> {
+	let has_suggested = !suggestions.is_empty();
+	for suggestion in suggestions {
+		result = result.suggestion(suggestion);
+	}
+
+	if let Some(def_msg) = default_msg {
+		if !has_suggested {
+			result = result.note(def_msg);
+		}
+	}
+
+	result
+}
+
 /// Calculatea a series of possibilities for potentially typos.
 ///
 /// `potentially_typod_thing`: the thing that was potentially typo'd.
@@ -95,18 +157,29 @@ pub fn calculate_did_you_mean_possibilities(
 	typo_possibilities: &[&str],
 	distance: usize,
 ) -> Vec<String> {
-	let mut suggestions = Vec::new();
-
-	for typo_possibility in typo_possibilities {
-		if string_differences(potentially_typod_thing, typo_possibility) <= distance {
-			suggestions.push(format!(
-				"Instead of: \"{}\", Did you mean: \"{}\"",
-				potentially_typod_thing, typo_possibility
-			));
-		}
-	}
+	calculate_suggestions(
+		potentially_typod_thing,
+		typo_possibilities.iter().copied(),
+		|_| distance,
+	)
+}
 
-	suggestions
+/// Calculatea a series of possibilities for potentially typos, where the
+/// distance is scaled to the length of each possibility (see:
+/// `adaptive_distance`).
+///
+/// `potentially_typod_thing`: the thing that was potentially typo'd.
+/// `typo_possibilities`: the typo possibilities.
+#[must_use]
+pub fn calculate_adaptive_did_you_mean_possibilities(
+	potentially_typod_thing: &str,
+	typo_possibilities: &[&str],
+) -> Vec<String> {
+	calculate_suggestions(
+		potentially_typod_thing,
+		typo_possibilities.iter().copied(),
+		adaptive_distance,
+	)
 }
 
 /// Calculatea a series of possibilities for potentially typos.
@@ -120,18 +193,11 @@ pub fn calculate_did_you_mean_possibilities_str(
 	typo_possibilities: &[String],
 	distance: usize,
 ) -> Vec<String> {
-	let mut suggestions = Vec::new();
-
-	for typo_possibility in typo_possibilities {
-		if string_differences(potentially_typod_thing, typo_possibility) <= distance {
-			suggestions.push(format!(
-				"Instead of: \"{}\", Did you mean: \"{}\"",
-				potentially_typod_thing, typo_possibility
-			));
-		}
-	}
-
-	suggestions
+	calculate_suggestions(
+		potentially_typod_thing,
+		typo_possibilities.iter().map(String::as_str),
+		|_| distance,
+	)
 }
 
 /// Takes an error, and adds `did you mean: "blah"` notes when possible.
@@ -149,29 +215,74 @@ pub fn calculate_did_you_mean_possibilities_str(
 ///
 /// If we have added a suggestion.
 pub fn add_did_you_mean_text<T>(
-	mut result: Result<T, Report>,
+	result: Result<T, Report>,
 	potentially_typod_thing: &str,
 	typo_possibilities: &[&str],
 	distance: usize,
 	default_msg: Option<&'static str>,
 ) -> Result<T, Report> {
-	let mut has_suggested = false;
+	add_suggestions(
+		result,
+		calculate_did_you_mean_possibilities(potentially_typod_thing, typo_possibilities, distance),
+		default_msg,
+	)
+}
 
-	for typo_possibility in typo_possibilities {
-		if string_differences(potentially_typod_thing, typo_possibility) <= distance {
-			result = result.suggestion(format!(
-				"Instead of: \"{}\", Did you mean: \"{}\"",
-				potentially_typod_thing, typo_possibility
-			));
-			has_suggested = true;
-		}
-	}
+/// Takes an error, and adds `did you mean: "blah"` notes when possible, where
+/// the distance is scaled to the length of each possibility (see:
+/// `adaptive_distance`).
+///
+/// `result`: the result to add text too.
+/// `potentially_typod_thing`: the thing to check against the list of possiblities.
+/// `typo_possiblities`: the list of possibilities to match against.
+///
+/// # Errors
+///
+/// When there is an error in the result parameter.
+pub fn add_adaptive_did_you_mean_text<T>(
+	result: Result<T, Report>,
+	potentially_typod_thing: &str,
+	typo_possibilities: &[&str],
+	default_msg: Option<&'static str>,
+) -> Result<T, Report> {
+	add_suggestions(
+		result,
+		calculate_adaptive_did_you_mean_possibilities(potentially_typod_thing, typo_possibilities),
+		default_msg,
+	)
+}
 
-	if let Some(def_msg) = default_msg {
-		if !has_suggested {
-			result = result.note(def_msg);
-		}
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn normalizes_unicode() {
+		let composed = "caf\u{e9}";
+		let decomposed = "cafe\u{301}";
+		assert_ne!(composed, decomposed);
+		assert_eq!(string_differences(composed, decomposed), 0);
+		assert_eq!(string_differences("caf\u{e9}s", decomposed), 1);
+		assert_eq!(string_differences(composed, "cafe"), 1);
 	}
 
-	result
+	#[test]
+	fn scales_distance_with_length() {
+		assert_eq!(adaptive_distance(""), 1);
+		assert_eq!(adaptive_distance("ls"), 1);
+		assert_eq!(adaptive_distance("test"), 1);
+		assert_eq!(adaptive_distance("build"), 2);
+		assert_eq!(adaptive_distance("integration-test"), 4);
+		assert_eq!(adaptive_distance("a-really-long-task-name-for-ci"), 4);
+
+		// Short names shouldn't match something that's mostly different.
+		assert!(calculate_adaptive_did_you_mean_possibilities("ab", &["cd"]).is_empty());
+		assert_eq!(
+			calculate_adaptive_did_you_mean_possibilities(
+				"integraton-tst",
+				&["integration-test", "lint"]
+			),
+			vec!["Instead of: \"integraton-tst\", Did you mean: \"integration-test\"".to_owned()],
+		);
+	}
 }
@@ -0,0 +1,394 @@
+//! Support for collecting the `artifacts` of a task (like test reports) into
+//! the `--output-dir` once the task has finished.
+//!
+//! Every executor runs tasks inside of the project root (the docker executor
+//! mounts it into the container), so artifacts relative to the project root
+//! are collected from the project root on the host after the task finishes.
+//! Artifacts with an absolute path only exist where the task ran (like inside
+//! of a docker container), so they're copied out by the executor instead.
+
+use crate::{
+	config::types::TaskConf,
+	dirs::{get_tmp_dir, set_directory_mode},
+	executors::Executor,
+	fetch::fs::{glob_matches, is_glob_pattern, split_glob_location},
+};
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
+use std::{
+	fs::{copy as copy_file, create_dir_all, read_dir, remove_dir_all, remove_file},
+	path::{Component, Path, PathBuf},
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+cfg_if::cfg_if! {
+  if #[cfg(unix)] {
+		use std::{fs::read_link, os::unix::fs::symlink};
+  }
+}
+
+/// Everything needed to collect the artifacts of a task.
+#[derive(Debug)]
+pub struct ArtifactCollector {
+	/// The root of the project, that artifacts are relative to.
+	root_dir: PathBuf,
+	/// The paths (or globs) of the artifacts to collect, and if they're required.
+	artifacts: Vec<(String, bool)>,
+}
+
+impl ArtifactCollector {
+	/// Create the artifact collector for a task, returns `None` if the task
+	/// has not declared any `artifacts`.
+	///
+	/// `root_dir`: the root of the project, that artifacts are relative to.
+	/// `task`: the configuration of the task.
+	#[must_use]
+	pub fn new(root_dir: &Path, task: &TaskConf) -> Option<Self> {
+		let artifacts = task
+			.get_artifacts()?
+			.iter()
+			.map(|artifact| (artifact.get_path().to_owned(), artifact.is_required()))
+			.collect::<Vec<_>>();
+		if artifacts.is_empty() {
+			return None;
+		}
+
+		Some(Self {
+			root_dir: root_dir.to_path_buf(),
+			artifacts,
+		})
+	}
+
+	/// Copy every artifact into `<output_dir>/<task_name>/`, keeping the path
+	/// they had relative to the project root (or their absolute path without
+	/// the leading `/` for artifacts outside of the project).
+	///
+	/// An artifact that doesn't match anything is warned about, unless it is
+	/// required in which case it's an error.
+	///
+	/// `executor`: the executor that ran the task, which artifacts outside of
+	///             the project are copied out of.
+	/// `task_name`: the name of the task.
+	/// `output_dir`: the directory to collect artifacts into.
+	///
+	/// # Errors
+	///
+	/// - When a required artifact doesn't match anything.
+	/// - When an artifact could not be copied.
+	pub async fn collect(
+		&self,
+		executor: &(dyn Executor + Sync + Send),
+		task_name: &str,
+		output_dir: &Path,
+	) -> Result<()> {
+		let task_output_dir = output_dir.join(task_name);
+
+		for (pattern, required) in &self.artifacts {
+			if Path::new(pattern)
+				.components()
+				.any(|component| component == Component::ParentDir)
+			{
+				return Err(eyre!(
+					"The artifact: [{}] for task: [{}] contains a `..`.",
+					pattern,
+					task_name,
+				))
+				.suggestion("Artifacts need to be relative to the root of the project (or absolute paths where the task runs), without any `..`.");
+			}
+
+			if Path::new(pattern).is_absolute() {
+				if is_glob_pattern(pattern) {
+					return Err(eyre!(
+						"The artifact: [{}] for task: [{}] is outside of the project, and uses a glob.",
+						pattern,
+						task_name,
+					))
+					.suggestion("Only artifacts relative to the root of the project can use globs, collect the directory instead.");
+				}
+
+				let collected = collect_from_executor(executor, pattern, &task_output_dir)
+					.await
+					.wrap_err(format!(
+						"Failed to collect the artifact: [{}] for task: [{}]",
+						pattern, task_name,
+					))?;
+				if !collected {
+					report_unmatched(pattern, *required, task_name)?;
+				}
+				continue;
+			}
+
+			let matched = expand_artifact(&self.root_dir, pattern);
+			if matched.is_empty() {
+				report_unmatched(pattern, *required, task_name)?;
+				continue;
+			}
+
+			for source in matched {
+				let relative = source.strip_prefix(&self.root_dir).unwrap_or(&source);
+				let destination = task_output_dir.join(relative);
+				debug!(
+					"Collecting artifact: [{:?}] into: [{:?}]",
+					source, destination
+				);
+				copy_recursively(&source, &destination)
+					.wrap_err(format!(
+						"Failed to collect the artifact: [{:?}] for task: [{}]",
+						source, task_name,
+					))
+					.note(format!("Tried to copy it to: [{:?}]", destination))?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Warn about an artifact that didn't match anything, or error if it's
+/// required.
+///
+/// # Errors
+///
+/// - When the artifact is required.
+fn report_unmatched(pattern: &str, required: bool, task_name: &str) -> Result<()> {
+	if required {
+		return Err(eyre!(
+			"The required artifact: [{}] for task: [{}] did not match anything.",
+			pattern,
+			task_name,
+		))
+		.note("Artifacts are relative to the root of the project, unless they're absolute paths.");
+	}
+
+	warn!(
+		"The artifact: [{}] for task: [{}] did not match anything, not collecting it.",
+		pattern, task_name,
+	);
+	Ok(())
+}
+
+/// Collect an artifact outside of the project through the executor that ran
+/// the task, into `<task_output_dir>/<path without the leading />`.
+///
+/// Returns `false` if the artifact doesn't exist.
+///
+/// # Errors
+///
+/// - When the executor couldn't copy the artifact out.
+/// - When the artifact couldn't be copied into the output directory.
+async fn collect_from_executor(
+	executor: &(dyn Executor + Sync + Send),
+	path: &str,
+	task_output_dir: &Path,
+) -> Result<bool> {
+	// The executor copies into a directory it can write to (a docker container
+	// has the temporary directory mounted), and it's moved to the output
+	// directory from there.
+	let staging_dir = get_tmp_dir().join(format!("dl-artifact-{}", Uuid::new_v4()));
+	create_dir_all(&staging_dir)
+		.wrap_err("Failed to create a directory to copy the artifact into")?;
+	set_directory_mode(&staging_dir, 0o777)?;
+
+	let collected = match executor.copy_to_host(path, &staging_dir).await {
+		Ok(Some(copied)) => {
+			let destination = task_output_dir.join(path.trim_start_matches('/'));
+			debug!("Collecting artifact: [{}] into: [{:?}]", path, destination);
+			copy_recursively(&copied, &destination)
+				.wrap_err(format!(
+					"Failed to copy the artifact to: [{:?}]",
+					destination
+				))
+				.map(|_| true)
+		}
+		Ok(None) => Ok(false),
+		Err(copy_err) => Err(copy_err),
+	};
+	let _ = remove_dir_all(&staging_dir);
+
+	collected
+}
+
+/// Find every path underneath `root_dir` an artifact matches.
+///
+/// Artifacts without any glob syntax are simply joined onto the root, and
+/// returned if they exist (so they can be a directory, or a symlink even if
+/// it's broken). Globs only ever match files (or symlinks). The results are
+/// sorted so they're stable.
+fn expand_artifact(root_dir: &Path, pattern: &str) -> Vec<PathBuf> {
+	if !is_glob_pattern(pattern) {
+		let path = root_dir.join(pattern);
+		return if path.symlink_metadata().is_ok() {
+			vec![path]
+		} else {
+			Vec::new()
+		};
+	}
+
+	let (base, glob) = split_glob_location(pattern);
+	let base_dir = root_dir.join(base);
+	let mut matched = list_files_without_following(&base_dir)
+		.unwrap_or_default()
+		.into_iter()
+		.filter(|path| {
+			let relative = if let Ok(relative) = path.strip_prefix(&base_dir) {
+				relative
+			} else {
+				return false;
+			};
+			let components = relative
+				.components()
+				.filter_map(|component| component.as_os_str().to_str())
+				.collect::<Vec<&str>>();
+			glob_matches(&glob, &components)
+		})
+		.collect::<Vec<_>>();
+	matched.sort();
+	matched
+}
+
+/// List every file (or symlink) in a directory, and all of it's children.
+///
+/// Unlike fetching a directory, symlinks to directories are never descended
+/// into, so a glob can't match anything outside of the directory, or loop
+/// forever.
+fn list_files_without_following(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	for entry in read_dir(dir)? {
+		let entry = entry?;
+		if entry.file_type()?.is_dir() {
+			files.extend(list_files_without_following(&entry.path())?);
+		} else {
+			files.push(entry.path());
+		}
+	}
+
+	Ok(files)
+}
+
+/// Copy a file, or directory (and everything in it) to a destination,
+/// creating any parent directories needed.
+///
+/// Symlinks are copied as links (pointing at the same target), rather than
+/// followed. So a link can't pull in anything from outside of the artifact,
+/// or loop forever.
+fn copy_recursively(source: &Path, destination: &Path) -> std::io::Result<()> {
+	let file_type = source.symlink_metadata()?.file_type();
+	if file_type.is_dir() {
+		create_dir_all(destination)?;
+		for entry in read_dir(source)? {
+			let entry = entry?;
+			copy_recursively(&entry.path(), &destination.join(entry.file_name()))?;
+		}
+		return Ok(());
+	}
+
+	if let Some(parent) = destination.parent() {
+		create_dir_all(parent)?;
+	}
+	if file_type.is_symlink() {
+		// Replace what a previous run collected, like copying a file would.
+		if destination.symlink_metadata().is_ok() {
+			remove_file(destination)?;
+		}
+		copy_symlink(source, destination)
+	} else {
+		copy_file(source, destination).map(|_| ())
+	}
+}
+
+// Create a link at `destination` pointing at the same target as `source`.
+#[cfg(unix)]
+fn copy_symlink(source: &Path, destination: &Path) -> std::io::Result<()> {
+	symlink(read_link(source)?, destination)
+}
+
+// Without symlinks, copy what the link points at.
+#[cfg(not(unix))]
+fn copy_symlink(source: &Path, destination: &Path) -> std::io::Result<()> {
+	copy_file(source, destination).map(|_| ())
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+	use crate::{dirs::TestDir, executors::host::Executor as HostExecutor};
+
+	#[test]
+	fn collects_artifacts() {
+		let test_dir = TestDir::new("artifacts");
+		let root_dir = test_dir.get_path();
+		let output_dir = root_dir.join("out");
+		let executor = HostExecutor::new(root_dir);
+		create_dir_all(root_dir.join("reports").join("unit")).unwrap();
+		std::fs::write(root_dir.join("reports").join("unit").join("a.xml"), "a").unwrap();
+		std::fs::write(root_dir.join("reports").join("b.xml"), "b").unwrap();
+		std::fs::write(root_dir.join("reports").join("c.txt"), "c").unwrap();
+
+		let task = serde_yaml::from_str::<TaskConf>(
+			"name: test\nartifacts:\n  - reports/**/*.xml\n  - missing.log\n",
+		)
+		.unwrap();
+		let collector = ArtifactCollector::new(root_dir, &task).unwrap();
+		async_std::task::block_on(collector.collect(&executor, "test", &output_dir)).unwrap();
+		assert!(output_dir.join("test/reports/unit/a.xml").exists());
+		assert!(output_dir.join("test/reports/b.xml").exists());
+		assert!(!output_dir.join("test/reports/c.txt").exists());
+		// Collecting again replaces what was already collected.
+		async_std::task::block_on(collector.collect(&executor, "test", &output_dir)).unwrap();
+
+		let required = serde_yaml::from_str::<TaskConf>(
+			"name: test\nartifacts:\n  - path: missing.log\n    required: true\n",
+		)
+		.unwrap();
+		assert!(async_std::task::block_on(
+			ArtifactCollector::new(root_dir, &required)
+				.unwrap()
+				.collect(&executor, "test", &output_dir)
+		)
+		.is_err());
+
+		// The host executor has nowhere outside of the project to copy from.
+		let outside =
+			serde_yaml::from_str::<TaskConf>("name: test\nartifacts:\n  - /var/log/report.xml\n")
+				.unwrap();
+		assert!(async_std::task::block_on(
+			ArtifactCollector::new(root_dir, &outside).unwrap().collect(
+				&executor,
+				"test",
+				&output_dir
+			)
+		)
+		.is_err());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn collects_symlinks_as_links() {
+		let test_dir = TestDir::new("artifact-links");
+		let root_dir = test_dir.get_path();
+		let output_dir = root_dir.join("out");
+		let executor = HostExecutor::new(root_dir);
+		create_dir_all(root_dir.join("reports")).unwrap();
+		std::fs::write(root_dir.join("reports").join("a.xml"), "a").unwrap();
+		// Links are copied as links, even ones that point at a parent.
+		symlink("..", root_dir.join("reports").join("parent")).unwrap();
+
+		let task = serde_yaml::from_str::<TaskConf>(
+			"name: test\nartifacts:\n  - reports/**/*\n  - reports/parent\n",
+		)
+		.unwrap();
+		let collector = ArtifactCollector::new(root_dir, &task).unwrap();
+		async_std::task::block_on(collector.collect(&executor, "test", &output_dir)).unwrap();
+		assert!(output_dir.join("test/reports/a.xml").exists());
+		assert_eq!(
+			read_link(output_dir.join("test/reports/parent")).unwrap(),
+			Path::new(".."),
+		);
+		// Collecting again replaces the link that was already collected.
+		async_std::task::block_on(collector.collect(&executor, "test", &output_dir)).unwrap();
+	}
+}
@@ -0,0 +1,99 @@
+//! Support for limiting how many steps of a `parallel-pipeline` (through
+//! `max_concurrency`) run at once.
+//!
+//! Every step of a parallel-pipeline is pushed into the work queue as it's
+//! own unit of work, so the limit is shared between all of them, and each
+//! worker takes a slot before it runs one.
+
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	Arc,
+};
+
+/// A limit on how many units of work may run at once.
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+	/// The most units of work that may run at once.
+	max: usize,
+	/// How many units of work are running right now.
+	running: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+	/// Create a new limit, that allows `max` units of work to run at once.
+	#[must_use]
+	pub fn new(max: usize) -> Self {
+		Self {
+			max,
+			running: AtomicUsize::new(0),
+		}
+	}
+
+	/// Attempt to take a slot, returns false if the limit has been reached.
+	pub fn try_acquire(&self) -> bool {
+		self.running
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+				if running < self.max {
+					Some(running + 1)
+				} else {
+					None
+				}
+			})
+			.is_ok()
+	}
+
+	/// Give back a slot taken with `try_acquire`.
+	pub fn release(&self) {
+		let _ = self
+			.running
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+				running.checked_sub(1)
+			});
+	}
+}
+
+/// Attempt to take a slot from every limit, either every slot is taken or
+/// none of them are.
+///
+/// `limits`: the limits to take a slot from (a parallel-pipeline nested in
+///           another one is under both limits).
+pub fn try_acquire_all(limits: &[Arc<ConcurrencyLimit>]) -> bool {
+	for (idx, limit) in limits.iter().enumerate() {
+		if !limit.try_acquire() {
+			release_all(&limits[..idx]);
+			return false;
+		}
+	}
+
+	true
+}
+
+/// Give back the slots taken with `try_acquire_all`.
+pub fn release_all(limits: &[Arc<ConcurrencyLimit>]) {
+	for limit in limits {
+		limit.release();
+	}
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn limits_concurrency() {
+		let outer = Arc::new(ConcurrencyLimit::new(2));
+		let inner = Arc::new(ConcurrencyLimit::new(1));
+		let both = vec![outer.clone(), inner.clone()];
+
+		assert!(try_acquire_all(&both));
+		// The inner limit is full, so the outer slot should be given back.
+		assert!(!try_acquire_all(&both));
+		assert!(try_acquire_all(std::slice::from_ref(&outer)));
+		assert!(!try_acquire_all(std::slice::from_ref(&outer)));
+
+		release_all(&both);
+		assert!(try_acquire_all(&[inner]));
+		assert!(outer.try_acquire());
+		assert!(!outer.try_acquire());
+	}
+}
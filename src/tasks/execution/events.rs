@@ -0,0 +1,226 @@
+//! Support for turning everything tasks report while running into structured
+//! `ExecutionEvent`s, which is what the terminal `TaskIndicator` renders.
+//!
+//! Tasks send their output (in whatever chunks it was read), and the events
+//! for starting/finishing to an `EventForwarder`. The forwarder splits output
+//! into lines, makes sure a task's output always comes after it started, and
+//! before it finished, and sends every event to each of it's sinks: the
+//! indicator, and optionally something else (like another UI) that wants to
+//! follow along.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::{
+	collections::{HashMap, HashSet},
+	time::Instant,
+};
+
+/// A single thing that happened while running tasks.
+///
+/// Tasks are identified the same way the terminal identifies them, the task
+/// name prefixed with the worker that ran it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecutionEvent {
+	/// A task started, and when it started.
+	TaskStarted(String, Instant),
+	/// A task finished, when it finished, it's exit code, and if it's allowed
+	/// to fail (with `allow_failure`).
+	TaskFinished(String, Instant, i32, bool),
+	/// A task was skipped (because it's `only_if` condition failed), and when
+	/// it was skipped.
+	TaskSkipped(String, Instant),
+	/// A single line of output from a task (without the trailing newline),
+	/// and if it came from STDERR.
+	LogLine(String, String, bool),
+}
+
+/// Turns everything tasks send into `ExecutionEvent`s, and sends them to
+/// every sink.
+pub struct EventForwarder {
+	/// The receiver for output from tasks.
+	log_receiver: Receiver<(String, String, bool)>,
+	/// The receiver for tasks starting/finishing/etc.
+	task_receiver: Receiver<ExecutionEvent>,
+	/// Where to send events.
+	sinks: Vec<Sender<ExecutionEvent>>,
+	/// Output that hasn't been sent yet, by task, and if it's STDERR.
+	line_buffers: HashMap<(String, bool), String>,
+	/// The tasks that have started, but not yet finished.
+	running: HashSet<String>,
+}
+
+impl EventForwarder {
+	/// Create a new forwarder.
+	///
+	/// `sinks`: where to send every event.
+	///
+	/// Returns a tuple of:
+	///   1. The forwarder, which needs to have `forward` called regularly.
+	///   2. A channel sender for tasks to send their output to.
+	///   3. A channel sender for tasks to send when they start/finish/etc.
+	#[must_use]
+	pub fn new(
+		sinks: Vec<Sender<ExecutionEvent>>,
+	) -> (Self, Sender<(String, String, bool)>, Sender<ExecutionEvent>) {
+		let (log_sender, log_receiver) = unbounded();
+		let (task_sender, task_receiver) = unbounded();
+
+		(
+			Self {
+				log_receiver,
+				task_receiver,
+				sinks,
+				line_buffers: HashMap::new(),
+				running: HashSet::new(),
+			},
+			log_sender,
+			task_sender,
+		)
+	}
+
+	/// Forward everything that has been sent so far.
+	///
+	/// Output is sent a line at a time once the task that sent it has
+	/// started, and whatever's left is sent right before the task finishes.
+	pub fn forward(&mut self) {
+		self.receive_output();
+
+		while let Ok(event) = self.task_receiver.try_recv() {
+			match &event {
+				ExecutionEvent::TaskStarted(task_name, _) => {
+					self.running.insert(task_name.clone());
+				}
+				ExecutionEvent::TaskFinished(task_name, _, _, _)
+				| ExecutionEvent::TaskSkipped(task_name, _) => {
+					// A task always sends all of it's output before it finishes.
+					self.receive_output();
+					self.send_lines(task_name, true);
+					self.running.remove(task_name);
+				}
+				ExecutionEvent::LogLine(_, _, _) => {}
+			}
+			self.send(&event);
+		}
+
+		let mut running = self.running.iter().cloned().collect::<Vec<_>>();
+		running.sort();
+		for task_name in running {
+			self.send_lines(&task_name, false);
+		}
+	}
+
+	/// Forward everything that's left, once every task has finished.
+	pub fn finish(mut self) {
+		self.forward();
+		let mut remaining = self.line_buffers.drain().collect::<Vec<_>>();
+		remaining.sort();
+		for ((task_name, is_stderr), buffered) in remaining {
+			for line in buffered.split_terminator('\n') {
+				self.send(&ExecutionEvent::LogLine(
+					task_name.clone(),
+					line.to_owned(),
+					is_stderr,
+				));
+			}
+		}
+	}
+
+	/// Send a single event to every sink.
+	fn send(&self, event: &ExecutionEvent) {
+		for sink in &self.sinks {
+			let _ = sink.send(event.clone());
+		}
+	}
+
+	/// Buffer any output that has been sent.
+	fn receive_output(&mut self) {
+		while let Ok((task_name, data, is_stderr)) = self.log_receiver.try_recv() {
+			self.line_buffers
+				.entry((task_name, is_stderr))
+				.or_default()
+				.push_str(&data);
+		}
+	}
+
+	/// Send every complete line of output a task has buffered.
+	///
+	/// `task_name`: the task to send the output of.
+	/// `partial`: if a trailing line without a newline should be sent too.
+	fn send_lines(&mut self, task_name: &str, partial: bool) {
+		for is_stderr in &[false, true] {
+			let key = (task_name.to_owned(), *is_stderr);
+			let buffered = if let Some(buffered) = self.line_buffers.remove(&key) {
+				buffered
+			} else {
+				continue;
+			};
+
+			let (complete, rest) = if partial {
+				(buffered.as_str(), "")
+			} else if let Some(newline_idx) = buffered.rfind('\n') {
+				(&buffered[..=newline_idx], &buffered[newline_idx + 1..])
+			} else {
+				("", buffered.as_str())
+			};
+			for line in complete.split_terminator('\n') {
+				self.send(&ExecutionEvent::LogLine(
+					task_name.to_owned(),
+					line.to_owned(),
+					*is_stderr,
+				));
+			}
+			if !rest.is_empty() {
+				self.line_buffers.insert(key, rest.to_owned());
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn forwards_events() {
+		let (indicator_sender, indicator_events) = unbounded();
+		let (event_sink, events) = unbounded();
+		let (mut forwarder, log_sender, task_sender) =
+			EventForwarder::new(vec![indicator_sender, event_sink]);
+
+		let started_at = Instant::now();
+		let task = "0-test".to_owned();
+		// Output that arrives before the task started still comes after it.
+		log_sender
+			.send((task.clone(), "one\ntw".to_owned(), false))
+			.unwrap();
+		task_sender
+			.send(ExecutionEvent::TaskStarted(task.clone(), started_at))
+			.unwrap();
+		forwarder.forward();
+		log_sender
+			.send((task.clone(), "o\nthree".to_owned(), false))
+			.unwrap();
+		log_sender
+			.send((task.clone(), "err".to_owned(), true))
+			.unwrap();
+		task_sender
+			.send(ExecutionEvent::TaskFinished(
+				task.clone(),
+				started_at,
+				1,
+				false,
+			))
+			.unwrap();
+		forwarder.finish();
+
+		let expected = vec![
+			ExecutionEvent::TaskStarted(task.clone(), started_at),
+			ExecutionEvent::LogLine(task.clone(), "one".to_owned(), false),
+			ExecutionEvent::LogLine(task.clone(), "two".to_owned(), false),
+			ExecutionEvent::LogLine(task.clone(), "three".to_owned(), false),
+			ExecutionEvent::LogLine(task.clone(), "err".to_owned(), true),
+			ExecutionEvent::TaskFinished(task, started_at, 1, false),
+		];
+		assert_eq!(events.try_iter().collect::<Vec<_>>(), expected);
+		assert_eq!(indicator_events.try_iter().collect::<Vec<_>>(), expected);
+	}
+}
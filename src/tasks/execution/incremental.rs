@@ -0,0 +1,342 @@
+//! Support for "incremental" tasks, or command tasks that declare their
+//! `inputs` (and optionally `outputs`) so they can be skipped when none of
+//! their inputs have changed since they last succeeded.
+//!
+//! The "fingerprint" of the last successful run of a task is stored inside of
+//! the current user's cache directory, and is calculated right before the
+//! task would run. So a task that generates the inputs of a later task in the same
+//! pipeline is always seen by that later task.
+
+use crate::{
+	config::types::TaskConf,
+	dirs::{
+		create_private_dir, get_user_cache_dir, is_private_to_current_user, write_private_file,
+	},
+	fetch::FetchedItem,
+};
+
+use color_eyre::{eyre::eyre, Result};
+use std::{
+	collections::HashMap,
+	hash::Hasher,
+	path::{Path, PathBuf},
+};
+use tracing::debug;
+use twox_hash::XxHash64;
+
+const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+
+/// Everything needed to determine if an incremental task can be skipped.
+#[derive(Debug)]
+pub struct IncrementalState {
+	/// A hash of everything about the task itself that should cause it to
+	/// re-run when changed (e.g. the script, or the arguments).
+	task_hash: u64,
+	/// The files, or directories the task reads from.
+	inputs: Vec<PathBuf>,
+	/// The files, or directories the task produces.
+	outputs: Vec<PathBuf>,
+	/// Where the fingerprint of the last successful run is stored.
+	cache_path: PathBuf,
+}
+
+impl IncrementalState {
+	/// Create the incremental state for a task, returns `None` if the task
+	/// has not declared any `inputs`, or there's no cache directory for the
+	/// current user to record fingerprints in.
+	///
+	/// `root_dir`: the root of the project, that inputs/outputs are relative to.
+	/// `task`: the configuration of the task.
+	/// `task_parts`: anything else that should cause the task to re-run when
+	///               it changes, like the script contents, and arguments.
+	#[must_use]
+	pub fn new(root_dir: &Path, task: &TaskConf, task_parts: &[&[u8]]) -> Option<Self> {
+		let cache_dir = get_user_cache_dir()?.join("incremental-cache");
+		Self::new_with_cache_dir(root_dir, task, task_parts, &cache_dir)
+	}
+
+	/// Create the incremental state for a task, storing fingerprints in a
+	/// particular directory. Returns `None` if the task has not declared any
+	/// `inputs`.
+	///
+	/// `root_dir`: the root of the project, that inputs/outputs are relative to.
+	/// `task`: the configuration of the task.
+	/// `task_parts`: anything else that should cause the task to re-run when
+	///               it changes, like the script contents, and arguments.
+	/// `cache_dir`: the directory to store fingerprints in.
+	#[must_use]
+	pub fn new_with_cache_dir(
+		root_dir: &Path,
+		task: &TaskConf,
+		task_parts: &[&[u8]],
+		cache_dir: &Path,
+	) -> Option<Self> {
+		let inputs = task.get_inputs()?;
+
+		let mut task_hasher = XxHash64::with_seed(0);
+		// Make sure a newer version of dev-loop never trusts an older fingerprint.
+		task_hasher.write(VERSION.unwrap_or("unknown").as_bytes());
+		for part in task_parts {
+			task_hasher.write_usize(part.len());
+			task_hasher.write(part);
+		}
+
+		let root_dir_str = root_dir.to_string_lossy();
+		let mut key_hasher = XxHash64::with_seed(0);
+		key_hasher.write_usize(root_dir_str.len());
+		key_hasher.write(root_dir_str.as_bytes());
+		key_hasher.write(task.get_name().as_bytes());
+		let cache_path = cache_dir.join(format!("{:016x}", key_hasher.finish()));
+
+		Some(Self {
+			task_hash: task_hasher.finish(),
+			inputs: inputs.iter().map(|input| root_dir.join(input)).collect(),
+			outputs: task
+				.get_outputs()
+				.map(|outputs| outputs.iter().map(|output| root_dir.join(output)).collect())
+				.unwrap_or_default(),
+			cache_path,
+		})
+	}
+
+	/// Calculate the fingerprint of the task as it is right now.
+	///
+	/// `environment_hash`: the hash of everything the task shares with the
+	///                     rest of the run, from `hash_environment`.
+	///
+	/// Returns `None` if an input could not be read, in which case the task
+	/// should always be run.
+	#[must_use]
+	pub fn fingerprint(&self, environment_hash: u64) -> Option<u64> {
+		let mut hasher = XxHash64::with_seed(0);
+		hasher.write_u64(self.task_hash);
+		hasher.write_u64(environment_hash);
+		for input in &self.inputs {
+			hash_path(&mut hasher, input)?;
+		}
+
+		Some(hasher.finish())
+	}
+
+	/// Determine if the task can be skipped, because it last succeeded with
+	/// the same fingerprint, and all of it's outputs still exist.
+	///
+	/// A fingerprint someone other than the current user could have written
+	/// is never trusted, otherwise they could cause a task to be skipped.
+	#[must_use]
+	pub fn is_up_to_date(&self, fingerprint: u64) -> bool {
+		if !self.outputs.iter().all(|output| output.exists()) {
+			return false;
+		}
+		let is_private = self
+			.cache_path
+			.parent()
+			.is_some_and(is_private_to_current_user)
+			&& is_private_to_current_user(&self.cache_path);
+		if !is_private {
+			return false;
+		}
+
+		matches!(
+			std::fs::read_to_string(&self.cache_path),
+			Ok(cached) if cached.trim() == format!("{:016x}", fingerprint)
+		)
+	}
+
+	/// Record that the task succeeded with a particular fingerprint.
+	///
+	/// Failing to record is never fatal, the task will just run again.
+	pub fn record_success(&self, fingerprint: u64) {
+		let write_res = self
+			.cache_path
+			.parent()
+			.map_or(Ok(()), |cache_dir| {
+				create_private_dir(cache_dir)?;
+				if is_private_to_current_user(cache_dir) {
+					Ok(())
+				} else {
+					Err(eyre!(
+						"The cache directory can be written to by other users."
+					))
+				}
+			})
+			.and_then(|_| {
+				write_private_file(&self.cache_path, format!("{:016x}", fingerprint).as_bytes())
+			});
+
+		if let Err(err) = write_res {
+			debug!(
+				"Failed to record incremental fingerprint to: [{:?}] due to: [{:?}]",
+				self.cache_path, err,
+			);
+		}
+	}
+}
+
+/// Hash everything that every task in a run shares, and that changes how a
+/// task behaves: the helpers, the `variables` from `.dl/config.yml`, and the
+/// environment variables passed with `--env`.
+///
+/// `helpers`: the helpers that will be sourced.
+/// `variables`: the variables exported into every task.
+/// `env_overrides`: the environment variables passed with `--env`.
+#[must_use]
+pub fn hash_environment(
+	helpers: &[FetchedItem],
+	variables: Option<&HashMap<String, String>>,
+	env_overrides: &[(String, String)],
+) -> u64 {
+	let mut hasher = XxHash64::with_seed(0);
+	let mut write_part = |part: &[u8]| {
+		hasher.write_usize(part.len());
+		hasher.write(part);
+	};
+
+	let mut sorted_variables = variables
+		.map(|vars| vars.iter().collect::<Vec<_>>())
+		.unwrap_or_default();
+	sorted_variables.sort();
+
+	write_part(&helpers.len().to_le_bytes());
+	for helper in helpers {
+		write_part(helper.get_contents());
+	}
+	write_part(&sorted_variables.len().to_le_bytes());
+	for (name, value) in sorted_variables {
+		write_part(name.as_bytes());
+		write_part(value.as_bytes());
+	}
+	// Order matters here, a later `--env` overrides an earlier one.
+	for (name, value) in env_overrides {
+		write_part(name.as_bytes());
+		write_part(value.as_bytes());
+	}
+
+	hasher.finish()
+}
+
+/// Hash a path into a hasher, recursing into directories.
+///
+/// A path that doesn't exist is hashed as missing, so it appearing later
+/// changes the fingerprint. Symlinks are never followed (so a link pointing
+/// back at one of it's parents can't recurse forever), only where they point
+/// is hashed. Returns `None` if the path could not be read.
+fn hash_path(hasher: &mut XxHash64, path: &Path) -> Option<()> {
+	hasher.write(path.to_string_lossy().as_bytes());
+
+	let metadata = match std::fs::symlink_metadata(path) {
+		Ok(metadata) => metadata,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+			hasher.write_u8(0);
+			return Some(());
+		}
+		Err(_) => return None,
+	};
+
+	if metadata.file_type().is_symlink() {
+		hasher.write_u8(3);
+		let target = std::fs::read_link(path).ok()?;
+		hasher.write(target.to_string_lossy().as_bytes());
+	} else if metadata.is_dir() {
+		hasher.write_u8(1);
+		let mut entries = std::fs::read_dir(path)
+			.ok()?
+			.map(|entry| entry.map(|entry| entry.path()))
+			.collect::<Result<Vec<PathBuf>, _>>()
+			.ok()?;
+		entries.sort();
+		for entry in entries {
+			hash_path(hasher, &entry)?;
+		}
+	} else {
+		hasher.write_u8(2);
+		let contents = std::fs::read(path).ok()?;
+		hasher.write_usize(contents.len());
+		hasher.write(&contents);
+	}
+
+	Some(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+	use crate::dirs::TestDir;
+	use std::fs::create_dir_all;
+
+	#[test]
+	fn keys_by_root_and_task_name() {
+		let task_x1 = serde_yaml::from_str::<TaskConf>("name: x1\ninputs: [src]\n").unwrap();
+		let task_1 = serde_yaml::from_str::<TaskConf>("name: 1\ninputs: [src]\n").unwrap();
+		let cache_dir = Path::new("/cache");
+		assert_ne!(
+			IncrementalState::new_with_cache_dir(Path::new("/proj"), &task_x1, &[], cache_dir)
+				.unwrap()
+				.cache_path,
+			IncrementalState::new_with_cache_dir(Path::new("/projx"), &task_1, &[], cache_dir)
+				.unwrap()
+				.cache_path,
+		);
+	}
+
+	#[test]
+	fn invalidates_when_inputs_change() {
+		let test_dir = TestDir::new("incremental");
+		let root_dir = test_dir.get_path();
+		create_dir_all(root_dir.join("src")).unwrap();
+		std::fs::write(root_dir.join("src").join("main.rs"), "fn main() {}").unwrap();
+
+		let task = serde_yaml::from_str::<TaskConf>("name: build\ninputs: [src]\noutputs: [out]\n")
+			.unwrap();
+		let cache_dir = root_dir.join("cache");
+		let state = IncrementalState::new_with_cache_dir(root_dir, &task, &[b"script"], &cache_dir)
+			.unwrap();
+
+		let fingerprint = state.fingerprint(0).unwrap();
+		assert!(!state.is_up_to_date(fingerprint));
+		state.record_success(fingerprint);
+		// The output doesn't exist yet.
+		assert!(!state.is_up_to_date(fingerprint));
+		std::fs::write(root_dir.join("out"), "built").unwrap();
+		assert!(state.is_up_to_date(state.fingerprint(0).unwrap()));
+
+		// Changing, or adding inputs should cause the task to re-run.
+		std::fs::write(root_dir.join("src").join("main.rs"), "fn main() { }").unwrap();
+		assert!(!state.is_up_to_date(state.fingerprint(0).unwrap()));
+		state.record_success(state.fingerprint(0).unwrap());
+		std::fs::write(root_dir.join("src").join("lib.rs"), "").unwrap();
+		assert!(!state.is_up_to_date(state.fingerprint(0).unwrap()));
+
+		// As should changing the task itself.
+		let changed_state =
+			IncrementalState::new_with_cache_dir(root_dir, &task, &[b"new script"], &cache_dir)
+				.unwrap();
+		state.record_success(state.fingerprint(0).unwrap());
+		assert!(!changed_state.is_up_to_date(changed_state.fingerprint(0).unwrap()));
+
+		// As should changing helpers, variables, or `--env`.
+		let fingerprint = state.fingerprint(0).unwrap();
+		let env_hash = hash_environment(&[], None, &[("A".to_owned(), "b".to_owned())]);
+		assert_ne!(env_hash, hash_environment(&[], None, &[]));
+		assert_ne!(state.fingerprint(env_hash).unwrap(), fingerprint);
+
+		// A symlink loop is hashed as a link, rather than followed forever.
+		#[cfg(unix)]
+		{
+			std::os::unix::fs::symlink("..", root_dir.join("src").join("parent")).unwrap();
+			assert!(!state.is_up_to_date(state.fingerprint(0).unwrap()));
+		}
+
+		// A fingerprint other users could have written is never trusted.
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+
+			state.record_success(state.fingerprint(0).unwrap());
+			assert!(state.is_up_to_date(state.fingerprint(0).unwrap()));
+			std::fs::set_permissions(&state.cache_path, std::fs::Permissions::from_mode(0o666))
+				.unwrap();
+			assert!(!state.is_up_to_date(state.fingerprint(0).unwrap()));
+		}
+	}
+}
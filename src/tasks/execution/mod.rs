@@ -2,127 +2,385 @@
 //! are thins like building the full list of "Task's" to run into an ordered
 //! vector.
 
+pub(crate) mod artifacts;
+pub(crate) mod concurrency;
+pub(crate) mod events;
+pub(crate) mod incremental;
 pub(crate) mod preparation;
 
 use crate::{
 	dirs::get_tmp_dir,
+	executors::shared::shell_quote,
 	fetch::FetchedItem,
 	sigint::has_ctrlc_been_hit,
-	tasks::execution::preparation::WorkUnit,
-	terminal::{task_indicator::TaskChange, TERM},
+	tasks::execution::{
+		concurrency::{release_all, try_acquire_all},
+		events::{EventForwarder, ExecutionEvent},
+		incremental::hash_environment,
+		preparation::{new_pipeline_id, ExecutableTask, WorkUnit},
+	},
+	terminal::{task_indicator::TaskSummary, TERM},
 };
-use color_eyre::Result;
+use color_eyre::{eyre::eyre, Result, Section};
 use crossbeam_channel::Sender;
-use crossbeam_deque::{Stealer, Worker};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
 use std::{
-	fs::create_dir_all,
+	collections::HashMap,
+	fs::{create_dir_all, remove_dir_all},
+	path::PathBuf,
 	sync::{
 		atomic::{AtomicBool, AtomicI32, Ordering},
 		Arc,
 	},
-	time::{SystemTime, UNIX_EPOCH},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tracing::{debug, error};
+use tracing::{debug, error, info, warn};
+
+/// The exit code a run has when it's stopped for going over `--max-runtime`,
+/// the same exit code the `timeout` command uses.
+pub const MAX_RUNTIME_EXCEEDED_RC: i32 = 124;
+/// The exit code a failing run has when it was stopped by Ctrl-C, the
+/// conventional exit code for being interrupted by `SIGINT`.
+pub const INTERRUPTED_RC: i32 = 130;
+
+/// The outcome of executing a series of tasks.
+#[derive(Debug)]
+pub struct ExecutionResult {
+	/// The exit code of the first task to fail (clamped to `1`-`255`), or `0`
+	/// if every task succeeded.
+	pub exit_code: i32,
+	/// The result of each task that finished, in the order they finished.
+	pub tasks: Vec<TaskSummary>,
+	/// If the run was stopped because it went over `--max-runtime`.
+	pub timed_out: bool,
+	/// If the run was stopped by Ctrl-C, and at least one task failed because
+	/// of it.
+	pub interrupted: bool,
+}
+
+impl ExecutionResult {
+	/// If every task that ran succeeded.
+	#[must_use]
+	pub fn is_success(&self) -> bool {
+		self.exit_code == 0
+	}
+
+	/// The names of every task that finished with a non-zero exit code, and
+	/// isn't allowed to fail.
+	#[must_use]
+	pub fn failed_tasks(&self) -> Vec<&str> {
+		self.tasks
+			.iter()
+			.filter(|task| task.rc != 0 && !task.allowed_failure)
+			.map(|task| task.name.as_str())
+			.collect()
+	}
+}
+
+/// Record the exit code of a failing task, if no other task has failed yet.
+///
+/// Exit codes are clamped into the range a process can actually exit with
+/// (`1` through `255`). Only the very first failure is kept, so when multiple
+/// branches fail at the same time exactly one of their exit codes is reported,
+/// rather than some combination of them.
+///
+/// `first_failure`: where the first failure is stored, `0` meaning no failure.
+/// `task_rc`: the exit code of the task that failed.
+fn record_failure(first_failure: &AtomicI32, task_rc: i32) {
+	if task_rc == 0 {
+		return;
+	}
+	let clamped_rc = if (1..=255).contains(&task_rc) {
+		task_rc
+	} else {
+		255
+	};
+	let _ = first_failure.compare_exchange(0, clamped_rc, Ordering::AcqRel, Ordering::Acquire);
+}
+
+/// Get the exit code a task should be treated as having, which is `0` when
+/// the task failed, but is allowed to fail.
+///
+/// `task`: the task that finished.
+/// `task_rc`: the exit code the task actually exited with.
+fn rc_after_allowed_failure(task: &ExecutableTask, task_rc: i32) -> i32 {
+	if task_rc != 0 && task.allows_failure() {
+		warn!(
+			"Task: [{}] failed with exit code: [{}], but is marked `allow_failure` so the run will continue.",
+			task.get_task_name(),
+			task_rc,
+		);
+		0
+	} else {
+		task_rc
+	}
+}
+
+/// Everything the lines of a run share while executing tasks.
+struct TaskLineContext {
+	/// The helpers, and variables to source before every task.
+	src_string: String,
+	/// The hash of everything tasks share, from `hash_environment`.
+	environment_hash: u64,
+	/// Run incremental tasks even if their inputs haven't changed.
+	force: bool,
+	/// Where to collect the `artifacts` of tasks into, if anywhere.
+	output_dir: Option<PathBuf>,
+	/// Work that is waiting for a `max_concurrency` slot to free up.
+	waiting_for_slot: Injector<WorkUnit>,
+	/// The exit code of the first task to fail, `0` meaning no failure.
+	first_failure: AtomicI32,
+	/// Set when every line should stop running tasks.
+	should_stop: Arc<AtomicBool>,
+}
+
+/// Execute a single task on it's executor.
+///
+/// If the task has an `only_if` condition it is run first on the same
+/// executor, and when it exits non-zero the task is skipped. If the task is
+/// incremental, and nothing has changed since it last succeeded it is
+/// skipped (unless `force` is set), acting like it succeeded.
+///
+/// Once the task has run, it's artifacts are collected into `output_dir`
+/// (if one was given).
+///
+/// Returns the exit code of the task, or `None` if it was skipped because
+/// it's `only_if` condition failed.
+async fn execute_task(
+	task: &ExecutableTask,
+	context: &TaskLineContext,
+	log_channel: Sender<(String, String, bool)>,
+	worker_count: usize,
+) -> Result<Option<i32>> {
+	let should_stop = context.should_stop.clone();
+	let src_string = context.src_string.as_str();
+
+	if let Some(condition) = task.get_only_if() {
+		let condition_rc = task
+			.get_executor()
+			.execute(
+				log_channel.clone(),
+				should_stop.clone(),
+				src_string,
+				condition,
+				worker_count,
+			)
+			.await?;
+		// A condition interrupted by a stop didn't really fail, so don't skip.
+		if condition_rc != 0 && should_stop.load(Ordering::Acquire) {
+			return Ok(Some(condition_rc));
+		}
+		if condition_rc != 0 {
+			info!(
+				"Task: [{}] is being skipped, it's `only_if` condition exited with: [{}].",
+				task.get_task_name(),
+				condition_rc,
+			);
+			return Ok(None);
+		}
+	}
+
+	let incremental = task.get_incremental().and_then(|incremental| {
+		Some((
+			incremental,
+			incremental.fingerprint(context.environment_hash)?,
+		))
+	});
+	if let Some((incremental, fingerprint)) = incremental {
+		if !context.force && incremental.is_up_to_date(fingerprint) {
+			info!(
+				"Task: [{}] is up to date (none of it's `inputs` have changed), skipping. Pass `--force` to run it anyway.",
+				task.get_task_name(),
+			);
+			return Ok(Some(0));
+		}
+	}
+
+	let task_rc = task
+		.get_executor()
+		.execute(log_channel, should_stop, src_string, task, worker_count)
+		.await?;
+	if task_rc == 0 {
+		if let Some((incremental, fingerprint)) = incremental {
+			incremental.record_success(fingerprint);
+		}
+	}
+	if let (Some(artifacts), Some(output_dir)) = (task.get_artifacts(), &context.output_dir) {
+		let collected = artifacts
+			.collect(
+				task.get_executor().as_ref(),
+				task.get_task_name(),
+				output_dir,
+			)
+			.await;
+		// A task that already failed keeps it's own exit code.
+		if task_rc == 0 {
+			collected?;
+		} else if let Err(collect_err) = collected {
+			warn!("{:?}", collect_err);
+		}
+	}
+
+	Ok(Some(task_rc))
+}
+
+/// Tell the event forwarder a task has finished (or been skipped), returning
+/// the exit code the task exited with.
+///
+/// `allows_failure`: if the task is marked `allow_failure`.
+fn send_task_finished(
+	task_channel: &Sender<ExecutionEvent>,
+	task_id: String,
+	allows_failure: bool,
+	task_rc_res: Result<Option<i32>>,
+) -> i32 {
+	match task_rc_res {
+		Ok(Some(rc)) => {
+			let _ = task_channel.send(ExecutionEvent::TaskFinished(
+				task_id,
+				Instant::now(),
+				rc,
+				allows_failure,
+			));
+			rc
+		}
+		Ok(None) => {
+			let _ = task_channel.send(ExecutionEvent::TaskSkipped(task_id, Instant::now()));
+			0
+		}
+		Err(error) => {
+			error!("{:?}", error);
+			let _ = task_channel.send(ExecutionEvent::TaskFinished(
+				task_id,
+				Instant::now(),
+				10,
+				allows_failure,
+			));
+			10
+		}
+	}
+}
+
+/// Take the next unit of work that is under all of it's `max_concurrency`
+/// limits, taking a slot from each of them.
+///
+/// Work that isn't under it's limits is set aside in `waiting_for_slot`
+/// (which every line shares) rather than being held onto, so whichever line
+/// is free when a slot frees up can take it, and no line sits waiting while
+/// there's other work it could be doing.
+///
+/// Returns `Steal::Retry` when the only work left is waiting for a slot, and
+/// `Steal::Empty` when there's no work left at all.
+fn take_ready_work(
+	waiting_for_slot: &Injector<WorkUnit>,
+	stealer: &Stealer<WorkUnit>,
+) -> Steal<WorkUnit> {
+	// Work that was set aside goes first, so it keeps it's place in line.
+	if let Steal::Success(work_unit) = waiting_for_slot.steal() {
+		if try_acquire_all(work_unit.get_concurrency_limits()) {
+			return Steal::Success(work_unit);
+		}
+		waiting_for_slot.push(work_unit);
+	}
+
+	loop {
+		match stealer.steal() {
+			Steal::Success(work_unit) => {
+				if try_acquire_all(work_unit.get_concurrency_limits()) {
+					return Steal::Success(work_unit);
+				}
+				waiting_for_slot.push(work_unit);
+			}
+			Steal::Retry => {}
+			Steal::Empty => {
+				return if waiting_for_slot.is_empty() {
+					Steal::Empty
+				} else {
+					Steal::Retry
+				};
+			}
+		}
+	}
+}
 
 /// Execute a particular "line" of tasks.
 async fn execute_task_line(
-	src_string: Arc<String>,
+	context: Arc<TaskLineContext>,
 	stealer: Stealer<WorkUnit>,
 	rc: Arc<AtomicI32>,
-	should_stop: Arc<AtomicBool>,
 	log_channel: Sender<(String, String, bool)>,
-	task_channel: Sender<TaskChange>,
+	task_channel: Sender<ExecutionEvent>,
 	worker_count: usize,
 ) {
+	let should_stop = &context.should_stop;
+	let first_failure = &context.first_failure;
+
 	// The order of executing a task line goes like this:
 	//
-	//  1. For each task, send an update over the task channel that it's started.
-	//  2. After each task finishes send an update on the task channel.
-	//  3. Check the rc. If it's not 0, break.
-	//  4. Check should_stop, if we should stop, break.
-	//  5. Otherwise keep iterating through the line.
-	//  6. At the end of the line return the rc.
+	//  1. Take a unit of work that's under it's `max_concurrency` limits, setting
+	//     aside any that aren't until a slot frees up.
+	//  2. For each task, send an update over the task channel that it's started.
+	//  3. After each task finishes send an update on the task channel.
+	//  4. Check the rc (treating allowed failures as 0). If it's not 0, record it as a failure (only the first
+	//     failure across all lines is kept), signal every other line to stop,
+	//     and break.
+	//  5. Check should_stop, if we should stop, break.
+	//  6. Otherwise keep iterating through the line.
+	//  7. At the end of the line return the rc.
 
 	// Incase we hit a stop before we actually started executing.
 	if should_stop.load(Ordering::Acquire) {
+		record_failure(first_failure, 10);
 		rc.store(10, Ordering::Release);
 		return;
 	}
 
 	let mut new_rc = 0;
 	loop {
-		let stolen = stealer.steal();
-		if stolen.is_empty() {
-			break;
-		}
-		if stolen.is_retry() {
-			continue;
-		}
+		let work_unit = match take_ready_work(&context.waiting_for_slot, &stealer) {
+			Steal::Success(work_unit) => work_unit,
+			Steal::Retry => {
+				if should_stop.load(Ordering::Acquire) {
+					break;
+				}
+				async_std::task::sleep(Duration::from_millis(50)).await;
+				continue;
+			}
+			Steal::Empty => break,
+		};
+		let concurrency_limits = work_unit.get_concurrency_limits().to_vec();
 
-		let work_unit = stolen.success().unwrap();
 		match work_unit {
 			WorkUnit::SingleTask(task) => {
-				let _ = task_channel.send(TaskChange::StartedTask(format!(
-					"{}-{}",
-					worker_count,
-					task.get_task_name()
-				)));
-				let task_rc_res = task
-					.get_executor()
-					.execute(
-						log_channel.clone(),
-						should_stop.clone(),
-						&(*src_string),
-						&task,
-						worker_count,
-					)
-					.await;
-				match task_rc_res {
-					Ok(rc) => {
-						new_rc = rc;
-					}
-					Err(error) => {
-						error!("{:?}", error);
-						new_rc = 10;
-					}
-				}
-				let _ = task_channel.send(TaskChange::FinishedTask(format!(
-					"{}-{}",
-					worker_count,
-					task.get_task_name()
-				)));
+				let _ = task_channel.send(ExecutionEvent::TaskStarted(
+					format!("{}-{}", worker_count, task.get_task_name()),
+					Instant::now(),
+				));
+				let task_rc_res =
+					execute_task(&task, &context, log_channel.clone(), worker_count).await;
+				new_rc = send_task_finished(
+					&task_channel,
+					format!("{}-{}", worker_count, task.get_task_name()),
+					task.allows_failure(),
+					task_rc_res,
+				);
+				new_rc = rc_after_allowed_failure(&task, new_rc);
 			}
 			WorkUnit::Pipeline(tasks) => {
 				for task in tasks {
-					let _ = task_channel.send(TaskChange::StartedTask(format!(
-						"{}-{}",
-						worker_count,
-						task.get_task_name()
-					)));
-					let task_rc_res = task
-						.get_executor()
-						.execute(
-							log_channel.clone(),
-							should_stop.clone(),
-							&(*src_string),
-							&task,
-							worker_count,
-						)
-						.await;
-					match task_rc_res {
-						Ok(rc) => {
-							new_rc = rc;
-						}
-						Err(error) => {
-							error!("{:?}", error);
-							new_rc = 10;
-						}
-					}
-					let _ = task_channel.send(TaskChange::FinishedTask(format!(
-						"{}-{}",
-						worker_count,
-						task.get_task_name()
-					)));
+					let _ = task_channel.send(ExecutionEvent::TaskStarted(
+						format!("{}-{}", worker_count, task.get_task_name()),
+						Instant::now(),
+					));
+					let task_rc_res =
+						execute_task(&task, &context, log_channel.clone(), worker_count).await;
+					new_rc = send_task_finished(
+						&task_channel,
+						format!("{}-{}", worker_count, task.get_task_name()),
+						task.allows_failure(),
+						task_rc_res,
+					);
+					new_rc = rc_after_allowed_failure(&task, new_rc);
 
 					if new_rc != 0 {
 						break;
@@ -130,8 +388,11 @@ async fn execute_task_line(
 				}
 			}
 		}
+		release_all(&concurrency_limits);
 
 		if new_rc != 0 {
+			record_failure(first_failure, new_rc);
+			should_stop.store(true, Ordering::Release);
 			break;
 		}
 	}
@@ -148,11 +409,85 @@ fn get_epoch_seconds() -> u64 {
 		.as_secs()
 }
 
+/// Determine if a name can be used as a shell variable name.
+#[must_use]
+pub fn is_valid_variable_name(name: &str) -> bool {
+	let mut name_chars = name.chars();
+	matches!(name_chars.next(), Some(first) if first == '_' || first.is_ascii_alphabetic())
+		&& name_chars.all(|character| character == '_' || character.is_ascii_alphanumeric())
+}
+
+/// Build the lines that export the `variables` from `.dl/config.yml`.
+///
+/// A variable is only exported if it isn't already set, so anything more
+/// specific (like an executor's `env_file`, or a variable already exported on
+/// the host) takes precedence. Only `--env` is exported unconditionally.
+///
+/// # Errors
+///
+/// If a variable name is not a valid shell variable name.
+fn build_variables_export_string(variables: Option<&HashMap<String, String>>) -> Result<String> {
+	let mut sorted_variables = variables
+		.map(|vars| vars.iter().collect::<Vec<_>>())
+		.unwrap_or_default();
+	sorted_variables.sort();
+
+	let mut export_string = String::new();
+	for (name, value) in sorted_variables {
+		if !is_valid_variable_name(name) {
+			return Err(eyre!(
+				"The variable: [{}] in `.dl/config.yml` is not a valid variable name.",
+				name,
+			))
+			.suggestion("Variable names should only contain letters, numbers, and underscores, and not start with a number.");
+		}
+
+		export_string += &format!(
+			"[ -n \"${{{name}+x}}\" ] || export {name}={value}\n",
+			name = name,
+			value = String::from_utf8_lossy(&shell_quote(value)),
+		);
+	}
+
+	Ok(export_string)
+}
+
+/// Build the lines that export the environment variables passed on the
+/// command line with `--env`.
+///
+/// These are always exported (after the helpers have been sourced), so they
+/// take precedence over everything else.
+fn build_env_overrides_string(env_overrides: &[(String, String)]) -> String {
+	env_overrides
+		.iter()
+		.map(|(name, value)| {
+			format!(
+				"export {}={}\n",
+				name,
+				String::from_utf8_lossy(&shell_quote(value))
+			)
+		})
+		.collect()
+}
+
 /// Build the "Source string", or the string to source all the helpers.
-fn build_helpers_source_string(helpers: Vec<FetchedItem>) -> Result<String> {
-	let epoch = get_epoch_seconds();
+///
+/// Helpers are sourced in the order they're given, which `fetch_helpers`
+/// makes stable (and free of duplicates).
+///
+/// Returns the source string, and the directory the helpers were written to.
+/// The directory ends with `-dl-host` so `clean` will remove it if it's ever
+/// left behind.
+fn build_helpers_source_string(helpers: Vec<FetchedItem>) -> Result<(String, PathBuf)> {
+	// The ID makes sure two runs started in the same second never share (and
+	// clean up) each others helpers.
+	let helper_dir_name = format!(
+		"{}-{}-helpers-dl-host",
+		get_epoch_seconds(),
+		new_pipeline_id()
+	);
 	let mut helper_dir = get_tmp_dir();
-	helper_dir.push(format!("{}-helpers-dl-host/", epoch));
+	helper_dir.push(&helper_dir_name);
 	create_dir_all(helper_dir.clone())?;
 
 	// We build the string to source all the helper files and copy that around since it's cheaper.
@@ -160,56 +495,140 @@ fn build_helpers_source_string(helpers: Vec<FetchedItem>) -> Result<String> {
 	// We need to write to `/tmp` for a couple reasons:
 	//   1. `/tmp` must always be mounted by the executors, and will always be present.
 	//   2. We need a local way to source files that were fetched remotely.
+	//
+	// Each helper is sourced on it's own, so one that can't be read, or has a
+	// syntax error is reported with it's name rather than silently skipped.
+	// This is checked (with the shell the entrypoint runs in) before sourcing,
+	// since the status of sourcing a helper is just the status of it's last
+	// command.
 	let mut src_string = String::new();
 	for (idx, fetched_helper) in helpers.into_iter().enumerate() {
 		let mut helper_path = helper_dir.clone();
 		helper_path.push(format!("helper-{}.sh", idx));
 		std::fs::write(helper_path.clone(), fetched_helper.get_contents())?;
 
-		let tmp_path = format!("/tmp/{}-helpers-dl-host/helper-{}.sh", epoch, idx);
-		if src_string.is_empty() {
-			src_string = format!(
-				"[[ -f \"{}\" ]] && source \"{}\" || source {:?}",
-				tmp_path, tmp_path, helper_path
-			);
-		} else {
-			src_string += &format!(
-				" && [[ -f \"{}\" ]] && source \"{}\" || source {:?}",
-				tmp_path, tmp_path, helper_path
-			);
-		}
+		let tmp_path = String::from_utf8_lossy(&shell_quote(&format!(
+			"/tmp/{}/helper-{}.sh",
+			helper_dir_name, idx
+		)))
+		.to_string();
+		src_string += &format!(
+			"if [ -f {tmp} ]; then DL_HELPER_PATH={tmp}; else DL_HELPER_PATH={host}; fi\n\
+			 if [ -r \"$DL_HELPER_PATH\" ] && ${{DL_SHELL:-bash}} -n \"$DL_HELPER_PATH\"; then . \"$DL_HELPER_PATH\"; else echo {msg} >&2; fi\n",
+			tmp = tmp_path,
+			host = String::from_utf8_lossy(&shell_quote(&helper_path)),
+			msg = String::from_utf8_lossy(&shell_quote(&format!(
+				"dev-loop: failed to source the helper: [{}], continuing without it.",
+				fetched_helper.get_source()
+			))),
+		);
+	}
+	if !src_string.is_empty() {
+		src_string += "unset DL_HELPER_PATH\n";
 	}
 
-	Ok(src_string)
+	Ok((src_string, helper_dir))
+}
+
+/// How a series of tasks should be run by `execute_tasks_in_parallel`.
+///
+/// Everything here has a sensible default, so only what's needed has to be
+/// set.
+#[derive(Debug, Default)]
+pub struct ExecutionSettings {
+	/// Print a summary of each task, it's rc, and how long it took once
+	/// everything has finished.
+	pub print_summary: bool,
+	/// Run incremental tasks even if their inputs haven't changed.
+	pub force: bool,
+	/// Where to collect the `artifacts` of tasks into, if anywhere.
+	pub output_dir: Option<PathBuf>,
+	/// Never render the list of running tasks, only their output.
+	pub no_indicator: bool,
+	/// How long the whole run may take, before every task is stopped (the
+	/// same as hitting Ctrl-C).
+	pub max_runtime: Option<Duration>,
+	/// Where to send progress events (tasks starting/finishing, and their
+	/// output), in addition to the terminal.
+	pub event_sink: Option<Sender<ExecutionEvent>>,
+	/// Send progress events only to the `event_sink`, instead of the terminal.
+	/// Nothing about the tasks is written to the terminal, so an embedder can
+	/// render it's own UI (`print_summary` still prints, if it's set).
+	pub sink_only: bool,
 }
 
 /// Execute a series of tasks in parallel.
 ///
 /// `helpers`: The list of helpers to render for each task.
+/// `variables`: the variables to export into every task's environment.
 /// `tasks`: the list of list of tasks. the top level list indicates a unit
 ///          of parralelization. the second list executes within order.
 /// `task_count`: the total count of tasks. yes we can derive this, but it's easier
 ///               for it to be derived as the list of lists is being created, and passed in.
+/// `settings`: how the tasks should be run.
+///
+/// Returns the exit code of the first task to fail (clamped to `1`-`255`), or
+/// `0` if every task succeeded, along with the result of every task that
+/// finished. As soon as any task fails every other line is signaled to stop.
+/// If the run goes over `max_runtime` the exit code is always
+/// `MAX_RUNTIME_EXCEEDED_RC`, and if the run was stopped by Ctrl-C (and any
+/// task failed) it's `INTERRUPTED_RC`.
 ///
 /// # Errors
 ///
 /// If we could not execute the tasks in parallel.
 pub async fn execute_tasks_in_parallel(
 	helpers: Vec<FetchedItem>,
+	variables: Option<&HashMap<String, String>>,
+	env_overrides: &[(String, String)],
 	tasks: Worker<WorkUnit>,
 	task_count: usize,
 	worker_size: usize,
-) -> Result<i32> {
+	settings: ExecutionSettings,
+) -> Result<ExecutionResult> {
+	let ExecutionSettings {
+		print_summary,
+		force,
+		output_dir,
+		no_indicator,
+		max_runtime,
+		event_sink,
+		sink_only,
+	} = settings;
+	let started_at = Instant::now();
+	let mut timed_out = false;
+	let mut ctrlc_hit = false;
 	let mut rc_indicators = Vec::new();
-	let should_stop = Arc::new(AtomicBool::new(false));
 
-	let (mut task_indicator, log_sender, task_sender) = TERM.create_task_indicator(task_count);
-	let src_string = build_helpers_source_string(helpers)?;
-	let src_string_ref = Arc::new(src_string);
+	// The indicator always follows along to build the summary of each task,
+	// it's just silent when only the event sink should see what happens.
+	let (task_indicator, indicator_sender) = TERM.create_task_indicator(task_count, no_indicator);
+	let mut task_indicator = task_indicator.with_terminal_output(!sink_only);
+	// Tasks report to the forwarder, which turns it all into events for the
+	// indicator (and the event sink if there is one).
+	let (mut event_forwarder, log_sender, task_sender) = EventForwarder::new(
+		std::iter::once(indicator_sender)
+			.chain(event_sink)
+			.collect(),
+	);
+	let environment_hash = hash_environment(&helpers, variables, env_overrides);
+	let (helpers_src_string, helper_dir) = build_helpers_source_string(helpers)?;
+	let src_string = build_variables_export_string(variables)?
+		+ &helpers_src_string
+		+ "\n" + &build_env_overrides_string(env_overrides);
+	let context = Arc::new(TaskLineContext {
+		src_string,
+		environment_hash,
+		force,
+		output_dir,
+		waiting_for_slot: Injector::new(),
+		first_failure: AtomicI32::new(0),
+		should_stop: Arc::new(AtomicBool::new(false)),
+	});
+	let should_stop = &context.should_stop;
 
 	for wc in 0..worker_size {
-		let cloned_src_string_ref = src_string_ref.clone();
-		let cloned_should_stop = should_stop.clone();
+		let cloned_context = context.clone();
 		let cloned_log_sender = log_sender.clone();
 		let cloned_task_sender = task_sender.clone();
 		let stealer = tasks.stealer();
@@ -219,10 +638,9 @@ pub async fn execute_tasks_in_parallel(
 
 		async_std::task::spawn(async move {
 			execute_task_line(
-				cloned_src_string_ref,
+				cloned_context,
 				stealer,
 				finished_clone,
-				cloned_should_stop,
 				cloned_log_sender,
 				cloned_task_sender,
 				wc,
@@ -232,42 +650,29 @@ pub async fn execute_tasks_in_parallel(
 		rc_indicators.push(finished_line);
 	}
 
-	let mut rc: i32 = 0;
-
 	loop {
+		event_forwarder.forward();
 		task_indicator.tick();
 
 		if has_ctrlc_been_hit() {
 			debug!("Detected Ctrl-C being hit! Shutting down.");
+			ctrlc_hit = true;
 			should_stop.store(true, Ordering::Release);
 		}
-
-		if rc != 0 {
-			should_stop.store(true, Ordering::Release);
-		}
-
-		let mut any_more = false;
-		for potential_rc in &rc_indicators {
-			let mut new_rc = potential_rc.load(Ordering::Acquire);
-			if new_rc == -1 {
-				any_more = true;
-				break;
-			} else {
-				debug!("Found finished task rc: [{}]", new_rc);
-				// If it's already not equal to 0 preserve the original exit code.
-				if rc == 0 {
-					if new_rc > 255 {
-						new_rc = 255;
-					}
-					if new_rc < 0 {
-						new_rc = 255;
-					}
-
-					rc += new_rc;
-				}
+		if let Some(max_runtime) = max_runtime {
+			if !timed_out && started_at.elapsed() >= max_runtime {
+				warn!(
+					"The run has gone over the `--max-runtime` of: [{}s], stopping all tasks.",
+					max_runtime.as_secs(),
+				);
+				timed_out = true;
+				should_stop.store(true, Ordering::Release);
 			}
 		}
 
+		let any_more = rc_indicators
+			.iter()
+			.any(|potential_rc| potential_rc.load(Ordering::Acquire) == -1);
 		if !any_more {
 			break;
 		}
@@ -275,7 +680,287 @@ pub async fn execute_tasks_in_parallel(
 		async_std::task::sleep(std::time::Duration::from_millis(50)).await;
 	}
 
-	task_indicator.stop_and_flush();
+	event_forwarder.finish();
+	let summaries = task_indicator.stop_and_flush();
+	if print_summary {
+		let as_json = std::env::var("RUST_LOG_FORMAT")
+			.as_ref()
+			.map(String::as_str)
+			== Ok("json");
+		let rendered = TERM.render_task_summary(&summaries, as_json);
+		if as_json {
+			println!("{}", rendered);
+		} else {
+			eprintln!("{}", rendered);
+		}
+	}
+
+	let first_failing_rc = context.first_failure.load(Ordering::Acquire);
+	// Tasks that don't treat Ctrl-C as a failure still succeed when
+	// interrupted, so only a run that failed is reported as interrupted.
+	let interrupted = ctrlc_hit && !timed_out && first_failing_rc != 0;
+	let result = ExecutionResult {
+		exit_code: if timed_out {
+			MAX_RUNTIME_EXCEEDED_RC
+		} else if interrupted {
+			INTERRUPTED_RC
+		} else {
+			first_failing_rc
+		},
+		tasks: summaries,
+		timed_out,
+		interrupted,
+	};
+	debug!(
+		"All task lines finished, first failing rc: [{}]",
+		result.exit_code
+	);
+	// Leave the helpers behind on failure to aid in debugging, `clean` will
+	// still remove them.
+	if result.is_success() {
+		if let Err(remove_err) = remove_dir_all(&helper_dir) {
+			debug!(
+				"Failed to remove helper directory: [{:?}] due to: [{:?}]",
+				helper_dir, remove_err,
+			);
+		}
+	}
+	Ok(result)
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+	use crate::{
+		dirs::TestDir,
+		executors::{host::Executor as HostExecutor, shared::create_entrypoint},
+		tasks::execution::concurrency::ConcurrencyLimit,
+	};
+
+	#[test]
+	fn keeps_first_failure() {
+		let first_failure = AtomicI32::new(0);
+		record_failure(&first_failure, 0);
+		assert_eq!(first_failure.load(Ordering::Acquire), 0);
+		record_failure(&first_failure, 3);
+		record_failure(&first_failure, 7);
+		assert_eq!(first_failure.load(Ordering::Acquire), 3);
+	}
+
+	#[test]
+	fn clamps_failures() {
+		let first_failure = AtomicI32::new(0);
+		record_failure(&first_failure, 300);
+		assert_eq!(first_failure.load(Ordering::Acquire), 255);
+
+		let first_failure = AtomicI32::new(0);
+		record_failure(&first_failure, -1);
+		assert_eq!(first_failure.load(Ordering::Acquire), 255);
+	}
+
+	#[test]
+	fn allowed_failures_are_not_failed_tasks() {
+		let summary = |name: &str, rc: i32, allowed_failure: bool| TaskSummary {
+			name: name.to_owned(),
+			rc,
+			duration_ms: 0,
+			skipped: false,
+			allowed_failure,
+		};
+		let result = ExecutionResult {
+			exit_code: 2,
+			tasks: vec![
+				summary("0-lint", 1, true),
+				summary("1-build", 0, false),
+				summary("2-test", 2, false),
+			],
+			timed_out: false,
+			interrupted: false,
+		};
+		assert_eq!(result.failed_tasks(), vec!["2-test"]);
+	}
+
+	#[test]
+	fn sets_aside_work_waiting_for_a_slot() {
+		let test_dir = TestDir::new("ready-work");
+		let executor: Arc<dyn crate::executors::Executor + Send + Sync> =
+			Arc::new(HostExecutor::new(test_dir.get_path()));
+		let unit = |name: &str| {
+			WorkUnit::SingleTask(Box::new(ExecutableTask::new(
+				Vec::new(),
+				executor.clone(),
+				FetchedItem::new(Vec::new(), "test.sh".to_owned()),
+				false,
+				"pipeline".to_owned(),
+				name.to_owned(),
+				None,
+			)))
+		};
+		let task_name = |stolen: Steal<WorkUnit>| match stolen {
+			Steal::Success(WorkUnit::SingleTask(task)) => task.get_task_name().to_owned(),
+			_ => panic!("expected a single task to be ready"),
+		};
 
-	Ok(rc)
+		let limit = Arc::new(ConcurrencyLimit::new(1));
+		let queue = Worker::new_fifo();
+		queue.push(unit("first").with_concurrency_limit(&limit));
+		queue.push(unit("second").with_concurrency_limit(&limit));
+		queue.push(unit("unlimited"));
+		let stealer = queue.stealer();
+		let waiting_for_slot = Injector::new();
+
+		assert_eq!(
+			task_name(take_ready_work(&waiting_for_slot, &stealer)),
+			"first"
+		);
+		// The second step has to wait, but that doesn't hold up other work.
+		assert_eq!(
+			task_name(take_ready_work(&waiting_for_slot, &stealer)),
+			"unlimited"
+		);
+		assert!(take_ready_work(&waiting_for_slot, &stealer).is_retry());
+		limit.release();
+		assert_eq!(
+			task_name(take_ready_work(&waiting_for_slot, &stealer)),
+			"second"
+		);
+		assert!(take_ready_work(&waiting_for_slot, &stealer).is_empty());
+	}
+
+	#[test]
+	fn simultaneous_failures_report_one_rc() {
+		let first_failure = Arc::new(AtomicI32::new(0));
+		let barrier = Arc::new(std::sync::Barrier::new(2));
+
+		let handles = [2, 3]
+			.iter()
+			.map(|task_rc| {
+				let task_rc = *task_rc;
+				let cloned_failure = first_failure.clone();
+				let cloned_barrier = barrier.clone();
+				std::thread::spawn(move || {
+					cloned_barrier.wait();
+					record_failure(&cloned_failure, task_rc);
+				})
+			})
+			.collect::<Vec<_>>();
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		// One of the failures wins, they are never combined.
+		let rc = first_failure.load(Ordering::Acquire);
+		assert!(rc == 2 || rc == 3);
+	}
+
+	#[test]
+	fn reports_helpers_that_fail_to_source() {
+		let helpers = vec![
+			FetchedItem::new(b"broken() {".to_vec(), "helpers/broken.sh".to_owned()),
+			FetchedItem::new(
+				b"working() { echo hi; }".to_vec(),
+				"helpers/working.sh".to_owned(),
+			),
+			// Loads fine, even though it's last command fails.
+			FetchedItem::new(
+				b"[[ -n \"$DL_UNSET_VAR\" ]] && export DL_SET_VAR=1".to_vec(),
+				"helpers/falsy.sh".to_owned(),
+			),
+		];
+		let (src_string, helper_dir) = build_helpers_source_string(helpers).unwrap();
+		let output = std::process::Command::new("bash")
+			.arg("-c")
+			.arg(src_string + "working")
+			.output()
+			.unwrap();
+		let _ = remove_dir_all(&helper_dir);
+
+		assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n");
+		assert!(String::from_utf8_lossy(&output.stderr)
+			.contains("failed to source the helper: [helpers/broken.sh]"));
+		assert!(!String::from_utf8_lossy(&output.stderr).contains("helpers/working.sh"));
+		assert!(!String::from_utf8_lossy(&output.stderr).contains("helpers/falsy.sh"));
+	}
+
+	#[test]
+	fn exports_variables() {
+		let mut variables = HashMap::new();
+		variables.insert("B_VAR".to_owned(), "it's".to_owned());
+		variables.insert("A_VAR".to_owned(), "value".to_owned());
+		assert_eq!(
+			build_variables_export_string(Some(&variables)).unwrap(),
+			"[ -n \"${A_VAR+x}\" ] || export A_VAR='value'\n[ -n \"${B_VAR+x}\" ] || export B_VAR='it'\\''s'\n",
+		);
+		assert_eq!(build_variables_export_string(None).unwrap(), "");
+
+		variables.insert("1_VAR".to_owned(), "value".to_owned());
+		assert!(build_variables_export_string(Some(&variables)).is_err());
+	}
+
+	#[test]
+	fn exports_env_overrides() {
+		assert_eq!(
+			build_env_overrides_string(&[
+				("DEBUG".to_owned(), "1".to_owned()),
+				("GREETING".to_owned(), "it's me".to_owned()),
+			]),
+			"export DEBUG='1'\nexport GREETING='it'\\''s me'\n",
+		);
+		assert_eq!(build_env_overrides_string(&[]), "");
+	}
+
+	#[test]
+	fn runs_entrypoints_with_posix_shells() {
+		let test_dir = TestDir::new("posix-entrypoint");
+		let helpers = vec![
+			FetchedItem::new(
+				b"export FROM_HELPER=helper".to_vec(),
+				"helpers/posix.sh".to_owned(),
+			),
+			FetchedItem::new(b"arr=(one two)".to_vec(), "helpers/bash.sh".to_owned()),
+		];
+		let mut variables = HashMap::new();
+		variables.insert("GREETING".to_owned(), "hello".to_owned());
+		let (helpers_src_string, helper_dir) = build_helpers_source_string(helpers).unwrap();
+		let src_string = build_variables_export_string(Some(&variables)).unwrap()
+			+ &helpers_src_string
+			+ &build_env_overrides_string(&[("TARGET".to_owned(), "sh".to_owned())]);
+
+		let task = ExecutableTask::new(
+			Vec::new(),
+			Arc::new(HostExecutor::new(test_dir.get_path())),
+			FetchedItem::new(
+				b"#!/bin/sh\necho \"$GREETING $FROM_HELPER $TARGET\"\n".to_vec(),
+				"test.sh".to_owned(),
+			),
+			false,
+			"pipeline".to_owned(),
+			"test".to_owned(),
+			None,
+		);
+		let entrypoint = create_entrypoint(
+			test_dir.get_path(),
+			"",
+			test_dir.get_path().to_path_buf(),
+			&src_string,
+			&task,
+			0,
+			false,
+			None,
+			None,
+			"/bin/sh",
+		)
+		.unwrap();
+		let output = std::process::Command::new(&entrypoint)
+			.env_remove("GREETING")
+			.output()
+			.unwrap();
+		let _ = remove_dir_all(&helper_dir);
+
+		assert!(output.status.success());
+		assert_eq!(String::from_utf8_lossy(&output.stdout), "hello helper sh\n");
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		assert!(stderr.contains("failed to source the helper: [helpers/bash.sh]"));
+		assert!(!stderr.contains("helpers/posix.sh"));
+	}
 }
@@ -1,7 +1,10 @@
 use crate::{
-	config::types::{TaskConf, TaskType, TopLevelConf},
+	config::types::{OneofOption, PipelineStep, TaskConf, TaskStdin, TaskType, TopLevelConf},
 	executors::{Executor, ExecutorRepository},
 	fetch::{FetchedItem, FetcherRepository},
+	tasks::execution::{
+		artifacts::ArtifactCollector, concurrency::ConcurrencyLimit, incremental::IncrementalState,
+	},
 };
 
 use color_eyre::{
@@ -9,13 +12,14 @@ use color_eyre::{
 	Result, Section,
 };
 use crossbeam_deque::Worker;
+use serde::Serialize;
 use std::{
 	collections::{HashMap, HashSet},
 	fmt::{Debug, Formatter},
 	future::Future,
 	hash::BuildHasher,
 	iter::FromIterator,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	pin::Pin,
 	sync::Arc,
 };
@@ -25,6 +29,8 @@ use uuid::Uuid;
 /// Represents an `ExecutableTask`, or a task that contains all the necessary
 /// bits of info needed for execution within an executor.
 pub struct ExecutableTask {
+	/// If a non-zero exit code should not fail the run.
+	allow_failure: bool,
 	/// The arguments for this particular task.
 	args: Vec<String>,
 	/// The executor that was chosen to be used.
@@ -39,14 +45,33 @@ pub struct ExecutableTask {
 	pipeline_id: String,
 	/// Get the contents of this particular task file.
 	script_contents: FetchedItem,
+	/// What to pass into the standard input of the task, if anything.
+	stdin: Option<Vec<u8>>,
 	/// The name of the task.
 	task_name: String,
+	/// How to determine if this task can be skipped, if it declared `inputs`.
+	incremental: Option<Box<IncrementalState>>,
+	/// The interpreter to run the script with, `None` meaning bash.
+	interpreter: Option<String>,
+	/// The condition to run before this task, the task is skipped if it fails.
+	only_if: Option<Box<ExecutableTask>>,
+	/// The artifacts to collect once this task has run, if it declared any.
+	artifacts: Option<Box<ArtifactCollector>>,
+	/// The `max_concurrency` limits of every parallel-pipeline this task is a
+	/// step of.
+	concurrency_limits: Vec<Arc<ConcurrencyLimit>>,
+	/// The directory (relative to the project root) to run in, `None` meaning
+	/// the project root.
+	working_dir: Option<PathBuf>,
 }
 
 impl Debug for ExecutableTask {
 	fn fmt(&self, formatter: &mut Formatter) -> Result<(), std::fmt::Error> {
 		formatter.write_str(&format!(
-			"ExecutableTask args: {}{}{} ctrlc_is_failure: {}{}{} pipeline_id: {}{}{}, task_name: {}{}{}",
+			"ExecutableTask allow_failure: {}{}{} args: {}{}{} ctrlc_is_failure: {}{}{} pipeline_id: {}{}{}, task_name: {}{}{}",
+			"{",
+			self.allow_failure,
+			"}",
 			"{",
 			self.args.join(" "),
 			"}",
@@ -73,17 +98,86 @@ impl ExecutableTask {
 		ctrlc_is_failure: bool,
 		pipeline_id: String,
 		task_name: String,
+		stdin: Option<Vec<u8>>,
 	) -> Self {
 		Self {
+			allow_failure: false,
 			args,
 			chosen_executor: executor,
 			ctrlc_is_failure,
 			pipeline_id,
 			script_contents: contents,
+			stdin,
 			task_name,
+			incremental: None,
+			interpreter: None,
+			only_if: None,
+			artifacts: None,
+			concurrency_limits: Vec::new(),
+			working_dir: None,
 		}
 	}
 
+	/// Run the script with a particular interpreter, rather than as a bash
+	/// script.
+	#[must_use]
+	pub fn with_interpreter(mut self, interpreter: Option<String>) -> Self {
+		self.interpreter = interpreter;
+		self
+	}
+
+	/// Only run this task when a condition (ran as it's own task beforehand)
+	/// succeeds.
+	#[must_use]
+	pub fn with_only_if(mut self, only_if: Option<ExecutableTask>) -> Self {
+		self.only_if = only_if.map(Box::new);
+		self
+	}
+
+	/// Collect artifacts once this task has run.
+	#[must_use]
+	pub fn with_artifacts(mut self, artifacts: Option<ArtifactCollector>) -> Self {
+		self.artifacts = artifacts.map(Box::new);
+		self
+	}
+
+	/// Run this task in a directory other than the project root.
+	///
+	/// `working_dir`: the directory, relative to the project root.
+	#[must_use]
+	pub fn with_working_dir(mut self, working_dir: Option<PathBuf>) -> Self {
+		self.working_dir = working_dir;
+		self
+	}
+
+	/// Count this task against the `max_concurrency` of a parallel-pipeline.
+	#[must_use]
+	pub fn with_concurrency_limit(mut self, limit: Arc<ConcurrencyLimit>) -> Self {
+		self.concurrency_limits.push(limit);
+		self
+	}
+
+	/// Allow this task to be skipped when it's inputs haven't changed since it
+	/// last succeeded.
+	#[must_use]
+	pub fn with_incremental(mut self, incremental: Option<IncrementalState>) -> Self {
+		self.incremental = incremental.map(Box::new);
+		self
+	}
+
+	/// Allow this task to exit with a non-zero exit code without failing
+	/// the run.
+	#[must_use]
+	pub fn with_allow_failure(mut self, allow_failure: bool) -> Self {
+		self.allow_failure = allow_failure;
+		self
+	}
+
+	#[must_use]
+	pub fn allows_failure(&self) -> bool {
+		self.allow_failure
+	}
+
 	#[must_use]
 	pub fn ctrlc_is_failure(&self) -> bool {
 		self.ctrlc_is_failure
@@ -94,6 +188,12 @@ impl ExecutableTask {
 		self.args.join(" ")
 	}
 
+	/// Get the arguments for this task.
+	#[must_use]
+	pub fn get_args(&self) -> &[String] {
+		&self.args
+	}
+
 	/// Get the pipeline id for this task.
 	#[must_use]
 	pub fn get_pipeline_id(&self) -> &str {
@@ -112,6 +212,49 @@ impl ExecutableTask {
 		&self.script_contents
 	}
 
+	/// Get what should be passed into the standard input of this task.
+	#[must_use]
+	pub fn get_stdin(&self) -> Option<&[u8]> {
+		self.stdin.as_deref()
+	}
+
+	/// Get the interpreter to run the script with, `None` meaning bash.
+	#[must_use]
+	pub fn get_interpreter(&self) -> Option<&str> {
+		self.interpreter.as_deref()
+	}
+
+	/// Get the condition that needs to succeed for this task to run, if any.
+	#[must_use]
+	pub fn get_only_if(&self) -> Option<&ExecutableTask> {
+		self.only_if.as_deref()
+	}
+
+	/// Get how to determine if this task can be skipped, if it's incremental.
+	#[must_use]
+	pub fn get_incremental(&self) -> Option<&IncrementalState> {
+		self.incremental.as_deref()
+	}
+
+	/// Get the artifacts to collect once this task has run, if any.
+	#[must_use]
+	pub fn get_artifacts(&self) -> Option<&ArtifactCollector> {
+		self.artifacts.as_deref()
+	}
+
+	/// Get the directory (relative to the project root) to run this task in,
+	/// `None` meaning the project root.
+	#[must_use]
+	pub fn get_working_dir(&self) -> Option<&Path> {
+		self.working_dir.as_deref()
+	}
+
+	/// Get the `max_concurrency` limits this task counts against.
+	#[must_use]
+	pub fn get_concurrency_limits(&self) -> &[Arc<ConcurrencyLimit>] {
+		&self.concurrency_limits
+	}
+
 	/// Get the executor for this particular task.
 	#[must_use]
 	pub fn get_executor(&self) -> &Arc<dyn Executor + Sync + Send> {
@@ -123,11 +266,41 @@ impl ExecutableTask {
 /// from a single queue.
 pub enum WorkUnit {
 	/// A SingleTask that it's in the work queue.
-	SingleTask(ExecutableTask),
+	SingleTask(Box<ExecutableTask>),
 	/// A Pipeline of tasks, that all need to be worked in a specific order.
 	Pipeline(Vec<ExecutableTask>),
 }
 
+impl WorkUnit {
+	/// Get the `max_concurrency` limits this unit of work counts against.
+	#[must_use]
+	pub fn get_concurrency_limits(&self) -> &[Arc<ConcurrencyLimit>] {
+		match self {
+			Self::SingleTask(task) => task.get_concurrency_limits(),
+			Self::Pipeline(tasks) => tasks
+				.first()
+				.map_or(&[], ExecutableTask::get_concurrency_limits),
+		}
+	}
+
+	/// Count every task in this unit of work against the `max_concurrency` of
+	/// a parallel-pipeline.
+	#[must_use]
+	pub fn with_concurrency_limit(self, limit: &Arc<ConcurrencyLimit>) -> Self {
+		match self {
+			Self::SingleTask(task) => {
+				Self::SingleTask(Box::new(task.with_concurrency_limit(limit.clone())))
+			}
+			Self::Pipeline(tasks) => Self::Pipeline(
+				tasks
+					.into_iter()
+					.map(|task| task.with_concurrency_limit(limit.clone()))
+					.collect(),
+			),
+		}
+	}
+}
+
 /// Describes a type of work queue. This helps easily build a pipeline of
 /// tasks, and a work queue.
 pub enum WorkQueue<'a> {
@@ -137,6 +310,99 @@ pub enum WorkQueue<'a> {
 	VecQueue(&'a mut Vec<ExecutableTask>),
 }
 
+/// Describes a single task in the execution plan, e.g. for `--plan-json`.
+#[derive(Debug, Serialize)]
+pub struct PlannedTask {
+	/// The name of the task.
+	pub task_name: String,
+	/// The ID of the executor that was chosen to run the task.
+	pub executor_id: Option<String>,
+	/// The arguments the task will be run with.
+	pub args: Vec<String>,
+	/// The pipeline the task is a part of.
+	pub pipeline_id: String,
+	/// If a non-zero exit code will not fail the run.
+	pub allow_failure: bool,
+	/// The name of the `only_if` condition that runs before the task, if any.
+	pub only_if: Option<String>,
+}
+
+/// Describes a single unit of work in the execution plan, mirroring a
+/// `WorkUnit`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlannedWorkUnit {
+	/// A task that runs on it's own.
+	SingleTask { task: PlannedTask },
+	/// A list of tasks that run one after another, in order.
+	Pipeline { tasks: Vec<PlannedTask> },
+}
+
+impl PlannedTask {
+	/// Describe a task that has been prepared for execution.
+	///
+	/// `task`: the task that would be executed.
+	/// `executors`: the repository the task's executor was selected from.
+	#[must_use]
+	pub fn new(task: &ExecutableTask, executors: &ExecutorRepository) -> Self {
+		Self {
+			task_name: task.get_task_name().to_owned(),
+			executor_id: executors.get_executor_id(task.get_executor()),
+			args: task.get_args().to_vec(),
+			pipeline_id: task.get_pipeline_id().to_owned(),
+			allow_failure: task.allows_failure(),
+			only_if: task
+				.get_only_if()
+				.map(|condition| condition.get_task_name().to_owned()),
+		}
+	}
+}
+
+/// Describe every unit of work in a work queue, in the order it would be run.
+///
+/// This consumes the queue, so it should only be used when the tasks are not
+/// going to be executed.
+///
+/// `worker`: the queue built by `build_ordered_execution_list`, or
+///           `build_concurrent_execution_list`.
+/// `executors`: the repository executors were selected from.
+#[must_use]
+pub fn describe_execution_plan(
+	worker: &Worker<WorkUnit>,
+	executors: &ExecutorRepository,
+) -> Vec<PlannedWorkUnit> {
+	let mut plan = Vec::new();
+	while let Some(unit) = worker.pop() {
+		plan.push(match unit {
+			WorkUnit::SingleTask(task) => PlannedWorkUnit::SingleTask {
+				task: PlannedTask::new(&task, executors),
+			},
+			WorkUnit::Pipeline(tasks) => PlannedWorkUnit::Pipeline {
+				tasks: tasks
+					.iter()
+					.map(|task| PlannedTask::new(task, executors))
+					.collect(),
+			},
+		});
+	}
+
+	plan
+}
+
+/// Merge the default arguments of a task with the arguments it was invoked
+/// with.
+///
+/// Default arguments always come first, so a script that uses the last
+/// value it sees for a flag will let invoked arguments override defaults.
+#[must_use]
+fn merge_default_args(default_args: Option<&Vec<String>>, args: Vec<String>) -> Vec<String> {
+	if let Some(defaults) = default_args {
+		defaults.iter().cloned().chain(args).collect()
+	} else {
+		args
+	}
+}
+
 /// Turns a command type task into an executable task.
 async fn command_to_executable_task(
 	pipeline_id: String,
@@ -147,9 +413,19 @@ async fn command_to_executable_task(
 	args: Vec<String>,
 ) -> Result<ExecutableTask> {
 	// First select the executor for this environment.
-	let selected_executor = executors.select_executor(task).await;
+	let executor_override = executors.get_executor_override().map(ToOwned::to_owned);
+	let selected_executor = executors
+		.select_executor(task, executor_override.as_deref())
+		.await;
 	if selected_executor.is_none() {
-		if task.get_execution_needs().is_some() || task.get_custom_executor().is_some() {
+		if let Some(executor_id) = executor_override {
+			return Err(eyre!(
+				"Executor: [{}] passed with `--executor` cannot run: [{}]",
+				executor_id,
+				task.get_name(),
+			))
+			.suggestion("Check the warnings above, and make sure the executor exists, and meets the `execution_needs` of the task.");
+		} else if task.get_execution_needs().is_some() || task.get_custom_executor().is_some() {
 			return Err(eyre!(
 				"Couldn't find a viable executor for: [{}]",
 				task.get_name()
@@ -183,17 +459,22 @@ async fn command_to_executable_task(
 	//
 	// A task file fetched from a remote endpoint specifying a FS endpoint
 	// would fetch from the root of the project, since it doesn't have an
-	// idea of what to be "relative" too.
+	// idea of what to be "relative" too. Tasks defined directly in a project
+	// configuration are relative to the root of the project, like the rest of
+	// the configuration.
 	let tf_loc: &str = task.get_source_path();
 
 	let mut relative_dir = root_directory.clone();
 	relative_dir.push(tf_loc);
 	relative_dir.pop();
 
-	let root_path = if relative_dir.exists() {
+	// `exec --cwd` fetches everything relative to the directory it was given.
+	let root_path = if let Some(cwd) = fetcher.get_cwd() {
+		root_directory.join(cwd)
+	} else if relative_dir.exists() && !task.is_defined_inline() {
 		relative_dir
 	} else {
-		root_directory
+		root_directory.clone()
 	};
 
 	let resulting_items = fetcher
@@ -212,6 +493,62 @@ async fn command_to_executable_task(
 	}
 	let resulting_item = resulting_items.into_iter().next().unwrap();
 
+	let stdin = match task.get_stdin() {
+		None => None,
+		Some(TaskStdin::Contents(contents)) => Some(contents.as_bytes().to_vec()),
+		Some(TaskStdin::File { file }) => {
+			let mut stdin_path = root_path.clone();
+			stdin_path.push(file);
+			Some(
+				async_std::fs::read(&stdin_path)
+					.await
+					.wrap_err(format!(
+						"Failed to read the stdin file: [{:?}] for task named: [{}]",
+						stdin_path,
+						task.get_name(),
+					))
+					.suggestion(
+						"The `stdin` file should be relative to the task file that defines it.",
+					)?,
+			)
+		}
+	};
+
+	let args = merge_default_args(task.get_default_args(), args);
+	let incremental = IncrementalState::new(
+		&root_directory,
+		task,
+		&[
+			resulting_item.get_contents(),
+			args.join("\0").as_bytes(),
+			stdin.as_deref().unwrap_or_default(),
+			selected_executor.get_type_name().as_bytes(),
+			task.get_interpreter().unwrap_or_default().as_bytes(),
+			fetcher
+				.get_cwd()
+				.map(|cwd| cwd.to_string_lossy().to_string())
+				.unwrap_or_default()
+				.as_bytes(),
+		],
+	);
+
+	// The condition runs on the same executor, as a bash script of it's own.
+	let only_if = task.get_only_if().map(|condition| {
+		ExecutableTask::new(
+			Vec::new(),
+			selected_executor.clone(),
+			FetchedItem::new(
+				condition.as_bytes().to_vec(),
+				format!("`only_if` of task: [{}]", task.get_name()),
+			),
+			task.ctrlc_is_failure(),
+			pipeline_id.clone(),
+			format!("{}-only-if", task.get_name()),
+			None,
+		)
+		.with_working_dir(fetcher.get_cwd().cloned())
+	});
+
 	Ok(ExecutableTask::new(
 		args,
 		selected_executor,
@@ -219,7 +556,14 @@ async fn command_to_executable_task(
 		task.ctrlc_is_failure(),
 		pipeline_id,
 		task.get_name().to_owned(),
-	))
+		stdin,
+	)
+	.with_allow_failure(task.allows_failure())
+	.with_only_if(only_if)
+	.with_artifacts(ArtifactCollector::new(&root_directory, task))
+	.with_incremental(incremental)
+	.with_interpreter(task.get_interpreter().map(String::from))
+	.with_working_dir(fetcher.get_cwd().cloned()))
 }
 
 /// Create a new pipeline id.
@@ -228,6 +572,34 @@ pub fn new_pipeline_id() -> String {
 	format!("{}", Uuid::new_v4())
 }
 
+/// How the tags requested by a `run` are matched against the tags on a task.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TagMatchMode {
+	/// A task runs if it has any of the requested tags.
+	#[default]
+	Any,
+	/// A task runs only if it has every one of the requested tags.
+	All,
+}
+
+impl TagMatchMode {
+	/// Determine if the tags on a task match the requested tags.
+	///
+	/// `requested_tags`: the tags that were asked to be run.
+	/// `tags_on_task`: the tags on the task (or oneof option).
+	#[must_use]
+	pub fn matches(self, requested_tags: &HashSet<&String>, tags_on_task: &[String]) -> bool {
+		let uniq_tags_on_task: HashSet<&String> = HashSet::from_iter(tags_on_task.iter());
+		match self {
+			// We had an intersection of some tags.
+			Self::Any => {
+				!has_unique_elements(requested_tags.iter().chain(uniq_tags_on_task.iter()))
+			}
+			Self::All => !requested_tags.is_empty() && requested_tags.is_subset(&uniq_tags_on_task),
+		}
+	}
+}
+
 /// Determine if a particular iter has all unique elements.
 #[must_use]
 pub fn has_unique_elements<T>(iter: T) -> bool
@@ -250,7 +622,7 @@ async fn add_command_task_to_execution_list<'a, 'b>(
 	work_queue: &'a mut WorkQueue<'b>,
 ) -> Result<usize> {
 	match work_queue {
-		WorkQueue::Queue(queue) => queue.push(WorkUnit::SingleTask(
+		WorkQueue::Queue(queue) => queue.push(WorkUnit::SingleTask(Box::new(
 			command_to_executable_task(
 				pipeline_id,
 				task,
@@ -260,7 +632,7 @@ async fn add_command_task_to_execution_list<'a, 'b>(
 				Vec::from(arguments),
 			)
 			.await?,
-		)),
+		))),
 		WorkQueue::VecQueue(vec) => vec.push(
 			command_to_executable_task(
 				pipeline_id,
@@ -316,7 +688,8 @@ async fn add_oneof_task_to_execution_list<'a, 'b, H: BuildHasher>(
 	}
 
 	// Try to grab the option based on the first argument.
-	// The other arguments are dropped on purpose.
+	// The other arguments are dropped on purpose, unless the option has
+	// opted into `forward_args`.
 	let potential_option = options
 		.iter()
 		.find(|option| option.get_name() == arguments[0]);
@@ -340,15 +713,13 @@ async fn add_oneof_task_to_execution_list<'a, 'b, H: BuildHasher>(
 			starting_task.get_name(),
 			selected_option.get_name(),
 			selected_option.get_task_name(),
-		)).suggestion("Please consult the log above to ensure no fetch errors were enounctered.");
+		))
+		.note("This task was most likely defined in a remote location that failed to fetch.")
+		.suggestion("Please consult the log above for which remote locations failed to fetch.");
 	}
 	let task = potential_option_as_task.unwrap();
 
-	let final_args = if let Some(args_ref) = selected_option.get_args() {
-		args_ref.clone()
-	} else {
-		Vec::new()
-	};
+	let final_args = get_oneof_option_args(selected_option, &arguments[1..]);
 
 	let mut size = 0;
 
@@ -384,6 +755,20 @@ async fn add_oneof_task_to_execution_list<'a, 'b, H: BuildHasher>(
 	Ok((false, size))
 }
 
+/// Get the arguments to run the task of a oneof option with, the configured
+/// `args` of the option followed by the remaining arguments if the option
+/// forwards them.
+///
+/// `option`: the option that was selected.
+/// `remaining_arguments`: the arguments after the name of the option.
+fn get_oneof_option_args(option: &OneofOption, remaining_arguments: &[String]) -> Vec<String> {
+	let mut args = option.get_args().cloned().unwrap_or_default();
+	if option.forwards_args() {
+		args.extend(remaining_arguments.iter().cloned());
+	}
+	args
+}
+
 /// Add a pipeline type task to the current execution list.
 async fn add_pipeline_to_execution_list<'a, 'b, H: BuildHasher>(
 	tasks: &'a HashMap<String, TaskConf, H>,
@@ -424,8 +809,9 @@ async fn add_pipeline_to_execution_list<'a, 'b, H: BuildHasher>(
 				step.get_name(),
 				step.get_task_name()
 			))
+			.note("This task was most likely defined in a remote location that failed to fetch.")
 			.suggestion(
-				"Please consult the log above to ensure no fetch errors were encountered.",
+				"Please consult the log above for which remote locations failed to fetch.",
 			);
 		}
 		let task = potential_task.unwrap();
@@ -474,6 +860,73 @@ async fn add_pipeline_to_execution_list<'a, 'b, H: BuildHasher>(
 	Ok(size)
 }
 
+/// Add a single step of a parallel-pipeline to the current execution list,
+/// under it's own pipeline id.
+async fn add_parallel_pipeline_step_to_execution_list<'a, 'b, H: BuildHasher>(
+	tasks: &'a HashMap<String, TaskConf, H>,
+	starting_task: &'a TaskConf,
+	step: &'a PipelineStep,
+	fetcher: &'a FetcherRepository,
+	executors: &'a mut ExecutorRepository,
+	root_directory: PathBuf,
+	work_queue: &'a mut WorkQueue<'b>,
+) -> Result<usize> {
+	let potential_task = tasks.get(step.get_task_name());
+	if potential_task.is_none() {
+		return Err(eyre!(
+			"The Parallel-Pipeline task: [{}], on step: [{}], failed to find the task associated to it: [{}]",
+			starting_task.get_name(),
+			step.get_name(),
+			step.get_task_name()
+		))
+		.note("This task was most likely defined in a remote location that failed to fetch.")
+		.suggestion("Please consult the log above for which remote locations failed to fetch.");
+	}
+	let task = potential_task.unwrap();
+
+	let final_args = if let Some(args_ref) = step.get_args() {
+		args_ref.clone()
+	} else {
+		Vec::new()
+	};
+
+	let task_pid = new_pipeline_id();
+	debug!(
+		"Parallel-Pipeline task: [{}], inner task: [{}] has been given the pipeline-id: [{}]",
+		starting_task.get_name(),
+		task.get_name(),
+		task_pid,
+	);
+
+	match *task.get_type() {
+		TaskType::Command => {
+			add_command_task_to_execution_list(
+				task,
+				fetcher,
+				executors,
+				root_directory,
+				&final_args,
+				task_pid,
+				work_queue,
+			)
+			.await
+		}
+		TaskType::Oneof | TaskType::Pipeline | TaskType::ParallelPipeline => {
+			build_ordered_execution_list(
+				tasks,
+				task,
+				fetcher,
+				executors,
+				root_directory,
+				&final_args,
+				task_pid,
+				work_queue,
+			)
+			.await
+		}
+	}
+}
+
 async fn add_parallel_pipeline_to_execution_list<'a, 'b, H: BuildHasher>(
 	tasks: &'a HashMap<String, TaskConf, H>,
 	starting_task: &'a TaskConf,
@@ -495,61 +948,52 @@ async fn add_parallel_pipeline_to_execution_list<'a, 'b, H: BuildHasher>(
 		);
 	}
 
-	let steps = optional_steps.unwrap();
-	for step in steps {
-		let potential_task = tasks.get(step.get_task_name());
-		if potential_task.is_none() {
+	let concurrency_limit = match starting_task.get_max_concurrency() {
+		Some(0) => {
 			return Err(eyre!(
-				"The Parallel-Pipeline task: [{}], on step: [{}], failed to find the task associated to it: [{}]",
+				"Parallel-Pipeline task: [{}] has a `max_concurrency` of 0, so none of it's steps could run.",
 				starting_task.get_name(),
-				step.get_name(),
-				step.get_task_name()
 			))
-			.suggestion(
-				"Please consult the log above to ensure no fetch errors were encountered.",
-			);
+			.suggestion("Set `max_concurrency` to a positive number, or remove it to run every step at once.");
 		}
-		let task = potential_task.unwrap();
-
-		let final_args = if let Some(args_ref) = step.get_args() {
-			args_ref.clone()
-		} else {
-			Vec::new()
-		};
-
-		let task_pid = new_pipeline_id();
-		debug!(
-			"Parallel-Pipeline task: [{}], inner task: [{}] has been given the pipeline-id: [{}]",
-			starting_task.get_name(),
-			task.get_name(),
-			task_pid,
-		);
+		Some(max_concurrency) => Some(Arc::new(ConcurrencyLimit::new(max_concurrency))),
+		None => None,
+	};
 
-		match *task.get_type() {
-			TaskType::Command => {
-				size += add_command_task_to_execution_list(
-					task,
+	let steps = optional_steps.unwrap();
+	for step in steps {
+		match (&concurrency_limit, &mut *work_queue) {
+			// Steps get built into their own queue first, so every unit of work
+			// they add can be counted against the limit.
+			(Some(limit), WorkQueue::Queue(queue)) => {
+				let mut step_units = Worker::new_fifo();
+				size += add_parallel_pipeline_step_to_execution_list(
+					tasks,
+					starting_task,
+					step,
 					fetcher,
 					executors,
 					root_directory.clone(),
-					&final_args,
-					task_pid,
-					work_queue,
+					&mut WorkQueue::Queue(&mut step_units),
 				)
-				.await?
+				.await?;
+				while let Some(unit) = step_units.pop() {
+					queue.push(unit.with_concurrency_limit(limit));
+				}
 			}
-			TaskType::Oneof | TaskType::Pipeline | TaskType::ParallelPipeline => {
-				size += build_ordered_execution_list(
+			// A queue of tasks is already run one at a time, so there's nothing
+			// to limit.
+			_ => {
+				size += add_parallel_pipeline_step_to_execution_list(
 					tasks,
-					task,
+					starting_task,
+					step,
 					fetcher,
 					executors,
 					root_directory.clone(),
-					&final_args,
-					task_pid,
 					work_queue,
 				)
-				.await?
+				.await?;
 			}
 		}
 	}
@@ -646,36 +1090,84 @@ pub async fn fetch_helpers(tlc: &TopLevelConf, fr: &FetcherRepository) -> Result
 		let mut fetched_items = Vec::new();
 
 		for (loc_idx, loc) in helper_locations.iter().enumerate() {
-			fetched_items.extend(
-				fr.fetch_filter(loc, Some(".sh".to_owned()))
-					.await
-					.wrap_err(format!(
-						"Failed fetching helpers defined at: `.dl/config.yml:helper_locations:{}`",
-						loc_idx
-					))?,
-			);
+			let mut fetched_from_location = fr
+				.fetch_filter(loc, Some(".sh".to_owned()))
+				.await
+				.wrap_err(format!(
+					"Failed fetching helpers defined at: `.dl/config.yml:helper_locations:{}`",
+					loc_idx
+				))?;
+			// The order files are found in a directory isn't stable, so sort them
+			// to make sure helpers are always sourced in the same order.
+			fetched_from_location.sort_by(|left, right| left.get_source().cmp(right.get_source()));
+			fetched_items.extend(fetched_from_location);
 		}
 
-		Ok(fetched_items)
+		Ok(dedup_helpers(fetched_items))
 	} else {
 		Ok(Vec::new())
 	}
 }
 
+/// Remove any helpers that have the exact same contents as a helper that came
+/// before them, so the same functions aren't sourced multiple times.
+///
+/// `helpers`: the helpers, in the order they should be sourced.
+#[must_use]
+pub fn dedup_helpers(helpers: Vec<FetchedItem>) -> Vec<FetchedItem> {
+	let mut seen_contents = HashSet::new();
+	helpers
+		.into_iter()
+		.filter(|helper| {
+			let is_new = seen_contents.insert(helper.get_contents().to_vec());
+			if !is_new {
+				debug!(
+					"Skipping helper: [{}] since a helper with the same contents has already been sourced.",
+					helper.get_source(),
+				);
+			}
+			is_new
+		})
+		.collect()
+}
+
+/// Record which of the requested tags selected a task (or oneof option).
+///
+/// `requested_tags`: the tags that were asked to be run.
+/// `tags_on_task`: the tags on the task (or oneof option) that was selected.
+/// `used_tags`: where to record the tags that were used.
+fn record_used_tags(
+	requested_tags: &HashSet<&String>,
+	tags_on_task: &[String],
+	used_tags: &mut HashSet<String>,
+) {
+	for tag in tags_on_task {
+		if requested_tags.contains(tag) {
+			used_tags.insert(tag.clone());
+		}
+	}
+}
+
 /// Build a concurrent execution list to use for the run command.
 ///
 /// `tasks`: the list of tasks to potentially run.
 /// `fetcher`: used for fetching particular files/executors/etc.
 /// `executors`: the list of executors.
 /// `root_directory`: the root directory of the project.
+/// `tag_match_mode`: if a task needs any, or all of the `tags` to run.
+/// `used_tags`: filled with every requested tag that selected at least one
+///              task, or oneof option to run.
 #[must_use]
+#[allow(clippy::too_many_arguments)]
 pub fn build_concurrent_execution_list<'a, H: BuildHasher>(
 	tasks: &'a HashMap<String, TaskConf, H>,
 	tags: &'a [String],
+	tag_match_mode: TagMatchMode,
 	fetcher: &'a FetcherRepository,
 	executors: &'a mut ExecutorRepository,
 	root_directory: PathBuf,
 	work_queue: &'a mut Worker<WorkUnit>,
+	used_tags: &'a mut HashSet<String>,
 ) -> Pin<Box<dyn 'a + Future<Output = Result<usize>>>> {
 	Box::pin(async move {
 		let unique_tags: HashSet<&String> = HashSet::from_iter(tags.iter());
@@ -689,10 +1181,9 @@ pub fn build_concurrent_execution_list<'a, H: BuildHasher>(
 			}
 
 			if let Some(tags_on_task) = task.get_tags() {
-				let uniq_tags_on_task: HashSet<&String> = HashSet::from_iter(tags_on_task.iter());
-				// We had an intersection of some tags.
-				if !has_unique_elements(unique_tags.iter().chain(uniq_tags_on_task.iter())) {
+				if tag_match_mode.matches(&unique_tags, tags_on_task) {
 					// We found a task to run.
+					record_used_tags(&unique_tags, tags_on_task, used_tags);
 					size += build_ordered_execution_list(
 						tasks,
 						task,
@@ -710,11 +1201,8 @@ pub fn build_concurrent_execution_list<'a, H: BuildHasher>(
 							continue;
 						}
 
-						let uniq_tags_on_option: HashSet<&String> =
-							HashSet::from_iter(option.get_tags().unwrap().iter());
-						if !has_unique_elements(
-							unique_tags.iter().chain(uniq_tags_on_option.iter()),
-						) {
+						if tag_match_mode.matches(&unique_tags, option.get_tags().unwrap()) {
+							record_used_tags(&unique_tags, option.get_tags().unwrap(), used_tags);
 							size += build_ordered_execution_list(
 								tasks,
 								task,
@@ -735,3 +1223,84 @@ pub fn build_concurrent_execution_list<'a, H: BuildHasher>(
 		Ok(size)
 	})
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn merges_default_args() {
+		assert_eq!(
+			merge_default_args(None, vec!["a".to_owned()]),
+			vec!["a".to_owned()]
+		);
+		assert_eq!(
+			merge_default_args(Some(&vec!["--verbose".to_owned()]), Vec::new()),
+			vec!["--verbose".to_owned()]
+		);
+		assert_eq!(
+			merge_default_args(
+				Some(&vec!["--verbose".to_owned(), "--level=1".to_owned()]),
+				vec!["--level=2".to_owned()],
+			),
+			vec![
+				"--verbose".to_owned(),
+				"--level=1".to_owned(),
+				"--level=2".to_owned()
+			]
+		);
+	}
+
+	#[test]
+	fn matches_tags_by_mode() {
+		let lint = "lint".to_owned();
+		let ci = "ci".to_owned();
+		let requested: HashSet<&String> = HashSet::from_iter(vec![&lint, &ci]);
+		let subset = vec!["lint".to_owned(), "other".to_owned()];
+		let superset = vec!["lint".to_owned(), "ci".to_owned(), "other".to_owned()];
+		let disjoint = vec!["other".to_owned()];
+
+		assert!(TagMatchMode::Any.matches(&requested, &subset));
+		assert!(TagMatchMode::Any.matches(&requested, &superset));
+		assert!(!TagMatchMode::Any.matches(&requested, &disjoint));
+
+		assert!(!TagMatchMode::All.matches(&requested, &subset));
+		assert!(TagMatchMode::All.matches(&requested, &superset));
+		assert!(!TagMatchMode::All.matches(&requested, &disjoint));
+		assert!(!TagMatchMode::All.matches(&HashSet::new(), &superset));
+	}
+
+	#[test]
+	fn forwards_oneof_args() {
+		let remaining = vec!["--filter".to_owned(), "foo".to_owned()];
+		let dropping =
+			serde_yaml::from_str::<OneofOption>("name: unit\ntask: test\nargs: [unit]\n").unwrap();
+		assert_eq!(
+			get_oneof_option_args(&dropping, &remaining),
+			vec!["unit".to_owned()]
+		);
+
+		let forwarding = serde_yaml::from_str::<OneofOption>(
+			"name: unit\ntask: test\nargs: [unit]\nforward_args: true\n",
+		)
+		.unwrap();
+		assert_eq!(
+			get_oneof_option_args(&forwarding, &remaining),
+			vec!["unit".to_owned(), "--filter".to_owned(), "foo".to_owned()]
+		);
+	}
+
+	#[test]
+	fn dedups_helpers() {
+		let helpers = vec![
+			FetchedItem::new(b"a() { :; }".to_vec(), "one/a.sh".to_owned()),
+			FetchedItem::new(b"b() { :; }".to_vec(), "one/b.sh".to_owned()),
+			FetchedItem::new(b"a() { :; }".to_vec(), "two/a.sh".to_owned()),
+		];
+		let sources = dedup_helpers(helpers)
+			.iter()
+			.map(|helper| helper.get_source().to_owned())
+			.collect::<Vec<String>>();
+		assert_eq!(sources, vec!["one/a.sh", "one/b.sh"]);
+	}
+}
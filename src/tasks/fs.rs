@@ -1,8 +1,98 @@
 //! Any filesystem related code for tasks...
 
-use crate::config::types::TopLevelConf;
-use color_eyre::{eyre::WrapErr, Result, Section};
-use std::{fs::create_dir_all, path::PathBuf};
+use crate::{
+	config::types::{EnsureDirectory, TopLevelConf},
+	dirs::{home_dir, set_directory_mode},
+};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result, Section,
+};
+use std::{
+	fs::create_dir_all,
+	path::{Path, PathBuf},
+};
+
+/// Resolve where a directory from `ensure_directories` should be created.
+///
+/// Paths are relative to the project root, unless they are absolute, or
+/// start with `~/` (in which case they're relative to the home directory).
+///
+/// # Errors
+///
+/// - When the path is empty, or the root directory.
+/// - When the path is relative to another user's home directory (`~user/`).
+/// - When the home directory could not be determined.
+fn resolve_ensure_dir_path(root_dir: &Path, ensure_dir: &str) -> Result<PathBuf> {
+	if ensure_dir.is_empty() || ensure_dir == "/" {
+		return Err(eyre!("The directory: [{}] can not be ensured.", ensure_dir)).suggestion(
+			"Entries in `ensure_directories` need to point at a directory other than `/`.",
+		);
+	}
+
+	if ensure_dir == "~" || ensure_dir.starts_with("~/") {
+		let home = home_dir()
+			.ok_or_else(|| {
+				eyre!(
+					"Failed to determine the home directory to create: [{}] in.",
+					ensure_dir
+				)
+			})
+			.suggestion("Make sure `HOME` is set, or use an absolute path instead.")?;
+		return Ok(home.join(ensure_dir.trim_start_matches('~').trim_start_matches('/')));
+	}
+	if ensure_dir.starts_with('~') {
+		return Err(eyre!(
+			"The directory: [{}] is relative to another user's home directory.",
+			ensure_dir
+		))
+		.suggestion(
+			"Only `~/` is supported, use an absolute path for another user's home directory.",
+		);
+	}
+
+	let path = Path::new(ensure_dir);
+	if path.is_absolute() {
+		Ok(path.to_path_buf())
+	} else {
+		Ok(root_dir.join(path))
+	}
+}
+
+/// Parse the permissions for a directory from an octal string, e.g. `0755`.
+///
+/// # Errors
+///
+/// - When the mode is not a valid octal number of permissions.
+fn parse_dir_mode(mode: &str) -> Result<u32> {
+	let trimmed = mode.trim();
+	let without_prefix = trimmed.strip_prefix("0o").unwrap_or(trimmed);
+	match u32::from_str_radix(without_prefix, 8) {
+		Ok(parsed) if !without_prefix.is_empty() && parsed <= 0o7777 => Ok(parsed),
+		_ => Err(eyre!("The directory mode: [{}] is not valid.", mode)).suggestion(
+			"Directory modes are octal permissions written as a string like: `\"0755\"`.",
+		),
+	}
+}
+
+/// Ensure a single directory from `ensure_directories` exists.
+///
+/// # Errors
+///
+/// If the directory is not valid, or fails to get created for any reason.
+fn ensure_dir(root_dir: &Path, edir: &EnsureDirectory) -> Result<()> {
+	let path = resolve_ensure_dir_path(root_dir, edir.get_path())?;
+	let mode = edir.get_mode().map(parse_dir_mode).transpose()?;
+
+	create_dir_all(&path)
+		.wrap_err("Cannot ensure directory specified in `.dl/config.yml` in `ensure_directories`.")
+		.note(format!("Tried to create directory: [{:?}]", path))?;
+	if let Some(mode) = mode {
+		set_directory_mode(&path, mode)?;
+	}
+
+	Ok(())
+}
 
 /// Ensure all the directories exist for a task that need to exist.
 ///
@@ -11,18 +101,51 @@ use std::{fs::create_dir_all, path::PathBuf};
 ///
 /// # Errors
 ///
-/// If a directory fails to get created for any reason.
+/// If a directory is not valid, or fails to get created for any reason.
 pub fn ensure_dirs(config: &TopLevelConf, root_dir: &PathBuf) -> Result<()> {
 	if let Some(edirs) = config.get_dirs_to_ensure() {
-		for ensure_dir in edirs {
-			let mut path = root_dir.clone();
-			path.push(ensure_dir);
-
-			create_dir_all(&path)
-				.wrap_err("Cannot ensure directory specified in `.dl/config.yml` in `ensure_directories`.")
-				.note(format!("Tried to create directory: [{:?}]", path))?;
+		for edir in edirs {
+			ensure_dir(root_dir, edir).note(format!(
+				"Entry in `ensure_directories`: [{}]",
+				edir.get_path()
+			))?;
 		}
 	}
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn resolves_ensure_dir_paths() {
+		let root_dir = PathBuf::from("/project");
+		assert_eq!(
+			resolve_ensure_dir_path(&root_dir, "cache").unwrap(),
+			PathBuf::from("/project/cache")
+		);
+		assert_eq!(
+			resolve_ensure_dir_path(&root_dir, "/var/cache/dl").unwrap(),
+			PathBuf::from("/var/cache/dl")
+		);
+		assert_eq!(
+			resolve_ensure_dir_path(&root_dir, "~/.cache/dl").unwrap(),
+			home_dir().unwrap().join(".cache/dl")
+		);
+		assert!(resolve_ensure_dir_path(&root_dir, "/").is_err());
+		assert!(resolve_ensure_dir_path(&root_dir, "").is_err());
+		assert!(resolve_ensure_dir_path(&root_dir, "~other/cache").is_err());
+	}
+
+	#[test]
+	fn parses_dir_modes() {
+		assert_eq!(parse_dir_mode("0755").unwrap(), 0o755);
+		assert_eq!(parse_dir_mode("700").unwrap(), 0o700);
+		assert_eq!(parse_dir_mode("0o1777").unwrap(), 0o1777);
+		assert!(parse_dir_mode("").is_err());
+		assert!(parse_dir_mode("0999").is_err());
+		assert!(parse_dir_mode("77777").is_err());
+	}
+}
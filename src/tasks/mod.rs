@@ -3,18 +3,32 @@
 //! Everything from the "DAG" of tasks, to running a specific task, etc.
 
 use crate::{
-	config::types::{LocationType, TaskConf, TaskConfFile, TaskType, TopLevelConf},
-	fetch::FetcherRepository,
-	strsim::add_did_you_mean_text,
-	yaml_err::contextualize,
+	config::{
+		types::{LocationType, TaskConf, TaskConfFile, TaskType, TopLevelConf},
+		warn_about_unknown_list_fields,
+	},
+	dirs::{
+		create_private_dir, get_user_cache_dir, is_private_to_current_user, write_private_file,
+	},
+	fetch::{FetchedItem, FetcherRepository},
+	strsim::add_adaptive_did_you_mean_text,
+	yaml_err::contextualize_list,
 };
 
 use color_eyre::{
 	eyre::{eyre, WrapErr},
-	Result, Section,
+	Report, Result, Section,
 };
-use std::collections::{HashMap, HashSet};
-use tracing::warn;
+use futures_util::future::join_all;
+use std::{
+	collections::{HashMap, HashSet},
+	hash::Hasher,
+	path::{Path, PathBuf},
+};
+use tracing::{debug, warn};
+use twox_hash::XxHash64;
+
+const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 
 pub(crate) mod execution;
 pub(crate) mod fs;
@@ -30,15 +44,66 @@ pub struct TaskGraph {
 	flattened_tasks: HashMap<String, TaskConf>,
 }
 
+/// The characters, besides letters and numbers, that a task name may contain.
+///
+/// Task names end up in file paths, and in commands run inside of docker, so
+/// anything that could be interpreted by a shell is not allowed.
+const ALLOWED_TASK_NAME_SYMBOLS: &[char] = &['-', '_', '.', ':', '@', '+'];
+
+/// Ensure a task name is something we can safely use as a map key, in file
+/// paths, and in commands.
+///
+/// `task_name`: the name of the task.
+/// `task_conf_file_src`: the file the task was defined in.
+///
+/// # Errors
+///
+/// If the task name is empty, starts with a `-`, is `.`/`..`, or contains
+/// characters that aren't safe for file paths, or shells.
+fn validate_task_name(task_name: &str, task_conf_file_src: &str) -> Result<()> {
+	if task_name.trim().is_empty() {
+		return Err(eyre!(
+			"Found a task with an empty name in config at: [{}]",
+			task_conf_file_src,
+		))
+		.suggestion("Please give the task a name, task names must be globally unique.");
+	}
+
+	let has_unsafe_character = task_name.chars().any(|character| {
+		!character.is_alphanumeric() && !ALLOWED_TASK_NAME_SYMBOLS.contains(&character)
+	});
+	if has_unsafe_character || task_name.starts_with('-') || task_name == "." || task_name == ".." {
+		return Err(eyre!(
+			"Found a task with the invalid name: [{:?}] in config at: [{}]",
+			task_name,
+			task_conf_file_src,
+		))
+		.note(
+			"Task names are used in file paths, and commands, so they must be safe to use in both.",
+		)
+		.suggestion(format!(
+			"Task names may only contain letters, numbers, and: [{}], and may not start with a `-`.",
+			ALLOWED_TASK_NAME_SYMBOLS
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<String>>()
+				.join(" "),
+		));
+	}
+
+	Ok(())
+}
+
 impl TaskGraph {
 	fn parse_task(
 		task_conf_file_src: &str,
 		task_conf: TaskConf,
 		internal_task_names: &mut HashSet<String>,
-		unsatisfied_task_names: &mut HashSet<String>,
+		unsatisfied_task_names: &mut HashMap<String, Vec<String>>,
 		flatenned_tasks: &mut HashMap<String, TaskConf>,
 	) -> Result<()> {
 		let task_name = task_conf.get_name();
+		validate_task_name(task_name, task_conf_file_src)?;
 
 		// If we've already seen this task... it's an error.
 		// Task names need to be globally unique.
@@ -60,7 +125,7 @@ impl TaskGraph {
 		// oneof/parallel-pipeline/pipeline they themselves have a reference.
 		//
 		// Next we check if the option "exists", if not. we add it to `unsatisfied_task_names`
-		// so it can be checked later.
+		// (along with everyone who referenced it) so it can be checked later.
 		let ttype = task_conf.get_type();
 		match ttype {
 			TaskType::Oneof => {
@@ -68,7 +133,11 @@ impl TaskGraph {
 					for option in options {
 						internal_task_names.remove(option.get_task_name());
 						if !flatenned_tasks.contains_key(option.get_task_name()) {
-							unsatisfied_task_names.insert(option.get_task_name().to_owned());
+							Self::add_unsatisfied_reference(
+								unsatisfied_task_names,
+								option.get_task_name(),
+								task_name,
+							);
 						}
 					}
 				}
@@ -78,7 +147,11 @@ impl TaskGraph {
 					for step in steps {
 						internal_task_names.remove(step.get_task_name());
 						if !flatenned_tasks.contains_key(step.get_task_name()) {
-							unsatisfied_task_names.insert(step.get_task_name().to_owned());
+							Self::add_unsatisfied_reference(
+								unsatisfied_task_names,
+								step.get_task_name(),
+								task_name,
+							);
 						}
 					}
 				}
@@ -88,7 +161,7 @@ impl TaskGraph {
 
 		// If we're an internal task, and someone hasn't referenced us already
 		// go ahead and add ourselves to the list of "waiting for a ref" set.
-		if task_conf.is_internal() && !unsatisfied_task_names.contains(task_name) {
+		if task_conf.is_internal() && !unsatisfied_task_names.contains_key(task_name) {
 			internal_task_names.insert(task_name.to_owned());
 		}
 		// NO-OP if we're not there, otherwise let people know we exist.
@@ -100,6 +173,26 @@ impl TaskGraph {
 		Ok(())
 	}
 
+	/// Record that `referenced_by` references a task we haven't seen yet.
+	///
+	/// A task can be referenced by many others before it's defined (if it
+	/// ever is), so we keep every referencer to report on.
+	fn add_unsatisfied_reference(
+		unsatisfied_task_names: &mut HashMap<String, Vec<String>>,
+		task_name: &str,
+		referenced_by: &str,
+	) {
+		let referencers = unsatisfied_task_names
+			.entry(task_name.to_owned())
+			.or_default();
+		if !referencers
+			.iter()
+			.any(|referencer| referencer == referenced_by)
+		{
+			referencers.push(referenced_by.to_owned());
+		}
+	}
+
 	/// Create a new `TaskGraph`.
 	///
 	/// NOTE: this will completely parse all the task files (remote or otherwise),
@@ -114,11 +207,38 @@ impl TaskGraph {
 	/// - When the task yaml files are invalid yaml.
 	/// - When the task yaml file has some sort of invariant error.
 	pub async fn new(tlc: &TopLevelConf, fetcher: &FetcherRepository) -> Result<Self> {
+		Self::new_with_cache_dir(tlc, fetcher, Self::default_cache_dir().as_deref()).await
+	}
+
+	/// Create a new `TaskGraph`, caching the parsed graph in a specific
+	/// directory.
+	///
+	/// `tlc`: The parsed top level config to start fetching tasks from.
+	/// `fetcher`: The repository of fetchers.
+	/// `cache_dir`: The directory to cache parsed task graphs in, `None` to
+	///              never cache.
+	///
+	/// # Errors
+	///
+	/// - When there is an error fetching the tasks yaml files.
+	/// - When the task yaml files are invalid yaml.
+	/// - When the task yaml file has some sort of invariant error.
+	pub async fn new_with_cache_dir(
+		tlc: &TopLevelConf,
+		fetcher: &FetcherRepository,
+		cache_dir: Option<&Path>,
+	) -> Result<Self> {
 		let span = tracing::info_span!("finding_tasks");
 		let _guard = span.enter();
 
-		// If we have tasks, we have some fetching to do...
-		if let Some(tasks) = tlc.get_task_locations() {
+		// If we have tasks, we have some fetching to do... Tasks defined directly
+		// in the configuration need the same validation even when there are no
+		// task locations to fetch.
+		let no_task_locations = Vec::new();
+		if let Some(tasks) = tlc
+			.get_task_locations()
+			.or_else(|| tlc.get_tasks().map(|_| &no_task_locations))
+		{
 			// These are hashsets to track for a "valid" DAG. A Valid DAG:
 			//   1. Does not have any "internal: true" nodes that are never referenced
 			//      (and thus can never be reached).
@@ -130,22 +250,56 @@ impl TaskGraph {
 			// validate the DAG, to try and let the program run. Obviously if someone tries to run
 			// a task from that HTTP endpoint on a plane, there's nothing we can do.
 			let mut internal_task_names = HashSet::new();
-			let mut unsatisfied_task_names = HashSet::new();
+			let mut unsatisfied_task_names = HashMap::new();
 			let mut allowing_dag_errors = false;
+			// The remote locations that failed, so we can point people at them
+			// if a task ends up missing.
+			let mut failed_remote_locations = Vec::new();
 
 			let mut flatenned_tasks: HashMap<String, TaskConf> = HashMap::new();
 
-			for (tl_idx, task_location) in tasks.iter().enumerate() {
-				// Go, and fetch all the task locations, if we're searching folders
-				// search for "dl-tasks.yml" files.
-				let resulting_fetched_tasks = fetcher
-					.fetch_filter(task_location, Some("dl-tasks.yml".to_owned()))
-					.await
-					.wrap_err(format!(
-						"Failed fetching tasks specified at `.dl/config.yml:task_locations:{}`",
-						tl_idx,
-					));
+			// Go, and fetch all the task locations at once, if we're searching folders
+			// search for "dl-tasks.yml" files.
+			//
+			// `join_all` hands back results in the same order as the locations, so
+			// error messages, and duplicate detection stay deterministic.
+			let all_fetched_tasks = join_all(tasks.iter().enumerate().map(
+				|(tl_idx, task_location)| async move {
+					fetcher
+						.fetch_filter(task_location, Some("dl-tasks.yml".to_owned()))
+						.await
+						.wrap_err(format!(
+							"Failed fetching tasks specified at `.dl/config.yml:task_locations:{}`",
+							tl_idx,
+						))
+				},
+			))
+			.await;
+
+			// If we fetched everything successfully, see if we've already parsed these
+			// exact task files before. If so we can skip parsing entirely.
+			let inline_tasks = tlc.get_tasks();
+			let cache_key = Self::cache_key(&all_fetched_tasks, inline_tasks);
+			if let (Some(cache_dir), Some(key)) = (cache_dir, cache_key) {
+				if let Some(cached_graph) = Self::read_from_cache(cache_dir, key) {
+					debug!("Using cached task graph: [{:016x}]", key);
+					return Ok(cached_graph);
+				}
+			}
 
+			// Tasks defined directly in the configuration go in first, so a duplicate
+			// in a task file points back at the configuration file it came from.
+			for task_conf in inline_tasks.into_iter().flatten() {
+				Self::parse_task(
+					task_conf.get_source_path(),
+					task_conf.clone(),
+					&mut internal_task_names,
+					&mut unsatisfied_task_names,
+					&mut flatenned_tasks,
+				)?;
+			}
+
+			for (task_location, resulting_fetched_tasks) in tasks.iter().zip(all_fetched_tasks) {
 				// For HTTP errors we're going to try to continue, if your FS fails
 				// well than something really bad is going on that we don't want to handle.
 				if let Err(err) = resulting_fetched_tasks {
@@ -153,6 +307,7 @@ impl TaskGraph {
 						warn!("{:?}", err);
 						warn!("Trying to continue, incase the failing remote endpoint doesn't matter for this run.");
 						allowing_dag_errors = true;
+						failed_remote_locations.push(task_location.get_at().to_owned());
 						continue;
 					}
 
@@ -171,17 +326,26 @@ impl TaskGraph {
 							warn!("{:?}", tye,);
 							warn!("Trying to continue, incase the failing remote endpoint doesn't matter for this run.");
 							allowing_dag_errors = true;
+							failed_remote_locations.push(task_location.get_at().to_owned());
 							continue;
 						}
 
-						return contextualize(
+						return contextualize_list::<_, TaskConf>(
 							Err(tye),
 							task_conf_file.get_source(),
-							&String::from_utf8_lossy(task_conf_file.get_contents()).to_string()
+							&String::from_utf8_lossy(task_conf_file.get_contents()).to_string(),
+							"tasks",
+							"Task",
 						).note("Full types, and supported values are documented at: https://dev-loop.kungfury.dev/docs/schemas/task-conf-file");
 					}
 					let mut task_yaml = task_yaml_res.unwrap();
 					task_yaml.set_task_location(task_conf_file.get_source());
+					warn_about_unknown_list_fields::<TaskConfFile, TaskConf>(
+						task_conf_file.get_contents(),
+						task_conf_file.get_source(),
+						"tasks",
+						"Task",
+					);
 
 					// This is the "core" loop, where we've now parsed a task config
 					// file, and need to enter it's contents into the DAG. We have to
@@ -208,17 +372,36 @@ impl TaskGraph {
 				if !unsatisfied_task_names.is_empty() {
 					let mut err = Err(eyre!(
 						"Tasks referenced that do not exist: {:?}",
-						unsatisfied_task_names
+						unsatisfied_task_names.keys().collect::<Vec<&String>>()
 					));
-					for unknown_task in unsatisfied_task_names {
-						err = add_did_you_mean_text(
+					for (unknown_task, referenced_by) in &unsatisfied_task_names {
+						if referenced_by.is_empty() {
+							continue;
+						}
+						err = err.note(format!(
+							"The task: [{}] is referenced by: [{}]",
+							unknown_task,
+							referenced_by
+								.iter()
+								.map(|referencer| format!(
+									"{} (defined in: {})",
+									referencer,
+									flatenned_tasks
+										.get(referencer)
+										.map_or("", |task| task.get_source_path()),
+								))
+								.collect::<Vec<_>>()
+								.join(", "),
+						));
+					}
+					for unknown_task in unsatisfied_task_names.keys() {
+						err = add_adaptive_did_you_mean_text(
 							err,
-							&unknown_task,
+							unknown_task,
 							&flatenned_tasks
 								.keys()
 								.map(String::as_str)
 								.collect::<Vec<&str>>(),
-							3,
 							None,
 						);
 					}
@@ -235,11 +418,47 @@ impl TaskGraph {
 					))
 					.suggestion("If an internal task is no longer needed it should be deleted.");
 				}
+			} else if !unsatisfied_task_names.is_empty() {
+				// We can't tell for sure these tasks are missing, they most likely live
+				// in the remote location that failed. Let people know now, rather than
+				// only when they try to run something that uses them.
+				let mut report = eyre!(
+					"Tasks referenced that could not be found: {:?}",
+					unsatisfied_task_names.keys().collect::<Vec<&String>>()
+				)
+				.note(format!(
+					"These tasks are most likely defined in a remote location that failed to fetch: {:?}",
+					failed_remote_locations,
+				));
+				for (unknown_task, referenced_by) in &unsatisfied_task_names {
+					if referenced_by.is_empty() {
+						continue;
+					}
+					report = report.note(format!(
+						"The task: [{}] is referenced by: [{}]",
+						unknown_task,
+						referenced_by.join(", "),
+					));
+				}
+				warn!(
+					"{:?}",
+					report.suggestion(
+						"Running any task that references these will fail until the remote location can be fetched."
+					)
+				);
 			}
 
-			Ok(Self {
+			let graph = Self {
 				flattened_tasks: flatenned_tasks,
-			})
+			};
+			// Only cache fully validated graphs, a graph that had a failing remote
+			// endpoint will never have a cache key anyway.
+			if let (Some(cache_dir), Some(key), false) = (cache_dir, cache_key, allowing_dag_errors)
+			{
+				graph.write_to_cache(cache_dir, key);
+			}
+
+			Ok(graph)
 		} else {
 			Ok(Self {
 				flattened_tasks: HashMap::new(),
@@ -247,9 +466,306 @@ impl TaskGraph {
 		}
 	}
 
+	/// Determine if the on-disk task graph cache has been disabled, given the
+	/// value of the `DL_DISABLE_TASK_CACHE` environment variable.
+	fn is_cache_disabled_value(value: Option<&str>) -> bool {
+		value == Some("true")
+	}
+
+	/// Get the directory the on-disk task graph cache lives in, or `None` if
+	/// it has been disabled (or there's no home directory to put it in).
+	///
+	/// This lives in the home directory rather than the temporary directory,
+	/// since the temporary directory is shared with every other user on the
+	/// host, and a cached graph decides which commands get run.
+	fn default_cache_dir() -> Option<PathBuf> {
+		if Self::is_cache_disabled_value(std::env::var("DL_DISABLE_TASK_CACHE").ok().as_deref()) {
+			None
+		} else {
+			get_user_cache_dir().map(|cache_dir| cache_dir.join("task-graph-cache"))
+		}
+	}
+
+	/// Calculate the key for the on-disk cache, this is a hash of the contents
+	/// (and sources) of every task file we fetched, along with any tasks
+	/// defined directly in the configuration.
+	///
+	/// Returns `None` if any location failed to fetch since we never want to
+	/// cache a partial graph.
+	fn cache_key(
+		fetched: &[Result<Vec<FetchedItem>>],
+		inline_tasks: Option<&Vec<TaskConf>>,
+	) -> Option<u64> {
+		let mut hasher = XxHash64::with_seed(0);
+		// Make sure a newer version of dev-loop never reads an older cache format.
+		hasher.write(VERSION.unwrap_or("unknown").as_bytes());
+		for fetch_result in fetched {
+			let items = fetch_result.as_ref().ok()?;
+			hasher.write_usize(items.len());
+			for item in items {
+				hasher.write(item.get_source().as_bytes());
+				hasher.write_usize(item.get_contents().len());
+				hasher.write(item.get_contents());
+			}
+		}
+		if let Some(inline_tasks) = inline_tasks {
+			hasher.write(&serde_json::to_vec(inline_tasks).ok()?);
+		}
+
+		Some(hasher.finish())
+	}
+
+	/// Get the path to a cached task graph.
+	fn cache_path(cache_dir: &Path, key: u64) -> PathBuf {
+		cache_dir.join(format!("{:016x}.json", key))
+	}
+
+	/// Attempt to read a previously parsed task graph from disk.
+	///
+	/// Any failure here is treated as a cache miss, as is a cache directory, or
+	/// file that someone other than the current user could have written to.
+	fn read_from_cache(cache_dir: &Path, key: u64) -> Option<Self> {
+		let path = Self::cache_path(cache_dir, key);
+		if !is_private_to_current_user(cache_dir) || !is_private_to_current_user(&path) {
+			if path.exists() {
+				debug!("Ignoring task graph cache that isn't private: [{:?}]", path);
+			}
+			return None;
+		}
+		let contents = std::fs::read(&path).ok()?;
+		match serde_json::from_slice::<HashMap<String, TaskConf>>(&contents) {
+			Ok(flattened_tasks) => Some(Self { flattened_tasks }),
+			Err(err) => {
+				debug!("Ignoring invalid task graph cache: [{:?}]", err);
+				None
+			}
+		}
+	}
+
+	/// Write this task graph to disk so it can be reused by a later invocation.
+	///
+	/// Failing to write the cache is never fatal.
+	fn write_to_cache(&self, cache_dir: &Path, key: u64) {
+		let path = Self::cache_path(cache_dir, key);
+		let write_res = create_private_dir(cache_dir)
+			.and_then(|_| {
+				if is_private_to_current_user(cache_dir) {
+					Ok(())
+				} else {
+					Err(eyre!(
+						"The cache directory can be written to by other users."
+					))
+				}
+			})
+			.and_then(|_| serde_json::to_vec(&self.flattened_tasks).map_err(Report::from))
+			.and_then(|serialized| write_private_file(&path, &serialized));
+
+		if let Err(err) = write_res {
+			debug!(
+				"Failed to write task graph cache to: [{:?}] due to: [{:?}]",
+				path, err,
+			);
+		}
+	}
+
 	/// Consume the overlying tasks type, and get all the tasks.
 	#[must_use]
 	pub fn consume_and_get_tasks(self) -> HashMap<String, TaskConf> {
 		self.flattened_tasks
 	}
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+	use crate::dirs::TestDir;
+	use std::fs::create_dir_all;
+
+	#[test]
+	fn validates_task_names() {
+		for valid_name in &[
+			"build",
+			"test-unit",
+			"lint_all",
+			"web:build",
+			"v1.2",
+			"café",
+		] {
+			assert!(
+				validate_task_name(valid_name, "dl-tasks.yml").is_ok(),
+				"{} should be a valid task name",
+				valid_name
+			);
+		}
+		for invalid_name in &["", "  ", "a b", "a/b", "..", "-flag", "$(rm)", "it's"] {
+			assert!(
+				validate_task_name(invalid_name, "dl-tasks.yml").is_err(),
+				"{} should be an invalid task name",
+				invalid_name
+			);
+		}
+	}
+
+	#[test]
+	fn keeps_every_unsatisfied_referencer() {
+		let mut internal_task_names = HashSet::new();
+		let mut unsatisfied_task_names = HashMap::new();
+		let mut flatenned_tasks = HashMap::new();
+		for task in &[
+			"name: first\ntype: pipeline\nsteps:\n  - name: a\n    task: missing\n  - name: b\n    task: missing\n",
+			"name: second\ntype: oneof\noptions:\n  - name: a\n    task: missing\n",
+		] {
+			TaskGraph::parse_task(
+				"dl-tasks.yml",
+				serde_yaml::from_str::<TaskConf>(task).unwrap(),
+				&mut internal_task_names,
+				&mut unsatisfied_task_names,
+				&mut flatenned_tasks,
+			)
+			.unwrap();
+		}
+		assert_eq!(
+			unsatisfied_task_names["missing"],
+			vec!["first".to_owned(), "second".to_owned()]
+		);
+
+		TaskGraph::parse_task(
+			"dl-tasks.yml",
+			serde_yaml::from_str::<TaskConf>(
+				"name: missing\nlocation:\n  type: path\n  at: missing.sh\n",
+			)
+			.unwrap(),
+			&mut internal_task_names,
+			&mut unsatisfied_task_names,
+			&mut flatenned_tasks,
+		)
+		.unwrap();
+		assert!(unsatisfied_task_names.is_empty());
+	}
+
+	#[test]
+	fn merges_inline_tasks() {
+		let test_dir = TestDir::new("inline-tasks");
+		let project_root = test_dir.get_path().to_path_buf();
+		create_dir_all(project_root.join("tasks")).unwrap();
+		std::fs::write(
+			project_root.join("tasks").join("dl-tasks.yml"),
+			"tasks:\n  - name: build\n    location:\n      type: path\n      at: build.sh\n",
+		)
+		.unwrap();
+		let fetcher = FetcherRepository::new(project_root.clone()).unwrap();
+		let locations = "task_locations:\n  - type: path\n    at: tasks\n";
+
+		// Inline tasks keep the file they were defined in through an overlay.
+		let mut config = serde_yaml::from_str::<TopLevelConf>(locations).unwrap();
+		config.set_task_location(".dl/config.yml");
+		let mut local = serde_yaml::from_str::<TopLevelConf>(
+			"tasks:\n  - name: all\n    type: pipeline\n    steps:\n      - name: build\n        task: build\n",
+		)
+		.unwrap();
+		local.set_task_location(".dl/config.local.yml");
+		let tasks = async_std::task::block_on(TaskGraph::new_with_cache_dir(
+			&config.overlay(local),
+			&fetcher,
+			None,
+		))
+		.unwrap()
+		.consume_and_get_tasks();
+		assert_eq!(tasks.len(), 2);
+		assert_eq!(tasks["all"].get_source_path(), ".dl/config.local.yml");
+		assert!(tasks["all"].is_defined_inline());
+		assert!(!tasks["build"].is_defined_inline());
+
+		let duplicate = serde_yaml::from_str::<TopLevelConf>(&format!(
+			"{}tasks:\n  - name: build\n    location:\n      type: path\n      at: build.sh\n",
+			locations
+		))
+		.unwrap();
+		assert!(async_std::task::block_on(TaskGraph::new_with_cache_dir(
+			&duplicate, &fetcher, None
+		))
+		.is_err());
+	}
+
+	#[test]
+	fn caches_task_graphs() {
+		let test_dir = TestDir::new("task-graph-cache");
+		let project_root = test_dir.get_path().to_path_buf();
+		let cache_dir = project_root.join("cache");
+		let task_file = project_root.join("tasks").join("dl-tasks.yml");
+		create_dir_all(project_root.join("tasks")).unwrap();
+		std::fs::write(
+			&task_file,
+			"tasks:\n  - name: build\n    location:\n      type: path\n      at: build.sh\n",
+		)
+		.unwrap();
+		let fetcher = FetcherRepository::new(project_root.clone()).unwrap();
+		let config = serde_yaml::from_str::<TopLevelConf>(
+			"task_locations:\n  - type: path\n    at: tasks\n",
+		)
+		.unwrap();
+		let get_tasks = || {
+			async_std::task::block_on(TaskGraph::new_with_cache_dir(
+				&config,
+				&fetcher,
+				Some(&cache_dir),
+			))
+			.unwrap()
+			.consume_and_get_tasks()
+		};
+		let cached_files = || {
+			std::fs::read_dir(&cache_dir)
+				.unwrap()
+				.map(|entry| entry.unwrap().path())
+				.collect::<Vec<_>>()
+		};
+
+		// A miss parses the task files, and writes the graph to the cache.
+		assert!(get_tasks().contains_key("build"));
+		let cached = cached_files();
+		assert_eq!(cached.len(), 1);
+
+		// A hit uses whatever graph is in the cache.
+		std::fs::write(&cached[0], "{}").unwrap();
+		assert!(get_tasks().is_empty());
+
+		// A corrupt cache file is a miss.
+		std::fs::write(&cached[0], "not json").unwrap();
+		assert!(get_tasks().contains_key("build"));
+
+		// A cache file, or directory, other users could write to is a miss.
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+
+			std::fs::write(&cached[0], "{}").unwrap();
+			std::fs::set_permissions(&cached[0], std::fs::Permissions::from_mode(0o666)).unwrap();
+			assert!(get_tasks().contains_key("build"));
+			std::fs::write(&cached[0], "{}").unwrap();
+			std::fs::set_permissions(&cache_dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+			assert!(get_tasks().contains_key("build"));
+			std::fs::set_permissions(&cache_dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+			assert!(get_tasks().is_empty());
+		}
+
+		// Changing a task file changes the key.
+		std::fs::write(
+			&task_file,
+			"tasks:\n  - name: test\n    location:\n      type: path\n      at: test.sh\n",
+		)
+		.unwrap();
+		assert!(get_tasks().contains_key("test"));
+		assert_eq!(cached_files().len(), 2);
+
+		// A location that failed to fetch never has a key.
+		assert!(TaskGraph::cache_key(&[Err(eyre!("failed"))], None).is_none());
+	}
+
+	#[test]
+	fn task_cache_can_be_disabled() {
+		assert!(TaskGraph::is_cache_disabled_value(Some("true")));
+		assert!(!TaskGraph::is_cache_disabled_value(Some("false")));
+		assert!(!TaskGraph::is_cache_disabled_value(Some("")));
+		assert!(!TaskGraph::is_cache_disabled_value(None));
+	}
+}
@@ -4,18 +4,153 @@
 //! as a module for now because it makes sense to be incase more than the list
 //! command ever needs to do something fancy.
 
+use crate::{tasks::execution::events::ExecutionEvent, time_helper::format_duration};
+
 use atty::Stream;
 use colored::Colorize;
 use crossbeam_channel::Sender;
 use lazy_static::lazy_static;
-use std::sync::Arc;
+use once_cell::sync::OnceCell;
+use std::{convert::TryFrom, sync::Arc, time::Duration};
 use term_size::dimensions as terminal_dimensions;
 
 pub(crate) mod task_indicator;
 pub(crate) mod throttle;
 
+/// The smallest width we'll wrap descriptions to, even on tiny terminals.
+const MINIMUM_DESCRIPTION_WIDTH: usize = 20;
+
+/// Wrap text on word boundaries so no line is longer than `width` characters.
+/// Words longer than `width` are split across lines.
+///
+/// `text`: the text to wrap.
+/// `width`: the maximum amount of characters per line.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+	let mut lines = Vec::new();
+	let mut current_line = String::new();
+	let mut current_len: usize = 0;
+
+	for word in text.split_whitespace() {
+		let mut word_chars: Vec<char> = word.chars().collect();
+		while !word_chars.is_empty() {
+			let needed = if current_len == 0 {
+				word_chars.len()
+			} else {
+				word_chars.len() + 1
+			};
+			if current_len + needed <= width {
+				if current_len != 0 {
+					current_line.push(' ');
+				}
+				current_line.extend(word_chars.iter());
+				current_len += needed;
+				word_chars.clear();
+			} else if current_len != 0 {
+				lines.push(std::mem::take(&mut current_line));
+				current_len = 0;
+			} else {
+				let remainder = word_chars.split_off(width);
+				lines.push(word_chars.into_iter().collect());
+				word_chars = remainder;
+			}
+		}
+	}
+	if !current_line.is_empty() || lines.is_empty() {
+		lines.push(current_line);
+	}
+
+	lines
+}
+
+/// Parse one of the `DL_FORCE_*COLOR` variables, `None` meaning it was not
+/// explicitly set to `true`, or `false`.
+fn parse_force_colour(value: Option<String>) -> Option<bool> {
+	match value.as_deref() {
+		Some("true") => Some(true),
+		Some("false") => Some(false),
+		_ => None,
+	}
+}
+
+/// Determine if colour should be used for a particular stream.
+///
+/// `forced`: the value of any `DL_FORCE_*COLOR` variable that applies.
+/// `no_colour`: the value of `NO_COLOR`.
+/// `ci`: the value of `CI`.
+/// `is_tty`: if the stream is a tty.
+fn should_use_colour(
+	forced: Option<bool>,
+	no_colour: Option<&str>,
+	ci: Option<&str>,
+	is_tty: bool,
+) -> bool {
+	if let Some(forced_value) = forced {
+		return forced_value;
+	}
+	if matches!(no_colour, Some(value) if !value.is_empty()) {
+		return false;
+	}
+	if matches!(ci, Some(value) if !value.is_empty() && value != "false") {
+		return false;
+	}
+
+	is_tty
+}
+
+/// How colour was asked for on the command line, with `--color`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColourMode {
+	/// Decide based off of the environment, and if the output is a tty.
+	#[default]
+	Auto,
+	/// Always use colour, ignoring the environment.
+	Always,
+	/// Never use colour, ignoring the environment.
+	Never,
+}
+
+impl ColourMode {
+	/// Parse a colour mode: `auto`, `always`, or `never`.
+	#[must_use]
+	pub fn parse(value: &str) -> Option<Self> {
+		match value {
+			"auto" => Some(Self::Auto),
+			"always" => Some(Self::Always),
+			"never" => Some(Self::Never),
+			_ => None,
+		}
+	}
+
+	/// Get if colour has been explicitly turned on, or off.
+	#[must_use]
+	fn as_forced(self) -> Option<bool> {
+		match self {
+			Self::Auto => None,
+			Self::Always => Some(true),
+			Self::Never => Some(false),
+		}
+	}
+}
+
+/// The colour mode passed on the command line, this must be set before the
+/// terminal is first used.
+static COLOUR_MODE: OnceCell<ColourMode> = OnceCell::new();
+
+/// Set the colour mode passed on the command line (with `--color`).
+///
+/// This needs to be called before `TERM` is used for the first time, since
+/// the terminal only determines if it should use colour once.
+pub fn set_colour_mode(mode: ColourMode) {
+	let _ = COLOUR_MODE.set(mode);
+	if let Some(forced) = mode.as_forced() {
+		colored::control::set_override(forced);
+	}
+}
+
 lazy_static! {
-	pub static ref TERM: Arc<Term> = Arc::new(Term::new());
+	pub static ref TERM: Arc<Term> = Arc::new(Term::with_colour_mode(
+		COLOUR_MODE.get().copied().unwrap_or_default()
+	));
 }
 
 /// Represents a `Term`, or terminal. the output needed in order to properly render
@@ -25,6 +160,10 @@ pub struct Term {
 	is_colour: bool,
 	/// Should we allow colour to STDERR?
 	is_colour_err: bool,
+	/// Should task output be prefixed with the task name even without colour?
+	prefix_task_output: bool,
+	/// Should the list of running tasks never be rendered?
+	no_indicator: bool,
 	/// The width of the terminal currently.
 	term_width: usize,
 }
@@ -38,13 +177,31 @@ impl Default for Term {
 }
 
 impl Term {
-	/// Create a new "Terminal" instance. Will determine if colour is allowed by checking for:
+	/// Create a new "Terminal" instance. Will determine if colour is allowed
+	/// using the following precedence (first match wins):
+	///
+	/// 0. A `--color` of `always`, or `never` (see: `with_colour_mode`).
+	/// 1. An explicit `DL_FORCE_STDOUT_COLOR`/`DL_FORCE_STDERR_COLOR`, and then
+	///    `DL_FORCE_COLOR` set to `true`, or `false`.
+	/// 2. The existance of a non-empty `NO_COLOR` disables colour.
+	/// 3. The existance of a non-empty `CI` variable (that isn't `false`)
+	///    disables colour.
+	/// 4. If STDOUT/STDERR are a tty.
 	///
-	/// 1. If STDOUT & STDERR are a tty.
-	/// 2. There is no existance of a `NO_COLOR`
-	/// 3. There is no existance of a `CI` variable.
+	/// Task output is always prefixed with the task name when using colour,
+	/// setting `DL_PREFIX_TASK_OUTPUT` to `true` keeps the prefix without
+	/// colour as well. Setting `DL_NO_INDICATOR` to `true` (or `1`) never
+	/// renders the list of running tasks, so no cursor movements are output.
 	#[must_use]
 	pub fn new() -> Self {
+		Self::with_colour_mode(ColourMode::Auto)
+	}
+
+	/// Create a new "Terminal" instance, with the colour mode passed on the
+	/// command line. Anything but `auto` takes precedence over every
+	/// environment variable for both STDOUT, and STDERR.
+	#[must_use]
+	pub fn with_colour_mode(mode: ColourMode) -> Self {
 		let tty_out = atty::is(Stream::Stdout);
 		let tty_err = atty::is(Stream::Stderr);
 
@@ -53,38 +210,35 @@ impl Term {
 			term_width = new_term_width;
 		}
 
-		let mut use_colour = true;
-		if use_colour {
-			if let Ok(value) = std::env::var("NO_COLOR") {
-				if !value.is_empty() {
-					use_colour = false;
-				}
-			}
-		}
-
-		let mut force_stdout_colour = false;
-		let mut force_stderr_colour = false;
-
-		if let Ok(value) = std::env::var("DL_FORCE_COLOR") {
-			if value == "true" {
-				force_stdout_colour = true;
-				force_stderr_colour = true;
-			}
-		}
-		if let Ok(value) = std::env::var("DL_FORCE_STDOUT_COLOR") {
-			if value == "true" {
-				force_stdout_colour = true;
-			}
-		}
-		if let Ok(value) = std::env::var("DL_FORCE_STDERR_COLOR") {
-			if value == "true" {
-				force_stderr_colour = true;
-			}
-		}
+		let no_colour = std::env::var("NO_COLOR").ok();
+		let ci = std::env::var("CI").ok();
+		let force_colour = parse_force_colour(std::env::var("DL_FORCE_COLOR").ok());
+		let force_stdout_colour = mode.as_forced().or_else(|| {
+			parse_force_colour(std::env::var("DL_FORCE_STDOUT_COLOR").ok()).or(force_colour)
+		});
+		let force_stderr_colour = mode.as_forced().or_else(|| {
+			parse_force_colour(std::env::var("DL_FORCE_STDERR_COLOR").ok()).or(force_colour)
+		});
 
 		Self {
-			is_colour: force_stdout_colour || (use_colour && tty_out),
-			is_colour_err: force_stderr_colour || (use_colour && tty_err),
+			is_colour: should_use_colour(
+				force_stdout_colour,
+				no_colour.as_deref(),
+				ci.as_deref(),
+				tty_out,
+			),
+			is_colour_err: should_use_colour(
+				force_stderr_colour,
+				no_colour.as_deref(),
+				ci.as_deref(),
+				tty_err,
+			),
+			prefix_task_output: parse_force_colour(std::env::var("DL_PREFIX_TASK_OUTPUT").ok())
+				.unwrap_or(false),
+			no_indicator: matches!(
+				std::env::var("DL_NO_INDICATOR").as_deref(),
+				Ok("true") | Ok("1")
+			),
 			term_width,
 		}
 	}
@@ -162,6 +316,14 @@ impl Term {
 			}
 		}
 
+		// Descriptions start after: two spaces, the key, and two more spaces.
+		let description_column = longest_key + 4;
+		let continuation_indent = " ".repeat(description_column);
+		let description_width = std::cmp::max(
+			self.term_width.saturating_sub(description_column),
+			MINIMUM_DESCRIPTION_WIDTH,
+		);
+
 		let mut result = String::new();
 
 		for (key, description) in list_with_descriptions {
@@ -179,10 +341,15 @@ impl Term {
 				padded += " ";
 			}
 
+			let mut lines = wrap_text(description, description_width).into_iter();
+			let first_line = lines.next().unwrap_or_default();
 			if self.is_colour {
-				result += &format!("  {}  {}{}\n", actual_key.cyan(), padded, description);
+				result += &format!("  {}  {}{}\n", actual_key.cyan(), padded, first_line);
 			} else {
-				result += &format!("  {}  {}{}\n", actual_key, padded, description);
+				result += &format!("  {}  {}{}\n", actual_key, padded, first_line);
+			}
+			for line in lines {
+				result += &format!("{}{}\n", continuation_indent, line);
 			}
 		}
 
@@ -206,25 +373,144 @@ impl Term {
 		)
 	}
 
+	/// Render a "tree section", or a tree of items with a title card.
+	///
+	/// `title`: The title of this tree.
+	/// `nodes`: Each line of the tree as: <tree prefix, item, description>,
+	///          the prefix being the lines that connect an item to it's parent.
+	#[must_use]
+	pub fn render_tree_section(&self, title: &str, nodes: &[(String, String, String)]) -> String {
+		let mut result = String::new();
+		for (prefix, item, description) in nodes {
+			let rendered_item = if self.is_colour {
+				item.cyan().to_string()
+			} else {
+				item.to_owned()
+			};
+			result += &format!("  {}{}  {}\n", prefix, rendered_item, description);
+		}
+
+		format!(
+			"{}\n\n{}",
+			self.render_title_bar(title, &format!("[{}]", nodes.len())),
+			result,
+		)
+	}
+
+	/// Render the summary of all the tasks that ran.
+	///
+	/// `summaries`: the summary of each task that ran.
+	/// `as_json`: render as a JSON array, instead of a table.
+	#[must_use]
+	pub fn render_task_summary(
+		&self,
+		summaries: &[task_indicator::TaskSummary],
+		as_json: bool,
+	) -> String {
+		if as_json {
+			return serde_json::to_string(summaries).unwrap_or_default();
+		}
+
+		let list = summaries
+			.iter()
+			.map(|summary| {
+				let took = format_duration(Duration::from_millis(
+					u64::try_from(summary.duration_ms).unwrap_or(u64::MAX),
+				));
+				(
+					summary.name.clone(),
+					if summary.skipped {
+						format!("skipped  took: {}", took)
+					} else if summary.allowed_failure {
+						format!("rc: {:<3}  took: {}  (allowed to fail)", summary.rc, took)
+					} else {
+						format!("rc: {:<3}  took: {}", summary.rc, took)
+					},
+				)
+			})
+			.collect::<Vec<(String, String)>>();
+
+		self.render_list_section("Task Summary", &list)
+	}
+
 	/// Create an indicator for outputting tasks to a tty.
 	///
+	/// `task_count`: The total number of tasks that we have to run.
+	/// `no_indicator`: Never render the list of running tasks (on top of the
+	///                 `DL_NO_INDICATOR` environment variable).
+	///
 	/// Returns a tuple of:
 	///   1. The task indicator instance.
-	///   2. A channel sender to send logs (and the task that created them).
-	///   3. A channel sender to send task changes too.
+	///   2. A channel sender to send events to.
 	#[must_use]
 	pub fn create_task_indicator(
 		&self,
 		task_count: usize,
-	) -> (
-		task_indicator::TaskIndicator,
-		Sender<(String, String, bool)>,
-		Sender<task_indicator::TaskChange>,
-	) {
+		no_indicator: bool,
+	) -> (task_indicator::TaskIndicator, Sender<ExecutionEvent>) {
 		task_indicator::TaskIndicator::new(
 			task_count,
 			self.should_color_stdout(),
 			self.should_color_stderr(),
+			self.prefix_task_output,
+			!(no_indicator || self.no_indicator),
 		)
 	}
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn colour_precedence() {
+		// Nothing set, just the tty decides.
+		assert!(should_use_colour(None, None, None, true));
+		assert!(!should_use_colour(None, None, None, false));
+
+		// NO_COLOR disables, unless it's empty.
+		assert!(!should_use_colour(None, Some("1"), None, true));
+		assert!(should_use_colour(None, Some(""), None, true));
+
+		// CI disables, unless it's empty, or explicitly false.
+		assert!(!should_use_colour(None, None, Some("true"), true));
+		assert!(should_use_colour(None, None, Some("false"), true));
+		assert!(should_use_colour(None, None, Some(""), true));
+
+		// Explicitly forcing always wins.
+		assert!(should_use_colour(
+			Some(true),
+			Some("1"),
+			Some("true"),
+			false
+		));
+		assert!(!should_use_colour(Some(false), None, None, true));
+	}
+
+	#[test]
+	fn wraps_text() {
+		assert_eq!(wrap_text("", 10), vec![String::new()]);
+		assert_eq!(wrap_text("short", 10), vec!["short".to_owned()]);
+		assert_eq!(
+			wrap_text("a description that wraps", 10),
+			vec![
+				"a".to_owned(),
+				"descriptio".to_owned(),
+				"n that".to_owned(),
+				"wraps".to_owned()
+			]
+		);
+		assert_eq!(
+			wrap_text("one two three four", 9),
+			vec!["one two".to_owned(), "three".to_owned(), "four".to_owned()]
+		);
+	}
+
+	#[test]
+	fn parses_force_colour() {
+		assert_eq!(parse_force_colour(Some("true".to_owned())), Some(true));
+		assert_eq!(parse_force_colour(Some("false".to_owned())), Some(false));
+		assert_eq!(parse_force_colour(Some("yes".to_owned())), None);
+		assert_eq!(parse_force_colour(None), None);
+	}
+}
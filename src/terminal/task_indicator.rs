@@ -1,16 +1,80 @@
-use crate::{log::HAS_OUTPUT_LOG_MSG, terminal::throttle::Throttle};
+use crate::{
+	log::HAS_OUTPUT_LOG_MSG, sigint::has_terminal_been_resized,
+	tasks::execution::events::ExecutionEvent, terminal::throttle::Throttle,
+	time_helper::format_duration,
+};
 
 use colored::Colorize;
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use std::{
+	collections::{HashMap, HashSet},
+	convert::TryFrom,
+	time::{Duration, Instant},
+};
 use term_size::dimensions as terminal_dimensions;
 
-/// Represents a `TaskChange` or a task starting/stopping.
-pub enum TaskChange {
-	/// Indicates a task starting.
-	StartedTask(String),
-	/// Indicates a task finishing.
-	FinishedTask(String),
+/// The number of tasks that need to finish before we'll estimate how long
+/// is left, any fewer and the estimate is mostly noise.
+const MIN_TASKS_FOR_ETA: usize = 2;
+
+/// Render how far through a run we are, e.g. `[3/10 30%]`, along with a
+/// rough estimate of the time left once enough tasks have finished.
+///
+/// The estimate assumes every remaining task takes as long as the average
+/// task that has finished, and that tasks keep running as parallel as they
+/// are right now.
+///
+/// `tasks_ran`: the number of tasks that have finished.
+/// `task_count`: the total number of tasks there are to run.
+/// `finished_durations`: how long each task that finished took.
+/// `running_count`: the number of tasks running right now.
+#[must_use]
+fn render_progress(
+	tasks_ran: usize,
+	task_count: usize,
+	finished_durations: &[Duration],
+	running_count: usize,
+) -> String {
+	let percentage = (std::cmp::min(tasks_ran, task_count) * 100)
+		.checked_div(task_count)
+		.unwrap_or(100);
+	let mut progress = format!("[{}/{} {}%", tasks_ran, task_count, percentage);
+
+	let remaining = task_count.saturating_sub(tasks_ran);
+	if remaining > 0 && finished_durations.len() >= MIN_TASKS_FOR_ETA {
+		let total: Duration = finished_durations.iter().sum();
+		let average = total / u32::try_from(finished_durations.len()).unwrap_or(u32::MAX);
+		let parallelism = u32::try_from(std::cmp::max(running_count, 1)).unwrap_or(u32::MAX);
+		let eta = average * u32::try_from(remaining).unwrap_or(u32::MAX) / parallelism;
+		progress += &format!(" ~{} left", format_duration(eta));
+	}
+
+	progress + "]"
+}
+
+/// Something to print, from the events that have come in.
+enum IndicatorLine {
+	/// A line of output from a task, and if it came from STDERR.
+	Output(String, String, bool),
+	/// A task finished (or was skipped), and the message to show about it.
+	Finished(String, String),
+}
+
+/// The result of a single task that finished running.
+#[derive(Debug, Serialize)]
+pub struct TaskSummary {
+	/// The name of the task (prefixed with the worker that ran it).
+	pub name: String,
+	/// The exit code of the task.
+	pub rc: i32,
+	/// How long the task took to run in milliseconds, from when it started
+	/// to when it finished.
+	pub duration_ms: u128,
+	/// If the task was skipped, rather than run.
+	pub skipped: bool,
+	/// If the task failed, but it's marked `allow_failure`.
+	pub allowed_failure: bool,
 }
 
 /// The `TaskIndicator` is used to help output the current tasks that are
@@ -19,47 +83,35 @@ pub enum TaskChange {
 ///
 /// This does always print to STDERR, and will properly respect "colour"
 /// settings for STDERR. If colour is disabled we will simply just output the
-/// logs of the tasks coming in. If colour is enabled we will throttle, and
-/// output the logs of the task coming in as well as a list of the tasks
-/// currently running.
-///
-/// The `TaskIndicator` works off a series of channels to receive updates from
-/// other threads. Both of these channels are multi-producer single consumer
-/// channels. They each serve a distinct purpose:
+/// logs of the tasks coming in (optionally still prefixed with the task name
+/// that output them). If colour is enabled we will throttle, and output the
+/// logs of the task coming in as well as a list of the tasks currently
+/// running.
 ///
-///   1. `TaskQueue`: Used to update the indicator on which tasks are currently
-///                   running. This way it's possible to know what to render to
-///                   a user. This will only render in colour mode.
-///   2. `OutputQueue`: This should be the actual output coming from each task.
-///                     It takes in a `task_name`, and the output to show. When
-///                     in colour mode this will "line buffer" so we can
-///                     prepend the task name that is running. For situations
-///                     like `run` where multiple tasks are executing at once.
+/// The `TaskIndicator` works off a single channel of `ExecutionEvent`s, as
+/// sent by an `EventForwarder`. Task starting/finishing updates which tasks
+/// are running (which is only rendered in colour mode), and each line of
+/// output is printed as it comes in, prefixed with the name of the task that
+/// output it when in colour mode (or prefixing without colour). For
+/// situations like `run` where multiple tasks are executing at once.
 pub struct TaskIndicator {
 	/// The amount of lines we'll need to erase to render the indicator again.
 	lines_previously_rendered: usize,
-	// The receiver for lines from STDOUT, and the task it's outputting for.
-	log_channel: Receiver<(String, String, bool)>,
-	// The receiver for tasks starting/finishing/etc.
-	task_changes: Receiver<TaskChange>,
+	/// The width of each line of the indicator we previously rendered, so we
+	/// know how many rows they take up if the terminal has been resized.
+	rendered_line_widths: Vec<usize>,
+	/// The receiver for everything that happens while running tasks.
+	events: Receiver<ExecutionEvent>,
 	/// The total number of tasks that there will be to execute.
 	task_count: usize,
-	// A series of buffers for output on tasks.
-	//
-	// We want to buffer on newlines for task_line_buffers
-	// so when doing colour'd output we can show:
-	// `task_name | blah blah blah`, and not have it split
-	// between lines.
-	//
-	// We will also flush lines if a task gets removed from the
-	// `tasks_running` hashset.
-	task_line_buffers: HashMap<String, String>,
-	// Like `task_line_buffers` but for STDERR.
-	task_line_buffers_err: HashMap<String, String>,
 	/// The total number of tasks that have run.
 	tasks_ran: usize,
 	/// The list of tasks that are currently running.
 	tasks_running: HashSet<String>,
+	/// When each task that is currently running started.
+	task_start_times: HashMap<String, Instant>,
+	/// The results of each task that has finished, in the order they finished.
+	task_summaries: Vec<TaskSummary>,
 	/// The width of the terminal.
 	terminal_width: usize,
 	/// The "throttle" instance that helps us not output
@@ -69,11 +121,18 @@ pub struct TaskIndicator {
 	use_colour_out: bool,
 	/// Should we show colour for STDERR?
 	use_colour_err: bool,
+	/// Should we prefix task output with the task name even without colour?
+	prefix_without_colour: bool,
+	/// Should we render the list of running tasks when using colour?
+	show_running_tasks: bool,
+	/// Should we write anything to the terminal at all? When not the
+	/// indicator only keeps track of the summary of each task.
+	terminal_output: bool,
 }
 
 impl TaskIndicator {
-	// Print a log line of colour.
-	fn print_new_log_line_colour(task_name: String, line: &str, is_stderr: bool) {
+	// Print a log line prefixed with the task name, optionally in colour.
+	fn print_new_log_line_prefixed(task_name: String, line: &str, is_stderr: bool, colour: bool) {
 		if line.is_empty() {
 			return;
 		}
@@ -88,22 +147,49 @@ impl TaskIndicator {
 		for _ in ftn.len()..10 {
 			padding += " ";
 		}
+		let prefix = if colour { ftn.cyan().to_string() } else { ftn };
+		if is_stderr {
+			eprintln!("{}{}| {}", prefix, padding, line);
+		} else {
+			println!("{}{}| {}", prefix, padding, line);
+		}
+	}
+
+	// Should we use colour for a particular stream?
+	fn is_colour(&self, is_stderr: bool) -> bool {
 		if is_stderr {
-			eprintln!("{}{}| {}", ftn.cyan(), padding, line);
+			self.use_colour_err
 		} else {
-			println!("{}{}| {}", ftn.cyan(), padding, line);
+			self.use_colour_out
 		}
 	}
 
-	// Print any new log lines that have come in.
-	fn print_new_log_lines_maybe_colour(&self, new_lines: Vec<(String, String, bool)>) {
-		for (task_name, line, is_err) in new_lines {
-			if (is_err && self.use_colour_err) || (!is_err && self.use_colour_out) {
-				Self::print_new_log_line_colour(task_name, &line, is_err);
-			} else if is_err {
-				eprintln!("{}", line);
-			} else {
-				println!("{}", line);
+	// Print a single log line, prefixing it with the task name if we can.
+	fn print_new_log_line(&self, task_name: String, line: &str, is_stderr: bool) {
+		let colour = self.is_colour(is_stderr);
+		if colour || self.prefix_without_colour {
+			Self::print_new_log_line_prefixed(task_name, line, is_stderr, colour);
+		} else if is_stderr {
+			eprintln!("{}", line);
+		} else {
+			println!("{}", line);
+		}
+	}
+
+	// Print any new lines that have come in, in the order they came in.
+	fn print_new_lines(&self, new_lines: Vec<IndicatorLine>) {
+		for new_line in new_lines {
+			match new_line {
+				IndicatorLine::Output(task_name, line, is_err) => {
+					self.print_new_log_line(task_name, &line, is_err);
+				}
+				IndicatorLine::Finished(task_name, message) => {
+					if self.use_colour_err || self.prefix_without_colour {
+						self.print_new_log_line(task_name, &message, true);
+					} else {
+						eprintln!("[{}] {}", task_name, message);
+					}
+				}
 			}
 		}
 	}
@@ -113,204 +199,207 @@ impl TaskIndicator {
 	/// `task_count`: The total number of tasks that we have to run.
 	/// `use_colour_out`: If we should bother outputting colour for STDOUT.
 	/// `use_colour_err`: If we should bother outputting colour for STDERR.
+	/// `prefix_without_colour`: If we should still prefix task output with the
+	///                          task name when colour is disabled.
+	/// `show_running_tasks`: If we should render the list of running tasks when
+	///                       using colour, rather than only streaming output.
 	///
 	/// Returns a tuple of:
 	///   1. The task indicator instance.
-	///   2. A channel sender to send logs (and the task that created them).
-	///   3. A channel sender to send task changes too.
+	///   2. A channel sender to send events to, output must already be split
+	///      into lines (which is what `EventForwarder` does).
 	#[must_use]
 	pub fn new(
 		task_count: usize,
 		use_colour_out: bool,
 		use_colour_err: bool,
-	) -> (Self, Sender<(String, String, bool)>, Sender<TaskChange>) {
-		let (log_sender, log_receiver) = unbounded();
-		let (tc_sender, tc_receiver) = unbounded();
+		prefix_without_colour: bool,
+		show_running_tasks: bool,
+	) -> (Self, Sender<ExecutionEvent>) {
+		let (event_sender, event_receiver) = unbounded();
 
 		(
 			Self {
 				lines_previously_rendered: 0,
-				log_channel: log_receiver,
-				task_changes: tc_receiver,
+				rendered_line_widths: Vec::new(),
+				events: event_receiver,
 				task_count,
-				task_line_buffers: HashMap::new(),
-				task_line_buffers_err: HashMap::new(),
 				tasks_ran: 0,
 				tasks_running: HashSet::new(),
+				task_start_times: HashMap::new(),
+				task_summaries: Vec::new(),
 				terminal_width: 80,
 				throttle: Throttle::new(),
 				use_colour_out,
 				use_colour_err,
+				prefix_without_colour,
+				show_running_tasks,
+				terminal_output: true,
 			},
-			log_sender,
-			tc_sender,
+			event_sender,
 		)
 	}
 
+	/// Set if this indicator should write anything to the terminal, when it
+	/// doesn't it only keeps track of the summary of each task. This is for
+	/// when something else (like another UI) is rendering the run.
+	///
+	/// `terminal_output`: if the indicator should write to the terminal.
+	#[must_use]
+	pub fn with_terminal_output(mut self, terminal_output: bool) -> Self {
+		self.terminal_output = terminal_output;
+		self
+	}
+
+	/// Record a task starting/finishing for timing, and the summary.
+	///
+	/// Returns the task name, and a message to show about it when it's a task
+	/// finishing.
+	fn record_task_change(&mut self, event: &ExecutionEvent) -> Option<(String, String)> {
+		let (task_name, finished_at, rc, skipped, allows_failure) = match event {
+			ExecutionEvent::TaskStarted(task_name, started_at) => {
+				self.task_start_times.insert(task_name.clone(), *started_at);
+				return None;
+			}
+			ExecutionEvent::TaskFinished(task_name, finished_at, rc, allows_failure) => {
+				(task_name, finished_at, *rc, false, *allows_failure)
+			}
+			ExecutionEvent::TaskSkipped(task_name, skipped_at) => {
+				(task_name, skipped_at, 0, true, false)
+			}
+			ExecutionEvent::LogLine(_, _, _) => return None,
+		};
+
+		let duration = self
+			.task_start_times
+			.remove(task_name)
+			.map_or(Duration::from_secs(0), |started_at| {
+				finished_at.saturating_duration_since(started_at)
+			});
+		self.task_summaries.push(TaskSummary {
+			name: task_name.clone(),
+			rc,
+			duration_ms: duration.as_millis(),
+			skipped,
+			allowed_failure: allows_failure && rc != 0,
+		});
+
+		let message = if skipped {
+			format!(
+				"skipped after {} (`only_if` condition failed)",
+				format_duration(duration)
+			)
+		} else if allows_failure && rc != 0 {
+			format!(
+				"finished in {} (rc: {}, allowed to fail)",
+				format_duration(duration),
+				rc
+			)
+		} else {
+			format!("finished in {} (rc: {})", format_duration(duration), rc)
+		};
+		Some((task_name.clone(), message))
+	}
+
 	/// "Tick", or update the task indicator.
 	///
 	/// This may be a no-op if the indicator decides so, and the indicator is
 	/// resilient to no set tick time.
 	pub fn tick(&mut self) {
+		if !self.terminal_output {
+			self.receive_events();
+			return;
+		}
+		// A resize always renders right away, so stale lines don't linger.
+		let was_resized = has_terminal_been_resized();
 		// First ensure the throttler is allowing us to render.
-		if !self.throttle.allowed() {
+		if !self.throttle.allowed() && !was_resized {
 			return;
 		}
 
-		if !self.use_colour_out && !self.use_colour_err {
+		let has_colour = self.use_colour_out || self.use_colour_err;
+		if !has_colour && !self.prefix_without_colour {
 			self.tick_no_colour();
 			return;
 		}
+		let render_tasks = has_colour && self.show_running_tasks;
 
-		// First process any changes to tasks that we have.
-		let mut tasks_need_flushing = HashSet::<String>::new();
-		let mut has_task_changes = false;
-		while let Ok(change) = self.task_changes.try_recv() {
-			has_task_changes = true;
+		let (new_lines, has_task_changes) = self.receive_events();
 
-			match change {
-				TaskChange::StartedTask(task_name) => {
-					self.tasks_running.insert(task_name);
-				}
-				TaskChange::FinishedTask(task_name) => {
-					self.tasks_ran += 1;
-					self.tasks_running.remove(&task_name);
-					tasks_need_flushing.insert(task_name);
-				}
-			}
-		}
-
-		// Next determine if we have any log lines that need to change.
-		let mut new_log_lines = Vec::<(String, String, bool)>::new();
-
-		// First check for any new lines that came in, buffering on new lines.
-		while let Ok((task_name, str_data, is_err)) = self.log_channel.try_recv() {
-			let mut lines = if is_err {
-				if self.task_line_buffers_err.contains_key(&task_name) {
-					self.task_line_buffers_err.remove(&task_name).unwrap() + &str_data
-				} else {
-					str_data
-				}
-			} else if self.task_line_buffers.contains_key(&task_name) {
-				self.task_line_buffers.remove(&task_name).unwrap() + &str_data
-			} else {
-				str_data
-			}
-			.split('\n')
-			.map(String::from)
-			.collect::<Vec<String>>();
-
-			if lines.len() == 1 {
-				if is_err {
-					self.task_line_buffers_err
-						.insert(task_name, lines.swap_remove(0));
-				} else {
-					self.task_line_buffers
-						.insert(task_name, lines.swap_remove(0));
-				}
-			} else {
-				for line in lines.iter().take(lines.len() - 1) {
-					new_log_lines.push((task_name.clone(), line.to_owned(), is_err));
-				}
-
-				if !lines[lines.len() - 1].is_empty() {
-					if is_err {
-						self.task_line_buffers_err
-							.insert(task_name, lines.swap_remove(lines.len() - 1));
-					} else {
-						self.task_line_buffers
-							.insert(task_name, lines.swap_remove(lines.len() - 1));
-					}
-				}
-			}
-		}
+		// Update the terminal width incase someone changed their terminal.
+		let updated_width = self.update_term_width() || was_resized;
 
-		// Next if any tasks finished, forcefully flush.
-		// We do this after processing new lines incase they come in after.
-		// It is possible a line gets really really delayed, but we shrug at that
-		// case. We'll print on the very end.
-		//
-		// The order of the one task will still be in order, there just may be things
-		// inbetween.
-		for flushable_task in tasks_need_flushing {
-			if self.task_line_buffers.contains_key(&flushable_task) {
-				let partial_line = self.task_line_buffers.remove(&flushable_task).unwrap();
-				new_log_lines.push((flushable_task.clone(), partial_line, false));
-			}
-			if self.task_line_buffers_err.contains_key(&flushable_task) {
-				let partial_line = self.task_line_buffers_err.remove(&flushable_task).unwrap();
-				new_log_lines.push((flushable_task, partial_line, true));
-			}
+		// Without colour (or with the indicator disabled) there's no list of
+		// running tasks to render, only the prefixed lines.
+		if !render_tasks {
+			self.print_new_lines(new_lines);
+			return;
 		}
 
-		// Update the terminal width incase someone changed their terminal.
-		let updated_width = self.update_term_width();
-
 		// If we have changes, it's time to re-render...
-		if has_task_changes || !new_log_lines.is_empty() || updated_width {
+		if has_task_changes || !new_lines.is_empty() || updated_width {
 			// Erase the previous task lines...
 			self.erase_task_lines();
 			// Print any new log lines that have come in...
-			self.print_new_log_lines_maybe_colour(new_log_lines);
+			self.print_new_lines(new_lines);
 			// Print the new tasks string.
 			self.print_tasks_colour();
 		}
 	}
 
-	/// Stop this task indicator, and flush all remaining logs.
-	pub fn stop_and_flush(mut self) {
-		// Ignore task status updates.
-		while self.task_changes.try_recv().is_ok() {}
+	/// Process every event that has come in.
+	///
+	/// Returns the lines to print in the order they should be printed, and if
+	/// any task started/finished.
+	fn receive_events(&mut self) -> (Vec<IndicatorLine>, bool) {
+		let mut new_lines = Vec::new();
+		let mut has_task_changes = false;
 
-		if !self.use_colour_out && !self.use_colour_err {
-			self.tick_no_colour();
-			return;
-		}
+		while let Ok(event) = self.events.try_recv() {
+			if let Some((task_name, message)) = self.record_task_change(&event) {
+				new_lines.push(IndicatorLine::Finished(task_name, message));
+			}
 
-		self.erase_task_lines();
-		for (key, value) in self.task_line_buffers {
-			if self.use_colour_out {
-				Self::print_new_log_line_colour(key.clone(), &value, false);
-			} else {
-				println!("{}", value);
+			match event {
+				ExecutionEvent::TaskStarted(task_name, _) => {
+					has_task_changes = true;
+					self.tasks_running.insert(task_name);
+				}
+				ExecutionEvent::TaskFinished(task_name, _, _, _)
+				| ExecutionEvent::TaskSkipped(task_name, _) => {
+					has_task_changes = true;
+					self.tasks_ran += 1;
+					self.tasks_running.remove(&task_name);
+				}
+				ExecutionEvent::LogLine(task_name, line, is_err) => {
+					new_lines.push(IndicatorLine::Output(task_name, line, is_err));
+				}
 			}
 		}
-		for (key, value) in self.task_line_buffers_err {
-			if self.use_colour_err {
-				Self::print_new_log_line_colour(key.clone(), &value, true);
-			} else {
-				eprintln!("{}", value);
-			}
+
+		(new_lines, has_task_changes)
+	}
+
+	/// Stop this task indicator, and flush all remaining logs.
+	///
+	/// Returns the summary of every task that finished.
+	pub fn stop_and_flush(mut self) -> Vec<TaskSummary> {
+		let (new_lines, _) = self.receive_events();
+		if !self.terminal_output {
+			return self.task_summaries;
 		}
-		while let Ok((task_name, str_data, is_stderr)) = self.log_channel.try_recv() {
-			let lines = str_data
-				.split('\n')
-				.map(String::from)
-				.collect::<Vec<String>>();
-			for line in lines {
-				if (is_stderr && self.use_colour_err) || (!is_stderr && self.use_colour_out) {
-					Self::print_new_log_line_colour(task_name.clone(), &line, is_stderr);
-				} else if is_stderr {
-					eprintln!("{}", line);
-				} else {
-					println!("{}", line);
-				}
-			}
+		// There's no running list left to render, only the lines.
+		if self.use_colour_out || self.use_colour_err || self.prefix_without_colour {
+			self.erase_task_lines();
 		}
+		self.print_new_lines(new_lines);
+
+		self.task_summaries
 	}
 
 	fn tick_no_colour(&mut self) {
-		// Make sure the buffer doesn't fill up, but we don't care about task/tasks.
-		while self.task_changes.try_recv().is_ok() {}
-
-		// Print out any lines that have come in...
-		while let Ok((_, str_data, is_err)) = self.log_channel.try_recv() {
-			if is_err {
-				eprint!("{}", str_data);
-			} else {
-				print!("{}", str_data);
-			}
-		}
+		let (new_lines, _) = self.receive_events();
+		self.print_new_lines(new_lines);
 	}
 
 	// Query for an updated terminal width.
@@ -339,10 +428,20 @@ impl TaskIndicator {
 			return;
 		}
 
+		// Lines we rendered may have wrapped if the terminal shrunk since, so
+		// they can take up more than one row now.
+		let terminal_width = std::cmp::max(self.terminal_width, 1);
+		let rows_to_erase = self.lines_previously_rendered
+			+ self
+				.rendered_line_widths
+				.iter()
+				.map(|width| width.div_ceil(terminal_width).saturating_sub(1))
+				.sum::<usize>();
+
 		// Erase the current line.
 		let mut line = "\x1B[2K".to_owned();
 		// For each extra line (more than one..) move the cursor up, and erase it.
-		for _ in 0..(self.lines_previously_rendered - 1) {
+		for _ in 0..(rows_to_erase - 1) {
 			line += "\x1B[1A\x1B[2K";
 		}
 		// Move the cursor back over to the far left. 1000 for extra padding, and the current width.
@@ -352,32 +451,151 @@ impl TaskIndicator {
 
 	// Print the "tasks" string with colour.
 	fn print_tasks_colour(&mut self) {
+		let finished_durations = self
+			.task_summaries
+			.iter()
+			.map(|summary| {
+				Duration::from_millis(u64::try_from(summary.duration_ms).unwrap_or(u64::MAX))
+			})
+			.collect::<Vec<_>>();
+		let progress = render_progress(
+			self.tasks_ran,
+			self.task_count,
+			&finished_durations,
+			self.tasks_running.len(),
+		);
+		let header = format!(" {} Tasks Running...", self.tasks_running.len());
+		self.rendered_line_widths = vec![progress.chars().count() + header.chars().count()];
+
 		if self.tasks_running.is_empty() {
-			eprint!(
-				"[{}/{}] {} Tasks Running...\n",
-				self.tasks_ran, self.task_count, 0
-			);
+			eprint!("{}{}\n", progress, header);
 
 			self.lines_previously_rendered = 2;
 		} else {
 			let mut task_output_str = String::new();
 			for running_task in &self.tasks_running {
-				if task_output_str.is_empty() {
-					task_output_str += "  ";
-				} else {
-					task_output_str += "\n  ";
+				let mut task_line = format!("  {}", running_task);
+				if let Some(started_at) = self.task_start_times.get(running_task) {
+					task_line += &format!(" ({})", format_duration(started_at.elapsed()));
 				}
-				task_output_str += running_task.as_str();
+				self.rendered_line_widths.push(task_line.chars().count());
+
+				if !task_output_str.is_empty() {
+					task_output_str += "\n";
+				}
+				task_output_str += &task_line;
 			}
 
-			eprint!(
-				"{} {} Tasks Running...\n{}\n",
-				&format!("[{}/{}]", self.tasks_ran, self.task_count).green(),
-				self.tasks_running.len(),
-				task_output_str,
-			);
+			eprint!("{}{}\n{}\n", progress.green(), header, task_output_str);
 
 			self.lines_previously_rendered = self.tasks_running.len() + 2;
 		}
 	}
 }
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn renders_progress() {
+		assert_eq!(render_progress(0, 4, &[], 2), "[0/4 0%]");
+		// Not enough tasks have finished for an estimate.
+		assert_eq!(
+			render_progress(1, 4, &[Duration::from_secs(10)], 2),
+			"[1/4 25%]"
+		);
+		let finished = [Duration::from_secs(10), Duration::from_secs(30)];
+		assert_eq!(
+			render_progress(2, 4, &finished, 1),
+			format!(
+				"[2/4 50% ~{} left]",
+				format_duration(Duration::from_secs(40))
+			)
+		);
+		assert_eq!(
+			render_progress(2, 4, &finished, 2),
+			format!(
+				"[2/4 50% ~{} left]",
+				format_duration(Duration::from_secs(20))
+			)
+		);
+		assert_eq!(render_progress(4, 4, &finished, 0), "[4/4 100%]");
+		assert_eq!(render_progress(0, 0, &[], 0), "[0/0 100%]");
+	}
+
+	#[test]
+	fn summarizes_events() {
+		let (mut indicator, events) = TaskIndicator::new(2, false, false, true, false);
+		let started_at = Instant::now();
+		events
+			.send(ExecutionEvent::TaskStarted(
+				"0-build".to_owned(),
+				started_at,
+			))
+			.unwrap();
+		events
+			.send(ExecutionEvent::TaskStarted("1-lint".to_owned(), started_at))
+			.unwrap();
+		events
+			.send(ExecutionEvent::LogLine(
+				"0-build".to_owned(),
+				"building".to_owned(),
+				false,
+			))
+			.unwrap();
+		indicator.tick_no_colour();
+		assert_eq!(indicator.tasks_running.len(), 2);
+
+		events
+			.send(ExecutionEvent::TaskFinished(
+				"0-build".to_owned(),
+				started_at + Duration::from_millis(1500),
+				3,
+				true,
+			))
+			.unwrap();
+		events
+			.send(ExecutionEvent::TaskSkipped("1-lint".to_owned(), started_at))
+			.unwrap();
+		let summaries = indicator.stop_and_flush();
+
+		assert_eq!(summaries.len(), 2);
+		assert_eq!(summaries[0].name, "0-build");
+		assert_eq!(summaries[0].rc, 3);
+		assert_eq!(summaries[0].duration_ms, 1500);
+		assert!(!summaries[0].skipped);
+		assert!(summaries[0].allowed_failure);
+		assert_eq!(summaries[1].name, "1-lint");
+		assert!(summaries[1].skipped);
+	}
+
+	#[test]
+	fn summarizes_events_without_terminal_output() {
+		let (indicator, events) = TaskIndicator::new(1, true, true, true, true);
+		let mut indicator = indicator.with_terminal_output(false);
+		let started_at = Instant::now();
+		events
+			.send(ExecutionEvent::TaskStarted(
+				"0-build".to_owned(),
+				started_at,
+			))
+			.unwrap();
+		indicator.tick();
+		// Events are still followed, but the running list is never rendered.
+		assert_eq!(indicator.tasks_running.len(), 1);
+		assert_eq!(indicator.lines_previously_rendered, 0);
+		events
+			.send(ExecutionEvent::TaskFinished(
+				"0-build".to_owned(),
+				started_at,
+				0,
+				false,
+			))
+			.unwrap();
+		let summaries = indicator.stop_and_flush();
+
+		assert_eq!(summaries.len(), 1);
+		assert_eq!(summaries[0].name, "0-build");
+	}
+}
@@ -0,0 +1,185 @@
+//! Small utilities for dealing with user provided durations, and the
+//! timestamps docker hands back to us.
+
+use color_eyre::{eyre::eyre, Result, Section};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parse a "human" duration string like: `90s`, `30m`, `2h`, or `1d`.
+///
+/// A bare number is treated as a count of seconds.
+///
+/// # Errors
+///
+/// - When the string is empty, or not a positive number with a known suffix.
+/// - When the duration is too large to represent.
+pub fn parse_human_duration(duration_str: &str) -> Result<Duration> {
+	let trimmed = duration_str.trim();
+	let (number_part, multiplier) = match trimmed.chars().last() {
+		Some('s') => (&trimmed[..trimmed.len() - 1], 1),
+		Some('m') => (&trimmed[..trimmed.len() - 1], 60),
+		Some('h') => (&trimmed[..trimmed.len() - 1], 60 * 60),
+		Some('d') => (&trimmed[..trimmed.len() - 1], 60 * 60 * 24),
+		_ => (trimmed, 1),
+	};
+
+	let amount = number_part.parse::<u64>().map_err(|_| {
+		eyre!(
+			"Duration: [{}] is not a valid duration.",
+			duration_str
+		)
+	})
+	.suggestion("Durations should be a positive number, followed by one of: `s`, `m`, `h`, or `d`. For example: `30m`.")?;
+
+	let seconds = amount.checked_mul(multiplier).ok_or_else(|| {
+		eyre!(
+			"Duration: [{}] is too large.",
+			duration_str
+		)
+	})
+	.suggestion("Durations can be at most: `18446744073709551615s`.")?;
+
+	Ok(Duration::from_secs(seconds))
+}
+
+/// Get the current epoch second count.
+#[must_use]
+pub fn get_epoch_seconds() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.expect("Dev-Loop does not support running on a system where time is before unix epoch!")
+		.as_secs()
+}
+
+/// Determine if something created at `created_at` (in epoch seconds) is at
+/// least `age` old.
+#[must_use]
+pub fn is_older_than(created_at: u64, age: Duration) -> bool {
+	get_epoch_seconds().saturating_sub(created_at) >= age.as_secs()
+}
+
+/// Format a duration for humans, e.g.: `1.23s`, `2m03s`, or `1h02m03s`.
+#[must_use]
+pub fn format_duration(duration: Duration) -> String {
+	let total_seconds = duration.as_secs();
+	if total_seconds < 60 {
+		format!("{:.2}s", duration.as_secs_f64())
+	} else if total_seconds < 60 * 60 {
+		format!("{}m{:02}s", total_seconds / 60, total_seconds % 60)
+	} else {
+		format!(
+			"{}h{:02}m{:02}s",
+			total_seconds / (60 * 60),
+			(total_seconds / 60) % 60,
+			total_seconds % 60
+		)
+	}
+}
+
+/// Convert a count of days since the unix epoch into a count of days for a
+/// particular civil date.
+///
+/// This is the `days_from_civil` algorithm from:
+///   - <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = if year >= 0 { year } else { year - 399 } / 400;
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+	era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse an RFC3339 timestamp (the format docker uses for things like
+/// network creation time) into epoch seconds.
+///
+/// Fractional seconds are ignored.
+#[must_use]
+pub fn parse_rfc3339_to_epoch(timestamp: &str) -> Option<u64> {
+	if timestamp.len() < 20 || !timestamp.is_char_boundary(19) {
+		return None;
+	}
+	let (date_time, remainder) = timestamp.split_at(19);
+
+	let year = date_time.get(0..4)?.parse::<i64>().ok()?;
+	let month = date_time.get(5..7)?.parse::<i64>().ok()?;
+	let day = date_time.get(8..10)?.parse::<i64>().ok()?;
+	let hour = date_time.get(11..13)?.parse::<i64>().ok()?;
+	let minute = date_time.get(14..16)?.parse::<i64>().ok()?;
+	let second = date_time.get(17..19)?.parse::<i64>().ok()?;
+
+	// Skip past any fractional seconds to find the offset.
+	let offset_str = remainder.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+	let offset_seconds = if offset_str == "Z" || offset_str == "z" {
+		0
+	} else {
+		let sign = match offset_str.chars().next()? {
+			'+' => 1,
+			'-' => -1,
+			_ => return None,
+		};
+		let offset_hours = offset_str.get(1..3)?.parse::<i64>().ok()?;
+		let offset_minutes = offset_str.get(4..6)?.parse::<i64>().ok()?;
+		sign * (offset_hours * 60 * 60 + offset_minutes * 60)
+	};
+
+	let epoch = days_from_civil(year, month, day) * 86_400 + hour * 60 * 60 + minute * 60 + second
+		- offset_seconds;
+	if epoch < 0 {
+		None
+	} else {
+		Some(epoch as u64)
+	}
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+
+	#[test]
+	fn parses_human_durations() {
+		assert_eq!(parse_human_duration("90").unwrap(), Duration::from_secs(90));
+		assert_eq!(
+			parse_human_duration("90s").unwrap(),
+			Duration::from_secs(90)
+		);
+		assert_eq!(
+			parse_human_duration("30m").unwrap(),
+			Duration::from_secs(1800)
+		);
+		assert_eq!(
+			parse_human_duration("2h").unwrap(),
+			Duration::from_secs(7200)
+		);
+		assert_eq!(
+			parse_human_duration("1d").unwrap(),
+			Duration::from_secs(86400)
+		);
+		assert!(parse_human_duration("").is_err());
+		assert!(parse_human_duration("m").is_err());
+		assert!(parse_human_duration("-1h").is_err());
+		assert!(parse_human_duration("1w").is_err());
+		assert!(parse_human_duration("18446744073709551615d").is_err());
+	}
+
+	#[test]
+	fn formats_durations() {
+		assert_eq!(format_duration(Duration::from_millis(1234)), "1.23s");
+		assert_eq!(format_duration(Duration::from_secs(123)), "2m03s");
+		assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+	}
+
+	#[test]
+	fn parses_rfc3339_timestamps() {
+		assert_eq!(parse_rfc3339_to_epoch("1970-01-01T00:00:00Z"), Some(0));
+		assert_eq!(
+			parse_rfc3339_to_epoch("2020-07-03T12:00:00.416543526Z"),
+			Some(1_593_777_600)
+		);
+		assert_eq!(
+			parse_rfc3339_to_epoch("2020-07-03T14:00:00+02:00"),
+			Some(1_593_777_600)
+		);
+		assert_eq!(parse_rfc3339_to_epoch("not a timestamp"), None);
+	}
+}
@@ -11,7 +11,8 @@ use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Report, Section,
 };
-use serde_yaml::Error as YamlError;
+use serde::de::DeserializeOwned;
+use serde_yaml::{Error as YamlError, Value as YamlValue};
 
 /// Location doesn't implement copy/clone, so we create a hacky struct to clone it into.
 struct LocationCopy {
@@ -36,27 +37,25 @@ fn loc_to_loc_copy(loc: Option<serde_yaml::Location>) -> Option<LocationCopy> {
 	}
 }
 
-/// Add "did you mean" text to unknown variant error message for YAML.
+/// Parse a serde "unknown" error message, like: "unknown variant `x`,
+/// expected one of `a`, `b`" returning what was typed, and what was expected.
 ///
 /// `err_msg` - the YAML error message.
-fn did_you_mean_variant(err_msg: &str) -> Option<Vec<String>> {
-	let them_split = err_msg.splitn(2, ": ").collect::<Vec<&str>>();
-	if them_split.len() != 2 {
-		return None;
-	}
-	let err_msg_without_field = them_split[1];
-	if !err_msg_without_field.starts_with("unknown variant `") {
-		return None;
-	}
-
-	let mut variant_errs = Vec::new();
+/// `unknown_kind` - the kind of thing that was unknown, e.g. `variant`, or
+///                  `field`.
+fn parse_unknown_err(err_msg: &str, unknown_kind: &str) -> Option<(String, Vec<String>)> {
+	let unknown_prefix = format!("unknown {} `", unknown_kind);
+	let unknown_start = err_msg.find(&unknown_prefix)?;
+	// Start at the backtick, so the first name parsed is what was typed.
+	let err_msg_from_names = &err_msg[unknown_start + unknown_prefix.len() - 1..];
 
+	let mut names = Vec::new();
 	let mut parsing = false;
 	let mut buff = String::new();
-	for the_char in err_msg_without_field.chars() {
+	for the_char in err_msg_from_names.chars() {
 		if parsing {
 			if the_char == '`' {
-				variant_errs.push(buff);
+				names.push(buff);
 				buff = String::new();
 				parsing = false;
 			} else {
@@ -67,18 +66,69 @@ fn did_you_mean_variant(err_msg: &str) -> Option<Vec<String>> {
 		}
 	}
 
-	if variant_errs.len() < 2 {
+	if names.is_empty() {
+		None
+	} else {
+		let what_was_typed = names.remove(0);
+		Some((what_was_typed, names))
+	}
+}
+
+/// Add "did you mean" text to unknown variant error message for YAML.
+///
+/// `err_msg` - the YAML error message.
+fn did_you_mean_variant(err_msg: &str) -> Option<Vec<String>> {
+	let (what_was_typed, possibilities) = parse_unknown_err(err_msg, "variant")?;
+	if possibilities.is_empty() {
 		None
 	} else {
-		let (what_was_typed, possibilities) = variant_errs.split_first().unwrap();
 		Some(calculate_did_you_mean_possibilities_str(
-			what_was_typed,
-			possibilities,
+			&what_was_typed,
+			&possibilities,
 			3,
 		))
 	}
 }
 
+/// Find every item in a list within a YAML document that fails to parse on
+/// it's own, so more than one error can be reported at once.
+///
+/// Returns a description of each item that failed to parse, empty if the
+/// document (or list) couldn't be found.
+///
+/// `src_data` - the file contents.
+/// `list_key` - the key in the document that holds the list of items.
+/// `item_kind` - what the items are called, e.g. `Task`.
+fn find_item_errors<Item: DeserializeOwned>(
+	src_data: &str,
+	list_key: &str,
+	item_kind: &str,
+) -> Vec<String> {
+	let items = match serde_yaml::from_str::<YamlValue>(src_data) {
+		Ok(YamlValue::Mapping(mapping)) => {
+			match mapping.get(&YamlValue::String(list_key.to_owned())) {
+				Some(YamlValue::Sequence(items)) => items.clone(),
+				_ => return Vec::new(),
+			}
+		}
+		_ => return Vec::new(),
+	};
+
+	items
+		.into_iter()
+		.enumerate()
+		.filter_map(|(idx, item)| {
+			let item_name = item
+				.get("name")
+				.and_then(YamlValue::as_str)
+				.map_or_else(String::new, |name| format!(" (`{}`)", name));
+			serde_yaml::from_value::<Item>(item)
+				.err()
+				.map(|item_err| format!("{} #{}{}: {}", item_kind, idx + 1, item_name, item_err))
+		})
+		.collect()
+}
+
 /// Add contextulization to a YAML Error.
 ///
 /// `result` - the result to contextualize.
@@ -143,3 +193,79 @@ pub fn contextualize<T>(
 		}
 	}
 }
+
+/// Add contextulization to a YAML Error for a file that contains a list of
+/// items (like tasks, or executors).
+///
+/// Serde stops at the first error it finds, so on an error every item in the
+/// list is also parsed on it's own. When more than one item has an error
+/// they're all reported at once, rather than one per run.
+///
+/// `result` - the result to contextualize.
+/// `src_filepath` - the file path of the source error.
+/// `src_data` - the file contents.
+/// `list_key` - the key in the document that holds the list of items.
+/// `item_kind` - what the items are called, e.g. `Task`.
+///
+/// # Errors
+///
+/// - If the first parameter error'd.
+pub fn contextualize_list<T, Item: DeserializeOwned>(
+	result: Result<T, YamlError>,
+	src_filepath: &str,
+	src_data: &str,
+	list_key: &str,
+	item_kind: &str,
+) -> Result<T, Report> {
+	if result.is_ok() {
+		return contextualize(result, src_filepath, src_data);
+	}
+
+	let item_errors = find_item_errors::<Item>(src_data, list_key, item_kind);
+	let mut new_err = contextualize(result, src_filepath, src_data);
+	if item_errors.len() > 1 {
+		new_err = new_err.note(format!(
+			"Found errors in {} items, rather than just the one above:\n  {}",
+			item_errors.len(),
+			item_errors.join("\n  "),
+		));
+	}
+
+	new_err
+}
+
+#[cfg(test)]
+mod unit_tests {
+	use super::*;
+	use crate::config::types::TaskConf;
+
+	#[test]
+	fn parses_unknown_errors() {
+		assert_eq!(
+			parse_unknown_err(
+				"tasks[0].type: unknown variant `ppipeline`, expected one of `command`, `pipeline` at line 3 column 11",
+				"variant"
+			),
+			Some((
+				"ppipeline".to_owned(),
+				vec!["command".to_owned(), "pipeline".to_owned()]
+			)),
+		);
+		assert_eq!(
+			parse_unknown_err("unknown field `nme`, there are no fields", "field"),
+			Some(("nme".to_owned(), Vec::new())),
+		);
+		assert_eq!(parse_unknown_err("missing field `name`", "field"), None);
+	}
+
+	#[test]
+	fn finds_every_item_error() {
+		let src = "tasks:\n  - name: good\n  - description: no name\n  - name: bad-type\n    type: nope\n";
+		let item_errors = find_item_errors::<TaskConf>(src, "tasks", "Task");
+		assert_eq!(item_errors.len(), 2);
+		assert!(item_errors[0].starts_with("Task #2: "));
+		assert!(item_errors[1].starts_with("Task #3 (`bad-type`): "));
+
+		assert!(find_item_errors::<TaskConf>("- not a mapping", "tasks", "Task").is_empty());
+	}
+}